@@ -1,3 +1,7 @@
+use std::hash;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::path;
 use std::sync;
 use winit::window;
 
@@ -5,9 +9,12 @@ pub mod rendering;
 pub mod simulation;
 pub mod sprite;
 pub mod user_interface;
+pub mod world;
 
-pub fn start() -> Result<(), anyhow::Error> {
+/// Runs the game, recording every fixed tick into `record` when it is given.
+pub fn start(record: Option<path::PathBuf>) -> Result<(), anyhow::Error> {
     let mut app = App::new();
+    app.record = record;
     let event_loop = winit::event_loop::EventLoop::builder().build()?;
 
     event_loop.set_control_flow(winit::event_loop::ControlFlow::Poll);
@@ -15,9 +22,31 @@ pub fn start() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+/// Replays a recording headlessly, failing at the first tick whose state hash differs.
+pub fn replay(path: &path::Path) -> Result<(), anyhow::Error> {
+    let report = crate::simulation::replay::verify(path)?;
+    if let Some(tick) = report.desync {
+        anyhow::bail!(
+            "replay desynced at tick {tick} after {} ticks",
+            report.ticks
+        );
+    }
+    log::info!("replay matched for {} ticks", report.ticks);
+    Ok(())
+}
+
 pub struct App<'window> {
     simulation: Option<crate::simulation::Simulation<'window>>,
     last_update: std::time::Instant,
+    record: Option<path::PathBuf>,
+    /// Touch that drives the pointer until it is lifted.
+    primary_touch: Option<u64>,
+}
+
+impl Default for App<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl App<'_> {
@@ -25,6 +54,8 @@ impl App<'_> {
         Self {
             simulation: None,
             last_update: std::time::Instant::now(),
+            record: None,
+            primary_touch: None,
         }
     }
 }
@@ -39,15 +70,20 @@ impl winit::application::ApplicationHandler for App<'_> {
             );
 
             let renderer = crate::rendering::Gpu::new(window.clone()).unwrap();
-            let simulation = crate::simulation::Simulation::new(renderer, window);
+            let mut simulation = crate::simulation::Simulation::new(renderer, window);
+            if let Some(path) = &self.record
+                && let Err(error) = simulation.record_to(path)
+            {
+                log::error!("Could not record to {}: {error:#}", path.display());
+            }
             self.simulation = Some(simulation);
         }
     }
 
     fn window_event(
         &mut self,
-        event_loop: &winit::event_loop::ActiveEventLoop,
-        window_id: window::WindowId,
+        _event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: window::WindowId,
         event: winit::event::WindowEvent,
     ) {
         use winit::event::WindowEvent;
@@ -60,7 +96,7 @@ impl winit::application::ApplicationHandler for App<'_> {
                 let mut gpu = simulation.gpu_handle.write().unwrap();
                 gpu.submit_command_buffer();
             }
-            WindowEvent::ActivationTokenDone { serial, token } => todo!(),
+            WindowEvent::ActivationTokenDone { .. } => todo!(),
             WindowEvent::Resized(physical_size) => {
                 let mut gpu = simulation.gpu_handle.write().unwrap();
                 gpu.surface_config_mut().width = physical_size.width;
@@ -71,17 +107,17 @@ impl winit::application::ApplicationHandler for App<'_> {
                         egui::vec2(physical_size.width as f32, physical_size.height as f32),
                     ));
             }
-            WindowEvent::Moved(physical_position) => (),
+            WindowEvent::Moved(_) => (),
             WindowEvent::CloseRequested => todo!(),
             WindowEvent::Destroyed => todo!(),
-            WindowEvent::DroppedFile(path_buf) => todo!(),
-            WindowEvent::HoveredFile(path_buf) => todo!(),
-            WindowEvent::HoveredFileCancelled => todo!(),
+            WindowEvent::DroppedFile(path_buf) => simulation.drop_file(path_buf),
+            WindowEvent::HoveredFile(path_buf) => simulation.hover_file(path_buf),
+            WindowEvent::HoveredFileCancelled => simulation.cancel_hover(),
             WindowEvent::Focused(focused) => {
                 simulation.user_interface.user_interface_input.focused = focused
             }
             WindowEvent::KeyboardInput {
-                device_id,
+                device_id: _device_id,
                 event,
                 is_synthetic,
             } => {
@@ -119,9 +155,9 @@ impl winit::application::ApplicationHandler for App<'_> {
                     command: modifiers.state().control_key(),
                 }
             }
-            WindowEvent::Ime(ime) => todo!(),
+            WindowEvent::Ime(_) => todo!(),
             WindowEvent::CursorMoved {
-                device_id,
+                device_id: _device_id,
                 position,
             } => {
                 let position = egui::Pos2 {
@@ -146,12 +182,29 @@ impl winit::application::ApplicationHandler for App<'_> {
                 .events
                 .push(egui::Event::PointerGone),
             WindowEvent::MouseWheel {
-                device_id,
+                device_id: _device_id,
                 delta,
-                phase,
-            } => todo!(),
+                phase: _phase,
+            } => {
+                let (unit, delta) = match delta {
+                    winit::event::MouseScrollDelta::LineDelta(x, y) => {
+                        (egui::MouseWheelUnit::Line, egui::vec2(x, y))
+                    }
+                    winit::event::MouseScrollDelta::PixelDelta(position) => (
+                        egui::MouseWheelUnit::Point,
+                        egui::vec2(position.x as f32, position.y as f32),
+                    ),
+                };
+                simulation.user_interface.user_interface_input.events.push(
+                    egui::Event::MouseWheel {
+                        unit,
+                        delta,
+                        modifiers: simulation.user_interface.user_interface_input.modifiers,
+                    },
+                )
+            }
             WindowEvent::MouseInput {
-                device_id,
+                device_id: _device_id,
                 state,
                 button,
             } => simulation.user_interface.user_interface_input.events.push(
@@ -170,44 +223,112 @@ impl winit::application::ApplicationHandler for App<'_> {
                 },
             ),
             WindowEvent::PinchGesture {
-                device_id,
+                device_id: _device_id,
                 delta,
-                phase,
-            } => todo!(),
+                phase: _phase,
+            } => simulation
+                .user_interface
+                .user_interface_input
+                .events
+                .push(egui::Event::Zoom(1.0 + delta as f32)),
             WindowEvent::PanGesture {
-                device_id,
+                device_id: _device_id,
                 delta,
-                phase,
-            } => todo!(),
-            WindowEvent::DoubleTapGesture { device_id } => todo!(),
+                phase: _phase,
+            } => simulation.user_interface.user_interface_input.events.push(
+                egui::Event::MouseWheel {
+                    unit: egui::MouseWheelUnit::Point,
+                    delta: egui::vec2(delta.x, delta.y),
+                    modifiers: simulation.user_interface.user_interface_input.modifiers,
+                },
+            ),
+            WindowEvent::DoubleTapGesture {
+                device_id: _device_id,
+            } => {
+                let position = simulation.user_interface.last_mouse_pos;
+                simulation.push_gesture(world::input::touch::Gesture::DoubleTap {
+                    position: glam::vec2(position.x, position.y),
+                })
+            }
             WindowEvent::RotationGesture {
-                device_id,
+                device_id: _device_id,
                 delta,
-                phase,
-            } => todo!(),
+                phase: _phase,
+            } => {
+                let position = simulation.user_interface.last_mouse_pos;
+                simulation.push_gesture(world::input::touch::Gesture::Rotate {
+                    center: glam::vec2(position.x, position.y),
+                    // winit turns counterclockwise for positive degrees.
+                    angle: -delta.to_radians(),
+                })
+            }
+            // Force clicks have no use yet, touch pressure arrives with the touches themselves.
             WindowEvent::TouchpadPressure {
-                device_id,
-                pressure,
-                stage,
-            } => todo!(),
+                device_id: _device_id,
+                pressure: _pressure,
+                stage: _stage,
+            } => (),
             WindowEvent::AxisMotion {
-                device_id,
-                axis,
-                value,
+                device_id: _device_id,
+                axis: _axis,
+                value: _value,
             } => todo!(),
-            WindowEvent::Touch(touch) => todo!(),
+            WindowEvent::Touch(touch) => {
+                let position = egui::pos2(touch.location.x as f32, touch.location.y as f32);
+                let phase = match touch.phase {
+                    winit::event::TouchPhase::Started => egui::TouchPhase::Start,
+                    winit::event::TouchPhase::Moved => egui::TouchPhase::Move,
+                    winit::event::TouchPhase::Ended => egui::TouchPhase::End,
+                    winit::event::TouchPhase::Cancelled => egui::TouchPhase::Cancel,
+                };
+                let mut device = hash::DefaultHasher::new();
+                touch.device_id.hash(&mut device);
+                let input = &mut simulation.user_interface.user_interface_input;
+                input.events.push(egui::Event::Touch {
+                    device_id: egui::TouchDeviceId(device.finish()),
+                    id: egui::TouchId(touch.id),
+                    phase,
+                    pos: position,
+                    force: touch.force.map(|force| force.normalized() as f32),
+                });
+                // The first finger also drives the pointer, so everything that only knows the
+                // mouse works with touch.
+                if self.primary_touch.is_none() && phase == egui::TouchPhase::Start {
+                    self.primary_touch = Some(touch.id);
+                }
+                if self.primary_touch != Some(touch.id) {
+                    return;
+                }
+                simulation.user_interface.last_mouse_pos = position;
+                input.events.push(egui::Event::PointerMoved(position));
+                let pressed = match phase {
+                    egui::TouchPhase::Start => true,
+                    egui::TouchPhase::Move => return,
+                    egui::TouchPhase::End | egui::TouchPhase::Cancel => false,
+                };
+                input.events.push(egui::Event::PointerButton {
+                    pos: position,
+                    button: egui::PointerButton::Primary,
+                    pressed,
+                    modifiers: input.modifiers,
+                });
+                if !pressed {
+                    input.events.push(egui::Event::PointerGone);
+                    self.primary_touch = None;
+                }
+            }
             WindowEvent::ScaleFactorChanged {
-                scale_factor,
-                inner_size_writer,
+                scale_factor: _scale_factor,
+                inner_size_writer: _inner_size_writer,
             } => (),
-            WindowEvent::ThemeChanged(theme) => todo!(),
+            WindowEvent::ThemeChanged(_) => todo!(),
             WindowEvent::Occluded(_) => todo!(),
         }
     }
 
     fn new_events(
         &mut self,
-        event_loop: &winit::event_loop::ActiveEventLoop,
+        _event_loop: &winit::event_loop::ActiveEventLoop,
         cause: winit::event::StartCause,
     ) {
         use winit::event::StartCause;
@@ -223,12 +344,12 @@ impl winit::application::ApplicationHandler for App<'_> {
             StartCause::Init => (),
             StartCause::Poll => (),
             StartCause::ResumeTimeReached {
-                start,
-                requested_resume,
+                start: _start,
+                requested_resume: _requested_resume,
             } => todo!(),
             StartCause::WaitCancelled {
-                start,
-                requested_resume,
+                start: _start,
+                requested_resume: _requested_resume,
             } => todo!(),
         }
     }
@@ -247,7 +368,13 @@ impl winit::application::ApplicationHandler for App<'_> {
     }
 
     fn about_to_wait(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
-        let _ = event_loop;
+        if self
+            .simulation
+            .as_ref()
+            .is_some_and(|simulation| simulation.exit_requested())
+        {
+            event_loop.exit();
+        }
     }
 
     fn suspended(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
@@ -4,5 +4,21 @@ fn main() {
         simplelog::ConfigBuilder::new().build(),
     );
 
-    let _ = game_test::start();
+    let arguments = std::env::args().collect::<Vec<_>>();
+    let path_after = |flag: &str| {
+        arguments
+            .iter()
+            .position(|argument| argument == flag)
+            .and_then(|index| arguments.get(index + 1))
+            .map(std::path::PathBuf::from)
+    };
+    if let Some(path) = path_after("--replay") {
+        if let Err(error) = game_test::replay(&path) {
+            log::error!("{error:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let _ = game_test::start(path_after("--record"));
 }
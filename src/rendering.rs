@@ -1,9 +1,17 @@
 use anyhow::Result;
-use egui::output;
 use std::sync;
 
 pub mod buffer;
+pub mod camera;
+pub mod compression;
+pub mod graph;
+pub mod lighting;
+pub mod mipmap;
+pub mod post;
 pub mod renderable;
+pub mod sampler;
+pub mod shader;
+pub mod textures;
 
 // const SHADER: &[u8] = include_bytes!("shader.wgsl");
 
@@ -14,10 +22,16 @@ pub struct Gpu<'window> {
     queue: wgpu::Queue,
     belt: wgpu::util::StagingBelt,
     belt_encoder: wgpu::CommandEncoder,
-    surface: wgpu::Surface<'window>,
+    /// `None` for a headless GPU, which draws to `offscreen` instead.
+    surface: Option<wgpu::Surface<'window>>,
     surface_config: wgpu::SurfaceConfiguration,
     output: Option<wgpu::SurfaceTexture>,
+    offscreen: Option<wgpu::Texture>,
     command_buffer: Vec<wgpu::CommandBuffer>,
+    samplers: sampler::SamplerRegistry,
+    mipmaps: mipmap::MipGenerator,
+    compression: Option<compression::CompressedFormat>,
+    graph: graph::RenderGraph,
 }
 impl<'window> Gpu<'window> {
     pub fn new(window: sync::Arc<winit::window::Window>) -> Result<GpuHandle<'window>> {
@@ -55,32 +69,100 @@ impl<'window> Gpu<'window> {
             view_formats: Vec::new(),
         };
 
-        let (device, queue) = pollster::block_on(adapter.request_device(
+        let (device, queue) = Self::request_device(&adapter)?;
+        surface.configure(&device, &surface_config);
+        let output = surface.get_current_texture()?;
+
+        let mut gpu = Self::with_device(device, queue, surface_config);
+        gpu.surface = Some(surface);
+        gpu.output = Some(output);
+        Ok(sync::Arc::new(sync::RwLock::new(gpu)))
+    }
+    /// A GPU without a window, whose output is an offscreen texture of `width` by `height` that
+    /// [`Gpu::read_output`] reads back after each submitted frame.
+    ///
+    /// `WGPU_BACKEND` picks other backends, like `gl` on machines without Vulkan.
+    pub fn headless(width: u32, height: u32) -> Result<GpuHandle<'window>> {
+        let wgpu_instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::from_env().unwrap_or(wgpu::Backends::PRIMARY),
+            flags: wgpu::InstanceFlags::from_build_config(),
+            backend_options: wgpu::BackendOptions::from_env_or_default(),
+        });
+        let adapter = pollster::block_on(wgpu_instance.request_adapter(
+            &wgpu::RequestAdapterOptionsBase {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                force_fallback_adapter: false,
+                compatible_surface: None,
+            },
+        ))
+        .ok_or(anyhow::anyhow!("No adapters available"))?;
+        let (device, queue) = Self::request_device(&adapter)?;
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: Vec::new(),
+        };
+        let offscreen = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("offscreen output"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: surface_config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let mut gpu = Self::with_device(device, queue, surface_config);
+        gpu.offscreen = Some(offscreen);
+        Ok(sync::Arc::new(sync::RwLock::new(gpu)))
+    }
+    fn request_device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue)> {
+        Ok(pollster::block_on(adapter.request_device(
             &wgpu::DeviceDescriptor {
                 label: Some("device"),
-                required_features: wgpu::Features::empty(),
+                required_features: adapter.features() & compression::CompressedFormat::FEATURES,
                 required_limits: wgpu::Limits::default(),
                 memory_hints: wgpu::MemoryHints::Performance,
             },
             None,
-        ))?;
+        ))?)
+    }
+    fn with_device(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        surface_config: wgpu::SurfaceConfiguration,
+    ) -> Self {
         let belt = wgpu::util::StagingBelt::new(16 * 1024);
         let belt_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Gpu belt encoder"),
         });
-        surface.configure(&device, &surface_config);
-        let output = surface.get_current_texture()?;
-
-        Ok(sync::Arc::new(sync::RwLock::new(Self {
-            device,
+        Self {
             queue,
             belt,
             belt_encoder,
-            surface,
+            surface: None,
             surface_config,
-            output: Some(output),
+            output: None,
+            offscreen: None,
             command_buffer: vec![],
-        })))
+            samplers: sampler::SamplerRegistry::default(),
+            mipmaps: mipmap::MipGenerator::default(),
+            compression: compression::CompressedFormat::choose(device.features()),
+            graph: graph::RenderGraph::default(),
+            device,
+        }
     }
     pub fn device(&self) -> &wgpu::Device {
         &self.device
@@ -88,17 +170,30 @@ impl<'window> Gpu<'window> {
     pub fn queue(&self) -> &wgpu::Queue {
         &self.queue
     }
+    /// The shared sampler for `sampler`, created on first use.
+    pub fn sampler(&self, sampler: sampler::Sampler) -> wgpu::Sampler {
+        self.samplers.get(&self.device, sampler)
+    }
+    /// Regenerates the mips of `texture` from its first level, see [`mipmap::MipGenerator`].
+    pub fn generate_mips(&self, texture: &wgpu::Texture) {
+        self.mipmaps.generate(self, texture);
+    }
+    /// The block compression sprite sheets are baked to, `None` if they stay RGBA.
+    pub fn compression(&self) -> Option<compression::CompressedFormat> {
+        self.compression
+    }
     pub fn write_buffer(
         &mut self,
         target: &wgpu::Buffer,
         offset: wgpu::BufferAddress,
         size: wgpu::BufferSize,
-    ) -> wgpu::BufferViewMut {
+    ) -> wgpu::BufferViewMut<'_> {
         self.belt
             .write_buffer(&mut self.belt_encoder, target, offset, size, &self.device)
     }
-    pub fn surface(&self) -> &wgpu::Surface {
-        &self.surface
+    /// `None` for a headless GPU.
+    pub fn surface(&self) -> Option<&wgpu::Surface<'window>> {
+        self.surface.as_ref()
     }
     pub fn surface_config(&self) -> &wgpu::SurfaceConfiguration {
         &self.surface_config
@@ -107,23 +202,36 @@ impl<'window> Gpu<'window> {
         &mut self.surface_config
     }
     pub fn configure_surface(&self) {
-        self.surface.configure(&self.device, &self.surface_config);
+        if let Some(surface) = &self.surface {
+            surface.configure(&self.device, &self.surface_config);
+        }
     }
     pub fn output(&mut self) -> anyhow::Result<&wgpu::SurfaceTexture> {
         if let Some(ref output) = self.output {
             Ok(output)
         } else {
-            let output = self.surface.get_current_texture()?;
+            let Some(surface) = &self.surface else {
+                anyhow::bail!("a headless GPU has no surface");
+            };
+            let output = surface.get_current_texture()?;
             self.output = Some(output);
-            Ok(&self
+            Ok(self
                 .output
                 .as_ref()
                 .expect("output was literally just set to some"))
         }
     }
+    /// Where the frame is drawn, passes added to it run when the frame is submitted.
+    pub fn render_graph(&mut self) -> &mut graph::RenderGraph {
+        &mut self.graph
+    }
+    pub fn render_graph_stats(&self) -> &graph::RenderGraphStats {
+        self.graph.stats()
+    }
     pub fn push_command_buffer(&mut self, command_buffer: wgpu::CommandBuffer) {
         self.command_buffer.push(command_buffer)
     }
+    /// Submits the staged writes, then the passes of the render graph, and presents the output.
     pub fn submit_command_buffer(&mut self) {
         self.belt.finish();
         let mut swap_encoder =
@@ -134,6 +242,22 @@ impl<'window> Gpu<'window> {
         std::mem::swap(&mut self.belt_encoder, &mut swap_encoder);
         self.push_command_buffer(swap_encoder.finish());
 
+        let output = match self.offscreen.clone() {
+            Some(offscreen) => Some(offscreen),
+            None => match self.output() {
+                Ok(output) => Some(output.texture.clone()),
+                Err(error) => {
+                    log::error!("Could not get the output: {error}");
+                    None
+                }
+            },
+        };
+        let output_size = (self.surface_config.width, self.surface_config.height);
+        let passes = self
+            .graph
+            .execute(&self.device, output.as_ref(), output_size);
+        self.command_buffer.extend(passes);
+
         self.queue.submit(self.command_buffer.drain(..));
 
         self.belt.recall();
@@ -142,4 +266,63 @@ impl<'window> Gpu<'window> {
             self.configure_surface();
         }
     }
+    /// The last frame a headless GPU submitted, waiting for the GPU to finish it.
+    pub fn read_output(&self) -> Result<image::RgbaImage> {
+        let Some(offscreen) = &self.offscreen else {
+            anyhow::bail!("only a headless GPU can read its output back");
+        };
+        self.read_texture(offscreen)
+    }
+    /// Copies an 8 bit RGBA texture back from the GPU, waiting for the copy to finish.
+    pub fn read_texture(&self, texture: &wgpu::Texture) -> Result<image::RgbaImage> {
+        anyhow::ensure!(
+            texture.format().block_copy_size(None) == Some(4),
+            "cannot read back {:?} textures",
+            texture.format()
+        );
+        let (width, height) = (texture.width(), texture.height());
+        let bytes_per_row = (4 * width).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback buffer"),
+            size: bytes_per_row as u64 * height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut command_encoder =
+            self.device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("readback encoder"),
+                });
+        command_encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
+        self.queue.submit([command_encoder.finish()]);
+
+        let (sender, receiver) = sync::mpsc::channel();
+        buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+        let mapped = buffer.slice(..).get_mapped_range();
+        let mut pixels = Vec::with_capacity((4 * width * height) as usize);
+        for row in mapped.chunks(bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..(4 * width) as usize]);
+        }
+        drop(mapped);
+        buffer.unmap();
+        image::RgbaImage::from_raw(width, height, pixels)
+            .ok_or(anyhow::anyhow!("readback has the wrong size"))
+    }
 }
@@ -1,10 +1,13 @@
 use std::collections;
 
+// Unfinished allocator, nothing uses it yet.
+#[allow(dead_code)]
 pub struct GpuBuffer {
     buffer: wgpu::Buffer,
     allocations: collections::BTreeMap<wgpu::BufferAddress, wgpu::BufferSize>,
 }
 
+#[allow(dead_code)]
 impl GpuBuffer {
     fn new(size: wgpu::BufferSize, device: &wgpu::Device) -> Self {
         Self {
@@ -20,7 +23,9 @@ impl GpuBuffer {
     fn allocate(&self, size: wgpu::BufferSize) -> wgpu::BufferAddress {
         let mut start: u64 = 0;
         for allocation in self.allocations.iter() {
-            if allocation.0 - start >= size.get() {}
+            if allocation.0 - start >= size.get() {
+                break;
+            }
             start = allocation.0 + allocation.1.get()
         }
         todo!()
@@ -0,0 +1,31 @@
+use crate::world::spatial;
+
+/// Orthographic view of the world, `zoom` is in pixels per world unit and y points up.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Camera {
+    pub position: glam::Vec2,
+    pub zoom: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            position: glam::Vec2::ZERO,
+            zoom: 1.0,
+        }
+    }
+}
+
+impl Camera {
+    pub fn view_rect(&self, viewport: glam::Vec2) -> spatial::Aabb {
+        spatial::Aabb::from_center(self.position, viewport * 0.5 / self.zoom)
+    }
+    pub fn view_projection(&self, viewport: glam::Vec2) -> glam::Mat4 {
+        let view = self.view_rect(viewport);
+        glam::Mat4::orthographic_rh(view.min.x, view.max.x, view.min.y, view.max.y, -1.0, 1.0)
+    }
+    pub fn screen_to_world(&self, screen: glam::Vec2, viewport: glam::Vec2) -> glam::Vec2 {
+        let centered = screen - viewport * 0.5;
+        self.position + glam::vec2(centered.x, -centered.y) / self.zoom
+    }
+}
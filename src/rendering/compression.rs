@@ -0,0 +1,710 @@
+//! Block compression of sprite sheets on the CPU, done on the bake and import threads.
+//!
+//! BC3 is used where the adapter has BC support and ETC2 where it has ETC2 support, everything
+//! else gets RGBA. BC7 and ASTC would compress better but need encoders this crate does not
+//! have yet.
+//!
+//! Encoding is slow, so [`compress_cached`] keeps the blocks in a file next to the source and
+//! reuses them until the pixels change.
+
+use crate::simulation::save::codec;
+use std::ffi;
+use std::fs;
+use std::path;
+
+const CACHE_MAGIC: &[u8; 4] = b"GBLK";
+/// Bump whenever an encoder changes, caches of other versions are encoded again.
+const CACHE_VERSION: u32 = 1;
+
+/// Pixels of one 4x4 block, row by row.
+type Block = [[u8; 4]; 16];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CompressedFormat {
+    /// BC1 colour with BC4 alpha, for desktop adapters.
+    Bc3,
+    /// ETC2 colour with EAC alpha, for mobile adapters.
+    Etc2,
+}
+
+impl CompressedFormat {
+    /// The features [`CompressedFormat::choose`] picks from, requested when the adapter has them.
+    pub const FEATURES: wgpu::Features =
+        wgpu::Features::TEXTURE_COMPRESSION_BC.union(wgpu::Features::TEXTURE_COMPRESSION_ETC2);
+
+    /// The format the device can sample, preferring BC3, or `None` to upload RGBA.
+    pub fn choose(features: wgpu::Features) -> Option<Self> {
+        if features.contains(wgpu::Features::TEXTURE_COMPRESSION_BC) {
+            Some(Self::Bc3)
+        } else if features.contains(wgpu::Features::TEXTURE_COMPRESSION_ETC2) {
+            Some(Self::Etc2)
+        } else {
+            None
+        }
+    }
+    pub fn texture_format(&self) -> wgpu::TextureFormat {
+        match self {
+            Self::Bc3 => wgpu::TextureFormat::Bc3RgbaUnormSrgb,
+            Self::Etc2 => wgpu::TextureFormat::Etc2Rgba8UnormSrgb,
+        }
+    }
+    /// Appended to the source file name to name its cache.
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Bc3 => "bc3",
+            Self::Etc2 => "etc2",
+        }
+    }
+    fn encode(&self, block: &Block) -> [u8; 16] {
+        match self {
+            Self::Bc3 => encode_bc3(block),
+            Self::Etc2 => encode_etc2(block),
+        }
+    }
+}
+
+/// A block compressed image with its whole mip chain, 16 bytes per 4x4 block.
+pub struct CompressedImage {
+    pub format: CompressedFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Blocks of each mip level row by row, the first level first.
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl CompressedImage {
+    pub fn size(&self) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: 1,
+        }
+    }
+}
+
+/// Compresses `image` and its mips, or `None` if its size is not a multiple of the block size
+/// and it has to stay RGBA.
+pub fn compress(image: &image::RgbaImage, format: CompressedFormat) -> Option<CompressedImage> {
+    if image.width() == 0 || !image.width().is_multiple_of(4) || !image.height().is_multiple_of(4) {
+        return None;
+    }
+    let mut levels = Vec::new();
+    let mut level = image.clone();
+    loop {
+        levels.push(compress_level(&level, format));
+        if level.width() == 1 && level.height() == 1 {
+            break;
+        }
+        level = image::imageops::resize(
+            &level,
+            (level.width() / 2).max(1),
+            (level.height() / 2).max(1),
+            image::imageops::FilterType::Triangle,
+        );
+    }
+    Some(CompressedImage {
+        format,
+        width: image.width(),
+        height: image.height(),
+        levels,
+    })
+}
+
+/// Where [`compress_cached`] keeps the blocks of `source`, `sheet.png` caching its BC3 blocks in
+/// `sheet.png.bc3`.
+pub fn cache_path(source: &path::Path, format: CompressedFormat) -> path::PathBuf {
+    let mut name = ffi::OsString::from(source.as_os_str());
+    name.push(".");
+    name.push(format.extension());
+    path::PathBuf::from(name)
+}
+
+/// Like [`compress`], but reads the blocks from the cache of `source` when it was written for
+/// the same pixels, and writes the cache otherwise.
+pub fn compress_cached(
+    image: &image::RgbaImage,
+    format: CompressedFormat,
+    source: &path::Path,
+) -> Option<CompressedImage> {
+    let cache = cache_path(source, format);
+    let hash = pixel_hash(image);
+    if let Ok(bytes) = fs::read(&cache) {
+        match read_cache(&bytes, format, hash) {
+            Ok(compressed) => return Some(compressed),
+            Err(error) => log::debug!("Encoding {} again: {error}", source.display()),
+        }
+    }
+    let compressed = compress(image, format)?;
+    if let Err(error) = fs::write(&cache, write_cache(&compressed, hash)) {
+        log::warn!("Failed to write {}: {error}", cache.display());
+    }
+    Some(compressed)
+}
+
+/// FNV-1a of the size and pixels of `image`.
+fn pixel_hash(image: &image::RgbaImage) -> u64 {
+    let size = [image.width(), image.height()].map(u32::to_le_bytes);
+    size.iter()
+        .flatten()
+        .chain(image.as_raw())
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+}
+
+fn write_cache(compressed: &CompressedImage, hash: u64) -> Vec<u8> {
+    let mut writer = codec::Writer::default();
+    writer.u32(CACHE_VERSION);
+    writer.string(compressed.format.extension());
+    writer.u64(hash);
+    writer.u32(compressed.width);
+    writer.u32(compressed.height);
+    writer.u32(compressed.levels.len() as u32);
+    for level in &compressed.levels {
+        writer.bytes(level);
+    }
+    let mut bytes = CACHE_MAGIC.to_vec();
+    bytes.extend(writer.into_bytes());
+    bytes
+}
+
+/// The cached blocks, or an error if they belong to other pixels, another format or encoder
+/// version, or do not fit the image.
+fn read_cache(
+    bytes: &[u8],
+    format: CompressedFormat,
+    hash: u64,
+) -> anyhow::Result<CompressedImage> {
+    let Some((magic, rest)) = bytes.split_first_chunk::<4>() else {
+        anyhow::bail!("not a block cache");
+    };
+    anyhow::ensure!(magic == CACHE_MAGIC, "not a block cache");
+    let mut reader = codec::Reader::new(rest);
+    anyhow::ensure!(
+        reader.u32()? == CACHE_VERSION,
+        "cache of another encoder version"
+    );
+    anyhow::ensure!(
+        reader.string()? == format.extension(),
+        "cache of another format"
+    );
+    anyhow::ensure!(reader.u64()? == hash, "the pixels changed");
+    let (width, height) = (reader.u32()?, reader.u32()?);
+    let count = reader.u32()?;
+    // Levels halve down to 1x1, like those of `compress`.
+    let expected_count = u32::BITS - width.max(height).leading_zeros();
+    anyhow::ensure!(
+        count == expected_count,
+        "{count} levels instead of {expected_count}"
+    );
+    let mut levels = Vec::new();
+    let (mut level_width, mut level_height) = (width, height);
+    for index in 0..count {
+        let level = reader.bytes()?;
+        let expected = level_width.div_ceil(4) * level_height.div_ceil(4) * 16;
+        anyhow::ensure!(
+            level.len() == expected as usize,
+            "level {index} has {} bytes instead of {expected}",
+            level.len()
+        );
+        levels.push(level.to_vec());
+        (level_width, level_height) = ((level_width / 2).max(1), (level_height / 2).max(1));
+    }
+    anyhow::ensure!(reader.is_empty(), "block cache has trailing bytes");
+    Ok(CompressedImage {
+        format,
+        width,
+        height,
+        levels,
+    })
+}
+
+fn compress_level(image: &image::RgbaImage, format: CompressedFormat) -> Vec<u8> {
+    let (blocks_x, blocks_y) = (image.width().div_ceil(4), image.height().div_ceil(4));
+    let mut data = Vec::with_capacity((blocks_x * blocks_y * 16) as usize);
+    for block_y in 0..blocks_y {
+        for block_x in 0..blocks_x {
+            // Levels smaller than a block repeat their edge pixels.
+            let block = std::array::from_fn(|pixel| {
+                let x = (block_x * 4 + pixel as u32 % 4).min(image.width() - 1);
+                let y = (block_y * 4 + pixel as u32 / 4).min(image.height() - 1);
+                image.get_pixel(x, y).0
+            });
+            data.extend_from_slice(&format.encode(&block));
+        }
+    }
+    data
+}
+
+/// Fully transparent pixels do not constrain the colour of a block.
+fn color_weight(pixel: &[u8; 4]) -> u32 {
+    if pixel[3] == 0 { 0 } else { 1 }
+}
+
+fn color_error(a: [i32; 3], b: [i32; 3]) -> u32 {
+    (0..3)
+        .map(|channel| (a[channel] - b[channel]).pow(2) as u32)
+        .sum()
+}
+
+fn encode_bc3(block: &Block) -> [u8; 16] {
+    let mut encoded = [0; 16];
+
+    // BC4 alpha: the extremes and six steps between them.
+    let alpha_max = block.iter().map(|pixel| pixel[3]).max().unwrap_or(0);
+    let alpha_min = block.iter().map(|pixel| pixel[3]).min().unwrap_or(0);
+    encoded[0] = alpha_max;
+    encoded[1] = alpha_min;
+    if alpha_max > alpha_min {
+        let (max, min) = (alpha_max as u32, alpha_min as u32);
+        let palette: [u32; 8] = std::array::from_fn(|index| match index {
+            0 => max,
+            1 => min,
+            step => (max * (8 - step as u32) + min * (step as u32 - 1)) / 7,
+        });
+        let mut bits = 0u64;
+        for (pixel, color) in block.iter().enumerate() {
+            let index = (0..8)
+                .min_by_key(|index| palette[*index].abs_diff(color[3] as u32))
+                .unwrap_or(0) as u64;
+            bits |= index << (3 * pixel);
+        }
+        encoded[2..8].copy_from_slice(&bits.to_le_bytes()[..6]);
+    }
+
+    // BC1 colour: the corners of the bounding box along its main diagonal.
+    let weighted = block
+        .iter()
+        .filter(|pixel| color_weight(pixel) > 0)
+        .collect::<Vec<_>>();
+    let pixels = if weighted.is_empty() {
+        block.iter().collect()
+    } else {
+        weighted
+    };
+    let mut low = [255u8; 3];
+    let mut high = [0u8; 3];
+    for pixel in &pixels {
+        for channel in 0..3 {
+            low[channel] = low[channel].min(pixel[channel]);
+            high[channel] = high[channel].max(pixel[channel]);
+        }
+    }
+    // Swap green and blue ends when they fall against red, so the line follows the colours.
+    let center = (0..3)
+        .map(|channel| (low[channel] as i32 + high[channel] as i32) / 2)
+        .collect::<Vec<_>>();
+    let (mut red_green, mut red_blue) = (0i32, 0i32);
+    for pixel in &pixels {
+        let red = pixel[0] as i32 - center[0];
+        red_green += red * (pixel[1] as i32 - center[1]);
+        red_blue += red * (pixel[2] as i32 - center[2]);
+    }
+    if red_green < 0 {
+        std::mem::swap(&mut low[1], &mut high[1]);
+    }
+    if red_blue < 0 {
+        std::mem::swap(&mut low[2], &mut high[2]);
+    }
+    let to_565 = |color: [u8; 3]| {
+        ((color[0] as u16 >> 3) << 11) | ((color[1] as u16 >> 2) << 5) | (color[2] as u16 >> 3)
+    };
+    let from_565 = |color: u16| {
+        let expand = |value: u16, bits: u32| {
+            let value = value as i32;
+            (value << (8 - bits)) | (value >> (2 * bits - 8))
+        };
+        [
+            expand(color >> 11, 5),
+            expand((color >> 5) & 0x3f, 6),
+            expand(color & 0x1f, 5),
+        ]
+    };
+    let (color0, color1) = (to_565(high), to_565(low));
+    let (end0, end1) = (from_565(color0), from_565(color1));
+    let palette: [[i32; 3]; 4] = [
+        end0,
+        end1,
+        std::array::from_fn(|channel| (2 * end0[channel] + end1[channel]) / 3),
+        std::array::from_fn(|channel| (end0[channel] + 2 * end1[channel]) / 3),
+    ];
+    let mut bits = 0u32;
+    for (pixel, color) in block.iter().enumerate() {
+        let color = [color[0] as i32, color[1] as i32, color[2] as i32];
+        let index = (0..4)
+            .min_by_key(|index| color_error(palette[*index], color))
+            .unwrap_or(0) as u32;
+        bits |= index << (2 * pixel);
+    }
+    encoded[8..10].copy_from_slice(&color0.to_le_bytes());
+    encoded[10..12].copy_from_slice(&color1.to_le_bytes());
+    encoded[12..16].copy_from_slice(&bits.to_le_bytes());
+    encoded
+}
+
+/// Modifiers of the EAC alpha tables.
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+/// Small and large modifier of each ETC colour table.
+const ETC_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+/// ETC pixel index of the pixel at `x`, `y`, they run down the columns.
+fn etc_index(pixel: usize) -> usize {
+    (pixel % 4) * 4 + pixel / 4
+}
+
+fn encode_etc2(block: &Block) -> [u8; 16] {
+    let mut encoded = [0; 16];
+    encoded[..8].copy_from_slice(&encode_eac_alpha(block).to_be_bytes());
+    encoded[8..].copy_from_slice(&encode_etc_color(block).to_be_bytes());
+    encoded
+}
+
+/// Tries every table and multiplier around the middle of the alpha range.
+fn encode_eac_alpha(block: &Block) -> u64 {
+    let alpha_max = block.iter().map(|pixel| pixel[3] as i32).max().unwrap_or(0);
+    let alpha_min = block.iter().map(|pixel| pixel[3] as i32).min().unwrap_or(0);
+    let base = (alpha_max + alpha_min + 1) / 2;
+    // Table 13 has a zero modifier, which a constant alpha uses everywhere.
+    let mut best = (u32::MAX, 13, 1, [4u64; 16]);
+    let multipliers = if alpha_max == alpha_min {
+        1..=1
+    } else {
+        1..=15
+    };
+    for (table, modifiers) in EAC_MODIFIERS.iter().enumerate() {
+        for multiplier in multipliers.clone() {
+            let mut error = 0;
+            let mut indices = [0u64; 16];
+            for (pixel, color) in block.iter().enumerate() {
+                let (index, pixel_error) = (0..8)
+                    .map(|index| {
+                        let value = (base + modifiers[index] * multiplier).clamp(0, 255);
+                        (index, value.abs_diff(color[3] as i32))
+                    })
+                    .min_by_key(|(_, error)| *error)
+                    .unwrap_or((0, 0));
+                error += pixel_error * pixel_error;
+                indices[pixel] = index as u64;
+            }
+            if error < best.0 {
+                best = (error, table, multiplier, indices);
+            }
+        }
+    }
+    let (_, table, multiplier, indices) = best;
+    let mut bits = (base as u64) << 56 | (multiplier as u64) << 52 | (table as u64) << 48;
+    for (pixel, index) in indices.iter().enumerate() {
+        bits |= index << (45 - 3 * etc_index(pixel));
+    }
+    bits
+}
+
+/// Individual mode, two sub-blocks with a 4 bit colour each, split whichever way fits better.
+fn encode_etc_color(block: &Block) -> u64 {
+    let mut best = (u32::MAX, 0u64);
+    for flip in [false, true] {
+        let mut error = 0;
+        let mut bits = (flip as u64) << 32;
+        for sub_block in 0..2 {
+            let pixels = (0..16)
+                .filter(|pixel| {
+                    let (x, y) = (pixel % 4, pixel / 4);
+                    match flip {
+                        false => (x / 2) == sub_block,
+                        true => (y / 2) == sub_block,
+                    }
+                })
+                .collect::<Vec<_>>();
+            // A fully transparent sub-block still gets its average colour.
+            let transparent = pixels.iter().all(|pixel| color_weight(&block[*pixel]) == 0);
+            let weights = pixels
+                .iter()
+                .map(|pixel| match transparent {
+                    true => 1,
+                    false => color_weight(&block[*pixel]),
+                })
+                .collect::<Vec<_>>();
+            let average: [i32; 3] = std::array::from_fn(|channel| {
+                let sum = pixels
+                    .iter()
+                    .zip(&weights)
+                    .map(|(pixel, weight)| block[*pixel][channel] as u32 * weight)
+                    .sum::<u32>();
+                (sum / weights.iter().sum::<u32>()) as i32
+            });
+            let base4 = average.map(|value| ((value + 8) / 17).clamp(0, 15));
+            let base = base4.map(|value| value * 17);
+            let mut sub_best = (u32::MAX, 0, Vec::new());
+            for (table, [small, large]) in ETC_MODIFIERS.iter().enumerate() {
+                // Index values 0 to 3 stand for +small, +large, -small and -large.
+                let modifiers = [*small, *large, -small, -large];
+                let mut sub_error = 0;
+                let mut indices = Vec::new();
+                for (pixel, weight) in pixels.iter().zip(&weights) {
+                    let color = block[*pixel];
+                    let color = [color[0] as i32, color[1] as i32, color[2] as i32];
+                    let (index, pixel_error) = (0..4)
+                        .map(|index| {
+                            let shifted =
+                                base.map(|value| (value + modifiers[index]).clamp(0, 255));
+                            (index, color_error(shifted, color))
+                        })
+                        .min_by_key(|(_, error)| *error)
+                        .unwrap_or((0, 0));
+                    sub_error += pixel_error * weight;
+                    indices.push((*pixel, index as u64));
+                }
+                if sub_error < sub_best.0 {
+                    sub_best = (sub_error, table, indices);
+                }
+            }
+            let (sub_error, table, indices) = sub_best;
+            error += sub_error;
+            for (channel, value) in base4.iter().enumerate() {
+                bits |= (*value as u64) << (60 - 8 * channel - 4 * sub_block);
+            }
+            bits |= (table as u64) << (37 - 3 * sub_block);
+            for (pixel, index) in indices {
+                let position = etc_index(pixel);
+                bits |= (index >> 1) << (16 + position);
+                bits |= (index & 1) << position;
+            }
+        }
+        if error < best.0 {
+            best = (error, bits);
+        }
+    }
+    best.1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads a BC3 block back, the colour half always in four colour mode as in BC2 and BC3.
+    fn decode_bc3(encoded: &[u8; 16]) -> Block {
+        let (alpha0, alpha1) = (encoded[0] as u32, encoded[1] as u32);
+        let alphas: [u32; 8] = std::array::from_fn(|index| match index {
+            0 => alpha0,
+            1 => alpha1,
+            step if alpha0 > alpha1 => {
+                (alpha0 * (8 - step as u32) + alpha1 * (step as u32 - 1)) / 7
+            }
+            6 => 0,
+            7 => 255,
+            step => (alpha0 * (6 - step as u32) + alpha1 * (step as u32 - 1)) / 5,
+        });
+        let mut alpha_bits = [0; 8];
+        alpha_bits[..6].copy_from_slice(&encoded[2..8]);
+        let alpha_bits = u64::from_le_bytes(alpha_bits);
+        let expand = |color: u16| {
+            let (red, green, blue) = (
+                (color >> 11) as u32,
+                (color >> 5) as u32 & 0x3f,
+                color as u32 & 0x1f,
+            );
+            [
+                red << 3 | red >> 2,
+                green << 2 | green >> 4,
+                blue << 3 | blue >> 2,
+            ]
+        };
+        let end0 = expand(u16::from_le_bytes([encoded[8], encoded[9]]));
+        let end1 = expand(u16::from_le_bytes([encoded[10], encoded[11]]));
+        let colors: [[u32; 3]; 4] = [
+            end0,
+            end1,
+            std::array::from_fn(|channel| (2 * end0[channel] + end1[channel]) / 3),
+            std::array::from_fn(|channel| (end0[channel] + 2 * end1[channel]) / 3),
+        ];
+        let color_bits = u32::from_le_bytes([encoded[12], encoded[13], encoded[14], encoded[15]]);
+        std::array::from_fn(|pixel| {
+            let color = colors[(color_bits >> (2 * pixel) & 3) as usize];
+            let alpha = alphas[(alpha_bits >> (3 * pixel) & 7) as usize];
+            [color[0], color[1], color[2], alpha].map(|value| value as u8)
+        })
+    }
+
+    /// Reads an ETC2 RGBA block back, as long as its colour uses the individual mode.
+    fn decode_etc2(encoded: &[u8; 16]) -> Block {
+        let alpha = u64::from_be_bytes(encoded[..8].try_into().unwrap());
+        let color = u64::from_be_bytes(encoded[8..].try_into().unwrap());
+        assert_eq!(color >> 33 & 1, 0, "differential mode");
+        let flip = color >> 32 & 1 == 1;
+        let (base, multiplier, table) = (
+            (alpha >> 56) as i32,
+            (alpha >> 52 & 0xf) as i32,
+            (alpha >> 48 & 0xf) as usize,
+        );
+        std::array::from_fn(|pixel| {
+            let (x, y) = (pixel % 4, pixel / 4);
+            let column = x * 4 + y;
+            let sub_block = if flip { y / 2 } else { x / 2 };
+            let base_color: [i32; 3] = std::array::from_fn(|channel| {
+                (color >> (60 - 8 * channel - 4 * sub_block) & 0xf) as i32 * 17
+            });
+            let [small, large] = ETC_MODIFIERS[(color >> (37 - 3 * sub_block) & 7) as usize];
+            let index = (color >> (16 + column) & 1) << 1 | color >> column & 1;
+            let modifier = [small, large, -small, -large][index as usize];
+            let alpha_index = (alpha >> (45 - 3 * column) & 7) as usize;
+            let alpha = base + EAC_MODIFIERS[table][alpha_index] * multiplier;
+            let [red, green, blue] = base_color.map(|value| value + modifier);
+            [red, green, blue, alpha].map(|value| value.clamp(0, 255) as u8)
+        })
+    }
+
+    fn round_trip(format: CompressedFormat, block: &Block) -> Block {
+        let encoded = format.encode(block);
+        match format {
+            CompressedFormat::Bc3 => decode_bc3(&encoded),
+            CompressedFormat::Etc2 => decode_etc2(&encoded),
+        }
+    }
+
+    /// The largest difference of the colour of opaque pixels and of any alpha.
+    fn max_error(original: &Block, decoded: &Block) -> (u8, u8) {
+        original
+            .iter()
+            .zip(decoded)
+            .fold((0, 0), |(color, alpha), (original, decoded)| {
+                let color_error = (0..3)
+                    .filter(|_| original[3] != 0)
+                    .map(|channel| original[channel].abs_diff(decoded[channel]))
+                    .max()
+                    .unwrap_or(0);
+                (
+                    color.max(color_error),
+                    alpha.max(original[3].abs_diff(decoded[3])),
+                )
+            })
+    }
+
+    const FORMATS: [CompressedFormat; 2] = [CompressedFormat::Bc3, CompressedFormat::Etc2];
+
+    #[test]
+    fn solid_blocks_decode_to_their_colour() {
+        for format in FORMATS {
+            let block = [[200, 100, 50, 255]; 16];
+            let (color, alpha) = max_error(&block, &round_trip(format, &block));
+            assert!(color <= 8, "{format:?}: colour off by {color}");
+            assert_eq!(alpha, 0, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn gradients_keep_their_steps() {
+        for format in FORMATS {
+            let block = std::array::from_fn(|pixel| {
+                let value = (pixel % 4 * 85) as u8;
+                [value, value, value, 255 - (pixel / 4 * 60) as u8]
+            });
+            let decoded = round_trip(format, &block);
+            let (color, alpha) = max_error(&block, &decoded);
+            assert!(color <= 24, "{format:?}: colour off by {color}");
+            // Half a step of the eight BC4 alphas spread over 75 to 255.
+            assert!(alpha <= 13, "{format:?}: alpha off by {alpha}");
+            for row in decoded.chunks(4) {
+                assert!(
+                    row.windows(2).all(|pair| pair[0][0] < pair[1][0]),
+                    "{format:?}: {row:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn transparent_pixels_stay_transparent() {
+        for format in FORMATS {
+            // Transparent pixels hold colours far from the opaque ones, which must not pull
+            // the opaque colour towards them.
+            let block = std::array::from_fn(|pixel| match pixel % 2 {
+                0 => [40, 180, 220, 255],
+                _ => [255, 0, 0, 0],
+            });
+            let (color, alpha) = max_error(&block, &round_trip(format, &block));
+            assert!(color <= 8, "{format:?}: colour off by {color}");
+            assert_eq!(alpha, 0, "{format:?}");
+            let block = [[90, 90, 90, 0]; 16];
+            let decoded = round_trip(format, &block);
+            assert!(decoded.iter().all(|pixel| pixel[3] == 0), "{format:?}");
+        }
+    }
+
+    fn checkerboard(seed: u8) -> image::RgbaImage {
+        image::RgbaImage::from_fn(8, 4, |x, y| {
+            image::Rgba([(x * 30) as u8 ^ seed, (y * 60) as u8, seed, 255])
+        })
+    }
+
+    #[test]
+    fn cache_is_reused_until_the_pixels_change() {
+        let source = std::env::temp_dir().join(format!("compression-{}.png", std::process::id()));
+        let cache = cache_path(&source, CompressedFormat::Bc3);
+        let image = checkerboard(0);
+        let first = compress_cached(&image, CompressedFormat::Bc3, &source).unwrap();
+        let written = fs::read(&cache).unwrap();
+        // Blocks only ever come from the cache while it matches the pixels, a marked copy
+        // shows which path was taken.
+        let mut marked = first.levels.clone();
+        marked[0][8] ^= 0xff;
+        fs::write(
+            &cache,
+            write_cache(
+                &CompressedImage {
+                    levels: marked.clone(),
+                    ..first
+                },
+                pixel_hash(&image),
+            ),
+        )
+        .unwrap();
+        let cached = compress_cached(&image, CompressedFormat::Bc3, &source).unwrap();
+        let changed = compress_cached(&checkerboard(7), CompressedFormat::Bc3, &source).unwrap();
+        let rewritten = fs::read(&cache).unwrap();
+        fs::remove_file(&cache).unwrap();
+        assert_eq!(cached.levels, marked);
+        assert_eq!(
+            changed.levels,
+            compress(&checkerboard(7), CompressedFormat::Bc3)
+                .unwrap()
+                .levels
+        );
+        assert_ne!(rewritten, written);
+        assert_eq!(cached.levels.len(), 4);
+        assert!(
+            read_cache(
+                &rewritten,
+                CompressedFormat::Etc2,
+                pixel_hash(&checkerboard(7))
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,492 @@
+use std::collections;
+
+/// A texture passes draw to or sample from, declared anew every frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ResourceId(usize);
+
+/// The swapchain texture, presented once the frame is submitted.
+pub const OUTPUT: ResourceId = ResourceId(0);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TargetSize {
+    /// The size of [`OUTPUT`].
+    Output,
+    /// The size of [`OUTPUT`] divided by this and rounded up, for downsampled effects.
+    Divided(u32),
+}
+
+/// A texture that only lives for the frame, see [`RenderGraph::create_target`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TargetDescriptor {
+    pub label: &'static str,
+    pub format: wgpu::TextureFormat,
+    pub size: TargetSize,
+}
+
+/// How a pass uses a resource, which decides the order the passes run in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Access {
+    /// Draws the resource from scratch, before everything that modifies or reads it.
+    Write,
+    /// Draws on top of what the writers left, in the order the passes were added.
+    Modify,
+    /// Samples the resource once every writer and modifier has run.
+    Read,
+}
+
+pub type PassFunction = Box<dyn FnOnce(&mut wgpu::CommandEncoder, &PassResources) + Send + Sync>;
+
+struct Pass {
+    name: &'static str,
+    accesses: Vec<(ResourceId, Access)>,
+    execute: PassFunction,
+}
+
+/// Declares what a pass touches, see [`RenderGraph::add_pass`].
+pub struct PassBuilder<'graph> {
+    graph: &'graph mut RenderGraph,
+    name: &'static str,
+    accesses: Vec<(ResourceId, Access)>,
+}
+
+impl PassBuilder<'_> {
+    pub fn write(self, resource: ResourceId) -> Self {
+        self.access(resource, Access::Write)
+    }
+    pub fn modify(self, resource: ResourceId) -> Self {
+        self.access(resource, Access::Modify)
+    }
+    pub fn read(self, resource: ResourceId) -> Self {
+        self.access(resource, Access::Read)
+    }
+    /// A pass touches each resource once, the last access declared wins.
+    pub fn access(mut self, resource: ResourceId, access: Access) -> Self {
+        self.accesses.retain(|(declared, _)| *declared != resource);
+        self.accesses.push((resource, access));
+        self
+    }
+    /// Adds the pass, `execute` records its commands when the frame is submitted.
+    pub fn execute<F>(self, execute: F)
+    where
+        F: FnOnce(&mut wgpu::CommandEncoder, &PassResources) + Send + Sync + 'static,
+    {
+        self.graph.passes.push(Pass {
+            name: self.name,
+            accesses: self.accesses,
+            execute: Box::new(execute),
+        });
+    }
+}
+
+/// The textures of the frame as a pass sees them.
+pub struct PassResources<'frame> {
+    views: &'frame collections::HashMap<ResourceId, (wgpu::Texture, wgpu::TextureView)>,
+    /// Resources the pass is the first to draw to this frame.
+    clears: collections::HashSet<ResourceId>,
+}
+
+impl PassResources<'_> {
+    /// The view of a resource the pass declared.
+    pub fn view(&self, resource: ResourceId) -> &wgpu::TextureView {
+        &self
+            .views
+            .get(&resource)
+            .expect("pass should declare the resources it uses")
+            .1
+    }
+    /// The texture behind a resource the pass declared, for copies into or out of it.
+    pub fn texture(&self, resource: ResourceId) -> &wgpu::Texture {
+        &self
+            .views
+            .get(&resource)
+            .expect("pass should declare the resources it uses")
+            .0
+    }
+    pub fn size(&self, resource: ResourceId) -> (u32, u32) {
+        let texture = self.texture(resource);
+        (texture.width(), texture.height())
+    }
+    /// Clears the resource if this is the first pass drawing to it this frame, which makes
+    /// aliased targets safe to draw to, and loads it otherwise.
+    pub fn load_op(&self, resource: ResourceId) -> wgpu::LoadOp<wgpu::Color> {
+        match (self.clears.contains(&resource), resource) {
+            (false, _) => wgpu::LoadOp::Load,
+            (true, OUTPUT) => wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            (true, _) => wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+        }
+    }
+    pub fn color_attachment(
+        &self,
+        resource: ResourceId,
+    ) -> Option<wgpu::RenderPassColorAttachment<'_>> {
+        Some(wgpu::RenderPassColorAttachment {
+            view: self.view(resource),
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: self.load_op(resource),
+                store: wgpu::StoreOp::Store,
+            },
+        })
+    }
+}
+
+struct PooledTexture {
+    format: wgpu::TextureFormat,
+    size: wgpu::Extent3d,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    /// Position in the execution order after which the texture is free again this frame.
+    busy_until: Option<usize>,
+}
+
+/// What the last frame ran, for the debugger.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RenderGraphStats {
+    /// Names of the passes in the order they ran.
+    pub passes: Vec<&'static str>,
+    pub targets: usize,
+    /// Textures behind the targets, fewer than the targets when some were aliased.
+    pub textures: usize,
+}
+
+/// Passes of one frame and the transient targets they share, run in dependency order when the
+/// frame is submitted.
+///
+/// Passes may be added in any order by any subsystem during the frame. Each resource is first
+/// drawn by its writers, then by its modifiers, then sampled by its readers. Targets whose
+/// lifetimes in that order do not overlap share one texture, which is kept for later frames.
+#[derive(Default)]
+pub struct RenderGraph {
+    targets: Vec<TargetDescriptor>,
+    passes: Vec<Pass>,
+    pool: Vec<PooledTexture>,
+    stats: RenderGraphStats,
+}
+
+impl RenderGraph {
+    /// Declares a texture for this frame, it holds nothing until a pass draws to it.
+    pub fn create_target(&mut self, descriptor: TargetDescriptor) -> ResourceId {
+        self.targets.push(descriptor);
+        ResourceId(self.targets.len())
+    }
+    pub fn add_pass(&mut self, name: &'static str) -> PassBuilder<'_> {
+        PassBuilder {
+            graph: self,
+            name,
+            accesses: Vec::new(),
+        }
+    }
+    pub fn stats(&self) -> &RenderGraphStats {
+        &self.stats
+    }
+    /// Records every pass of the frame, one command buffer each, and starts the next frame.
+    ///
+    /// Passes that use [`OUTPUT`] are skipped when `output` is `None`.
+    pub fn execute(
+        &mut self,
+        device: &wgpu::Device,
+        output: Option<&wgpu::Texture>,
+        output_size: (u32, u32),
+    ) -> Vec<wgpu::CommandBuffer> {
+        let passes = std::mem::take(&mut self.passes);
+        let targets = std::mem::take(&mut self.targets);
+        let order = Self::sort(&passes);
+
+        let mut views = collections::HashMap::new();
+        if let Some(output) = output {
+            let view = output.create_view(&wgpu::TextureViewDescriptor::default());
+            views.insert(OUTPUT, (output.clone(), view));
+        }
+        for pooled in &mut self.pool {
+            pooled.busy_until = None;
+        }
+        let mut used = collections::HashSet::new();
+        // Lifetimes in execution order, a target no pass touches is never allocated.
+        let mut lifetimes = collections::BTreeMap::<ResourceId, (usize, usize)>::new();
+        for (position, pass) in order.iter().map(|index| &passes[*index]).enumerate() {
+            for (resource, _) in &pass.accesses {
+                if *resource != OUTPUT {
+                    let lifetime = lifetimes.entry(*resource).or_insert((position, position));
+                    lifetime.1 = position;
+                }
+            }
+        }
+        let mut by_start = lifetimes.into_iter().collect::<Vec<_>>();
+        by_start.sort_by_key(|(_, (first, _))| *first);
+        for (resource, (first, last)) in by_start {
+            let Some(descriptor) = targets.get(resource.0 - 1) else {
+                log::error!("render graph resource {resource:?} was not declared this frame");
+                continue;
+            };
+            let size = match descriptor.size {
+                TargetSize::Output => output_size,
+                TargetSize::Divided(divisor) => (
+                    output_size.0.div_ceil(divisor.max(1)),
+                    output_size.1.div_ceil(divisor.max(1)),
+                ),
+            };
+            let size = wgpu::Extent3d {
+                width: size.0.max(1),
+                height: size.1.max(1),
+                depth_or_array_layers: 1,
+            };
+            let free = self.pool.iter().position(|pooled| {
+                pooled.format == descriptor.format
+                    && pooled.size == size
+                    && pooled
+                        .busy_until
+                        .is_none_or(|busy_until| busy_until < first)
+            });
+            let index = free.unwrap_or_else(|| {
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some(descriptor.label),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: descriptor.format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::COPY_SRC
+                        | wgpu::TextureUsages::COPY_DST,
+                    view_formats: &[],
+                });
+                self.pool.push(PooledTexture {
+                    format: descriptor.format,
+                    size,
+                    view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                    texture,
+                    busy_until: None,
+                });
+                self.pool.len() - 1
+            });
+            let pooled = &mut self.pool[index];
+            pooled.busy_until = Some(last);
+            used.insert(index);
+            views.insert(resource, (pooled.texture.clone(), pooled.view.clone()));
+        }
+        // Textures of an old output size or of targets that went away.
+        let mut index = 0;
+        self.pool.retain(|_| {
+            index += 1;
+            used.contains(&(index - 1))
+        });
+
+        let mut drawn = collections::HashSet::new();
+        let mut command_buffers = Vec::new();
+        let mut ran = Vec::new();
+        let mut passes = passes.into_iter().map(Some).collect::<Vec<_>>();
+        for pass in order.into_iter().filter_map(|index| passes[index].take()) {
+            if let Some((resource, _)) = pass
+                .accesses
+                .iter()
+                .find(|(resource, _)| !views.contains_key(resource))
+            {
+                log::debug!("Skipped {}, {resource:?} is not available", pass.name);
+                continue;
+            }
+            if let Some(resource) = undrawn_read(&pass, &drawn) {
+                log::warn!(
+                    "Skipped {}, it reads {resource:?} before any pass drew it this frame",
+                    pass.name
+                );
+                continue;
+            }
+            let clears = pass
+                .accesses
+                .iter()
+                .filter(|(_, access)| *access != Access::Read)
+                .map(|(resource, _)| *resource)
+                .filter(|resource| drawn.insert(*resource))
+                .collect();
+            let resources = PassResources {
+                views: &views,
+                clears,
+            };
+            let mut command_encoder =
+                device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some(pass.name),
+                });
+            (pass.execute)(&mut command_encoder, &resources);
+            command_buffers.push(command_encoder.finish());
+            ran.push(pass.name);
+        }
+        self.stats = RenderGraphStats {
+            passes: ran,
+            targets: views.len() - usize::from(views.contains_key(&OUTPUT)),
+            textures: self.pool.len(),
+        };
+        command_buffers
+    }
+    /// Execution order as indices into `passes`, stable where the dependencies allow.
+    ///
+    /// Passes in a cycle are logged and run in the order they were added.
+    fn sort(passes: &[Pass]) -> Vec<usize> {
+        let mut users = collections::BTreeMap::<ResourceId, [Vec<usize>; 3]>::new();
+        for (index, pass) in passes.iter().enumerate() {
+            for (resource, access) in &pass.accesses {
+                let group = match access {
+                    Access::Write => 0,
+                    Access::Modify => 1,
+                    Access::Read => 2,
+                };
+                users.entry(*resource).or_default()[group].push(index);
+            }
+        }
+        let mut after = vec![collections::BTreeSet::new(); passes.len()];
+        for [writers, modifiers, readers] in users.values() {
+            let chain = writers.iter().chain(modifiers).collect::<Vec<_>>();
+            for pair in chain.windows(2) {
+                after[*pair[1]].insert(*pair[0]);
+            }
+            if let Some(last) = chain.last() {
+                for reader in readers {
+                    after[*reader].insert(**last);
+                }
+            }
+        }
+        let mut order = Vec::with_capacity(passes.len());
+        let mut done = vec![false; passes.len()];
+        while order.len() < passes.len() {
+            let ready = (0..passes.len())
+                .find(|index| !done[*index] && after[*index].iter().all(|before| done[*before]));
+            let next = ready.unwrap_or_else(|| {
+                let stuck = (0..passes.len()).find(|index| !done[*index]).unwrap_or(0);
+                log::error!(
+                    "render graph has a cycle through {}, running it early",
+                    passes[stuck].name
+                );
+                stuck
+            });
+            done[next] = true;
+            order.push(next);
+        }
+        order
+    }
+}
+
+/// A target `pass` reads although no pass drew it yet this frame, which would sample whatever
+/// an aliased texture held last.
+fn undrawn_read(pass: &Pass, drawn: &collections::HashSet<ResourceId>) -> Option<ResourceId> {
+    pass.accesses
+        .iter()
+        .find(|(resource, access)| {
+            *access == Access::Read && *resource != OUTPUT && !drawn.contains(resource)
+        })
+        .map(|(resource, _)| *resource)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(graph: &mut RenderGraph, label: &'static str) -> ResourceId {
+        graph.create_target(TargetDescriptor {
+            label,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            size: TargetSize::Output,
+        })
+    }
+
+    fn order(graph: &RenderGraph) -> Vec<&'static str> {
+        RenderGraph::sort(&graph.passes)
+            .into_iter()
+            .map(|index| graph.passes[index].name)
+            .collect()
+    }
+
+    #[test]
+    fn writers_run_before_modifiers_before_readers() {
+        let mut graph = RenderGraph::default();
+        let scene = target(&mut graph, "scene");
+        graph
+            .add_pass("present")
+            .read(scene)
+            .write(OUTPUT)
+            .execute(|_, _| {});
+        graph.add_pass("lights").modify(scene).execute(|_, _| {});
+        graph.add_pass("sprites").write(scene).execute(|_, _| {});
+        assert_eq!(order(&graph), ["sprites", "lights", "present"]);
+    }
+
+    #[test]
+    fn independent_passes_keep_the_order_they_were_added_in() {
+        let mut graph = RenderGraph::default();
+        let scene = target(&mut graph, "scene");
+        let bloom = target(&mut graph, "bloom");
+        graph.add_pass("bloom").write(bloom).execute(|_, _| {});
+        graph.add_pass("sprites").write(scene).execute(|_, _| {});
+        graph.add_pass("tiles").modify(scene).execute(|_, _| {});
+        graph.add_pass("outlines").modify(scene).execute(|_, _| {});
+        graph
+            .add_pass("interface")
+            .modify(OUTPUT)
+            .execute(|_, _| {});
+        assert_eq!(
+            order(&graph),
+            ["bloom", "sprites", "tiles", "outlines", "interface"]
+        );
+    }
+
+    #[test]
+    fn cycles_still_run_every_pass_once() {
+        let mut graph = RenderGraph::default();
+        let first = target(&mut graph, "first");
+        let second = target(&mut graph, "second");
+        graph.add_pass("setup").write(OUTPUT).execute(|_, _| {});
+        graph
+            .add_pass("ping")
+            .read(second)
+            .write(first)
+            .execute(|_, _| {});
+        graph
+            .add_pass("pong")
+            .read(first)
+            .write(second)
+            .execute(|_, _| {});
+        graph
+            .add_pass("present")
+            .read(first)
+            .modify(OUTPUT)
+            .execute(|_, _| {});
+        assert_eq!(order(&graph), ["setup", "ping", "pong", "present"]);
+    }
+
+    #[test]
+    fn reads_of_targets_nothing_drew_are_found() {
+        let mut graph = RenderGraph::default();
+        let scene = target(&mut graph, "scene");
+        let forgotten = target(&mut graph, "forgotten");
+        graph
+            .add_pass("composite")
+            .read(scene)
+            .read(forgotten)
+            .execute(|_, _| {});
+        graph.add_pass("sprites").write(scene).execute(|_, _| {});
+        graph
+            .add_pass("present")
+            .read(scene)
+            .read(OUTPUT)
+            .execute(|_, _| {});
+        let mut drawn = collections::HashSet::new();
+        let mut undrawn = Vec::new();
+        for index in RenderGraph::sort(&graph.passes) {
+            let pass = &graph.passes[index];
+            undrawn.push((pass.name, undrawn_read(pass, &drawn)));
+            drawn.extend(
+                pass.accesses
+                    .iter()
+                    .filter(|(_, access)| *access != Access::Read)
+                    .map(|(resource, _)| *resource),
+            );
+        }
+        assert_eq!(
+            undrawn,
+            [
+                ("sprites", None),
+                ("composite", Some(forgotten)),
+                ("present", None)
+            ]
+        );
+    }
+}
@@ -0,0 +1,466 @@
+use wgpu::util::DeviceExt;
+
+use crate::rendering;
+use crate::rendering::camera;
+use crate::rendering::graph;
+use crate::rendering::shader;
+use crate::sprite::renderer;
+use crate::world;
+use crate::world::components;
+use crate::world::lighting;
+use crate::world::physics::collision;
+use crate::world::spatial;
+
+const SHADER: &str = include_str!("lighting.wgsl");
+/// Occluders beyond this many, the furthest from the camera, cast no shadows.
+pub const MAX_OCCLUDERS: usize = 64;
+/// Format the lights add up in, which may exceed 1 where they overlap.
+const LIGHT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+const LIGHTING_BIND_GROUP_LAYOUT_DESCRIPTOR: wgpu::BindGroupLayoutDescriptor<'_> =
+    wgpu::BindGroupLayoutDescriptor {
+        label: Some("lighting bind group layout"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+    };
+
+const fn unfiltered_texture(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+const NORMALS_BIND_GROUP_LAYOUT_DESCRIPTOR: wgpu::BindGroupLayoutDescriptor<'_> =
+    wgpu::BindGroupLayoutDescriptor {
+        label: Some("light normals bind group layout"),
+        entries: &[unfiltered_texture(0)],
+    };
+
+const COMPOSITE_BIND_GROUP_LAYOUT_DESCRIPTOR: wgpu::BindGroupLayoutDescriptor<'_> =
+    wgpu::BindGroupLayoutDescriptor {
+        label: Some("lighting composite bind group layout"),
+        entries: &[unfiltered_texture(1), unfiltered_texture(2)],
+    };
+
+fn create_pipeline(
+    gpu: &rendering::Gpu,
+    shader: &wgpu::ShaderModule,
+    label: &'static str,
+    textures: &wgpu::BindGroupLayoutDescriptor,
+    entry_points: (&'static str, &'static str),
+    buffers: &[wgpu::VertexBufferLayout],
+    target: wgpu::ColorTargetState,
+) -> wgpu::RenderPipeline {
+    let device = gpu.device();
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(
+            &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: &[
+                    &device.create_bind_group_layout(&LIGHTING_BIND_GROUP_LAYOUT_DESCRIPTOR),
+                    &device.create_bind_group_layout(textures),
+                ],
+                push_constant_ranges: &[],
+            }),
+        ),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some(entry_points.0),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            buffers,
+        },
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some(entry_points.1),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            targets: &[Some(target)],
+        }),
+        multiview: None,
+        cache: None,
+    })
+}
+
+fn create_light_pipeline(
+    gpu: &rendering::Gpu,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    let additive = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    };
+    create_pipeline(
+        gpu,
+        shader,
+        "light render pipeline",
+        &NORMALS_BIND_GROUP_LAYOUT_DESCRIPTOR,
+        ("light_vertex", "light_fragment"),
+        &[LightInstance::BUFFER_LAYOUT],
+        wgpu::ColorTargetState {
+            format: LIGHT_FORMAT,
+            blend: Some(wgpu::BlendState {
+                color: additive,
+                alpha: additive,
+            }),
+            write_mask: wgpu::ColorWrites::all(),
+        },
+    )
+}
+
+fn create_composite_pipeline(
+    gpu: &rendering::Gpu,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    create_pipeline(
+        gpu,
+        shader,
+        "lighting composite render pipeline",
+        &COMPOSITE_BIND_GROUP_LAYOUT_DESCRIPTOR,
+        ("composite_vertex", "composite_fragment"),
+        &[],
+        wgpu::ColorTargetState {
+            format: gpu.surface_config().format,
+            blend: None,
+            write_mask: wgpu::ColorWrites::all(),
+        },
+    )
+}
+
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct LightInstance {
+    position: glam::Vec2,
+    radius: f32,
+    height: f32,
+    color: glam::Vec3,
+    source_radius: f32,
+    direction: glam::Vec2,
+    /// Cosines of the angles at which a spot light has faded out and starts to fade.
+    cone: glam::Vec2,
+}
+
+impl LightInstance {
+    const BUFFER_LAYOUT: wgpu::VertexBufferLayout<'_> = wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<Self>() as u64,
+        step_mode: wgpu::VertexStepMode::Instance,
+        attributes: &wgpu::vertex_attr_array![
+            0 => Float32x4,
+            1 => Float32x4,
+            2 => Float32x4,
+        ],
+    };
+    fn new(position: glam::Vec2, light: &lighting::Light) -> Self {
+        let (direction, cone) = match light.kind {
+            // Every direction lies past the cone.
+            lighting::LightKind::Point => (glam::Vec2::ZERO, glam::vec2(-2.0, -1.0)),
+            lighting::LightKind::Spot {
+                direction,
+                half_angle,
+            } => (
+                glam::Vec2::from_angle(direction),
+                glam::vec2(half_angle.cos(), (half_angle * 0.75).cos()),
+            ),
+        };
+        Self {
+            position,
+            radius: light.radius,
+            height: light.height,
+            color: light.color * light.intensity,
+            source_radius: light.source_radius,
+            direction,
+            cone,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct OccluderUniform {
+    center: glam::Vec2,
+    half_extents: glam::Vec2,
+    radius: f32,
+    _padding: [f32; 3],
+}
+
+impl OccluderUniform {
+    fn new(position: glam::Vec2, occluder: &lighting::Occluder) -> Self {
+        let (center, half_extents, radius) = match &occluder.shape {
+            collision::Shape::Aabb { half_extents } => (position, *half_extents, 0.0),
+            collision::Shape::Circle { radius } => (position, glam::Vec2::ZERO, *radius),
+            shape @ collision::Shape::Polygon { .. } => {
+                let aabb = shape.aabb(position);
+                (aabb.center(), aabb.half_extents(), 0.0)
+            }
+        };
+        Self {
+            center,
+            half_extents,
+            radius,
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct LightingUniform {
+    view_projection: glam::Mat4,
+    ambient: glam::Vec3,
+    occluder_count: u32,
+    occluders: [OccluderUniform; MAX_OCCLUDERS],
+}
+
+/// Lights the sprites with every [`lighting::Light`] the camera can see and composites them
+/// onto the output.
+///
+/// Each light is a quad adding its colour into a light buffer, shaded by the normals the sprites
+/// left behind and shadowed by the [`lighting::Occluder`]s. The composite multiplies the sprite
+/// colours by that buffer plus the [`lighting::AmbientLight`].
+pub struct LightRenderer<'window> {
+    gpu_handle: rendering::GpuHandle<'window>,
+    uniform_buffer: wgpu::Buffer,
+    light_pipeline: shader::HotPipeline,
+    composite_pipeline: shader::HotPipeline,
+    instance_buffer: wgpu::Buffer,
+    instances: Vec<LightInstance>,
+}
+
+impl<'window> LightRenderer<'window> {
+    const INITIAL_INSTANCES: u64 = 64;
+
+    pub fn new(gpu_handle: rendering::GpuHandle<'window>) -> Self {
+        let gpu = gpu_handle.read().unwrap();
+        let uniform_buffer = gpu
+            .device()
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("lighting uniform buffer"),
+                contents: bytemuck::bytes_of(&LightingUniform {
+                    view_projection: glam::Mat4::IDENTITY,
+                    ambient: glam::Vec3::ONE,
+                    occluder_count: 0,
+                    occluders: [OccluderUniform::default(); MAX_OCCLUDERS],
+                }),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+        let instance_buffer = Self::create_instance_buffer(gpu.device(), Self::INITIAL_INSTANCES);
+        drop(gpu);
+        Self {
+            gpu_handle,
+            uniform_buffer,
+            light_pipeline: shader::HotPipeline::new(
+                "light shader",
+                SHADER,
+                "src/rendering/lighting.wgsl",
+                create_light_pipeline,
+            ),
+            composite_pipeline: shader::HotPipeline::new(
+                "lighting composite shader",
+                SHADER,
+                "src/rendering/lighting.wgsl",
+                create_composite_pipeline,
+            ),
+            instance_buffer,
+            instances: Vec::new(),
+        }
+    }
+    fn create_instance_buffer(device: &wgpu::Device, instances: u64) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("light instance buffer"),
+            size: instances * std::mem::size_of::<LightInstance>() as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+    /// The lights reaching into the view, and the occluders nearest to it that can shadow them.
+    fn prepare(&mut self, world: &world::World, view: &spatial::Aabb) -> Vec<OccluderUniform> {
+        self.instances.clear();
+        let mut reach = *view;
+        world.query::<(&components::Position, &lighting::Light)>(|_, (position, light)| {
+            let bounds = spatial::Aabb::from_center(position.0, glam::Vec2::splat(light.radius));
+            if bounds.intersects(view) {
+                self.instances.push(LightInstance::new(position.0, light));
+                reach = reach.union(&bounds);
+            }
+        });
+        let mut occluders = Vec::new();
+        if !self.instances.is_empty() {
+            world.query::<(&components::Position, &lighting::Occluder)>(
+                |_, (position, occluder)| {
+                    if occluder.shape.aabb(position.0).intersects(&reach) {
+                        occluders.push(OccluderUniform::new(position.0, occluder));
+                    }
+                },
+            );
+        }
+        if occluders.len() > MAX_OCCLUDERS {
+            let center = view.center();
+            occluders.sort_by(|a, b| {
+                a.center
+                    .distance_squared(center)
+                    .total_cmp(&b.center.distance_squared(center))
+            });
+            occluders.truncate(MAX_OCCLUDERS);
+        }
+        occluders
+    }
+    /// Adds the passes lighting `scene` and writing the lit scene to `output`.
+    pub fn render(
+        &mut self,
+        world: &world::World,
+        scene: &renderer::SceneTargets,
+        output: graph::ResourceId,
+    ) {
+        let camera = world
+            .resource::<camera::Camera>()
+            .map(|camera| *camera)
+            .unwrap_or_default();
+        let ambient = world
+            .resource::<lighting::AmbientLight>()
+            .map_or(glam::Vec3::ONE, |ambient| ambient.color);
+        let gpu = self.gpu_handle.read().unwrap();
+        let viewport = glam::vec2(
+            gpu.surface_config().width as f32,
+            gpu.surface_config().height as f32,
+        );
+        drop(gpu);
+        let found = self.prepare(world, &camera.view_rect(viewport));
+        let mut occluders = [OccluderUniform::default(); MAX_OCCLUDERS];
+        occluders[..found.len()].copy_from_slice(&found);
+
+        self.light_pipeline.refresh(&self.gpu_handle);
+        self.composite_pipeline.refresh(&self.gpu_handle);
+        let mut gpu = self.gpu_handle.write().unwrap();
+        gpu.queue().write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::bytes_of(&LightingUniform {
+                view_projection: camera.view_projection(viewport),
+                ambient,
+                occluder_count: found.len() as u32,
+                occluders,
+            }),
+        );
+        let required = self.instances.len() as u64;
+        if required * std::mem::size_of::<LightInstance>() as u64 > self.instance_buffer.size() {
+            self.instance_buffer =
+                Self::create_instance_buffer(gpu.device(), required.next_power_of_two());
+        }
+        if !self.instances.is_empty() {
+            gpu.queue().write_buffer(
+                &self.instance_buffer,
+                0,
+                bytemuck::cast_slice(&self.instances),
+            );
+        }
+
+        // Bind groups of the frame's targets are created once the targets exist.
+        let device = gpu.device().clone();
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("lighting bind group"),
+            layout: &device.create_bind_group_layout(&LIGHTING_BIND_GROUP_LAYOUT_DESCRIPTOR),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: self.uniform_buffer.as_entire_binding(),
+            }],
+        });
+        let graph = gpu.render_graph();
+        let light = graph.create_target(graph::TargetDescriptor {
+            label: "light",
+            format: LIGHT_FORMAT,
+            size: graph::TargetSize::Output,
+        });
+
+        let pipeline = self.light_pipeline.current().clone();
+        let instance_buffer = self.instance_buffer.clone();
+        let instances = self.instances.len() as u32;
+        let normals = scene.normals;
+        let bind_group = uniform_bind_group.clone();
+        let light_device = device.clone();
+        graph.add_pass("lights").read(normals).write(light).execute(
+            move |command_encoder, resources| {
+                let normals_bind_group =
+                    light_device.create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: Some("light normals bind group"),
+                        layout: &light_device
+                            .create_bind_group_layout(&NORMALS_BIND_GROUP_LAYOUT_DESCRIPTOR),
+                        entries: &[wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(resources.view(normals)),
+                        }],
+                    });
+                let mut render_pass =
+                    command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("light render pass"),
+                        color_attachments: &[resources.color_attachment(light)],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                    });
+                if instances == 0 {
+                    return;
+                }
+                render_pass.set_pipeline(&pipeline);
+                render_pass.set_bind_group(0, &bind_group, &[]);
+                render_pass.set_bind_group(1, &normals_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, instance_buffer.slice(..));
+                render_pass.draw(0..6, 0..instances);
+            },
+        );
+
+        let pipeline = self.composite_pipeline.current().clone();
+        let color = scene.color;
+        graph
+            .add_pass("lighting composite")
+            .read(color)
+            .read(light)
+            .write(output)
+            .execute(move |command_encoder, resources| {
+                let textures_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("lighting composite bind group"),
+                    layout: &device
+                        .create_bind_group_layout(&COMPOSITE_BIND_GROUP_LAYOUT_DESCRIPTOR),
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(resources.view(color)),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::TextureView(resources.view(light)),
+                        },
+                    ],
+                });
+                let mut render_pass =
+                    command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("lighting composite render pass"),
+                        color_attachments: &[resources.color_attachment(output)],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                    });
+                render_pass.set_pipeline(&pipeline);
+                render_pass.set_bind_group(0, &uniform_bind_group, &[]);
+                render_pass.set_bind_group(1, &textures_bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            });
+    }
+}
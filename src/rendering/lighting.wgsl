@@ -0,0 +1,156 @@
+const MAX_OCCLUDERS: u32 = 64u;
+const SHADOW_STEPS: i32 = 32;
+
+// Boxes with rounded corners, circles have no half extents.
+struct Occluder {
+    center_half_extents: vec4<f32>,
+    radius: f32,
+}
+
+struct Lighting {
+    view_projection: mat4x4<f32>,
+    ambient: vec3<f32>,
+    occluder_count: u32,
+    occluders: array<Occluder, MAX_OCCLUDERS>,
+}
+
+@group(0) @binding(0)
+var<uniform> lighting: Lighting;
+
+struct LightInput {
+    @location(0) position_radius_height: vec4<f32>,
+    @location(1) color_source_radius: vec4<f32>,
+    @location(2) direction_cones: vec4<f32>,
+}
+
+struct LightOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec2<f32>,
+    @location(1) light_position: vec2<f32>,
+    @location(2) radius_height: vec2<f32>,
+    @location(3) color_source_radius: vec4<f32>,
+    @location(4) direction_cones: vec4<f32>,
+}
+
+@vertex
+fn light_vertex(@builtin(vertex_index) vertex_index: u32, light: LightInput) -> LightOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    let radius = light.position_radius_height.z;
+    let world_position = light.position_radius_height.xy + corners[vertex_index] * radius;
+
+    var output: LightOutput;
+    output.clip_position = lighting.view_projection * vec4<f32>(world_position, 0.0, 1.0);
+    output.world_position = world_position;
+    output.light_position = light.position_radius_height.xy;
+    output.radius_height = light.position_radius_height.zw;
+    output.color_source_radius = light.color_source_radius;
+    output.direction_cones = light.direction_cones;
+    return output;
+}
+
+fn occluder_distance(occluder: Occluder, point: vec2<f32>) -> f32 {
+    let bounds = occluder.center_half_extents;
+    let outside = abs(point - bounds.xy) - bounds.zw;
+    return length(max(outside, vec2<f32>(0.0))) + min(max(outside.x, outside.y), 0.0)
+        - occluder.radius;
+}
+
+fn scene_distance(point: vec2<f32>) -> f32 {
+    var distance = 1e9;
+    for (var index = 0u; index < min(lighting.occluder_count, MAX_OCCLUDERS); index++) {
+        distance = min(distance, occluder_distance(lighting.occluders[index], point));
+    }
+    return distance;
+}
+
+// Sphere traces from the lit point towards the light, the closest the ray passes an occluder
+// relative to the size of the light decides how deep into the penumbra the point lies.
+fn visibility(origin: vec2<f32>, light: vec2<f32>, source_radius: f32) -> f32 {
+    let delta = light - origin;
+    let span = length(delta);
+    if lighting.occluder_count == 0u || span < 1e-4 {
+        return 1.0;
+    }
+    // Points inside an occluder are its own top, which the light reaches.
+    if scene_distance(origin) <= 0.0 {
+        return 1.0;
+    }
+    let direction = delta / span;
+    let light_angle = max(source_radius / span, 1e-3);
+    var lit = 1.0;
+    var travelled = 1e-3 * span;
+    for (var step = 0; step < SHADOW_STEPS && travelled < span; step++) {
+        let distance = scene_distance(origin + direction * travelled);
+        lit = min(lit, clamp(0.5 + 0.5 * distance / (travelled * light_angle), 0.0, 1.0));
+        if lit <= 0.0 {
+            break;
+        }
+        travelled += max(distance, 1e-2 * span);
+    }
+    return smoothstep(0.0, 1.0, lit);
+}
+
+@group(1) @binding(0)
+var normals: texture_2d<f32>;
+
+@fragment
+fn light_fragment(input: LightOutput) -> @location(0) vec4<f32> {
+    let to_light = input.light_position - input.world_position;
+    let distance = length(to_light);
+    let radius = input.radius_height.x;
+    if distance >= radius {
+        discard;
+    }
+    let attenuation = pow(1.0 - distance / radius, 2.0);
+
+    let direction = input.direction_cones.xy;
+    let cone = smoothstep(
+        input.direction_cones.z,
+        input.direction_cones.w,
+        dot(direction, -to_light / max(distance, 1e-4)),
+    );
+
+    let encoded = textureLoad(normals, vec2<i32>(input.clip_position.xy), 0);
+    var normal = vec3<f32>(0.0, 0.0, 1.0);
+    if encoded.a > 0.0 {
+        normal = normalize(encoded.rgb * 2.0 - 1.0);
+    }
+    let light_direction = normalize(vec3<f32>(to_light, input.radius_height.y));
+    let diffuse = max(dot(normal, light_direction), 0.0);
+
+    let shadow = visibility(input.world_position, input.light_position, input.color_source_radius.w);
+    return vec4<f32>(input.color_source_radius.rgb * attenuation * cone * diffuse * shadow, 1.0);
+}
+
+struct CompositeOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+@vertex
+fn composite_vertex(@builtin(vertex_index) vertex_index: u32) -> CompositeOutput {
+    // One triangle covering the screen.
+    let corner = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var output: CompositeOutput;
+    output.clip_position = vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+    return output;
+}
+
+@group(1) @binding(1)
+var scene_color: texture_2d<f32>;
+@group(1) @binding(2)
+var light_map: texture_2d<f32>;
+
+@fragment
+fn composite_fragment(input: CompositeOutput) -> @location(0) vec4<f32> {
+    let texel = vec2<i32>(input.clip_position.xy);
+    let color = textureLoad(scene_color, texel, 0);
+    let light = textureLoad(light_map, texel, 0).rgb;
+    return vec4<f32>(color.rgb * (lighting.ambient + light), 1.0);
+}
@@ -0,0 +1,137 @@
+use crate::rendering;
+use crate::rendering::sampler;
+use crate::rendering::shader;
+use crate::sprite;
+use std::sync;
+
+const SHADER: &str = include_str!("mipmap.wgsl");
+/// The only format mips are generated for, every uploaded image uses it.
+pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Fills the mip chain of a texture on the GPU, each level a linear downsample of the last.
+pub struct MipGenerator {
+    /// Locked since textures are uploaded through a shared [`rendering::Gpu`].
+    pipeline: sync::Mutex<shader::HotPipeline>,
+}
+
+impl Default for MipGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MipGenerator {
+    pub fn new() -> Self {
+        Self {
+            pipeline: sync::Mutex::new(shader::HotPipeline::new(
+                "mip shader",
+                SHADER,
+                "src/rendering/mipmap.wgsl",
+                create_render_pipeline,
+            )),
+        }
+    }
+    /// Rebuilds every level below the first from the first. The texture needs
+    /// `RENDER_ATTACHMENT` usage and [`FORMAT`].
+    pub fn generate(&self, gpu: &rendering::Gpu, texture: &wgpu::Texture) {
+        if texture.mip_level_count() < 2 {
+            return;
+        }
+        assert_eq!(
+            texture.format(),
+            FORMAT,
+            "mips are only generated for {FORMAT:?}"
+        );
+        let device = gpu.device();
+        let mut pipeline = self.pipeline.lock().unwrap();
+        pipeline.refresh_with(gpu);
+        let pipeline = pipeline.current();
+        let layout = pipeline.get_bind_group_layout(0);
+        let sampler = gpu.sampler(sampler::Sampler::LINEAR);
+        let level_view = |level| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("mip level view"),
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                ..Default::default()
+            })
+        };
+        let mut command_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("mip command encoder"),
+        });
+        for level in 1..texture.mip_level_count() {
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("mip bind group"),
+                layout: &layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&level_view(level - 1)),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&sampler),
+                    },
+                ],
+            });
+            let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("mip render pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &level_view(level),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        // Submitted right away so it runs after the queued upload of the first level.
+        gpu.queue().submit([command_encoder.finish()]);
+    }
+}
+
+fn create_render_pipeline(
+    gpu: &rendering::Gpu,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    let device = gpu.device();
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("mip render pipeline"),
+        layout: Some(
+            &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("mip render pipeline layout"),
+                bind_group_layouts: &[&device
+                    .create_bind_group_layout(&sprite::GpuTexture::BIND_GROUP_LAYOUT_DESCRIPTOR)],
+                push_constant_ranges: &[],
+            }),
+        ),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vertex_main"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            buffers: &[],
+        },
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some("fragment_main"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: FORMAT,
+                blend: None,
+                write_mask: wgpu::ColorWrites::all(),
+            })],
+        }),
+        multiview: None,
+        cache: None,
+    })
+}
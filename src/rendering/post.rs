@@ -0,0 +1,886 @@
+use std::fs;
+use std::io::Write;
+use std::path;
+
+use crate::rendering;
+use crate::rendering::graph;
+use crate::rendering::sampler;
+use crate::rendering::shader;
+use crate::sprite;
+
+const SHADER: &str = include_str!("post.wgsl");
+/// Slices of the identity LUT used when no other is given.
+pub const LUT_SIZE: u32 = 16;
+/// Format the bloom is blurred in, so faint glow does not band.
+const BLOOM_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+const POST_BIND_GROUP_LAYOUT_DESCRIPTOR: wgpu::BindGroupLayoutDescriptor<'_> =
+    wgpu::BindGroupLayoutDescriptor {
+        label: Some("post process bind group layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ],
+    };
+
+const fn filtered_texture(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+/// The source of a pass, the bloom and the LUT, every pass binds all three.
+const SOURCE_BIND_GROUP_LAYOUT_DESCRIPTOR: wgpu::BindGroupLayoutDescriptor<'_> =
+    wgpu::BindGroupLayoutDescriptor {
+        label: Some("post process source bind group layout"),
+        entries: &[
+            filtered_texture(0),
+            filtered_texture(1),
+            filtered_texture(2),
+        ],
+    };
+
+fn create_pipeline(
+    gpu: &rendering::Gpu,
+    shader: &wgpu::ShaderModule,
+    label: &'static str,
+    fragment: &'static str,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    let device = gpu.device();
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(
+            &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: &[
+                    &device.create_bind_group_layout(&POST_BIND_GROUP_LAYOUT_DESCRIPTOR),
+                    &device.create_bind_group_layout(&SOURCE_BIND_GROUP_LAYOUT_DESCRIPTOR),
+                ],
+                push_constant_ranges: &[],
+            }),
+        ),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vertex_main"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            buffers: &[],
+        },
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some(fragment),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::all(),
+            })],
+        }),
+        multiview: None,
+        cache: None,
+    })
+}
+
+fn create_threshold_pipeline(
+    gpu: &rendering::Gpu,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    create_pipeline(
+        gpu,
+        shader,
+        "bloom threshold render pipeline",
+        "threshold_fragment",
+        BLOOM_FORMAT,
+    )
+}
+
+fn create_blur_horizontal_pipeline(
+    gpu: &rendering::Gpu,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    create_pipeline(
+        gpu,
+        shader,
+        "bloom blur horizontal render pipeline",
+        "blur_horizontal_fragment",
+        BLOOM_FORMAT,
+    )
+}
+
+fn create_blur_vertical_pipeline(
+    gpu: &rendering::Gpu,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    create_pipeline(
+        gpu,
+        shader,
+        "bloom blur vertical render pipeline",
+        "blur_vertical_fragment",
+        BLOOM_FORMAT,
+    )
+}
+
+fn create_final_pipeline(
+    gpu: &rendering::Gpu,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    create_pipeline(
+        gpu,
+        shader,
+        "post process render pipeline",
+        "final_fragment",
+        gpu.surface_config().format,
+    )
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum RetroFilter {
+    #[default]
+    Off,
+    /// Blocks of [`PostProcessSettings::pixel_size`] pixels.
+    PixelArt,
+    /// Pixel art on a curved tube with scanlines.
+    Crt,
+}
+
+impl RetroFilter {
+    pub const ALL: [Self; 3] = [Self::Off, Self::PixelArt, Self::Crt];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RetroFilter::Off => "off",
+            RetroFilter::PixelArt => "pixel art",
+            RetroFilter::Crt => "crt",
+        }
+    }
+}
+
+/// Which effects run between the lit world and the user interface, saved next to the
+/// executable like the bindings.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PostProcessSettings {
+    pub bloom: bool,
+    /// Brightness from which colours glow, from 0 to 1.
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+    pub grading: bool,
+    /// How far the colours move towards the LUT, from 0 to 1.
+    pub grading_strength: f32,
+    /// A PNG of `size` slices of `size` by `size` side by side, the identity LUT if `None`.
+    pub lut: Option<path::PathBuf>,
+    pub vignette: bool,
+    /// How dark the corners get, from 0 to 1.
+    pub vignette_intensity: f32,
+    pub chromatic_aberration: bool,
+    /// How far red and blue shift apart in the corners, in pixels.
+    pub aberration: f32,
+    pub retro: RetroFilter,
+    pub pixel_size: u32,
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        Self {
+            bloom: false,
+            bloom_threshold: 0.8,
+            bloom_intensity: 1.0,
+            grading: false,
+            grading_strength: 1.0,
+            lut: None,
+            vignette: false,
+            vignette_intensity: 0.4,
+            chromatic_aberration: false,
+            aberration: 2.0,
+            retro: RetroFilter::Off,
+            pixel_size: 4,
+        }
+    }
+}
+
+impl PostProcessSettings {
+    /// Whether any effect runs, the lit world is drawn straight to the output otherwise.
+    pub fn is_enabled(&self) -> bool {
+        self.bloom
+            || self.grading
+            || self.vignette
+            || self.chromatic_aberration
+            || self.retro != RetroFilter::Off
+    }
+    /// One `name=value` line per setting.
+    pub fn to_text(&self) -> String {
+        let lut = self
+            .lut
+            .as_ref()
+            .map(|lut| lut.display().to_string())
+            .unwrap_or_default();
+        [
+            format!("bloom={}", self.bloom),
+            format!("bloom_threshold={}", self.bloom_threshold),
+            format!("bloom_intensity={}", self.bloom_intensity),
+            format!("grading={}", self.grading),
+            format!("grading_strength={}", self.grading_strength),
+            format!("lut={lut}"),
+            format!("vignette={}", self.vignette),
+            format!("vignette_intensity={}", self.vignette_intensity),
+            format!("chromatic_aberration={}", self.chromatic_aberration),
+            format!("aberration={}", self.aberration),
+            format!("retro={}", self.retro.name()),
+            format!("pixel_size={}", self.pixel_size),
+        ]
+        .map(|line| line + "\n")
+        .concat()
+    }
+    /// Reads [`PostProcessSettings::to_text`] output over the defaults.
+    pub fn from_text(text: &str) -> anyhow::Result<Self> {
+        let mut settings = Self::default();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let Some((name, value)) = line.split_once('=') else {
+                anyhow::bail!("malformed setting line: {line}");
+            };
+            let value = value.trim();
+            match name.trim() {
+                "bloom" => settings.bloom = value.parse()?,
+                "bloom_threshold" => settings.bloom_threshold = value.parse()?,
+                "bloom_intensity" => settings.bloom_intensity = value.parse()?,
+                "grading" => settings.grading = value.parse()?,
+                "grading_strength" => settings.grading_strength = value.parse()?,
+                "lut" => settings.lut = (!value.is_empty()).then(|| path::PathBuf::from(value)),
+                "vignette" => settings.vignette = value.parse()?,
+                "vignette_intensity" => settings.vignette_intensity = value.parse()?,
+                "chromatic_aberration" => settings.chromatic_aberration = value.parse()?,
+                "aberration" => settings.aberration = value.parse()?,
+                "retro" => {
+                    settings.retro = RetroFilter::ALL
+                        .into_iter()
+                        .find(|retro| retro.name() == value)
+                        .ok_or(anyhow::anyhow!("unknown retro filter {value}"))?;
+                }
+                "pixel_size" => settings.pixel_size = value.parse()?,
+                name => log::warn!("Ignoring unknown setting {name}"),
+            }
+        }
+        Ok(settings)
+    }
+    /// Settings live next to the executable, like the bindings.
+    pub fn path() -> path::PathBuf {
+        let mut path = std::env::current_exe().unwrap_or_default();
+        path.pop();
+        path.join("post_process.txt")
+    }
+    /// The saved settings, or the defaults if there are none or they cannot be read.
+    pub fn load() -> Self {
+        let path = Self::path();
+        let Ok(text) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        Self::from_text(&text).unwrap_or_else(|error| {
+            log::warn!("Ignoring {}: {error:#}", path.display());
+            Self::default()
+        })
+    }
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = Self::path();
+        let temporary = path.with_extension("txt.tmp");
+        let mut file = fs::File::create(&temporary)?;
+        file.write_all(self.to_text().as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&temporary, &path)?;
+        Ok(())
+    }
+}
+
+/// A LUT that leaves every colour as it is, `size` slices of red by green, one per blue.
+pub fn identity_lut(size: u32) -> image::RgbaImage {
+    let level = |value: u32| (value * 255 / (size - 1).max(1)) as u8;
+    image::RgbaImage::from_fn(size * size, size, |x, y| {
+        image::Rgba([level(x % size), level(y), level(x / size), 255])
+    })
+}
+
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct PostUniform {
+    resolution: glam::Vec2,
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+    grading_strength: f32,
+    lut_size: f32,
+    vignette_intensity: f32,
+    vignette_radius: f32,
+    aberration: f32,
+    pixel_size: f32,
+    crt: f32,
+    _padding: f32,
+}
+
+impl PostUniform {
+    fn new(settings: &PostProcessSettings, resolution: glam::Vec2, lut_size: u32) -> Self {
+        let enabled = |enabled: bool, value: f32| if enabled { value } else { 0.0 };
+        Self {
+            resolution,
+            bloom_threshold: settings.bloom_threshold,
+            bloom_intensity: enabled(settings.bloom, settings.bloom_intensity),
+            grading_strength: enabled(settings.grading, settings.grading_strength),
+            lut_size: lut_size as f32,
+            vignette_intensity: enabled(settings.vignette, settings.vignette_intensity),
+            vignette_radius: 0.5,
+            aberration: enabled(settings.chromatic_aberration, settings.aberration),
+            pixel_size: match settings.retro {
+                RetroFilter::Off => 1.0,
+                _ => settings.pixel_size.max(1) as f32,
+            },
+            crt: enabled(settings.retro == RetroFilter::Crt, 1.0),
+            _padding: 0.0,
+        }
+    }
+}
+
+/// Runs the effects of its [`PostProcessSettings`] over the lit world before the user interface
+/// is drawn on top.
+///
+/// Bloom blurs the bright parts at a quarter of the resolution, then a single pass applies the
+/// retro filter, chromatic aberration, the bloom, the colour grading and the vignette.
+pub struct PostProcessor<'window> {
+    gpu_handle: rendering::GpuHandle<'window>,
+    settings: PostProcessSettings,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    threshold_pipeline: shader::HotPipeline,
+    blur_horizontal_pipeline: shader::HotPipeline,
+    blur_vertical_pipeline: shader::HotPipeline,
+    final_pipeline: shader::HotPipeline,
+    lut: sprite::GpuTexture,
+    lut_view: wgpu::TextureView,
+}
+
+impl<'window> PostProcessor<'window> {
+    pub fn new(gpu_handle: rendering::GpuHandle<'window>, settings: PostProcessSettings) -> Self {
+        let gpu = gpu_handle.read().unwrap();
+        let uniform_buffer = gpu.device().create_buffer(&wgpu::BufferDescriptor {
+            label: Some("post process uniform buffer"),
+            size: std::mem::size_of::<PostUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let uniform_bind_group = gpu.device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("post process bind group"),
+            layout: &gpu
+                .device()
+                .create_bind_group_layout(&POST_BIND_GROUP_LAYOUT_DESCRIPTOR),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(
+                        &gpu.sampler(sampler::Sampler::LINEAR),
+                    ),
+                },
+            ],
+        });
+        drop(gpu);
+        let pipeline = |label: &'static str, build: shader::BuildPipeline| {
+            shader::HotPipeline::new(label, SHADER, "src/rendering/post.wgsl", build)
+        };
+        let lut = Self::create_lut(&identity_lut(LUT_SIZE), &gpu_handle);
+        let mut post_processor = Self {
+            uniform_buffer,
+            uniform_bind_group,
+            threshold_pipeline: pipeline("bloom threshold shader", create_threshold_pipeline),
+            blur_horizontal_pipeline: pipeline(
+                "bloom blur horizontal shader",
+                create_blur_horizontal_pipeline,
+            ),
+            blur_vertical_pipeline: pipeline(
+                "bloom blur vertical shader",
+                create_blur_vertical_pipeline,
+            ),
+            final_pipeline: pipeline("post process shader", create_final_pipeline),
+            lut_view: lut
+                .texture()
+                .create_view(&wgpu::TextureViewDescriptor::default()),
+            lut,
+            settings: PostProcessSettings::default(),
+            gpu_handle,
+        };
+        post_processor.set_settings(settings);
+        post_processor
+    }
+    fn create_lut(
+        image: &image::RgbaImage,
+        gpu_handle: &rendering::GpuHandle,
+    ) -> sprite::GpuTexture {
+        sprite::GpuTexture::from_image_with(
+            "colour grading lut",
+            image,
+            sprite::TextureOptions {
+                sampler: sampler::Sampler::LINEAR,
+                mipmaps: false,
+                linear: true,
+            },
+            gpu_handle.clone(),
+        )
+    }
+    pub fn settings(&self) -> &PostProcessSettings {
+        &self.settings
+    }
+    /// Applies `settings` from the next frame on, loading the LUT again if its path changed.
+    pub fn set_settings(&mut self, settings: PostProcessSettings) {
+        let lut_changed = settings.lut != self.settings.lut;
+        self.settings = settings;
+        if !lut_changed {
+            return;
+        }
+        let loaded = match self.settings.lut.clone() {
+            Some(path) => image::open(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|lut| self.set_lut(&lut.to_rgba8()))
+                .map_err(|error| error.context(format!("could not load {}", path.display()))),
+            None => self.set_lut(&identity_lut(LUT_SIZE)),
+        };
+        if let Err(error) = loaded {
+            log::error!("{error:#}");
+            let _ = self.set_lut(&identity_lut(LUT_SIZE));
+        }
+    }
+    /// Grades with `lut`, which must be as wide as it is high squared.
+    pub fn set_lut(&mut self, lut: &image::RgbaImage) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            lut.height() >= 2 && lut.width() == lut.height() * lut.height(),
+            "a {}x{} LUT is not {} slices side by side",
+            lut.width(),
+            lut.height(),
+            lut.height()
+        );
+        self.lut = Self::create_lut(lut, &self.gpu_handle);
+        self.lut_view = self
+            .lut
+            .texture()
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        Ok(())
+    }
+    /// Where the world should be drawn this frame, straight to the output when no effect runs.
+    pub fn input_target(&mut self) -> graph::ResourceId {
+        if !self.settings.is_enabled() {
+            return graph::OUTPUT;
+        }
+        let mut gpu = self.gpu_handle.write().unwrap();
+        let format = gpu.surface_config().format;
+        gpu.render_graph().create_target(graph::TargetDescriptor {
+            label: "post process input",
+            format,
+            size: graph::TargetSize::Output,
+        })
+    }
+    /// Adds the passes applying the enabled effects to `input` and writing the result to
+    /// `output`, nothing when they are the same.
+    pub fn render(&mut self, input: graph::ResourceId, output: graph::ResourceId) {
+        if input == output {
+            return;
+        }
+        for pipeline in [
+            &mut self.threshold_pipeline,
+            &mut self.blur_horizontal_pipeline,
+            &mut self.blur_vertical_pipeline,
+            &mut self.final_pipeline,
+        ] {
+            pipeline.refresh(&self.gpu_handle);
+        }
+        let mut gpu = self.gpu_handle.write().unwrap();
+        let resolution = glam::vec2(
+            gpu.surface_config().width as f32,
+            gpu.surface_config().height as f32,
+        );
+        gpu.queue().write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::bytes_of(&PostUniform::new(
+                &self.settings,
+                resolution,
+                self.lut.size().1,
+            )),
+        );
+        let pass = FullscreenPass {
+            device: gpu.device().clone(),
+            uniform_bind_group: self.uniform_bind_group.clone(),
+            lut: self.lut_view.clone(),
+        };
+        let graph = gpu.render_graph();
+        let bloom = self.settings.bloom.then(|| {
+            let target = |graph: &mut graph::RenderGraph, label, divisor| {
+                graph.create_target(graph::TargetDescriptor {
+                    label,
+                    format: BLOOM_FORMAT,
+                    size: graph::TargetSize::Divided(divisor),
+                })
+            };
+            let bright = target(graph, "bloom bright", 2);
+            let blurred_horizontally = target(graph, "bloom blur horizontal", 4);
+            let blurred = target(graph, "bloom blur", 4);
+            pass.add(
+                graph,
+                "bloom threshold",
+                &self.threshold_pipeline,
+                (input, None),
+                bright,
+            );
+            pass.add(
+                graph,
+                "bloom blur horizontal",
+                &self.blur_horizontal_pipeline,
+                (bright, None),
+                blurred_horizontally,
+            );
+            pass.add(
+                graph,
+                "bloom blur vertical",
+                &self.blur_vertical_pipeline,
+                (blurred_horizontally, None),
+                blurred,
+            );
+            blurred
+        });
+        pass.add(
+            graph,
+            "post process",
+            &self.final_pipeline,
+            (input, bloom),
+            output,
+        );
+    }
+}
+
+/// What every post process pass binds besides its sources.
+struct FullscreenPass {
+    device: wgpu::Device,
+    uniform_bind_group: wgpu::BindGroup,
+    lut: wgpu::TextureView,
+}
+
+impl FullscreenPass {
+    /// Adds a pass drawing `pipeline` over `target`, sampling the first source and the bloom in
+    /// the second, which is the first again when there is none.
+    fn add(
+        &self,
+        graph: &mut graph::RenderGraph,
+        name: &'static str,
+        pipeline: &shader::HotPipeline,
+        (source, bloom): (graph::ResourceId, Option<graph::ResourceId>),
+        target: graph::ResourceId,
+    ) {
+        let pipeline = pipeline.current().clone();
+        let device = self.device.clone();
+        let uniform_bind_group = self.uniform_bind_group.clone();
+        let lut = self.lut.clone();
+        let mut builder = graph.add_pass(name).read(source).write(target);
+        if let Some(bloom) = bloom {
+            builder = builder.read(bloom);
+        }
+        builder.execute(move |command_encoder, resources| {
+            let source_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("post process source bind group"),
+                layout: &device.create_bind_group_layout(&SOURCE_BIND_GROUP_LAYOUT_DESCRIPTOR),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(resources.view(source)),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(
+                            resources.view(bloom.unwrap_or(source)),
+                        ),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&lut),
+                    },
+                ],
+            });
+            let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(name),
+                color_attachments: &[resources.color_attachment(target)],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render_pass.set_pipeline(&pipeline);
+            render_pass.set_bind_group(0, &uniform_bind_group, &[]);
+            render_pass.set_bind_group(1, &source_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Size of the frames the tests render.
+    const SIZE: u32 = 64;
+    const CENTRE: u32 = SIZE / 2;
+    /// First column of the bright square.
+    const EDGE: u32 = SIZE * 3 / 8;
+
+    /// Grey with a white square in the middle, in the output format.
+    fn scene() -> image::RgbaImage {
+        let square = EDGE..SIZE * 5 / 8;
+        image::RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+            match square.contains(&x) && square.contains(&y) {
+                true => image::Rgba([255, 255, 255, 255]),
+                false => image::Rgba([128, 128, 128, 255]),
+            }
+        })
+    }
+
+    /// A post processor on a headless GPU with [`scene`] uploaded to copy into its input.
+    struct Fixture<'window> {
+        post_processor: PostProcessor<'window>,
+        scene: wgpu::Texture,
+    }
+
+    impl Fixture<'_> {
+        /// Renders the scene with `settings` and reads it back.
+        fn render(&mut self, settings: PostProcessSettings) -> image::RgbaImage {
+            self.post_processor.set_settings(settings);
+            self.render_again()
+        }
+        /// Renders the scene with the settings and LUT already set.
+        fn render_again(&mut self) -> image::RgbaImage {
+            let input = self.post_processor.input_target();
+            let source = self.scene.clone();
+            self.post_processor
+                .gpu_handle
+                .write()
+                .unwrap()
+                .render_graph()
+                .add_pass("test scene")
+                .write(input)
+                .execute(move |command_encoder, resources| {
+                    command_encoder.copy_texture_to_texture(
+                        source.as_image_copy(),
+                        resources.texture(input).as_image_copy(),
+                        source.size(),
+                    );
+                });
+            self.post_processor.render(input, graph::OUTPUT);
+            let mut gpu = self.post_processor.gpu_handle.write().unwrap();
+            gpu.submit_command_buffer();
+            gpu.read_output().unwrap()
+        }
+    }
+
+    /// Runs `test` on a headless GPU, `WGPU_BACKEND=gl` picks one on machines without Vulkan.
+    fn with_fixture(test: impl FnOnce(&mut Fixture)) {
+        let gpu_handle = rendering::Gpu::headless(SIZE, SIZE).expect("no headless GPU adapter");
+        let scene = {
+            let gpu = gpu_handle.read().unwrap();
+            let texture = gpu.device().create_texture(&wgpu::TextureDescriptor {
+                label: Some("test scene"),
+                size: wgpu::Extent3d {
+                    width: SIZE,
+                    height: SIZE,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: gpu.surface_config().format,
+                usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            });
+            gpu.queue().write_texture(
+                texture.as_image_copy(),
+                scene().as_raw(),
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * SIZE),
+                    rows_per_image: Some(SIZE),
+                },
+                texture.size(),
+            );
+            texture
+        };
+        test(&mut Fixture {
+            post_processor: PostProcessor::new(gpu_handle, PostProcessSettings::default()),
+            scene,
+        });
+    }
+
+    /// Largest difference of the colour channels.
+    fn difference(a: &image::Rgba<u8>, b: &image::Rgba<u8>) -> u8 {
+        (0..3)
+            .map(|channel| a[channel].abs_diff(b[channel]))
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn assert_matches(
+        image: &image::RgbaImage,
+        expected: impl Fn(u32, u32) -> image::Rgba<u8>,
+        tolerance: u8,
+    ) {
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let expected = expected(x, y);
+            assert!(
+                difference(pixel, &expected) <= tolerance,
+                "pixel {x}, {y} is {pixel:?} instead of {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with --ignored"]
+    fn everything_off_leaves_the_frame_unchanged() {
+        with_fixture(|fixture| {
+            let scene = scene();
+            let output = fixture.render(PostProcessSettings::default());
+            assert_matches(&output, |x, y| *scene.get_pixel(x, y), 1);
+        });
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with --ignored"]
+    fn vignette_darkens_the_corners_but_not_the_centre() {
+        with_fixture(|fixture| {
+            let scene = scene();
+            let output = fixture.render(PostProcessSettings {
+                vignette: true,
+                ..Default::default()
+            });
+            assert!(output.get_pixel(0, 0)[0] + 20 < scene.get_pixel(0, 0)[0]);
+            assert!(
+                difference(
+                    output.get_pixel(CENTRE, CENTRE),
+                    scene.get_pixel(CENTRE, CENTRE)
+                ) <= 2
+            );
+        });
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with --ignored"]
+    fn bloom_brightens_the_surroundings_of_the_bright_square() {
+        with_fixture(|fixture| {
+            let scene = scene();
+            let output = fixture.render(PostProcessSettings {
+                bloom: true,
+                ..Default::default()
+            });
+            assert!(
+                output.get_pixel(EDGE - 4, CENTRE)[0] > scene.get_pixel(EDGE - 4, CENTRE)[0] + 8
+            );
+            assert!(output.get_pixel(0, 0)[0] <= scene.get_pixel(0, 0)[0] + 2);
+        });
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with --ignored"]
+    fn grading_applies_the_lut() {
+        with_fixture(|fixture| {
+            let scene = scene();
+            let output = fixture.render(PostProcessSettings {
+                grading: true,
+                ..Default::default()
+            });
+            assert_matches(&output, |x, y| *scene.get_pixel(x, y), 3);
+
+            let mut inverted = identity_lut(LUT_SIZE);
+            for pixel in inverted.pixels_mut() {
+                for channel in 0..3 {
+                    pixel[channel] = 255 - pixel[channel];
+                }
+            }
+            fixture.post_processor.set_lut(&inverted).unwrap();
+            let output = fixture.render_again();
+            assert_matches(
+                &output,
+                |x, y| {
+                    let pixel = scene.get_pixel(x, y);
+                    image::Rgba([255 - pixel[0], 255 - pixel[1], 255 - pixel[2], 255])
+                },
+                8,
+            );
+        });
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with --ignored"]
+    fn chromatic_aberration_splits_red_from_blue_at_edges() {
+        with_fixture(|fixture| {
+            let scene = scene();
+            let output = fixture.render(PostProcessSettings {
+                chromatic_aberration: true,
+                aberration: 8.0,
+                ..Default::default()
+            });
+            let split = output.get_pixel(EDGE, CENTRE);
+            assert!(split[2] > split[0] + 40, "{split:?}");
+            assert!(
+                difference(
+                    output.get_pixel(CENTRE, CENTRE),
+                    scene.get_pixel(CENTRE, CENTRE)
+                ) <= 1
+            );
+        });
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with --ignored"]
+    fn pixel_art_fills_each_block_with_one_colour() {
+        with_fixture(|fixture| {
+            let pixel_size = 8;
+            let output = fixture.render(PostProcessSettings {
+                retro: RetroFilter::PixelArt,
+                pixel_size,
+                ..Default::default()
+            });
+            assert_matches(
+                &output,
+                |x, y| *output.get_pixel(x / pixel_size * pixel_size, y / pixel_size * pixel_size),
+                1,
+            );
+        });
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with --ignored"]
+    fn crt_curves_the_corners_away_and_darkens_every_other_line() {
+        with_fixture(|fixture| {
+            let output = fixture.render(PostProcessSettings {
+                retro: RetroFilter::Crt,
+                pixel_size: 1,
+                ..Default::default()
+            });
+            assert_eq!(output.get_pixel(0, 0)[0], 0);
+            assert!(output.get_pixel(8, CENTRE + 1)[0] + 10 < output.get_pixel(8, CENTRE)[0]);
+        });
+    }
+}
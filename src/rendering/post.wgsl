@@ -0,0 +1,145 @@
+struct Post {
+    resolution: vec2<f32>,
+    bloom_threshold: f32,
+    // Effects below are off at 0, the pixel size at 1.
+    bloom_intensity: f32,
+    grading_strength: f32,
+    lut_size: f32,
+    vignette_intensity: f32,
+    vignette_radius: f32,
+    aberration: f32,
+    pixel_size: f32,
+    crt: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> post: Post;
+@group(0) @binding(1)
+var linear_sampler: sampler;
+
+@group(1) @binding(0)
+var source: texture_2d<f32>;
+@group(1) @binding(1)
+var bloom: texture_2d<f32>;
+@group(1) @binding(2)
+var lut: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vertex_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    // One triangle covering the screen.
+    let corner = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var output: VertexOutput;
+    output.clip_position = vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+    output.uv = vec2<f32>(corner.x, 1.0 - corner.y);
+    return output;
+}
+
+fn sample_source(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(source, linear_sampler, uv, 0.0).rgb;
+}
+
+@fragment
+fn threshold_fragment(input: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 0.5 / vec2<f32>(textureDimensions(source));
+    let color = (sample_source(input.uv + vec2<f32>(-texel.x, -texel.y))
+        + sample_source(input.uv + vec2<f32>(texel.x, -texel.y))
+        + sample_source(input.uv + vec2<f32>(-texel.x, texel.y))
+        + sample_source(input.uv + vec2<f32>(texel.x, texel.y))) * 0.25;
+    let brightness = max(color.r, max(color.g, color.b));
+    let bright = max(brightness - post.bloom_threshold, 0.0) / max(brightness, 1e-4);
+    return vec4<f32>(color * bright, 1.0);
+}
+
+// Nine taps of a gaussian in five samples, between texels the linear filter blends two.
+fn blur(uv: vec2<f32>, direction: vec2<f32>) -> vec4<f32> {
+    let texel = direction / vec2<f32>(textureDimensions(source));
+    var color = sample_source(uv) * 0.2270270270;
+    color += (sample_source(uv + texel * 1.3846153846) + sample_source(uv - texel * 1.3846153846))
+        * 0.3162162162;
+    color += (sample_source(uv + texel * 3.2307692308) + sample_source(uv - texel * 3.2307692308))
+        * 0.0702702703;
+    return vec4<f32>(color, 1.0);
+}
+
+@fragment
+fn blur_horizontal_fragment(input: VertexOutput) -> @location(0) vec4<f32> {
+    return blur(input.uv, vec2<f32>(1.0, 0.0));
+}
+
+@fragment
+fn blur_vertical_fragment(input: VertexOutput) -> @location(0) vec4<f32> {
+    return blur(input.uv, vec2<f32>(0.0, 1.0));
+}
+
+// LUTs are authored on gamma encoded colours.
+fn to_gamma(color: vec3<f32>) -> vec3<f32> {
+    return pow(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)), vec3<f32>(1.0 / 2.2));
+}
+
+fn to_linear(color: vec3<f32>) -> vec3<f32> {
+    return pow(color, vec3<f32>(2.2));
+}
+
+// The LUT is `lut_size` slices of red by green side by side, one per step of blue.
+fn grade(color: vec3<f32>) -> vec3<f32> {
+    let size = post.lut_size;
+    let encoded = to_gamma(color);
+    let blue = encoded.b * (size - 1.0);
+    let slice = floor(blue);
+    let next = min(slice + 1.0, size - 1.0);
+    let within = (encoded.rg * (size - 1.0) + 0.5) / vec2<f32>(size * size, size);
+    let low = textureSampleLevel(lut, linear_sampler, within + vec2<f32>(slice / size, 0.0), 0.0);
+    let high = textureSampleLevel(lut, linear_sampler, within + vec2<f32>(next / size, 0.0), 0.0);
+    return to_linear(mix(low.rgb, high.rgb, blue - slice));
+}
+
+// Bulges the picture like the glass of a tube.
+fn curve(uv: vec2<f32>) -> vec2<f32> {
+    let centered = uv * 2.0 - 1.0;
+    let curved = centered * (1.0 + centered.yx * centered.yx * 0.08);
+    return curved * 0.5 + 0.5;
+}
+
+@fragment
+fn final_fragment(input: VertexOutput) -> @location(0) vec4<f32> {
+    var uv = input.uv;
+    if post.crt > 0.0 {
+        uv = curve(uv);
+        if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) {
+            return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+        }
+    }
+    if post.pixel_size > 1.0 {
+        let cells = post.resolution / post.pixel_size;
+        uv = (floor(uv * cells) + 0.5) / cells;
+    }
+
+    var color = sample_source(uv);
+    if post.aberration > 0.0 {
+        // Red and blue shift apart towards the edges, `aberration` pixels each in the corners.
+        let offset = (uv - 0.5) * 2.0 * post.aberration / post.resolution;
+        color.r = sample_source(uv + offset).r;
+        color.b = sample_source(uv - offset).b;
+    }
+    if post.bloom_intensity > 0.0 {
+        color += textureSampleLevel(bloom, linear_sampler, uv, 0.0).rgb * post.bloom_intensity;
+    }
+    if post.grading_strength > 0.0 {
+        color = mix(color, grade(color), post.grading_strength);
+    }
+    if post.crt > 0.0 {
+        let period = max(post.pixel_size, 2.0);
+        let dark = fract(input.clip_position.y / period) >= 0.5;
+        color *= select(1.0, 0.7, dark);
+    }
+    if post.vignette_intensity > 0.0 {
+        let distance = length(input.uv - 0.5) * sqrt(2.0);
+        color *= 1.0 - post.vignette_intensity * smoothstep(post.vignette_radius, 1.0, distance);
+    }
+    return vec4<f32>(color, 1.0);
+}
@@ -5,6 +5,7 @@ pub trait Renderable<'window>: Sized {
         target: wgpu::Surface,
     ) -> wgpu::CommandBuffer;
 }
+#[allow(dead_code)]
 pub struct OldRenderable<T: Vertex> {
     vertex_buffer: Vec<T>,
     index_buffer: Vec<u32>,
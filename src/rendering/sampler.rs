@@ -0,0 +1,90 @@
+use std::collections;
+use std::sync;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Filter {
+    /// Every texel stays a hard edged square, at any zoom.
+    #[default]
+    Nearest,
+    /// Sharp when magnified and blended across mip levels when minified, for pixel art that
+    /// must not shimmer when zoomed out.
+    Pixel,
+    Linear,
+    /// Linear with up to this many samples along the slope, clamped to 1..=16.
+    Anisotropic(u16),
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Address {
+    #[default]
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+/// How a texture is sampled, one `wgpu::Sampler` per distinct value is shared by every texture.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Sampler {
+    pub filter: Filter,
+    pub address: Address,
+}
+
+impl Sampler {
+    pub const NEAREST: Self = Self {
+        filter: Filter::Nearest,
+        address: Address::Clamp,
+    };
+    pub const PIXEL: Self = Self {
+        filter: Filter::Pixel,
+        address: Address::Clamp,
+    };
+    pub const LINEAR: Self = Self {
+        filter: Filter::Linear,
+        address: Address::Clamp,
+    };
+    fn descriptor(&self) -> wgpu::SamplerDescriptor<'static> {
+        let address_mode = match self.address {
+            Address::Clamp => wgpu::AddressMode::ClampToEdge,
+            Address::Repeat => wgpu::AddressMode::Repeat,
+            Address::Mirror => wgpu::AddressMode::MirrorRepeat,
+        };
+        let (mag_filter, min_filter, anisotropy_clamp) = match self.filter {
+            Filter::Nearest => (wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest, 1),
+            Filter::Pixel => (wgpu::FilterMode::Nearest, wgpu::FilterMode::Linear, 1),
+            Filter::Linear => (wgpu::FilterMode::Linear, wgpu::FilterMode::Linear, 1),
+            Filter::Anisotropic(samples) => (
+                wgpu::FilterMode::Linear,
+                wgpu::FilterMode::Linear,
+                samples.clamp(1, 16),
+            ),
+        };
+        wgpu::SamplerDescriptor {
+            label: Some("Texture Sampler"),
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+            address_mode_w: address_mode,
+            mag_filter,
+            min_filter,
+            mipmap_filter: min_filter,
+            anisotropy_clamp,
+            ..Default::default()
+        }
+    }
+}
+
+/// Samplers created so far, owned by the [`super::Gpu`] so textures share them.
+#[derive(Default)]
+pub struct SamplerRegistry {
+    samplers: sync::Mutex<collections::HashMap<Sampler, wgpu::Sampler>>,
+}
+
+impl SamplerRegistry {
+    pub fn get(&self, device: &wgpu::Device, sampler: Sampler) -> wgpu::Sampler {
+        self.samplers
+            .lock()
+            .unwrap()
+            .entry(sampler)
+            .or_insert_with(|| device.create_sampler(&sampler.descriptor()))
+            .clone()
+    }
+}
@@ -0,0 +1,147 @@
+use crate::rendering;
+use std::collections;
+use std::fs;
+use std::path;
+use std::sync;
+use std::time;
+
+/// How often debug builds look at the shader sources for changes.
+const POLL_INTERVAL: time::Duration = time::Duration::from_millis(250);
+
+/// Compile errors of every shader whose latest source failed, by shader label.
+static ERRORS: sync::Mutex<collections::BTreeMap<&'static str, String>> =
+    sync::Mutex::new(collections::BTreeMap::new());
+
+pub type BuildPipeline = fn(&rendering::Gpu, &wgpu::ShaderModule) -> wgpu::RenderPipeline;
+
+/// A render pipeline whose WGSL is embedded in release builds and reloaded from the source tree
+/// in debug builds whenever the file changes.
+///
+/// A source that fails to compile keeps the last working pipeline and shows its error through
+/// [`errors_user_interface`] until a later save compiles.
+pub struct HotPipeline {
+    label: &'static str,
+    embedded: &'static str,
+    source: Option<path::PathBuf>,
+    build: BuildPipeline,
+    modified: Option<time::SystemTime>,
+    checked: Option<time::Instant>,
+    pipeline: Option<wgpu::RenderPipeline>,
+}
+
+impl HotPipeline {
+    /// `source` is the path of the shader relative to the crate root, watched in debug builds.
+    pub fn new(
+        label: &'static str,
+        embedded: &'static str,
+        source: &'static str,
+        build: BuildPipeline,
+    ) -> Self {
+        let source = cfg!(debug_assertions)
+            .then(|| path::Path::new(env!("CARGO_MANIFEST_DIR")).join(source))
+            .filter(|source| source.is_file());
+        Self {
+            label,
+            embedded,
+            source,
+            build,
+            modified: None,
+            checked: None,
+            pipeline: None,
+        }
+    }
+    /// Builds the pipeline on first use and rebuilds it once the watched source changed.
+    pub fn refresh(&mut self, gpu_handle: &rendering::GpuHandle) {
+        self.refresh_with(&gpu_handle.read().unwrap());
+    }
+    /// [`HotPipeline::refresh`] for callers that already hold the GPU.
+    pub fn refresh_with(&mut self, gpu: &rendering::Gpu) {
+        if self.pipeline.is_some()
+            && self
+                .checked
+                .is_some_and(|checked| checked.elapsed() < POLL_INTERVAL)
+        {
+            return;
+        }
+        self.checked = Some(time::Instant::now());
+        let modified = self
+            .source
+            .as_ref()
+            .and_then(|source| fs::metadata(source).and_then(|meta| meta.modified()).ok());
+        if self.pipeline.is_some() && modified == self.modified {
+            return;
+        }
+        self.modified = modified;
+
+        let loaded = self.source.as_ref().map(|source| {
+            fs::read_to_string(source)
+                .map_err(|error| format!("could not read {}: {error}", source.display()))
+                .and_then(|text| self.compile(gpu, &text))
+        });
+        match loaded {
+            Some(Ok(pipeline)) => {
+                if self.pipeline.is_some() {
+                    log::info!("Reloaded {}", self.label);
+                }
+                self.pipeline = Some(pipeline);
+                ERRORS.lock().unwrap().remove(self.label);
+            }
+            Some(Err(error)) => {
+                log::error!("{} failed to compile: {error}", self.label);
+                ERRORS.lock().unwrap().insert(self.label, error);
+                if self.pipeline.is_none() {
+                    self.pipeline = Some(self.compile_embedded(gpu));
+                }
+            }
+            None => self.pipeline = Some(self.compile_embedded(gpu)),
+        }
+    }
+    /// The current pipeline, [`HotPipeline::refresh`] must have run once before.
+    pub fn current(&self) -> &wgpu::RenderPipeline {
+        self.pipeline
+            .as_ref()
+            .expect("pipeline should be refreshed before use")
+    }
+    fn compile_embedded(&self, gpu: &rendering::Gpu) -> wgpu::RenderPipeline {
+        self.compile(gpu, self.embedded)
+            .unwrap_or_else(|error| panic!("embedded {} is invalid: {error}", self.label))
+    }
+    /// Creates the module and pipeline inside an error scope, so invalid WGSL is returned
+    /// instead of reaching the device's uncaptured error handler.
+    fn compile(&self, gpu: &rendering::Gpu, text: &str) -> Result<wgpu::RenderPipeline, String> {
+        let device = gpu.device();
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(self.label),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(text)),
+        });
+        let pipeline = (self.build)(gpu, &module);
+        match pollster::block_on(device.pop_error_scope()) {
+            Some(error) => Err(error.to_string()),
+            None => Ok(pipeline),
+        }
+    }
+}
+
+/// Lists the compile errors of hot reloaded shaders on top of everything else.
+pub fn errors_user_interface(context: &egui::Context) {
+    let errors = ERRORS.lock().unwrap();
+    if errors.is_empty() {
+        return;
+    }
+    egui::Window::new("shader errors")
+        .order(egui::Order::Foreground)
+        .default_width(640.0)
+        .show(context, |user_interface: &mut egui::Ui| {
+            egui::ScrollArea::vertical().show(user_interface, |user_interface| {
+                for (label, error) in errors.iter() {
+                    user_interface.strong(*label);
+                    user_interface.label(
+                        egui::RichText::new(error)
+                            .monospace()
+                            .color(egui::Color32::LIGHT_RED),
+                    );
+                }
+            });
+        });
+}
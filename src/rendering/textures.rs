@@ -0,0 +1,364 @@
+use crate::rendering;
+use crate::rendering::compression;
+use crate::sprite;
+use std::path;
+use std::sync;
+
+/// Budget of a new [`TextureManager`].
+pub const DEFAULT_BUDGET: u64 = 256 * 1024 * 1024;
+
+/// Names a texture of a [`TextureManager`], valid whether or not the texture is resident.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TextureHandle(usize);
+
+/// Where an evicted texture is uploaded from again.
+#[derive(Clone)]
+pub enum TextureSource {
+    Image(sync::Arc<image::RgbaImage>),
+    /// A PNG that is read again on every upload.
+    File(path::PathBuf),
+    /// Blocks baked by [`compression::compress`], uploaded with their mips as they are.
+    Compressed(sync::Arc<compression::CompressedImage>),
+}
+
+impl TextureSource {
+    fn size(&self) -> anyhow::Result<(u32, u32)> {
+        Ok(match self {
+            TextureSource::Image(image) => image.dimensions(),
+            TextureSource::File(path) => image::image_dimensions(path)?,
+            TextureSource::Compressed(image) => (image.width, image.height),
+        })
+    }
+}
+
+struct Entry {
+    label: String,
+    source: TextureSource,
+    options: sprite::TextureOptions,
+    size: (u32, u32),
+    texture: Option<sync::Arc<sprite::GpuTexture>>,
+    last_used: u64,
+}
+
+/// What the texture manager holds, for the debugger.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TextureStats {
+    pub budget: u64,
+    pub resident_bytes: u64,
+    pub textures: usize,
+    pub resident: usize,
+    /// Textures uploaded from block compressed sources.
+    pub compressed: usize,
+    pub uploads: u64,
+    pub evictions: u64,
+}
+
+/// Sprite sheets and other large textures, uploaded when first drawn and evicted least recently
+/// used first once their memory exceeds the budget.
+///
+/// Textures used in the current frame are never evicted, so a frame that needs more than the
+/// budget goes over it instead of uploading the same texture twice.
+pub struct TextureManager {
+    entries: Vec<Entry>,
+    budget: u64,
+    frame: u64,
+    uploads: u64,
+    evictions: u64,
+}
+
+impl Default for TextureManager {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUDGET)
+    }
+}
+
+impl TextureManager {
+    pub fn new(budget: u64) -> Self {
+        Self {
+            entries: Vec::new(),
+            budget,
+            frame: 0,
+            uploads: 0,
+            evictions: 0,
+        }
+    }
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+    /// Takes effect at the end of the frame, see [`TextureManager::end_frame`].
+    pub fn set_budget(&mut self, budget: u64) {
+        self.budget = budget;
+    }
+    /// Adds a texture without uploading it, that happens when it is first used.
+    pub fn insert(
+        &mut self,
+        label: &str,
+        source: TextureSource,
+        options: sprite::TextureOptions,
+    ) -> anyhow::Result<TextureHandle> {
+        let size = source.size()?;
+        self.entries.push(Entry {
+            label: label.to_owned(),
+            source,
+            options,
+            size,
+            texture: None,
+            last_used: self.frame,
+        });
+        Ok(TextureHandle(self.entries.len() - 1))
+    }
+    pub fn size(&self, handle: TextureHandle) -> (u32, u32) {
+        self.entries[handle.0].size
+    }
+    /// The resident texture, uploaded again if it was evicted. `None` if a file source could
+    /// not be read or changed its size, sizes only change through [`TextureManager::update`].
+    pub fn get(
+        &mut self,
+        handle: TextureHandle,
+        gpu_handle: &rendering::GpuHandle,
+    ) -> Option<sync::Arc<sprite::GpuTexture>> {
+        let frame = self.frame;
+        let entry = &mut self.entries[handle.0];
+        entry.last_used = frame;
+        if let Some(texture) = &entry.texture {
+            return Some(texture.clone());
+        }
+        let image = match &entry.source {
+            TextureSource::Image(image) => image.clone(),
+            TextureSource::File(path) => match image::open(path) {
+                Ok(image) if image.width() == entry.size.0 && image.height() == entry.size.1 => {
+                    sync::Arc::new(image.into_rgba8())
+                }
+                Ok(image) => {
+                    log::error!(
+                        "Could not upload {} again, it is {}x{} now instead of {}x{}",
+                        path.display(),
+                        image.width(),
+                        image.height(),
+                        entry.size.0,
+                        entry.size.1
+                    );
+                    return None;
+                }
+                Err(error) => {
+                    log::error!("Could not upload {} again: {error}", path.display());
+                    return None;
+                }
+            },
+            TextureSource::Compressed(image) => {
+                let texture = sync::Arc::new(sprite::GpuTexture::from_compressed(
+                    &entry.label,
+                    image,
+                    entry.options.sampler,
+                    gpu_handle.clone(),
+                ));
+                entry.texture = Some(texture.clone());
+                self.uploads += 1;
+                return Some(texture);
+            }
+        };
+        let texture = sync::Arc::new(sprite::GpuTexture::from_image_with(
+            &entry.label,
+            &image,
+            entry.options,
+            gpu_handle.clone(),
+        ));
+        entry.texture = Some(texture.clone());
+        self.uploads += 1;
+        Some(texture)
+    }
+    /// Replaces the contents of a texture of the same size, in place if it is resident and
+    /// `source` has the kind of the old one.
+    ///
+    /// A PNG is still read from its file after an eviction, unless it is replaced by compressed
+    /// blocks.
+    pub fn update(
+        &mut self,
+        handle: TextureHandle,
+        source: TextureSource,
+        gpu_handle: &rendering::GpuHandle,
+    ) -> anyhow::Result<()> {
+        let entry = &mut self.entries[handle.0];
+        let size = source.size()?;
+        if entry.size != size {
+            anyhow::bail!(
+                "{} is {}x{}, not {}x{}",
+                entry.label,
+                entry.size.0,
+                entry.size.1,
+                size.0,
+                size.1
+            );
+        }
+        if let Some(texture) = &entry.texture {
+            match (&source, &entry.source) {
+                (TextureSource::Image(image), TextureSource::Image(_) | TextureSource::File(_)) => {
+                    texture.write_image(image, gpu_handle)
+                }
+                (TextureSource::Compressed(image), TextureSource::Compressed(old))
+                    if image.format == old.format =>
+                {
+                    texture.write_compressed(image, gpu_handle)
+                }
+                // Uploaded again when it is next used.
+                _ => entry.texture = None,
+            }
+        }
+        match (&entry.source, source) {
+            (TextureSource::File(_), TextureSource::Image(_)) => {}
+            (_, source) => entry.source = source,
+        }
+        Ok(())
+    }
+    /// Starts the next frame after evicting what the budget has no room for.
+    pub fn end_frame(&mut self) {
+        let mut resident_bytes = self.resident_bytes();
+        while resident_bytes > self.budget {
+            let Some(entry) = self
+                .entries
+                .iter_mut()
+                .filter(|entry| entry.texture.is_some() && entry.last_used < self.frame)
+                .min_by_key(|entry| entry.last_used)
+            else {
+                break;
+            };
+            if let Some(texture) = entry.texture.take() {
+                resident_bytes -= texture.memory_size();
+                log::debug!("Evicted {}", entry.label);
+                self.evictions += 1;
+            }
+        }
+        self.frame += 1;
+    }
+    pub fn stats(&self) -> TextureStats {
+        TextureStats {
+            budget: self.budget,
+            resident_bytes: self.resident_bytes(),
+            textures: self.entries.len(),
+            resident: self
+                .entries
+                .iter()
+                .filter(|entry| entry.texture.is_some())
+                .count(),
+            compressed: self
+                .entries
+                .iter()
+                .filter(|entry| matches!(entry.source, TextureSource::Compressed(_)))
+                .count(),
+            uploads: self.uploads,
+            evictions: self.evictions,
+        }
+    }
+    fn resident_bytes(&self) -> u64 {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.texture.as_ref())
+            .map(|texture| texture.memory_size())
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Bytes of one [`square`] texture, normal maps have no mips.
+    const SQUARE_BYTES: u64 = 16 * 16 * 4;
+
+    fn square(shade: u8) -> TextureSource {
+        TextureSource::Image(sync::Arc::new(image::RgbaImage::from_pixel(
+            16,
+            16,
+            image::Rgba([shade, shade, shade, 255]),
+        )))
+    }
+
+    fn insert(textures: &mut TextureManager, source: TextureSource) -> TextureHandle {
+        textures
+            .insert("test", source, sprite::TextureOptions::NORMALS)
+            .unwrap()
+    }
+
+    fn headless() -> rendering::GpuHandle<'static> {
+        rendering::Gpu::headless(16, 16).expect("no headless GPU adapter")
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with --ignored"]
+    fn evicts_the_least_recently_used_texture_over_the_budget() {
+        let gpu_handle = headless();
+        let mut textures = TextureManager::new(2 * SQUARE_BYTES);
+        let handles = [0, 1, 2].map(|shade| insert(&mut textures, square(shade)));
+        for handle in handles {
+            textures.get(handle, &gpu_handle).unwrap();
+            textures.end_frame();
+        }
+        let stats = textures.stats();
+        assert_eq!((stats.resident, stats.evictions), (2, 1));
+        assert_eq!(stats.resident_bytes, 2 * SQUARE_BYTES);
+        assert!(textures.entries[handles[0].0].texture.is_none());
+
+        // Both textures this frame uses stay, so the third goes although it is the newest.
+        textures.get(handles[1], &gpu_handle).unwrap();
+        textures.get(handles[0], &gpu_handle).unwrap();
+        textures.end_frame();
+        assert!(textures.entries[handles[2].0].texture.is_none());
+        assert_eq!(textures.stats().uploads, 4);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with --ignored"]
+    fn keeps_what_the_frame_used_even_over_the_budget() {
+        let gpu_handle = headless();
+        let mut textures = TextureManager::new(SQUARE_BYTES);
+        let handles = [0, 1].map(|shade| insert(&mut textures, square(shade)));
+        for handle in handles {
+            textures.get(handle, &gpu_handle).unwrap();
+        }
+        textures.end_frame();
+        assert_eq!(textures.stats().resident, 2);
+        textures.end_frame();
+        assert_eq!(textures.stats().resident, 1);
+        textures.set_budget(0);
+        textures.end_frame();
+        assert_eq!(textures.stats().resident, 0);
+        assert_eq!(textures.stats().evictions, 2);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with --ignored"]
+    fn updates_keep_the_size_and_stay_resident() {
+        let gpu_handle = headless();
+        let mut textures = TextureManager::default();
+        let handle = insert(&mut textures, square(0));
+        let texture = textures.get(handle, &gpu_handle).unwrap();
+        textures.update(handle, square(255), &gpu_handle).unwrap();
+        assert!(sync::Arc::ptr_eq(
+            &texture,
+            &textures.get(handle, &gpu_handle).unwrap()
+        ));
+        let larger = TextureSource::Image(sync::Arc::new(image::RgbaImage::new(32, 16)));
+        assert!(textures.update(handle, larger, &gpu_handle).is_err());
+        assert_eq!(textures.size(handle), (16, 16));
+        assert_eq!(textures.stats().uploads, 1);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with --ignored"]
+    fn files_that_changed_size_are_not_uploaded_again() {
+        let gpu_handle = headless();
+        let path = std::env::temp_dir().join(format!("textures-{}.png", std::process::id()));
+        image::RgbaImage::new(16, 16).save(&path).unwrap();
+        let mut textures = TextureManager::new(0);
+        let handle = insert(&mut textures, TextureSource::File(path.clone()));
+        textures.get(handle, &gpu_handle).unwrap();
+        textures.end_frame();
+        textures.end_frame();
+        image::RgbaImage::new(32, 16).save(&path).unwrap();
+        let texture = textures.get(handle, &gpu_handle);
+        fs::remove_file(&path).unwrap();
+        assert!(texture.is_none());
+        assert_eq!(textures.size(handle), (16, 16));
+    }
+}
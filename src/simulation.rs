@@ -1,134 +1,282 @@
 use crate::rendering;
+use crate::sprite;
 use crate::user_interface;
-use either::Either;
-use std::fs;
+use crate::world;
 use std::path;
 use std::sync;
-use std::thread;
 use winit::window;
 
+pub mod debugger;
+pub mod game;
+pub mod import;
+pub mod loading;
+pub mod menu;
+pub mod pause;
+pub mod replay;
+pub mod save;
+pub mod scene;
+
 pub struct Simulation<'window> {
     pub gpu_handle: rendering::GpuHandle<'window>,
     pub user_interface: user_interface::UserInterface<'window>,
     sprite_sheet: Vec<sync::Arc<image::RgbaImage>>,
-    state: State,
-    window: sync::Arc<window::Window>,
+    sprites: Vec<sprite::Sprite>,
+    sprite_renderer: sprite::renderer::SpriteRenderer<'window>,
+    light_renderer: rendering::lighting::LightRenderer<'window>,
+    post_processor: rendering::post::PostProcessor<'window>,
+    textures: rendering::textures::TextureManager,
+    scenes: scene::SceneStack,
+    world: world::World,
+    autosave: save::Autosave,
+    recorder: Option<replay::Recorder>,
+    gamepads: world::input::gamepad::Gamepads,
+    /// Gestures the platform recognized since the last update.
+    gestures: Vec<world::input::touch::Gesture>,
+    imports: import::Imports,
+    /// Kept alive for as long as the simulation draws into it.
+    _window: sync::Arc<window::Window>,
 }
 impl<'window> Simulation<'window> {
     pub fn new(
         gpu_handle: rendering::GpuHandle<'window>,
         window: sync::Arc<window::Window>,
     ) -> Self {
-        let state = State::Debug(Debuger {});
         let sprite_sheet = Vec::new();
-        Self {
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos() as u64);
+        log::info!("simulation seed: {seed}");
+        let world = build_world(seed);
+        let gamepads = world::input::gamepad::Gamepads::new();
+        if let Some(mut actions) = world.resource_mut::<world::input::Actions>() {
+            actions.set_bindings(world::input::bindings::Bindings::load());
+        }
+        let compression = gpu_handle.read().unwrap().compression();
+        let mut simulation = Self {
             gpu_handle: gpu_handle.clone(),
             user_interface: user_interface::UserInterface::new(gpu_handle.clone()),
             sprite_sheet,
-            state,
-            window,
-        }
+            sprites: Vec::new(),
+            sprite_renderer: sprite::renderer::SpriteRenderer::new(gpu_handle.clone()),
+            light_renderer: rendering::lighting::LightRenderer::new(gpu_handle.clone()),
+            post_processor: rendering::post::PostProcessor::new(
+                gpu_handle.clone(),
+                rendering::post::PostProcessSettings::load(),
+            ),
+            textures: rendering::textures::TextureManager::default(),
+            scenes: scene::SceneStack::new(),
+            world,
+            autosave: save::Autosave::new(),
+            recorder: None,
+            gamepads,
+            gestures: Vec::new(),
+            imports: import::Imports::new(compression),
+            _window: window,
+        };
+        simulation.push_scene(loading::InitLoading::scene(compression));
+        simulation
     }
-
-    pub fn update(&mut self) {
-        let window = self.window.clone();
-        self.process_user_interface();
-        // self.process_user_interface();
+    /// Records every tick the world runs from now on, see [`replay::verify`].
+    pub fn record_to(&mut self, path: &path::Path) -> anyhow::Result<()> {
+        self.recorder = Some(replay::Recorder::create(path)?);
+        Ok(())
     }
-    fn process_user_interface(&mut self) {
-        match self.state {
-            State::Debug(ref debuger) => self.user_interface.update(debuger.user_interface()),
-            State::InitStartup => {
-                self.state = State::InitLoading(InitLoading::new());
-                return;
-            }
-            State::InitLoading(ref init_loading) => {
-                self.user_interface.update(init_loading.user_interface())
-            }
-            State::InitError(ref error) => todo!(),
+    pub fn gamepads_mut(&mut self) -> &mut world::input::gamepad::Gamepads {
+        &mut self.gamepads
+    }
+    pub fn push_gesture(&mut self, gesture: world::input::touch::Gesture) {
+        self.gestures.push(gesture);
+    }
+    pub fn hover_file(&mut self, path: path::PathBuf) {
+        self.imports.hover(path);
+    }
+    pub fn cancel_hover(&mut self) {
+        self.imports.cancel_hover();
+    }
+    /// Imports a dropped model, image or bake manifest, see [`import::Imports`].
+    pub fn drop_file(&mut self, path: path::PathBuf) {
+        self.imports.drop_file(path);
+    }
+    pub fn exit_requested(&self) -> bool {
+        self.scenes.is_empty()
+    }
+    pub fn push_scene(&mut self, scene: Box<dyn scene::Scene>) {
+        self.apply_transition(scene::Transition::Push(scene));
+    }
+    pub fn pop_scene(&mut self) {
+        self.apply_transition(scene::Transition::Pop);
+    }
+    pub fn replace_scene(&mut self, scene: Box<dyn scene::Scene>) {
+        self.apply_transition(scene::Transition::Replace(scene));
+    }
+    fn apply_transition(&mut self, transition: scene::Transition) {
+        let mut context = scene::SceneContext {
+            gpu_handle: &self.gpu_handle,
+            user_interface: &mut self.user_interface,
+            sprite_sheet: &mut self.sprite_sheet,
+            sprites: &mut self.sprites,
+            sprite_renderer: &mut self.sprite_renderer,
+            light_renderer: &mut self.light_renderer,
+            post_processor: &mut self.post_processor,
+            textures: &mut self.textures,
+            world: &mut self.world,
+            recorder: self.recorder.as_mut(),
         };
+        self.scenes.apply(transition, &mut context);
     }
-}
-
-pub enum State {
-    Debug(Debuger),
-    InitStartup,
-    InitLoading(InitLoading),
-    InitError(anyhow::Error),
-}
-
-pub struct Debuger {}
 
-impl Debuger {
-    fn user_interface(&self) -> impl FnMut(&egui::Context) {
-        |context| {
-            egui::CentralPanel::default().show(context, |user_interface: &mut egui::Ui| {
-                user_interface.add(egui::Label::new("testing"))
-            });
+    pub fn update(&mut self) {
+        let events = self.user_interface.user_interface_input.events.clone();
+        for event in &events {
+            self.scenes.handle_input(event);
+        }
+        let events = world::input::uncaptured(
+            events.into_iter().filter(replay::is_recorded),
+            self.user_interface.wants_keyboard_input(),
+            self.user_interface.wants_pointer_input(),
+        );
+        let input = world::input::InputEvents {
+            events,
+            gamepad: self.gamepads.poll(),
+            gestures: std::mem::take(&mut self.gestures),
+        };
+        let contexts = self
+            .world
+            .resource::<world::input::Actions>()
+            .map(|actions| actions.contexts().to_vec())
+            .unwrap_or_default();
+        world::input::feed(&self.world, input.clone());
+        let start = self
+            .recorder
+            .as_ref()
+            .filter(|recorder| !recorder.is_started())
+            .map(|_| save::SaveGame::capture(&self.world));
+        self.imports.watch(&self.sprites);
+        let imported = self.imports.poll(
+            &self.gpu_handle,
+            &mut self.textures,
+            &mut self.sprite_sheet,
+            &mut self.sprites,
+        );
+        if imported > 0 && self.recorder.take().is_some() {
+            log::warn!("Recording stopped: imported sprites cannot be replayed");
+        }
+        self.sync_sprite_sources();
+        let mut context = scene::SceneContext {
+            gpu_handle: &self.gpu_handle,
+            user_interface: &mut self.user_interface,
+            sprite_sheet: &mut self.sprite_sheet,
+            sprites: &mut self.sprites,
+            sprite_renderer: &mut self.sprite_renderer,
+            light_renderer: &mut self.light_renderer,
+            post_processor: &mut self.post_processor,
+            textures: &mut self.textures,
+            world: &mut self.world,
+            recorder: self.recorder.as_mut(),
+        };
+        self.scenes.update(&mut context);
+        self.scenes.render(&mut context);
+        // Recordings cannot follow a jump to a loaded save, ones not started yet start after it.
+        let restored = self.world.remove_resource::<save::Restored>().is_some();
+        if restored {
+            self.autosave.restored(&self.world);
+        }
+        if restored
+            && self
+                .recorder
+                .as_ref()
+                .is_some_and(replay::Recorder::is_started)
+        {
+            log::warn!("Recording stopped: a save was loaded");
+            self.recorder = None;
+        }
+        if let Some(recorder) = &mut self.recorder
+            && !restored
+            && let Err(error) = recorder.record(start.as_ref(), &input, &contexts, &self.world)
+        {
+            log::error!("Recording stopped: {error:#}");
+            self.recorder = None;
+        }
+        if self.scenes.names().any(|name| name == "game") {
+            self.autosave.update(&self.world);
         }
+        let rumbles = self
+            .world
+            .resource_mut::<world::input::gamepad::Rumbles>()
+            .map(|mut rumbles| std::mem::take(&mut rumbles.requests))
+            .unwrap_or_default();
+        for rumble in rumbles {
+            self.gamepads.rumble(rumble);
+        }
+        // Only egui sees these, gameplay already got the gamepad through the actions.
+        let in_menu = self
+            .world
+            .resource::<world::input::Actions>()
+            .is_some_and(|actions| actions.context() == Some(world::input::MENU));
+        if in_menu {
+            let navigation = self
+                .gamepads
+                .navigation(&input.gamepad, self.user_interface.has_focus());
+            self.user_interface
+                .user_interface_input
+                .events
+                .extend(navigation);
+        }
+        self.process_user_interface();
+        self.textures.end_frame();
     }
-}
-pub struct InitLoading {
-    loading_thread: thread::JoinHandle<anyhow::Result<Vec<sync::Arc<image::RgbaImage>>>>,
-    progress: sync::Arc<sync::atomic::AtomicU32>,
-    total_work: u32,
-}
-
-impl InitLoading {
-    pub fn new() -> Self {
-        let mut source = std::env::current_exe().unwrap();
-        source.pop();
-
-        let models_to_load = fs::read_dir(source)
-            .unwrap()
-            .map(|entry| match entry {
-                Ok(entry) => entry.path(),
-                Err(error) => todo!(),
-            })
-            .filter(|entry| {
-                entry
-                    .extension()
-                    .is_some_and(|extension| extension == "fbx")
+    /// Keeps [`world::components::SpriteSources`] listing the sprites loaded right now.
+    fn sync_sprite_sources(&mut self) {
+        let sources = self
+            .sprites
+            .iter()
+            .map(|sprite| {
+                sprite
+                    .source()
+                    .map(path::Path::to_path_buf)
+                    .unwrap_or_default()
             })
             .collect::<Vec<_>>();
-
-        let progress = sync::Arc::new(sync::atomic::AtomicU32::new(0));
-        let progress_clone = sync::Arc::clone(&progress);
-        let total_work = models_to_load.len() as u32;
-
-        let loading_thread =
-            thread::spawn(move || Self::generate_sprite_sheet(progress_clone, models_to_load));
-
-        Self {
-            loading_thread,
-            progress,
-            total_work,
-        }
-    }
-    fn generate_sprite_sheet(
-        progress: sync::Arc<sync::atomic::AtomicU32>,
-        model_paths: impl IntoIterator<Item = impl AsRef<path::Path>>,
-    ) -> anyhow::Result<Vec<sync::Arc<image::RgbaImage>>> {
-        let sprite_sheet = Vec::new();
-        let importer = asset_importer::Importer::new();
-
-        for model_path in model_paths {
-            let model = importer.import_file(model_path)?;
-
-            todo!();
-            progress.fetch_add(1, sync::atomic::Ordering::AcqRel);
+        let current = self.world.resource::<world::components::SpriteSources>();
+        if current.is_none_or(|current| current.0 != sources) {
+            self.world
+                .insert_resource(world::components::SpriteSources(sources));
         }
-
-        Ok(sprite_sheet)
     }
-    fn user_interface(&self) -> impl FnMut(&egui::Context) {
-        |context| {
-            egui::CentralPanel::default().show(context, |user_interface: &mut egui::Ui| {
-                user_interface.add(egui::ProgressBar::new(
-                    self.progress.load(sync::atomic::Ordering::Acquire) as f32
-                        / self.total_work as f32,
-                ))
-            });
-        }
+    fn process_user_interface(&mut self) {
+        let scenes = &mut self.scenes;
+        let imports = &mut self.imports;
+        self.user_interface.update(&mut self.textures, |context| {
+            scenes.user_interface(context);
+            imports.user_interface(context);
+            rendering::shader::errors_user_interface(context);
+        });
     }
 }
+
+/// The world with every resource and system the simulation runs, shared with headless replays.
+pub fn build_world(seed: u64) -> world::World {
+    let mut world = world::World::new();
+    world.insert_resource(rendering::camera::Camera::default());
+    world.insert_resource(world::spatial::SpatialIndex::new(
+        world::spatial::Aabb::from_center(glam::Vec2::ZERO, glam::Vec2::splat(4096.0)),
+    ));
+    world.insert_resource(world::random::Random::new(seed));
+    world.insert_resource(world::input::InputEvents::default());
+    world.insert_resource(world::input::Actions::new(
+        world::input::bindings::Bindings::defaults(),
+    ));
+    world.insert_resource(world::input::gamepad::Rumbles::default());
+    world.insert_resource(world::physics::PhysicsSettings::default());
+    world.insert_resource(world::physics::CollisionEvents::default());
+    world.insert_resource(world::behaviour::Library::default());
+    world.insert_resource(world::pathfinding::service::PathService::new(
+        world::pathfinding::DiagonalRule::default(),
+    ));
+    world.add_system(world::tilemap::rebuild_system);
+    world.add_system(world::spatial::update_system);
+    world.add_system(world::behaviour::tick_system);
+    world.add_system(world::pathfinding::update_system);
+    world.add_system(world::physics::step_system);
+    world
+}
@@ -0,0 +1,199 @@
+use crate::rendering::graph;
+use crate::rendering::textures;
+use crate::simulation::scene;
+use crate::world;
+use crate::world::behaviour;
+
+pub struct Debugger {
+    sprites: usize,
+    textures: textures::TextureStats,
+    /// Budget in MiB picked in the texture panel, applied on the next update.
+    texture_budget: Option<u64>,
+    render_graph: graph::RenderGraphStats,
+    behaviours: Vec<world::Entity>,
+    selected: Option<world::Entity>,
+    tree: Vec<behaviour::NodeView>,
+    blackboard: Vec<(String, String)>,
+    close: bool,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            sprites: 0,
+            textures: textures::TextureStats::default(),
+            texture_budget: None,
+            render_graph: graph::RenderGraphStats::default(),
+            behaviours: Vec::new(),
+            selected: None,
+            tree: Vec::new(),
+            blackboard: Vec::new(),
+            close: false,
+        }
+    }
+    /// Copies the state of the selected entity's behaviour tree for the next frame.
+    fn inspect_behaviours(&mut self, world: &world::World) {
+        self.behaviours = world
+            .storage::<behaviour::Behaviour>()
+            .map(|storage| storage.entities().to_vec())
+            .unwrap_or_default();
+        if !self
+            .selected
+            .is_some_and(|selected| self.behaviours.contains(&selected))
+        {
+            self.selected = self.behaviours.first().copied();
+        }
+        let behaviour = self
+            .selected
+            .and_then(|selected| world.get::<behaviour::Behaviour>(selected));
+        (self.tree, self.blackboard) = match behaviour {
+            Some(behaviour) => (
+                behaviour.describe(),
+                behaviour
+                    .blackboard
+                    .iter()
+                    .map(|(key, value)| (key.to_owned(), format!("{value:?}")))
+                    .collect(),
+            ),
+            None => Default::default(),
+        };
+    }
+    fn texture_user_interface(&mut self, user_interface: &mut egui::Ui) {
+        const MIB: u64 = 1024 * 1024;
+        let stats = &self.textures;
+        user_interface.label(format!(
+            "resident: {} of {} textures, {:.1} MiB",
+            stats.resident,
+            stats.textures,
+            stats.resident_bytes as f64 / MIB as f64
+        ));
+        user_interface.label(format!("block compressed: {}", stats.compressed));
+        user_interface.label(format!(
+            "uploads: {}, evictions: {}",
+            stats.uploads, stats.evictions
+        ));
+        let mut budget = self.texture_budget.unwrap_or(stats.budget / MIB);
+        let slider = egui::Slider::new(&mut budget, 1..=4096)
+            .logarithmic(true)
+            .text("budget (MiB)");
+        if user_interface.add(slider).changed() {
+            self.texture_budget = Some(budget);
+        }
+        if stats.resident_bytes > stats.budget {
+            user_interface.colored_label(
+                egui::Color32::YELLOW,
+                "over budget, every resident texture was drawn this frame",
+            );
+        }
+    }
+    fn behaviour_user_interface(&mut self, user_interface: &mut egui::Ui) {
+        let name = |entity: world::Entity| format!("{}v{}", entity.index(), entity.generation());
+        egui::ComboBox::from_label("entity")
+            .selected_text(self.selected.map(name).unwrap_or_default())
+            .show_ui(user_interface, |user_interface| {
+                for entity in &self.behaviours {
+                    user_interface.selectable_value(
+                        &mut self.selected,
+                        Some(*entity),
+                        name(*entity),
+                    );
+                }
+            });
+        for node in &self.tree {
+            let text = egui::RichText::new(format!(
+                "{}{} ({})",
+                "    ".repeat(node.depth),
+                node.name,
+                node.kind
+            ))
+            .monospace();
+            let text = match node.status {
+                Some(behaviour::Status::Running) => text
+                    .color(egui::Color32::BLACK)
+                    .background_color(egui::Color32::YELLOW),
+                Some(behaviour::Status::Success) => text.color(egui::Color32::LIGHT_GREEN),
+                Some(behaviour::Status::Failure) => text.color(egui::Color32::LIGHT_RED),
+                None => text.weak(),
+            };
+            user_interface.label(text);
+        }
+        if !self.blackboard.is_empty() {
+            user_interface.separator();
+            egui::Grid::new("blackboard").show(user_interface, |user_interface| {
+                for (key, value) in &self.blackboard {
+                    user_interface.label(key);
+                    user_interface.label(value);
+                    user_interface.end_row();
+                }
+            });
+        }
+    }
+}
+
+impl scene::Scene for Debugger {
+    fn name(&self) -> &'static str {
+        "debugger"
+    }
+    fn is_overlay(&self) -> bool {
+        true
+    }
+    fn update(&mut self, context: &mut scene::SceneContext) -> scene::Transition {
+        self.sprites = context.sprites.len();
+        if let Some(budget) = self.texture_budget.take() {
+            context.textures.set_budget(budget * 1024 * 1024);
+        }
+        self.textures = context.textures.stats();
+        self.render_graph = context
+            .gpu_handle
+            .read()
+            .unwrap()
+            .render_graph_stats()
+            .clone();
+        self.inspect_behaviours(context.world);
+        if self.close {
+            return scene::Transition::Pop;
+        }
+        scene::Transition::None
+    }
+    fn user_interface(&mut self, context: &egui::Context) {
+        egui::Window::new("debugger").show(context, |user_interface: &mut egui::Ui| {
+            user_interface.label(format!("sprites: {}", self.sprites));
+            user_interface.collapsing("textures", |user_interface| {
+                self.texture_user_interface(user_interface)
+            });
+            user_interface.collapsing("render graph", |user_interface| {
+                let stats = &self.render_graph;
+                user_interface.label(format!(
+                    "targets: {} in {} textures",
+                    stats.targets, stats.textures
+                ));
+                for (index, pass) in stats.passes.iter().enumerate() {
+                    user_interface.monospace(format!("{index}: {pass}"));
+                }
+            });
+            if !self.behaviours.is_empty() {
+                user_interface.collapsing("behaviours", |user_interface| {
+                    self.behaviour_user_interface(user_interface)
+                });
+            }
+        });
+    }
+    fn handle_input(&mut self, event: &egui::Event) -> bool {
+        if let egui::Event::Key {
+            key: egui::Key::F3,
+            pressed: true,
+            ..
+        } = event
+        {
+            self.close = true;
+            return true;
+        }
+        false
+    }
+}
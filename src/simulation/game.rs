@@ -0,0 +1,158 @@
+use crate::rendering;
+use crate::rendering::camera;
+use crate::simulation::debugger;
+use crate::simulation::pause;
+use crate::simulation::scene;
+use crate::sprite;
+use crate::world::clock;
+use crate::world::components;
+use crate::world::input;
+use crate::world::input::touch;
+
+const MIN_ZOOM: f32 = 0.1;
+const MAX_ZOOM: f32 = 10.0;
+
+pub struct Game {
+    /// Whole sheets, scaled to the height of a baked frame.
+    previews: Vec<(egui::TextureId, egui::Vec2)>,
+    clock: clock::FixedClock,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Game {
+    pub fn new() -> Self {
+        Self {
+            previews: Vec::new(),
+            clock: clock::FixedClock::new(),
+        }
+    }
+    /// Previews every sprite that has none yet and returns the index of the first new one.
+    fn add_previews(&mut self, context: &mut scene::SceneContext) -> usize {
+        let first = self.previews.len();
+        for sprite in &context.sprites[first..] {
+            let texture = context.user_interface.register_texture(sprite.texture());
+            let (width, height) = context.textures.size(sprite.texture());
+            let scale = sprite::bake::FRAME_SIZE as f32 / height.max(1) as f32;
+            self.previews
+                .push((texture, egui::vec2(width as f32, height as f32) * scale));
+        }
+        first
+    }
+}
+
+impl scene::Scene for Game {
+    fn name(&self) -> &'static str {
+        "game"
+    }
+    fn enter(&mut self, context: &mut scene::SceneContext) {
+        self.clock.pause();
+        self.previews.clear();
+        self.add_previews(context);
+        if let Some(mut actions) = context.world.resource_mut::<input::Actions>() {
+            actions.push_context(input::GAMEPLAY);
+        }
+        if context.world.is_empty() {
+            for sprite in 0..context.sprites.len() {
+                spawn_sprite(context, sprite);
+            }
+        }
+    }
+    fn exit(&mut self, context: &mut scene::SceneContext) {
+        if let Some(mut actions) = context.world.resource_mut::<input::Actions>() {
+            actions.pop_context(input::GAMEPLAY);
+        }
+    }
+    fn update(&mut self, context: &mut scene::SceneContext) -> scene::Transition {
+        // Sprites imported while playing are spawned next to the others.
+        if self.previews.len() < context.sprites.len() {
+            let first = self.add_previews(context);
+            for sprite in first..context.sprites.len() {
+                spawn_sprite(context, sprite);
+            }
+        }
+        for _ in 0..self.clock.ticks() {
+            context.tick();
+        }
+        let Some(actions) = context.world.resource::<input::Actions>() else {
+            return scene::Transition::None;
+        };
+        if let Some(mut camera) = context.world.resource_mut::<camera::Camera>() {
+            let gpu = context.gpu_handle.read().unwrap();
+            let viewport = glam::vec2(
+                gpu.surface_config().width as f32,
+                gpu.surface_config().height as f32,
+            );
+            drop(gpu);
+            apply_gestures(&mut camera, actions.gestures(), viewport);
+        }
+        // The world stands still while another scene is on top.
+        if actions.just_pressed("pause") {
+            self.clock.pause();
+            scene::Transition::Push(Box::new(pause::Pause::new()))
+        } else if actions.just_pressed("debugger") {
+            self.clock.pause();
+            scene::Transition::Push(Box::new(debugger::Debugger::new()))
+        } else {
+            scene::Transition::None
+        }
+    }
+    fn render(&mut self, context: &mut scene::SceneContext) {
+        let scene =
+            context
+                .sprite_renderer
+                .render(context.world, context.sprites, context.textures);
+        let lit = context.post_processor.input_target();
+        context.light_renderer.render(context.world, &scene, lit);
+        context.post_processor.render(lit, rendering::graph::OUTPUT);
+    }
+    fn user_interface(&mut self, context: &egui::Context) {
+        egui::Window::new("sprites").default_open(false).show(
+            context,
+            |user_interface: &mut egui::Ui| {
+                for (texture, size) in &self.previews {
+                    user_interface
+                        .add(egui::Image::new((*texture, *size)).fit_to_original_size(1.0));
+                }
+            },
+        );
+    }
+}
+
+/// Places `sprite` in the row of sprites, one and a half frames after the previous one.
+fn spawn_sprite(context: &mut scene::SceneContext, sprite: usize) {
+    let size = glam::Vec2::splat(sprite::bake::FRAME_SIZE as f32);
+    let entity = context.world.spawn();
+    let position = glam::vec2(sprite as f32 * size.x * 1.5, 0.0);
+    context.world.insert(entity, components::Position(position));
+    context.world.insert(entity, components::Bounds::new(size));
+    context.world.insert(
+        entity,
+        components::SpriteInstance {
+            sprite,
+            frame: 0,
+            size,
+        },
+    );
+}
+
+/// Dragging pans the camera and pinching zooms around the pinch.
+fn apply_gestures(camera: &mut camera::Camera, gestures: &[touch::Gesture], viewport: glam::Vec2) {
+    for gesture in gestures {
+        match gesture {
+            touch::Gesture::Drag { delta, .. } => {
+                camera.position += glam::vec2(-delta.x, delta.y) / camera.zoom;
+            }
+            touch::Gesture::Pinch { center, scale } => {
+                let anchor = camera.screen_to_world(*center, viewport);
+                camera.zoom = (camera.zoom * scale).clamp(MIN_ZOOM, MAX_ZOOM);
+                camera.position += anchor - camera.screen_to_world(*center, viewport);
+            }
+            _ => (),
+        }
+    }
+}
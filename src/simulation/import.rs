@@ -0,0 +1,358 @@
+use crate::rendering;
+use crate::sprite;
+use std::collections;
+use std::fs;
+use std::path;
+use std::sync;
+use std::thread;
+use std::time;
+
+/// Extension of bake manifests, text files naming one model per line relative to the manifest.
+/// Blank lines and lines starting with `#` are skipped.
+pub const MANIFEST_EXTENSION: &str = "bake";
+/// How often the sources of the sprites are looked at for changes.
+const POLL_INTERVAL: time::Duration = time::Duration::from_millis(500);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImportKind {
+    /// Baked into a strip of [`sprite::bake::ANGLES`] frames.
+    Model,
+    /// Every model or image the manifest names, each imported like a dropped file.
+    Manifest,
+    /// Cut into frames by [`sprite::sheet::load`].
+    Image,
+}
+
+impl ImportKind {
+    pub fn of(path: &path::Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "fbx" => Some(Self::Model),
+            "png" => Some(Self::Image),
+            MANIFEST_EXTENSION => Some(Self::Manifest),
+            _ => None,
+        }
+    }
+}
+
+struct Import {
+    path: path::PathBuf,
+    /// The model or image behind each sheet the thread returns.
+    sources: Vec<path::PathBuf>,
+    /// Sprite whose texture the result replaces, new sprites are added if `None`.
+    target: Option<usize>,
+    thread: thread::JoinHandle<anyhow::Result<Vec<sprite::sheet::SpriteSheet>>>,
+    progress: sync::Arc<sprite::bake::BakeProgress>,
+    cancel: sprite::bake::CancelToken,
+}
+
+impl Import {
+    fn start(
+        path: path::PathBuf,
+        kind: ImportKind,
+        target: Option<usize>,
+        compression: Option<rendering::compression::CompressedFormat>,
+    ) -> anyhow::Result<Self> {
+        let sources = match kind {
+            ImportKind::Model | ImportKind::Image => vec![path.clone()],
+            ImportKind::Manifest => read_manifest(&path)?,
+        };
+        let progress = sync::Arc::new(sprite::bake::BakeProgress::default());
+        let cancel = sprite::bake::CancelToken::default();
+        let thread = sprite::sheet::spawn(
+            sources.clone(),
+            compression,
+            progress.clone(),
+            cancel.clone(),
+        );
+        Ok(Self {
+            path,
+            sources,
+            target,
+            thread,
+            progress,
+            cancel,
+        })
+    }
+}
+
+/// Files dropped onto the window, baked in the background through the same pipeline as
+/// [`super::loading::InitLoading`] and added to the sprites once they are done.
+///
+/// Sprites that know their [`sprite::Sprite::source`] are rebuilt the same way when the source
+/// changes on disk, and their textures are overwritten in place.
+pub struct Imports {
+    hovered: Vec<path::PathBuf>,
+    running: Vec<Import>,
+    failed: Vec<(path::PathBuf, anyhow::Error)>,
+    /// Last seen modification time of the source of each sprite, by sprite index.
+    modified: collections::BTreeMap<usize, (path::PathBuf, time::SystemTime)>,
+    checked: Option<time::Instant>,
+    /// What imported sheets are compressed to, like those of the initial load.
+    compression: Option<rendering::compression::CompressedFormat>,
+}
+
+impl Imports {
+    pub fn new(compression: Option<rendering::compression::CompressedFormat>) -> Self {
+        Self {
+            hovered: Vec::new(),
+            running: Vec::new(),
+            failed: Vec::new(),
+            modified: collections::BTreeMap::new(),
+            checked: None,
+            compression,
+        }
+    }
+    pub fn hover(&mut self, path: path::PathBuf) {
+        if !self.hovered.contains(&path) {
+            self.hovered.push(path);
+        }
+    }
+    pub fn cancel_hover(&mut self) {
+        self.hovered.clear();
+    }
+    /// Starts importing `path`, unsupported files are reported in the import window.
+    pub fn drop_file(&mut self, path: path::PathBuf) {
+        self.hovered.retain(|hovered| *hovered != path);
+        let Some(kind) = ImportKind::of(&path) else {
+            let error =
+                anyhow::anyhow!("only .fbx, .png and .{MANIFEST_EXTENSION} can be imported");
+            self.failed.push((path, error));
+            return;
+        };
+        log::info!("Importing {}", path.display());
+        match Import::start(path.clone(), kind, None, self.compression) {
+            Ok(import) => self.running.push(import),
+            Err(error) => self.failed.push((path, error)),
+        }
+    }
+    pub fn is_hovering(&self) -> bool {
+        !self.hovered.is_empty()
+    }
+    /// Starts rebuilding every sprite whose source changed since it was last looked at.
+    pub fn watch(&mut self, sprites: &[sprite::Sprite]) {
+        if self
+            .checked
+            .is_some_and(|checked| checked.elapsed() < POLL_INTERVAL)
+        {
+            return;
+        }
+        self.checked = Some(time::Instant::now());
+        self.modified.retain(|index, _| *index < sprites.len());
+        for (index, sprite) in sprites.iter().enumerate() {
+            let Some(source) = sprite.source() else {
+                continue;
+            };
+            // Metadata of image sheets counts as part of the source.
+            let Some(modified) = std::iter::once(source.to_path_buf())
+                .chain(sprite::sheet::metadata_path(source))
+                .filter_map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+                .max()
+            else {
+                continue;
+            };
+            let previous = self
+                .modified
+                .insert(index, (source.to_path_buf(), modified));
+            let changed =
+                previous.is_some_and(|(previous, time)| previous == source && time != modified);
+            let reloading = self
+                .running
+                .iter()
+                .any(|import| import.target == Some(index));
+            let Some(kind) = ImportKind::of(source) else {
+                continue;
+            };
+            if !changed || reloading {
+                continue;
+            }
+            log::info!("Reloading {}", source.display());
+            match Import::start(source.to_path_buf(), kind, Some(index), self.compression) {
+                Ok(import) => self.running.push(import),
+                Err(error) => self.failed.push((source.to_path_buf(), error)),
+            }
+        }
+    }
+    /// Registers the sheets of every finished import and returns how many sprites were added.
+    ///
+    /// A manifest whose sheets could not all be loaded adds nothing. Sheets that fail to
+    /// register are reported by source and the rest of the manifest is kept.
+    ///
+    /// Reloaded sheets replace the contents of the existing texture, so every holder of it sees
+    /// the change. A reload that changed the size of the sheet is reported instead, the file
+    /// has to be dropped again to import it as a new sprite.
+    pub fn poll(
+        &mut self,
+        gpu_handle: &rendering::GpuHandle,
+        textures: &mut rendering::textures::TextureManager,
+        sprite_sheet: &mut Vec<sync::Arc<image::RgbaImage>>,
+        sprites: &mut Vec<sprite::Sprite>,
+    ) -> usize {
+        let before = sprites.len();
+        let (finished, running) = std::mem::take(&mut self.running)
+            .into_iter()
+            .partition::<Vec<_>, _>(|import| import.thread.is_finished());
+        self.running = running;
+        for import in finished {
+            let result = import
+                .thread
+                .join()
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("import thread panicked")));
+            let sheets = match result {
+                Ok(sheets) => sheets,
+                Err(error) => {
+                    log::error!("Importing {} failed: {error:#}", import.path.display());
+                    self.failed.push((import.path, error));
+                    continue;
+                }
+            };
+            if let Some(target) = import.target {
+                let (Some(sheet), Some(sprite)) =
+                    (sheets.into_iter().next(), sprites.get_mut(target))
+                else {
+                    continue;
+                };
+                let source = sheet.texture_source(&import.path);
+                if let Err(error) = textures.update(sprite.texture(), source, gpu_handle) {
+                    let error = error.context("the sheet changed size, drop it again to import it");
+                    self.failed.push((import.path, error));
+                    continue;
+                }
+                if let (Some(handle), Some(normals)) = (sprite.normals(), &sheet.normals) {
+                    let normals = rendering::textures::TextureSource::Image(normals.clone());
+                    if let Err(error) = textures.update(handle, normals, gpu_handle) {
+                        log::error!(
+                            "Could not reload the normals of {}: {error}",
+                            import.path.display()
+                        );
+                    }
+                }
+                sprite.set_frames(sheet.frames);
+                if let Some(old) = sprite_sheet.get_mut(target) {
+                    *old = sheet.image;
+                }
+                log::info!("Reloaded {}", import.path.display());
+                continue;
+            }
+            // Each source of a manifest stands on its own, one that fails is reported by name and
+            // the others are still added.
+            let total = import.sources.len();
+            let mut imported = 0;
+            for (sheet, source) in sheets.into_iter().zip(import.sources) {
+                let label = format!("sprite sheet {}", sprites.len());
+                match sheet.register(&label, source.clone(), textures) {
+                    Ok(sprite) => {
+                        sprites.push(sprite);
+                        sprite_sheet.push(sheet.image);
+                        imported += 1;
+                    }
+                    Err(error) if source == import.path => self.failed.push((source, error)),
+                    Err(error) => {
+                        let error = error.context(format!("listed in {}", import.path.display()));
+                        self.failed.push((source, error));
+                    }
+                }
+            }
+            if imported == total {
+                log::info!("Imported {}", import.path.display());
+            } else {
+                log::error!(
+                    "Imported {imported} of {total} sources of {}",
+                    import.path.display()
+                );
+            }
+        }
+        sprites.len() - before
+    }
+    /// The drop overlay while files hover the window, and the progress of running imports.
+    pub fn user_interface(&mut self, context: &egui::Context) {
+        if self.is_hovering() {
+            let screen = context.screen_rect();
+            egui::Area::new(egui::Id::new("import hover"))
+                .order(egui::Order::Foreground)
+                .fixed_pos(screen.min)
+                .interactable(false)
+                .show(context, |user_interface| {
+                    user_interface.painter().rect_filled(
+                        screen,
+                        0.0,
+                        egui::Color32::from_black_alpha(160),
+                    );
+                    user_interface.scope_builder(
+                        egui::UiBuilder::new().max_rect(screen.shrink(32.0)),
+                        |user_interface| {
+                            user_interface.vertical_centered(|user_interface| {
+                                user_interface.heading("Drop to import");
+                                for path in &self.hovered {
+                                    let name = path.file_name().unwrap_or(path.as_os_str());
+                                    let label = match ImportKind::of(path) {
+                                        Some(_) => name.to_string_lossy().into_owned(),
+                                        None => format!("{} (unsupported)", name.to_string_lossy()),
+                                    };
+                                    user_interface.label(label);
+                                }
+                            });
+                        },
+                    );
+                });
+        }
+        if self.running.is_empty() && self.failed.is_empty() {
+            return;
+        }
+        egui::Window::new("imports").show(context, |user_interface: &mut egui::Ui| {
+            self.running.retain(|import| {
+                let mut keep = true;
+                user_interface.horizontal(|user_interface| {
+                    let verb = match import.target {
+                        Some(_) => "reloading",
+                        None => "importing",
+                    };
+                    user_interface.label(format!("{verb} {}", import.path.display()));
+                    user_interface.add(
+                        egui::ProgressBar::new(import.progress.fraction())
+                            .desired_width(120.0)
+                            .show_percentage(),
+                    );
+                    if user_interface.button("Cancel").clicked() {
+                        import.cancel.cancel();
+                        keep = false;
+                    }
+                });
+                keep
+            });
+            for (path, error) in &self.failed {
+                user_interface.colored_label(
+                    egui::Color32::LIGHT_RED,
+                    format!("{}: {error:#}", path.display()),
+                );
+            }
+            if !self.failed.is_empty() && user_interface.button("Dismiss").clicked() {
+                self.failed.clear();
+            }
+        });
+    }
+}
+
+impl Drop for Imports {
+    fn drop(&mut self) {
+        for import in &self.running {
+            import.cancel.cancel();
+        }
+    }
+}
+
+/// Models of a bake manifest, see [`MANIFEST_EXTENSION`].
+pub fn read_manifest(path: &path::Path) -> anyhow::Result<Vec<path::PathBuf>> {
+    let text = fs::read_to_string(path)?;
+    let directory = path.parent().unwrap_or(path::Path::new(""));
+    let models = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| directory.join(line))
+        .collect::<Vec<_>>();
+    if models.is_empty() {
+        anyhow::bail!("{} names no models", path.display());
+    }
+    Ok(models)
+}
@@ -0,0 +1,212 @@
+use crate::rendering::compression;
+use crate::simulation::menu;
+use crate::simulation::scene;
+use crate::sprite;
+use anyhow::Context;
+use std::fs;
+use std::path;
+use std::sync;
+use std::thread;
+
+pub struct InitLoading {
+    loading_thread: Option<thread::JoinHandle<anyhow::Result<Vec<sprite::sheet::SpriteSheet>>>>,
+    progress: sync::Arc<sprite::bake::BakeProgress>,
+    cancel: sprite::bake::CancelToken,
+    models: Vec<path::PathBuf>,
+    compression: Option<compression::CompressedFormat>,
+}
+
+impl InitLoading {
+    /// Loads every model and image next to the executable, compressing them to `compression`.
+    pub fn new(compression: Option<compression::CompressedFormat>) -> anyhow::Result<Self> {
+        let mut source = std::env::current_exe().context("failed to locate the executable")?;
+        source.pop();
+
+        let mut models_to_load = fs::read_dir(&source)
+            .with_context(|| format!("failed to read {}", source.display()))?
+            .filter_map(|entry| match entry {
+                Ok(entry) => Some(entry.path()),
+                Err(error) => {
+                    log::warn!("Skipping unreadable entry in {}: {error}", source.display());
+                    None
+                }
+            })
+            .filter(|entry| {
+                entry
+                    .extension()
+                    .is_some_and(|extension| extension == "fbx")
+                    || sprite::sheet::is_image(entry)
+            })
+            .collect::<Vec<_>>();
+        // Sprites are numbered in load order, sorting keeps the numbers the same between runs.
+        models_to_load.sort();
+
+        let progress = sync::Arc::new(sprite::bake::BakeProgress::default());
+        let cancel = sprite::bake::CancelToken::default();
+        let loading_thread = sprite::sheet::spawn(
+            models_to_load.clone(),
+            compression,
+            progress.clone(),
+            cancel.clone(),
+        );
+
+        Ok(Self {
+            loading_thread: Some(loading_thread),
+            progress,
+            cancel,
+            models: models_to_load,
+            compression,
+        })
+    }
+    /// The loading scene, or the error scene offering a retry when loading could not start.
+    pub fn scene(compression: Option<compression::CompressedFormat>) -> Box<dyn scene::Scene> {
+        match Self::new(compression) {
+            Ok(loading) => Box::new(loading),
+            Err(error) => Box::new(InitError::new(error, compression)),
+        }
+    }
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+    /// Returns the baked sheets once the loading thread has finished, without blocking.
+    pub fn poll(&mut self) -> Option<anyhow::Result<Vec<sprite::sheet::SpriteSheet>>> {
+        if !self.loading_thread.as_ref()?.is_finished() {
+            return None;
+        }
+        let loading_thread = self.loading_thread.take()?;
+        Some(
+            loading_thread
+                .join()
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("sprite baking thread panicked"))),
+        )
+    }
+    fn register_sprites(
+        &self,
+        sheets: Vec<sprite::sheet::SpriteSheet>,
+        context: &mut scene::SceneContext,
+    ) -> anyhow::Result<()> {
+        *context.sprites = sheets
+            .iter()
+            .zip(&self.models)
+            .enumerate()
+            .map(|(index, (sheet, model))| {
+                sheet.register(
+                    &format!("sprite sheet {index}"),
+                    model.clone(),
+                    context.textures,
+                )
+            })
+            .collect::<anyhow::Result<_>>()?;
+        *context.sprite_sheet = sheets.into_iter().map(|sheet| sheet.image).collect();
+        Ok(())
+    }
+}
+
+impl scene::Scene for InitLoading {
+    fn name(&self) -> &'static str {
+        "loading"
+    }
+    fn update(&mut self, context: &mut scene::SceneContext) -> scene::Transition {
+        match self.poll() {
+            Some(Ok(sheets)) => match self.register_sprites(sheets, context) {
+                Ok(()) => scene::Transition::Replace(Box::new(menu::MainMenu::new())),
+                Err(error) => {
+                    scene::Transition::Replace(Box::new(InitError::new(error, self.compression)))
+                }
+            },
+            Some(Err(error)) => {
+                scene::Transition::Replace(Box::new(InitError::new(error, self.compression)))
+            }
+            None => scene::Transition::None,
+        }
+    }
+    fn user_interface(&mut self, context: &egui::Context) {
+        egui::CentralPanel::default().show(context, |user_interface: &mut egui::Ui| {
+            let load =
+                |counter: &sync::atomic::AtomicU32| counter.load(sync::atomic::Ordering::Acquire);
+            user_interface.add(egui::ProgressBar::new(self.progress.fraction()).show_percentage());
+            user_interface.label(format!(
+                "models: {}/{}",
+                load(&self.progress.models_done),
+                load(&self.progress.models_total)
+            ));
+            user_interface.label(format!(
+                "frames: {}/{}",
+                load(&self.progress.frames_done),
+                load(&self.progress.frames_total)
+            ));
+            user_interface.label(format!(
+                "baked: {} KiB",
+                self.progress
+                    .bytes_baked
+                    .load(sync::atomic::Ordering::Acquire)
+                    / 1024
+            ));
+            if user_interface.button("Cancel").clicked() {
+                self.cancel();
+            }
+        });
+    }
+}
+
+impl Drop for InitLoading {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+pub struct InitError {
+    error: anyhow::Error,
+    choice: Option<InitErrorChoice>,
+    compression: Option<compression::CompressedFormat>,
+}
+
+pub enum InitErrorChoice {
+    Retry,
+    Quit,
+}
+
+impl InitError {
+    pub fn new(error: anyhow::Error, compression: Option<compression::CompressedFormat>) -> Self {
+        Self {
+            error,
+            choice: None,
+            compression,
+        }
+    }
+}
+
+impl scene::Scene for InitError {
+    fn name(&self) -> &'static str {
+        "loading error"
+    }
+    fn update(&mut self, _context: &mut scene::SceneContext) -> scene::Transition {
+        match self.choice.take() {
+            Some(InitErrorChoice::Retry) => {
+                scene::Transition::Replace(InitLoading::scene(self.compression))
+            }
+            Some(InitErrorChoice::Quit) => scene::Transition::Quit,
+            None => scene::Transition::None,
+        }
+    }
+    fn user_interface(&mut self, context: &egui::Context) {
+        egui::CentralPanel::default().show(context, |user_interface: &mut egui::Ui| {
+            user_interface.heading("Failed to load sprites");
+            for (depth, cause) in self.error.chain().enumerate() {
+                if depth == 0 {
+                    user_interface.label(cause.to_string());
+                } else {
+                    user_interface.label(format!("caused by: {cause}"));
+                }
+            }
+            user_interface.horizontal(|user_interface| {
+                if user_interface.button("Retry").clicked() {
+                    self.choice = Some(InitErrorChoice::Retry);
+                }
+                if user_interface.button("Quit").clicked() {
+                    self.choice = Some(InitErrorChoice::Quit);
+                }
+            });
+        });
+    }
+}
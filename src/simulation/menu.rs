@@ -0,0 +1,81 @@
+use crate::simulation::game;
+use crate::simulation::save;
+use crate::simulation::scene;
+use crate::world::input;
+
+pub struct MainMenu {
+    pending: Option<scene::Transition>,
+    slots: Vec<save::SlotSummary>,
+    load: Option<save::Slot>,
+    message: Option<String>,
+}
+
+impl Default for MainMenu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MainMenu {
+    pub fn new() -> Self {
+        Self {
+            pending: None,
+            slots: Vec::new(),
+            load: None,
+            message: None,
+        }
+    }
+}
+
+impl scene::Scene for MainMenu {
+    fn name(&self) -> &'static str {
+        "main menu"
+    }
+    fn enter(&mut self, context: &mut scene::SceneContext) {
+        self.slots = save::summaries();
+        if let Some(mut actions) = context.world.resource_mut::<input::Actions>() {
+            actions.push_context(input::MENU);
+        }
+    }
+    fn exit(&mut self, context: &mut scene::SceneContext) {
+        if let Some(mut actions) = context.world.resource_mut::<input::Actions>() {
+            actions.pop_context(input::MENU);
+        }
+    }
+    fn update(&mut self, context: &mut scene::SceneContext) -> scene::Transition {
+        if let Some(slot) = self.load.take() {
+            let save = save::read(slot);
+            match save.and_then(|save| save.restore(context.world)) {
+                Ok(()) => return scene::Transition::Replace(Box::new(game::Game::new())),
+                Err(error) => self.message = Some(format!("Loading failed: {error:#}")),
+            }
+        }
+        self.pending.take().unwrap_or(scene::Transition::None)
+    }
+    fn user_interface(&mut self, context: &egui::Context) {
+        egui::CentralPanel::default().show(context, |user_interface: &mut egui::Ui| {
+            user_interface.vertical_centered(|user_interface| {
+                if let Some(newest) = self.slots.first()
+                    && user_interface.button("Continue").clicked()
+                {
+                    self.load = Some(newest.slot);
+                }
+                if user_interface.button("Play").clicked() {
+                    self.pending = Some(scene::Transition::Replace(Box::new(game::Game::new())));
+                }
+                for summary in &self.slots {
+                    let label = format!("Load {} (tick {})", summary.slot.name(), summary.tick);
+                    if user_interface.button(label).clicked() {
+                        self.load = Some(summary.slot);
+                    }
+                }
+                if let Some(message) = &self.message {
+                    user_interface.label(message);
+                }
+                if user_interface.button("Quit").clicked() {
+                    self.pending = Some(scene::Transition::Quit);
+                }
+            });
+        });
+    }
+}
@@ -0,0 +1,267 @@
+use crate::rendering::post;
+use crate::simulation::game;
+use crate::simulation::menu;
+use crate::simulation::save;
+use crate::simulation::scene;
+use crate::world::input;
+use crate::world::input::bindings;
+
+enum SlotAction {
+    Save(save::Slot),
+    Load(save::Slot),
+}
+
+enum ControlsAction {
+    Rebind(String),
+    Cancel,
+    Reset,
+}
+
+pub struct Pause {
+    pending: Option<scene::Transition>,
+    slots: Vec<save::SlotSummary>,
+    slot_action: Option<SlotAction>,
+    message: Option<String>,
+    /// Gameplay actions with the names of their bindings.
+    controls: Vec<(String, String)>,
+    controls_action: Option<ControlsAction>,
+    rebinding: Option<String>,
+    graphics: post::PostProcessSettings,
+    /// Edited since the post processor last got the settings.
+    graphics_changed: bool,
+    /// Edited since the pause menu opened, saved when it closes.
+    graphics_unsaved: bool,
+}
+
+impl Default for Pause {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pause {
+    pub fn new() -> Self {
+        Self {
+            pending: None,
+            slots: Vec::new(),
+            slot_action: None,
+            message: None,
+            controls: Vec::new(),
+            controls_action: None,
+            rebinding: None,
+            graphics: post::PostProcessSettings::default(),
+            graphics_changed: false,
+            graphics_unsaved: false,
+        }
+    }
+    fn apply_slot_action(&mut self, action: SlotAction, context: &mut scene::SceneContext) {
+        match action {
+            SlotAction::Save(slot) => {
+                let result = save::write(slot, &save::SaveGame::capture(context.world));
+                self.message = Some(match result {
+                    Ok(()) => format!("Saved to {}", slot.name()),
+                    Err(error) => format!("Saving failed: {error:#}"),
+                });
+                self.slots = save::summaries();
+            }
+            SlotAction::Load(slot) => {
+                match save::read(slot).and_then(|save| save.restore(context.world)) {
+                    Ok(()) => {
+                        self.pending = Some(scene::Transition::Reset(Box::new(game::Game::new())));
+                    }
+                    Err(error) => self.message = Some(format!("Loading failed: {error:#}")),
+                }
+            }
+        }
+    }
+    fn update_controls(&mut self, actions: &mut input::Actions) {
+        match self.controls_action.take() {
+            Some(ControlsAction::Rebind(action)) => {
+                actions.start_rebind(input::GAMEPLAY, &action);
+                self.rebinding = Some(action);
+            }
+            Some(ControlsAction::Cancel) => {
+                actions.cancel_rebind();
+                self.rebinding = None;
+            }
+            Some(ControlsAction::Reset) => {
+                actions.set_bindings(bindings::Bindings::defaults());
+                self.save_controls(actions);
+            }
+            None => (),
+        }
+        if self.rebinding.is_some() && !actions.is_rebinding() {
+            self.rebinding = None;
+            self.save_controls(actions);
+        }
+        self.controls = actions
+            .bindings()
+            .contexts
+            .get(input::GAMEPLAY)
+            .map(|gameplay| {
+                gameplay
+                    .actions
+                    .iter()
+                    .map(|(action, bound)| {
+                        let names = bound
+                            .iter()
+                            .map(|binding| bindings::binding_name(*binding))
+                            .collect::<Vec<_>>();
+                        (action.clone(), names.join(", "))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+    }
+    fn save_controls(&mut self, actions: &input::Actions) {
+        self.message = Some(match actions.bindings().save() {
+            Ok(()) => "Controls saved".to_owned(),
+            Err(error) => format!("Saving controls failed: {error:#}"),
+        });
+    }
+}
+
+impl scene::Scene for Pause {
+    fn name(&self) -> &'static str {
+        "pause"
+    }
+    fn is_overlay(&self) -> bool {
+        true
+    }
+    fn enter(&mut self, context: &mut scene::SceneContext) {
+        self.slots = save::summaries();
+        self.graphics = context.post_processor.settings().clone();
+        if let Some(mut actions) = context.world.resource_mut::<input::Actions>() {
+            actions.push_context(input::MENU);
+        }
+    }
+    fn exit(&mut self, context: &mut scene::SceneContext) {
+        if self.graphics_unsaved
+            && let Err(error) = context.post_processor.settings().save()
+        {
+            log::error!("Saving graphics settings failed: {error:#}");
+        }
+        if let Some(mut actions) = context.world.resource_mut::<input::Actions>() {
+            actions.cancel_rebind();
+            actions.pop_context(input::MENU);
+        }
+    }
+    fn update(&mut self, context: &mut scene::SceneContext) -> scene::Transition {
+        if let Some(action) = self.slot_action.take() {
+            self.apply_slot_action(action, context);
+        }
+        if std::mem::take(&mut self.graphics_changed) {
+            context.post_processor.set_settings(self.graphics.clone());
+            self.graphics_unsaved = true;
+        }
+        if let Some(mut actions) = context.world.resource_mut::<input::Actions>() {
+            self.update_controls(&mut actions);
+            if actions.just_pressed("back") {
+                self.pending.get_or_insert(scene::Transition::Pop);
+            }
+        }
+        self.pending.take().unwrap_or(scene::Transition::None)
+    }
+    fn user_interface(&mut self, context: &egui::Context) {
+        egui::Window::new("paused")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(context, |user_interface: &mut egui::Ui| {
+                if user_interface.button("Resume").clicked() {
+                    self.pending = Some(scene::Transition::Pop);
+                }
+                for slot in (1..=save::MANUAL_SLOTS).map(save::Slot::Manual) {
+                    user_interface.horizontal(|user_interface| {
+                        user_interface.label(slot.name());
+                        if user_interface.button("Save").clicked() {
+                            self.slot_action = Some(SlotAction::Save(slot));
+                        }
+                        let saved = self.slots.iter().any(|summary| summary.slot == slot);
+                        if user_interface
+                            .add_enabled(saved, egui::Button::new("Load"))
+                            .clicked()
+                        {
+                            self.slot_action = Some(SlotAction::Load(slot));
+                        }
+                    });
+                }
+                egui::CollapsingHeader::new("Controls").show(user_interface, |user_interface| {
+                    egui::Grid::new("controls").show(user_interface, |user_interface| {
+                        for (action, bound) in &self.controls {
+                            user_interface.label(action);
+                            if self.rebinding.as_ref() == Some(action) {
+                                user_interface.label("press a key or button");
+                                if user_interface.button("Cancel").clicked() {
+                                    self.controls_action = Some(ControlsAction::Cancel);
+                                }
+                            } else {
+                                user_interface.label(bound);
+                                if user_interface.button("Rebind").clicked() {
+                                    self.controls_action =
+                                        Some(ControlsAction::Rebind(action.clone()));
+                                }
+                            }
+                            user_interface.end_row();
+                        }
+                    });
+                    if user_interface.button("Reset controls").clicked() {
+                        self.controls_action = Some(ControlsAction::Reset);
+                    }
+                });
+                egui::CollapsingHeader::new("Graphics").show(user_interface, |user_interface| {
+                    self.graphics_changed |=
+                        graphics_user_interface(user_interface, &mut self.graphics);
+                });
+                if let Some(message) = &self.message {
+                    user_interface.label(message);
+                }
+                if user_interface.button("Main menu").clicked() {
+                    self.pending = Some(scene::Transition::Reset(Box::new(menu::MainMenu::new())));
+                }
+            });
+    }
+}
+
+/// Toggles and strengths of the post process effects, true if any changed.
+fn graphics_user_interface(
+    user_interface: &mut egui::Ui,
+    settings: &mut post::PostProcessSettings,
+) -> bool {
+    let before = settings.clone();
+    user_interface.checkbox(&mut settings.bloom, "Bloom");
+    if settings.bloom {
+        user_interface
+            .add(egui::Slider::new(&mut settings.bloom_threshold, 0.0..=1.0).text("threshold"));
+        user_interface
+            .add(egui::Slider::new(&mut settings.bloom_intensity, 0.0..=4.0).text("intensity"));
+    }
+    user_interface.checkbox(&mut settings.grading, "Colour grading");
+    if settings.grading {
+        user_interface
+            .add(egui::Slider::new(&mut settings.grading_strength, 0.0..=1.0).text("strength"));
+        user_interface.label(match &settings.lut {
+            Some(lut) => format!("LUT: {}", lut.display()),
+            None => "LUT: identity".to_owned(),
+        });
+    }
+    user_interface.checkbox(&mut settings.vignette, "Vignette");
+    if settings.vignette {
+        user_interface
+            .add(egui::Slider::new(&mut settings.vignette_intensity, 0.0..=1.0).text("intensity"));
+    }
+    user_interface.checkbox(&mut settings.chromatic_aberration, "Chromatic aberration");
+    if settings.chromatic_aberration {
+        user_interface.add(egui::Slider::new(&mut settings.aberration, 0.0..=16.0).text("pixels"));
+    }
+    user_interface.horizontal(|user_interface| {
+        user_interface.label("Retro");
+        for retro in post::RetroFilter::ALL {
+            user_interface.radio_value(&mut settings.retro, retro, retro.name());
+        }
+    });
+    if settings.retro != post::RetroFilter::Off {
+        user_interface.add(egui::Slider::new(&mut settings.pixel_size, 1..=16).text("pixel size"));
+    }
+    *settings != before
+}
@@ -0,0 +1,746 @@
+use crate::simulation;
+use crate::simulation::save;
+use crate::simulation::save::codec;
+use crate::world;
+use crate::world::input;
+use crate::world::input::bindings;
+use crate::world::input::gamepad;
+use crate::world::input::touch;
+use crate::world::pathfinding::service;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path;
+
+const MAGIC: &[u8; 4] = b"GRPL";
+pub const VERSION: u32 = 6;
+
+const REBOUND: u8 = 1;
+const CONTEXTS: u8 = 1 << 1;
+
+/// FNV-1a of the world as it would be saved, compared tick by tick to catch desyncs.
+///
+/// Only what a save restores is hashed, anything else would differ from the first tick of a
+/// replay that starts from the recorded save.
+pub fn state_hash(world: &world::World) -> u64 {
+    save::SaveGame::capture(world)
+        .to_bytes()
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+}
+
+/// Writes the world once and then the input of every update, with the state hash of the world
+/// after each fixed tick.
+///
+/// The file is a header, the starting world as a save game, and one frame per update that holds
+/// the events, gamepad events and platform gestures, the bindings and input contexts if they
+/// changed, and the ticks the update ran, each with the path answers it took and the hash.
+/// Updates without a tick are kept too since they still press and release actions.
+pub struct Recorder {
+    writer: io::BufWriter<fs::File>,
+    started: bool,
+    bindings: Option<bindings::Bindings>,
+    contexts: Option<Vec<String>>,
+    /// Path answers taken and state hash of every tick of the current update.
+    ticks: Vec<(Vec<service::Ticket>, u64)>,
+}
+
+impl Recorder {
+    pub fn create(path: &path::Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            writer: io::BufWriter::new(fs::File::create(path)?),
+            started: false,
+            bindings: None,
+            contexts: None,
+            ticks: Vec::new(),
+        })
+    }
+    /// Whether the first tick was recorded, updates before it are skipped.
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+    /// Notes a tick the world just ran, written with the update it belongs to.
+    pub fn tick(&mut self, world: &world::World) {
+        let taken = world
+            .resource::<service::PathService>()
+            .map(|service| service.taken().to_vec())
+            .unwrap_or_default();
+        self.ticks.push((taken, state_hash(world)));
+    }
+    /// Appends one update and the ticks it ran, `start` is the world before the very first
+    /// recorded tick and `contexts` the input contexts the update was fed with.
+    pub fn record(
+        &mut self,
+        start: Option<&save::SaveGame>,
+        input: &input::InputEvents,
+        contexts: &[String],
+        world: &world::World,
+    ) -> anyhow::Result<()> {
+        if !self.started {
+            if self.ticks.is_empty() {
+                return Ok(());
+            }
+            let start = start.ok_or_else(|| anyhow::anyhow!("recording has no starting world"))?;
+            let mut header = codec::Writer::default();
+            header.bytes(&start.to_bytes());
+            self.writer.write_all(MAGIC)?;
+            self.writer.write_all(&VERSION.to_le_bytes())?;
+            self.writer.write_all(&header.into_bytes())?;
+            self.started = true;
+        }
+        let mut frame = codec::Writer::default();
+        let recorded = input
+            .events
+            .iter()
+            .filter(|event| is_recorded(event))
+            .collect::<Vec<_>>();
+        frame.u32(recorded.len() as u32);
+        for event in recorded {
+            write_event(&mut frame, event);
+        }
+        frame.u32(input.gamepad.len() as u32);
+        for event in &input.gamepad {
+            write_gamepad_event(&mut frame, event);
+        }
+        frame.u32(input.gestures.len() as u32);
+        for gesture in &input.gestures {
+            write_gesture(&mut frame, gesture);
+        }
+        let bindings = world
+            .resource::<input::Actions>()
+            .map(|actions| actions.bindings().clone())
+            .filter(|bindings| self.bindings.as_ref() != Some(bindings));
+        let contexts = Some(contexts).filter(|contexts| self.contexts.as_deref() != Some(contexts));
+        frame.u8(if bindings.is_some() { REBOUND } else { 0 }
+            | if contexts.is_some() { CONTEXTS } else { 0 });
+        if let Some(bindings) = bindings {
+            frame.string(&bindings.to_text());
+            self.bindings = Some(bindings);
+        }
+        if let Some(contexts) = contexts {
+            frame.u32(contexts.len() as u32);
+            for context in contexts {
+                frame.string(context);
+            }
+            self.contexts = Some(contexts.to_vec());
+        }
+        frame.u32(self.ticks.len() as u32);
+        for (taken, hash) in self.ticks.drain(..) {
+            frame.u32(taken.len() as u32);
+            for ticket in taken {
+                frame.u64(ticket.number());
+            }
+            frame.u64(hash);
+        }
+        self.writer.write_all(&frame.into_bytes())?;
+        Ok(())
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(error) = self.writer.flush() {
+            log::error!("Could not finish recording: {error}");
+        }
+    }
+}
+
+/// Events the world sees through [`input::InputEvents`], anything else only reaches the scenes.
+pub fn is_recorded(event: &egui::Event) -> bool {
+    matches!(
+        event,
+        egui::Event::Key { .. }
+            | egui::Event::PointerMoved(_)
+            | egui::Event::PointerButton { .. }
+            | egui::Event::PointerGone
+            | egui::Event::MouseWheel { .. }
+            | egui::Event::Touch { .. }
+            | egui::Event::Zoom(_)
+    )
+}
+
+fn write_modifiers(writer: &mut codec::Writer, modifiers: egui::Modifiers) {
+    writer.u8(modifiers.alt as u8
+        | (modifiers.ctrl as u8) << 1
+        | (modifiers.shift as u8) << 2
+        | (modifiers.mac_cmd as u8) << 3
+        | (modifiers.command as u8) << 4);
+}
+
+fn read_modifiers(reader: &mut codec::Reader) -> anyhow::Result<egui::Modifiers> {
+    let bits = reader.u8()?;
+    Ok(egui::Modifiers {
+        alt: bits & 1 != 0,
+        ctrl: bits & 1 << 1 != 0,
+        shift: bits & 1 << 2 != 0,
+        mac_cmd: bits & 1 << 3 != 0,
+        command: bits & 1 << 4 != 0,
+    })
+}
+
+fn key_index(key: egui::Key) -> u8 {
+    egui::Key::ALL
+        .iter()
+        .position(|candidate| *candidate == key)
+        .expect("every key is listed in Key::ALL") as u8
+}
+
+fn read_key(reader: &mut codec::Reader) -> anyhow::Result<Option<egui::Key>> {
+    match reader.u8()? {
+        u8::MAX => Ok(None),
+        index => egui::Key::ALL
+            .get(index as usize)
+            .copied()
+            .map(Some)
+            .ok_or_else(|| anyhow::anyhow!("unknown key {index}")),
+    }
+}
+
+fn write_position(writer: &mut codec::Writer, position: egui::Pos2) {
+    writer.vec2(glam::vec2(position.x, position.y));
+}
+
+fn read_position(reader: &mut codec::Reader) -> anyhow::Result<egui::Pos2> {
+    let position = reader.vec2()?;
+    Ok(egui::pos2(position.x, position.y))
+}
+
+fn write_event(writer: &mut codec::Writer, event: &egui::Event) {
+    match event {
+        egui::Event::Key {
+            key,
+            physical_key,
+            pressed,
+            repeat,
+            modifiers,
+        } => {
+            writer.u8(0);
+            writer.u8(key_index(*key));
+            writer.u8(physical_key.map_or(u8::MAX, key_index));
+            writer.bool(*pressed);
+            writer.bool(*repeat);
+            write_modifiers(writer, *modifiers);
+        }
+        egui::Event::PointerMoved(position) => {
+            writer.u8(1);
+            write_position(writer, *position);
+        }
+        egui::Event::PointerButton {
+            pos,
+            button,
+            pressed,
+            modifiers,
+        } => {
+            writer.u8(2);
+            write_position(writer, *pos);
+            writer.u8(*button as u8);
+            writer.bool(*pressed);
+            write_modifiers(writer, *modifiers);
+        }
+        egui::Event::PointerGone => writer.u8(3),
+        egui::Event::MouseWheel {
+            unit,
+            delta,
+            modifiers,
+        } => {
+            writer.u8(4);
+            writer.u8(match unit {
+                egui::MouseWheelUnit::Point => 0,
+                egui::MouseWheelUnit::Line => 1,
+                egui::MouseWheelUnit::Page => 2,
+            });
+            writer.vec2(glam::vec2(delta.x, delta.y));
+            write_modifiers(writer, *modifiers);
+        }
+        egui::Event::Touch {
+            device_id,
+            id,
+            phase,
+            pos,
+            force,
+        } => {
+            writer.u8(5);
+            writer.u64(device_id.0);
+            writer.u64(id.0);
+            writer.u8(match phase {
+                egui::TouchPhase::Start => 0,
+                egui::TouchPhase::Move => 1,
+                egui::TouchPhase::End => 2,
+                egui::TouchPhase::Cancel => 3,
+            });
+            write_position(writer, *pos);
+            writer.bool(force.is_some());
+            writer.f32(force.unwrap_or_default());
+        }
+        egui::Event::Zoom(scale) => {
+            writer.u8(6);
+            writer.f32(*scale);
+        }
+        _ => unreachable!("only recorded events are written"),
+    }
+}
+
+fn read_event(reader: &mut codec::Reader) -> anyhow::Result<egui::Event> {
+    Ok(match reader.u8()? {
+        0 => egui::Event::Key {
+            key: read_key(reader)?.ok_or_else(|| anyhow::anyhow!("key event without a key"))?,
+            physical_key: read_key(reader)?,
+            pressed: reader.bool()?,
+            repeat: reader.bool()?,
+            modifiers: read_modifiers(reader)?,
+        },
+        1 => egui::Event::PointerMoved(read_position(reader)?),
+        2 => egui::Event::PointerButton {
+            pos: read_position(reader)?,
+            button: match reader.u8()? {
+                0 => egui::PointerButton::Primary,
+                1 => egui::PointerButton::Secondary,
+                2 => egui::PointerButton::Middle,
+                3 => egui::PointerButton::Extra1,
+                4 => egui::PointerButton::Extra2,
+                button => anyhow::bail!("unknown pointer button {button}"),
+            },
+            pressed: reader.bool()?,
+            modifiers: read_modifiers(reader)?,
+        },
+        3 => egui::Event::PointerGone,
+        4 => egui::Event::MouseWheel {
+            unit: match reader.u8()? {
+                0 => egui::MouseWheelUnit::Point,
+                1 => egui::MouseWheelUnit::Line,
+                2 => egui::MouseWheelUnit::Page,
+                unit => anyhow::bail!("unknown wheel unit {unit}"),
+            },
+            delta: {
+                let delta = reader.vec2()?;
+                egui::vec2(delta.x, delta.y)
+            },
+            modifiers: read_modifiers(reader)?,
+        },
+        5 => egui::Event::Touch {
+            device_id: egui::TouchDeviceId(reader.u64()?),
+            id: egui::TouchId(reader.u64()?),
+            phase: match reader.u8()? {
+                0 => egui::TouchPhase::Start,
+                1 => egui::TouchPhase::Move,
+                2 => egui::TouchPhase::End,
+                3 => egui::TouchPhase::Cancel,
+                phase => anyhow::bail!("unknown touch phase {phase}"),
+            },
+            pos: read_position(reader)?,
+            force: {
+                let has_force = reader.bool()?;
+                let force = reader.f32()?;
+                has_force.then_some(force)
+            },
+        },
+        6 => egui::Event::Zoom(reader.f32()?),
+        tag => anyhow::bail!("unknown event {tag}"),
+    })
+}
+
+fn write_gamepad_event(writer: &mut codec::Writer, event: &gamepad::GamepadEvent) {
+    match event {
+        gamepad::GamepadEvent::Connected(gamepad) => {
+            writer.u8(0);
+            writer.u32(gamepad.0);
+        }
+        gamepad::GamepadEvent::Disconnected(gamepad) => {
+            writer.u8(1);
+            writer.u32(gamepad.0);
+        }
+        gamepad::GamepadEvent::Button {
+            gamepad,
+            button,
+            pressed,
+        } => {
+            writer.u8(2);
+            writer.u32(gamepad.0);
+            writer.u8(*button as u8);
+            writer.bool(*pressed);
+        }
+        gamepad::GamepadEvent::Axis {
+            gamepad,
+            axis,
+            value,
+        } => {
+            writer.u8(3);
+            writer.u32(gamepad.0);
+            writer.u8(*axis as u8);
+            writer.f32(*value);
+        }
+    }
+}
+
+fn read_gamepad_event(reader: &mut codec::Reader) -> anyhow::Result<gamepad::GamepadEvent> {
+    let tag = reader.u8()?;
+    let gamepad = gamepad::GamepadId(reader.u32()?);
+    Ok(match tag {
+        0 => gamepad::GamepadEvent::Connected(gamepad),
+        1 => gamepad::GamepadEvent::Disconnected(gamepad),
+        2 => gamepad::GamepadEvent::Button {
+            gamepad,
+            button: {
+                let button = reader.u8()?;
+                *input::GamepadButton::ALL
+                    .get(button as usize)
+                    .ok_or_else(|| anyhow::anyhow!("unknown gamepad button {button}"))?
+            },
+            pressed: reader.bool()?,
+        },
+        3 => gamepad::GamepadEvent::Axis {
+            gamepad,
+            axis: {
+                let axis = reader.u8()?;
+                *input::GamepadAxis::ALL
+                    .get(axis as usize)
+                    .ok_or_else(|| anyhow::anyhow!("unknown gamepad axis {axis}"))?
+            },
+            value: reader.f32()?,
+        },
+        tag => anyhow::bail!("unknown gamepad event {tag}"),
+    })
+}
+
+fn write_gesture(writer: &mut codec::Writer, gesture: &touch::Gesture) {
+    match gesture {
+        touch::Gesture::Tap { position } => {
+            writer.u8(0);
+            writer.vec2(*position);
+        }
+        touch::Gesture::DoubleTap { position } => {
+            writer.u8(1);
+            writer.vec2(*position);
+        }
+        touch::Gesture::Drag { position, delta } => {
+            writer.u8(2);
+            writer.vec2(*position);
+            writer.vec2(*delta);
+        }
+        touch::Gesture::Pinch { center, scale } => {
+            writer.u8(3);
+            writer.vec2(*center);
+            writer.f32(*scale);
+        }
+        touch::Gesture::Rotate { center, angle } => {
+            writer.u8(4);
+            writer.vec2(*center);
+            writer.f32(*angle);
+        }
+    }
+}
+
+fn read_gesture(reader: &mut codec::Reader) -> anyhow::Result<touch::Gesture> {
+    Ok(match reader.u8()? {
+        0 => touch::Gesture::Tap {
+            position: reader.vec2()?,
+        },
+        1 => touch::Gesture::DoubleTap {
+            position: reader.vec2()?,
+        },
+        2 => touch::Gesture::Drag {
+            position: reader.vec2()?,
+            delta: reader.vec2()?,
+        },
+        3 => touch::Gesture::Pinch {
+            center: reader.vec2()?,
+            scale: reader.f32()?,
+        },
+        4 => touch::Gesture::Rotate {
+            center: reader.vec2()?,
+            angle: reader.f32()?,
+        },
+        tag => anyhow::bail!("unknown gesture {tag}"),
+    })
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReplayReport {
+    pub ticks: u64,
+    /// First tick whose state hash differed from the recording.
+    pub desync: Option<u64>,
+}
+
+/// Replays a recording without a window or GPU, checking the state hash after every tick.
+pub fn verify(path: &path::Path) -> anyhow::Result<ReplayReport> {
+    verify_with(path, simulation::build_world)
+}
+
+/// Like [`verify`] with the world the replay is restored into made by `build`, which gets the
+/// recorded seed and registers the behaviour trees the recording used.
+pub fn verify_with(
+    path: &path::Path,
+    build: impl FnOnce(u64) -> world::World,
+) -> anyhow::Result<ReplayReport> {
+    let bytes = fs::read(path)?;
+    let Some((magic, rest)) = bytes.split_first_chunk::<4>() else {
+        anyhow::bail!("not a replay file");
+    };
+    anyhow::ensure!(magic == MAGIC, "not a replay file");
+    let Some((version, rest)) = rest.split_first_chunk::<4>() else {
+        anyhow::bail!("replay file has no version");
+    };
+    let version = u32::from_le_bytes(*version);
+    anyhow::ensure!(
+        version == VERSION,
+        "replay was recorded with format version {version}, this build only replays version \
+         {VERSION}"
+    );
+    let mut reader = codec::Reader::new(rest);
+    let start = save::SaveGame::from_bytes(reader.bytes()?)?;
+
+    let mut world = build(start.random.as_ref().map_or(0, |random| random.seed));
+    start.restore(&mut world)?;
+    let mut report = ReplayReport {
+        ticks: 0,
+        desync: None,
+    };
+    while !reader.is_empty() {
+        let count = reader.u32()?;
+        let events = (0..count)
+            .map(|_| read_event(&mut reader))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let count = reader.u32()?;
+        let gamepad = (0..count)
+            .map(|_| read_gamepad_event(&mut reader))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let count = reader.u32()?;
+        let gestures = (0..count)
+            .map(|_| read_gesture(&mut reader))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let flags = reader.u8()?;
+        if flags & REBOUND != 0 {
+            let bindings = bindings::Bindings::from_text(&reader.string()?)?;
+            if let Some(mut actions) = world.resource_mut::<input::Actions>() {
+                actions.set_bindings(bindings);
+            }
+        }
+        if flags & CONTEXTS != 0 {
+            let contexts = (0..reader.u32()?)
+                .map(|_| reader.string())
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            if let Some(mut actions) = world.resource_mut::<input::Actions>() {
+                actions.set_contexts(contexts);
+            }
+        }
+        input::feed(
+            &world,
+            input::InputEvents {
+                events,
+                gamepad,
+                gestures,
+            },
+        );
+        for _ in 0..reader.u32()? {
+            let taken = (0..reader.u32()?)
+                .map(|_| reader.u64().map(service::Ticket::from_number))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            if let Some(mut service) = world.resource_mut::<service::PathService>() {
+                service.replay_taken(taken);
+            }
+            let expected = reader.u64()?;
+            world.update();
+            report.ticks += 1;
+            if state_hash(&world) != expected {
+                report.desync = Some(world.tick());
+                return Ok(report);
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::behaviour;
+    use crate::world::components;
+    use crate::world::physics;
+    use crate::world::tilemap;
+
+    const FRAMES: u64 = 20;
+    /// Frames run none, one or two ticks in turn, like a clock that does not match the display.
+    const TICKS: u64 = 19;
+
+    fn ticks_in(frame: u64) -> u64 {
+        frame % 3
+    }
+
+    fn path(name: &str) -> path::PathBuf {
+        std::env::temp_dir().join(format!("replay-{}-{name}.replay", std::process::id()))
+    }
+
+    fn falling_ball() -> world::World {
+        let mut world = simulation::build_world(3);
+        let ball = world.spawn();
+        world.insert(ball, components::Position(glam::vec2(0.0, 10.0)));
+        world.insert(ball, physics::RigidBody::dynamic(1.0));
+        world
+    }
+
+    /// Steps right every third tick and counts its steps on the blackboard.
+    fn patrol() -> behaviour::BehaviourTree {
+        let mut tree = behaviour::BehaviourTree::new(behaviour::Node::selector("patrol"));
+        let cooldown = tree.add(
+            tree.root(),
+            behaviour::Node::new("rest", behaviour::NodeKind::Cooldown { ticks: 2 }),
+        );
+        tree.add(
+            cooldown,
+            behaviour::Node::action("step", |context| {
+                let steps = context.blackboard.integer("steps").unwrap_or_default() + 1;
+                context.blackboard.set("steps", steps);
+                context.commands.insert(
+                    context.entity,
+                    components::Position(glam::vec2(steps as f32, 0.0)),
+                );
+                behaviour::Status::Success
+            }),
+        );
+        tree.add(
+            tree.root(),
+            behaviour::Node::action("wait", |_| behaviour::Status::Running),
+        );
+        tree
+    }
+
+    fn build_patrol_world(seed: u64) -> world::World {
+        let world = simulation::build_world(seed);
+        if let Some(mut library) = world.resource_mut::<behaviour::Library>() {
+            library.register(patrol());
+        }
+        world
+    }
+
+    /// A body falling onto a solid tile next to a patrolling entity that already ran a few
+    /// ticks, so the recording starts with cooldowns and a blackboard to restore.
+    fn patrol_on_tiles() -> world::World {
+        let mut world = build_patrol_world(3);
+        let mut tileset = tilemap::Tileset::default();
+        let wall = tileset.add(tilemap::TileDefinition {
+            sprite: 0,
+            frame: 0,
+            solid: true,
+            autotile: None,
+        });
+        let mut tiles = tilemap::Tilemap::new(1.0, tileset);
+        let ground = tiles.add_layer("ground");
+        tiles.fill(ground, glam::ivec2(-4, -1), glam::ivec2(4, -1), Some(wall));
+        world.insert_resource(tiles);
+        let ball = world.spawn();
+        world.insert(ball, components::Position(glam::vec2(0.0, 2.0)));
+        world.insert(ball, physics::RigidBody::dynamic(1.0));
+        let tree = world
+            .resource::<behaviour::Library>()
+            .and_then(|library| library.get("patrol").cloned())
+            .unwrap();
+        let guard = world.spawn();
+        world.insert(guard, components::Position(glam::Vec2::ZERO));
+        world.insert(guard, behaviour::Behaviour::new(tree));
+        for _ in 0..4 {
+            world.update();
+        }
+        world
+    }
+
+    /// Records `world` with a key held for a while and the gameplay context pushed late.
+    ///
+    /// The first frame runs no tick, so the recording only starts on the second.
+    fn record(path: &path::Path, mut world: world::World) -> anyhow::Result<()> {
+        let start = save::SaveGame::capture(&world);
+        let mut recorder = Recorder::create(path)?;
+        for frame in 0..FRAMES {
+            let events = match frame {
+                2 | 9 => vec![egui::Event::Key {
+                    key: egui::Key::D,
+                    physical_key: None,
+                    pressed: frame == 2,
+                    repeat: false,
+                    modifiers: egui::Modifiers::default(),
+                }],
+                _ => Vec::new(),
+            };
+            let input = input::InputEvents {
+                events,
+                ..Default::default()
+            };
+            if frame == 5
+                && let Some(mut actions) = world.resource_mut::<input::Actions>()
+            {
+                actions.push_context(input::GAMEPLAY);
+            }
+            let contexts = world
+                .resource::<input::Actions>()
+                .map(|actions| actions.contexts().to_vec())
+                .unwrap_or_default();
+            input::feed(&world, input.clone());
+            for _ in 0..ticks_in(frame) {
+                world.update();
+                recorder.tick(&world);
+            }
+            recorder.record(Some(&start), &input, &contexts, &world)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn replays_without_desync() {
+        let path = path("clean");
+        record(&path, falling_ball()).unwrap();
+        let report = verify(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            report,
+            ReplayReport {
+                ticks: TICKS,
+                desync: None
+            }
+        );
+    }
+
+    #[test]
+    fn replays_behaviours_and_tilemaps_without_desync() {
+        let path = path("behaviours");
+        record(&path, patrol_on_tiles()).unwrap();
+        let report = verify_with(&path, build_patrol_world).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            report,
+            ReplayReport {
+                ticks: TICKS,
+                desync: None
+            }
+        );
+    }
+
+    #[test]
+    fn reports_tampered_hash() {
+        let path = path("tampered");
+        record(&path, falling_ball()).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        fs::write(&path, bytes).unwrap();
+        let report = verify(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(report.ticks, TICKS);
+        assert_eq!(report.desync, Some(TICKS));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let path = path("old");
+        record(&path, falling_ball()).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[4..8].copy_from_slice(&(VERSION - 1).to_le_bytes());
+        fs::write(&path, bytes).unwrap();
+        let error = verify(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(
+            error
+                .to_string()
+                .contains(&format!("recorded with format version {}", VERSION - 1))
+        );
+    }
+}
@@ -0,0 +1,724 @@
+use crate::world;
+use crate::world::behaviour;
+use crate::world::clock;
+use crate::world::components;
+use crate::world::random;
+use crate::world::tilemap;
+use std::collections;
+use std::fs;
+use std::io::Write;
+use std::path;
+use std::thread;
+use std::time;
+
+pub mod codec;
+
+const MAGIC: &[u8; 4] = b"GSAV";
+pub const VERSION: u32 = 1;
+pub const MANUAL_SLOTS: u8 = 3;
+/// Five minutes of fixed ticks.
+pub const AUTOSAVE_INTERVAL: u64 = clock::TICKS_PER_SECOND as u64 * 60 * 5;
+
+/// Rewrites a payload of one format version into the next.
+pub type Migration = fn(&[u8]) -> anyhow::Result<Vec<u8>>;
+
+/// Upgrades for older saves, entry `n` turns a version `n + 1` payload into version `n + 2`.
+///
+/// Append one whenever [`VERSION`] is bumped, old entries are never changed.
+const MIGRATIONS: &[Migration] = &[];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Slot {
+    Manual(u8),
+    Autosave,
+}
+
+impl Slot {
+    pub fn all() -> impl Iterator<Item = Slot> {
+        (1..=MANUAL_SLOTS)
+            .map(Slot::Manual)
+            .chain(std::iter::once(Slot::Autosave))
+    }
+    pub fn name(&self) -> String {
+        match self {
+            Slot::Manual(number) => format!("slot {number}"),
+            Slot::Autosave => "autosave".to_owned(),
+        }
+    }
+    /// Saves live in a `saves` directory next to the executable, like the models it loads.
+    pub fn path(&self) -> path::PathBuf {
+        let mut directory = std::env::current_exe().unwrap_or_default();
+        directory.pop();
+        directory.push("saves");
+        directory.join(match self {
+            Slot::Manual(number) => format!("slot-{number}.sav"),
+            Slot::Autosave => "autosave.sav".to_owned(),
+        })
+    }
+}
+
+struct ComponentSection {
+    name: String,
+    entries: Vec<((u32, u32), Vec<u8>)>,
+}
+
+/// Inserted into the world by [`SaveGame::restore`], so whoever drives the world notices that
+/// it jumped to another state.
+pub struct Restored;
+
+/// Everything needed to resume a simulation: the entities and their persisted components,
+/// the tilemap, the random streams and the tick counter. Loading always resumes in the game.
+pub struct SaveGame {
+    pub tick: u64,
+    pub random: Option<random::RandomState>,
+    /// Sources of the sprites that sprite indices in the save point at, see
+    /// [`components::SpriteSources`].
+    sprites: Vec<String>,
+    /// Encoded by [`codec::write_tilemap`], empty without a tilemap.
+    tilemap: Vec<u8>,
+    entities: world::EntitiesState,
+    components: Vec<ComponentSection>,
+}
+
+impl SaveGame {
+    pub fn capture(world: &world::World) -> Self {
+        Self {
+            tick: world.tick(),
+            random: world
+                .resource::<random::Random>()
+                .map(|random| random.state()),
+            sprites: world
+                .resource::<components::SpriteSources>()
+                .map(|sources| {
+                    sources
+                        .0
+                        .iter()
+                        .map(|source| source.to_string_lossy().into_owned())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            tilemap: world
+                .resource::<tilemap::Tilemap>()
+                .map(|tilemap| {
+                    let mut writer = codec::Writer::default();
+                    codec::write_tilemap(&tilemap, &mut writer);
+                    writer.into_bytes()
+                })
+                .unwrap_or_default(),
+            entities: world.entities_state(),
+            components: codec::components()
+                .into_iter()
+                .map(|codec| ComponentSection {
+                    name: codec.name.to_owned(),
+                    entries: (codec.save)(world)
+                        .into_iter()
+                        .map(|(entity, bytes)| ((entity.index(), entity.generation()), bytes))
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+    /// Replaces the entities, tilemap, random streams and tick of `world`, leaving it untouched
+    /// on error.
+    pub fn restore(&self, world: &mut world::World) -> anyhow::Result<()> {
+        let remap = self.sprite_remap(world)?;
+        let tilemap = self.tilemap(remap.as_deref())?;
+        let mut scratch = world::World::new();
+        let library = world
+            .resource::<behaviour::Library>()
+            .map(|library| library.clone());
+        if let Some(library) = library {
+            scratch.insert_resource(library);
+        }
+        self.restore_entities(&mut scratch, remap.as_deref())?;
+        self.restore_entities(world, remap.as_deref())?;
+        world.set_tick(self.tick);
+        if let Some(state) = &self.random {
+            let restored = world
+                .resource_mut::<random::Random>()
+                .map(|mut random| random.restore(state))
+                .is_some();
+            if !restored {
+                world.insert_resource(random::Random::from(state.clone()));
+            }
+        }
+        match tilemap {
+            Some(tilemap) => world.insert_resource(tilemap),
+            None => drop(world.remove_resource::<tilemap::Tilemap>()),
+        }
+        // Without loaded sprites, like when verifying a replay, the save's own table stands in.
+        if world.resource::<components::SpriteSources>().is_none() && !self.sprites.is_empty() {
+            world.insert_resource(components::SpriteSources(
+                self.sprites.iter().map(path::PathBuf::from).collect(),
+            ));
+        }
+        world.insert_resource(Restored);
+        Ok(())
+    }
+    /// Where each sprite of the save is loaded now, `None` when its indices can be kept.
+    fn sprite_remap(&self, world: &world::World) -> anyhow::Result<Option<Vec<usize>>> {
+        let Some(sources) = world.resource::<components::SpriteSources>() else {
+            return Ok(None);
+        };
+        if self.sprites.is_empty() {
+            return Ok(None);
+        }
+        self.sprites
+            .iter()
+            .map(|key| {
+                sources
+                    .0
+                    .iter()
+                    .position(|source| source.to_string_lossy() == *key)
+                    .ok_or_else(|| anyhow::anyhow!("the save uses {key}, which is not loaded"))
+            })
+            .collect::<anyhow::Result<_>>()
+            .map(Some)
+    }
+    fn tilemap(&self, remap: Option<&[usize]>) -> anyhow::Result<Option<tilemap::Tilemap>> {
+        if self.tilemap.is_empty() {
+            return Ok(None);
+        }
+        let mut tilemap = codec::read_tilemap(&mut codec::Reader::new(&self.tilemap))?;
+        for definition in &mut tilemap.tileset.definitions {
+            definition.sprite = remap_sprite(remap, definition.sprite)?;
+        }
+        Ok(Some(tilemap))
+    }
+    fn restore_entities(
+        &self,
+        world: &mut world::World,
+        remap: Option<&[usize]>,
+    ) -> anyhow::Result<()> {
+        world.restore_entities(self.entities.clone())?;
+        let alive = world
+            .entities()
+            .map(|entity| ((entity.index(), entity.generation()), entity))
+            .collect::<collections::HashMap<_, _>>();
+        let codecs = codec::components();
+        for section in &self.components {
+            let Some(codec) = codecs.iter().find(|codec| codec.name == section.name) else {
+                log::warn!("Skipping unknown component in save: {}", section.name);
+                continue;
+            };
+            for (id, bytes) in &section.entries {
+                let entity = alive
+                    .get(id)
+                    .ok_or_else(|| anyhow::anyhow!("{} belongs to a dead entity", section.name))?;
+                (codec.load)(world, *entity, bytes)?;
+            }
+        }
+        if let Some(mut instances) = world.storage_mut::<components::SpriteInstance>() {
+            for (_, instance) in instances.iter_mut() {
+                instance.sprite = remap_sprite(remap, instance.sprite)?;
+            }
+        }
+        Ok(())
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut payload = codec::Writer::default();
+        payload.u64(self.tick);
+        let mut random = codec::Writer::default();
+        if let Some(state) = &self.random {
+            state.write(&mut random);
+        }
+        payload.bytes(&random.into_bytes());
+        payload.u32(self.sprites.len() as u32);
+        for sprite in &self.sprites {
+            payload.string(sprite);
+        }
+        payload.bytes(&self.tilemap);
+        payload.u32(self.entities.generations.len() as u32);
+        for (generation, alive) in self.entities.generations.iter().zip(&self.entities.alive) {
+            payload.u32(*generation);
+            payload.bool(*alive);
+        }
+        payload.u32(self.entities.free.len() as u32);
+        for index in &self.entities.free {
+            payload.u32(*index);
+        }
+        payload.u32(self.components.len() as u32);
+        for section in &self.components {
+            payload.string(&section.name);
+            payload.u32(section.entries.len() as u32);
+            for ((index, generation), bytes) in &section.entries {
+                payload.u32(*index);
+                payload.u32(*generation);
+                payload.bytes(bytes);
+            }
+        }
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend(payload.into_bytes());
+        bytes
+    }
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let Some((magic, rest)) = bytes.split_first_chunk::<4>() else {
+            anyhow::bail!("not a save file");
+        };
+        anyhow::ensure!(magic == MAGIC, "not a save file");
+        let Some((version, payload)) = rest.split_first_chunk::<4>() else {
+            anyhow::bail!("save file has no version");
+        };
+        let payload = migrate(u32::from_le_bytes(*version), payload.to_vec())?;
+
+        let mut reader = codec::Reader::new(&payload);
+        let tick = reader.u64()?;
+        let random = match reader.bytes()? {
+            [] => None,
+            bytes => {
+                let mut reader = codec::Reader::new(bytes);
+                let state = random::RandomState::read(&mut reader)?;
+                anyhow::ensure!(reader.is_empty(), "trailing bytes after random state");
+                Some(state)
+            }
+        };
+        let sprites = (0..reader.u32()?)
+            .map(|_| reader.string())
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let tilemap = reader.bytes()?.to_vec();
+        if !tilemap.is_empty() {
+            let mut reader = codec::Reader::new(&tilemap);
+            codec::read_tilemap(&mut reader)?;
+            anyhow::ensure!(reader.is_empty(), "trailing bytes after the tilemap");
+        }
+        let mut entities = world::EntitiesState::default();
+        for _ in 0..reader.u32()? {
+            entities.generations.push(reader.u32()?);
+            entities.alive.push(reader.bool()?);
+        }
+        for _ in 0..reader.u32()? {
+            entities.free.push(reader.u32()?);
+        }
+        let mut components = Vec::new();
+        for _ in 0..reader.u32()? {
+            let name = reader.string()?;
+            let mut entries = Vec::new();
+            for _ in 0..reader.u32()? {
+                entries.push((reader.entity()?, reader.bytes()?.to_vec()));
+            }
+            components.push(ComponentSection { name, entries });
+        }
+        anyhow::ensure!(reader.is_empty(), "save file has trailing bytes");
+        Ok(Self {
+            tick,
+            random,
+            sprites,
+            tilemap,
+            entities,
+            components,
+        })
+    }
+}
+
+/// Maps a sprite index of a save through [`SaveGame::sprite_remap`].
+fn remap_sprite(remap: Option<&[usize]>, sprite: usize) -> anyhow::Result<usize> {
+    match remap {
+        Some(remap) => remap
+            .get(sprite)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("sprite {sprite} is missing from the save")),
+        None => Ok(sprite),
+    }
+}
+
+fn migrate(version: u32, mut payload: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    anyhow::ensure!(
+        (1..=VERSION).contains(&version),
+        "save format version {version} is not supported, expected at most {VERSION}"
+    );
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize - 1) {
+        log::info!("Migrating save from version {} to {}", from + 1, from + 2);
+        payload = migration(&payload)?;
+    }
+    Ok(payload)
+}
+
+/// Writes next to the slot first and renames over it, so a crash never leaves half a save.
+pub fn write(slot: Slot, save: &SaveGame) -> anyhow::Result<()> {
+    write_bytes(&slot.path(), &save.to_bytes())
+}
+
+fn write_bytes(path: &path::Path, bytes: &[u8]) -> anyhow::Result<()> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    let temporary = path.with_extension("sav.tmp");
+    let mut file = fs::File::create(&temporary)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+pub fn read(slot: Slot) -> anyhow::Result<SaveGame> {
+    let path = slot.path();
+    let bytes = fs::read(&path)
+        .map_err(|error| anyhow::anyhow!("could not read {}: {error}", path.display()))?;
+    SaveGame::from_bytes(&bytes)
+}
+
+/// What the menus show about a slot.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SlotSummary {
+    pub slot: Slot,
+    pub tick: u64,
+    pub modified: Option<time::SystemTime>,
+}
+
+/// Summaries of every slot holding a readable save, newest first.
+pub fn summaries() -> Vec<SlotSummary> {
+    let mut summaries = Slot::all()
+        .filter_map(|slot| {
+            let save = match read(slot) {
+                Ok(save) => save,
+                Err(error) => {
+                    if slot.path().exists() {
+                        log::warn!("Ignoring {}: {error:#}", slot.name());
+                    }
+                    return None;
+                }
+            };
+            Some(SlotSummary {
+                slot,
+                tick: save.tick,
+                modified: fs::metadata(slot.path())
+                    .and_then(|metadata| metadata.modified())
+                    .ok(),
+            })
+        })
+        .collect::<Vec<_>>();
+    summaries.sort_by_key(|summary| std::cmp::Reverse(summary.modified));
+    summaries
+}
+
+/// Saves into [`Slot::Autosave`] every [`AUTOSAVE_INTERVAL`] ticks, writing on a background thread.
+pub struct Autosave {
+    pub interval: u64,
+    /// Ticks run since the last autosave, summed frame by frame since loads move the tick.
+    elapsed: u64,
+    /// The tick of the previous update, or of the save loaded since.
+    seen_tick: u64,
+    writing: Option<thread::JoinHandle<anyhow::Result<()>>>,
+}
+
+impl Default for Autosave {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Autosave {
+    pub fn new() -> Self {
+        Self {
+            interval: AUTOSAVE_INTERVAL,
+            elapsed: 0,
+            seen_tick: 0,
+            writing: None,
+        }
+    }
+    /// Counts ticks from the tick of a save that was just loaded.
+    pub fn restored(&mut self, world: &world::World) {
+        self.seen_tick = world.tick();
+    }
+    pub fn update(&mut self, world: &world::World) {
+        if self
+            .writing
+            .as_ref()
+            .is_some_and(|writing| writing.is_finished())
+        {
+            match self.writing.take().map(thread::JoinHandle::join) {
+                Some(Ok(Err(error))) => log::error!("Autosave failed: {error:#}"),
+                Some(Err(_)) => log::error!("Autosave thread panicked"),
+                _ => (),
+            }
+        }
+        self.elapsed += world.tick().saturating_sub(self.seen_tick);
+        self.seen_tick = world.tick();
+        if self.writing.is_some() || self.elapsed < self.interval {
+            return;
+        }
+        self.elapsed = 0;
+        let bytes = SaveGame::capture(world).to_bytes();
+        let path = Slot::Autosave.path();
+        self.writing = Some(thread::spawn(move || write_bytes(&path, &bytes)));
+    }
+}
+
+impl Drop for Autosave {
+    fn drop(&mut self) {
+        if let Some(writing) = self.writing.take() {
+            let _ = writing.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::components;
+    use crate::world::physics;
+    use crate::world::physics::collision;
+    use rand::RngCore;
+
+    /// A world with a recycled entity, a hole in the ids and a few kinds of component.
+    fn world() -> (world::World, Vec<world::Entity>) {
+        let mut world = world::World::new();
+        let recycled = world.spawn();
+        world.despawn(recycled);
+        let hole = world.spawn();
+        let crate_entity = world.spawn();
+        let ball = world.spawn();
+        world.despawn(hole);
+
+        world.insert(crate_entity, components::Position(glam::vec2(3.0, -4.0)));
+        world.insert(crate_entity, components::Bounds::new(glam::vec2(2.0, 2.0)));
+        world.insert(
+            crate_entity,
+            physics::Collider::new(collision::Shape::polygon(vec![
+                glam::vec2(-1.0, -1.0),
+                glam::vec2(1.0, -1.0),
+                glam::vec2(0.0, 1.0),
+            ])),
+        );
+        world.insert(crate_entity, physics::RigidBody::fixed());
+        world.insert(ball, components::Position(glam::vec2(0.5, 8.0)));
+        let mut body = physics::RigidBody::dynamic(2.0);
+        body.velocity = glam::vec2(1.0, -3.0);
+        world.insert(ball, body);
+
+        let mut random = random::Random::new(11);
+        random.stream("loot").next_u64();
+        world.insert_resource(random);
+        world.set_tick(1234);
+        (world, vec![crate_entity, ball])
+    }
+
+    fn assert_same<T: PartialEq + std::fmt::Debug + 'static>(
+        first: &world::World,
+        second: &world::World,
+        entity: world::Entity,
+    ) {
+        assert_eq!(
+            first.get::<T>(entity).as_deref(),
+            second.get::<T>(entity).as_deref()
+        );
+    }
+
+    #[test]
+    fn save_round_trips_the_world() {
+        let (world, entities) = world();
+        let bytes = SaveGame::capture(&world).to_bytes();
+        let mut restored = world::World::new();
+        SaveGame::from_bytes(&bytes)
+            .unwrap()
+            .restore(&mut restored)
+            .unwrap();
+
+        assert_eq!(restored.tick(), 1234);
+        assert_eq!(restored.entities_state(), world.entities_state());
+        assert_eq!(
+            restored.entities().collect::<Vec<_>>(),
+            world.entities().collect::<Vec<_>>()
+        );
+        for entity in entities {
+            assert_same::<components::Position>(&world, &restored, entity);
+            assert_same::<components::Bounds>(&world, &restored, entity);
+            assert_same::<physics::Collider>(&world, &restored, entity);
+            assert_same::<physics::RigidBody>(&world, &restored, entity);
+        }
+        assert_eq!(
+            *restored.resource::<random::Random>().unwrap(),
+            *world.resource::<random::Random>().unwrap()
+        );
+        assert_eq!(SaveGame::capture(&restored).to_bytes(), bytes);
+    }
+
+    #[test]
+    fn truncated_saves_are_errors() {
+        let (world, _) = world();
+        let bytes = SaveGame::capture(&world).to_bytes();
+        for length in 0..bytes.len() {
+            assert!(SaveGame::from_bytes(&bytes[..length]).is_err());
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(SaveGame::from_bytes(&trailing).is_err());
+    }
+
+    #[test]
+    fn disagreeing_free_and_alive_lists_are_errors() {
+        let (world, entities) = world();
+        let mut save = SaveGame::capture(&world);
+        let valid = save.entities.clone();
+        let alive = entities[0].index();
+        let corruptions = [
+            world::EntitiesState {
+                free: vec![alive],
+                ..valid.clone()
+            },
+            world::EntitiesState {
+                free: vec![99],
+                ..valid.clone()
+            },
+            world::EntitiesState {
+                free: [valid.free.clone(), valid.free.clone()].concat(),
+                ..valid.clone()
+            },
+            world::EntitiesState {
+                free: Vec::new(),
+                ..valid.clone()
+            },
+            world::EntitiesState {
+                alive: vec![true],
+                ..valid.clone()
+            },
+        ];
+        let mut target = world::World::new();
+        let untouched = target.spawn();
+        for corruption in corruptions {
+            save.entities = corruption;
+            assert!(save.restore(&mut target).is_err());
+            assert!(target.is_alive(untouched));
+        }
+    }
+
+    fn tilemap() -> tilemap::Tilemap {
+        let mut tileset = tilemap::Tileset::default();
+        let floor = tileset.add(tilemap::TileDefinition {
+            sprite: 1,
+            frame: 2,
+            solid: false,
+            autotile: None,
+        });
+        let wall = tileset.add(tilemap::TileDefinition {
+            sprite: 0,
+            frame: 0,
+            solid: true,
+            autotile: Some(tilemap::AutotileRule {
+                frames: std::array::from_fn(|mask| mask as u16),
+            }),
+        });
+        let mut tilemap = tilemap::Tilemap::new(8.0, tileset);
+        let ground = tilemap.add_layer("ground");
+        tilemap.fill(
+            ground,
+            glam::ivec2(-20, -2),
+            glam::ivec2(20, 2),
+            Some(floor),
+        );
+        let walls = tilemap.add_layer("walls");
+        tilemap.set(walls, glam::ivec2(17, -40), Some(wall));
+        tilemap
+    }
+
+    #[test]
+    fn tilemaps_round_trip() {
+        let (mut world, _) = world();
+        world.insert_resource(tilemap());
+        let bytes = SaveGame::capture(&world).to_bytes();
+        let mut restored = world::World::new();
+        SaveGame::from_bytes(&bytes)
+            .unwrap()
+            .restore(&mut restored)
+            .unwrap();
+        let tilemap = restored.resource::<tilemap::Tilemap>().unwrap();
+        let original = world.resource::<tilemap::Tilemap>().unwrap();
+        assert_eq!(tilemap.tile_size, 8.0);
+        assert_eq!(tilemap.tileset, original.tileset);
+        assert_eq!(
+            tilemap
+                .layers()
+                .iter()
+                .map(|layer| layer.name.as_str())
+                .collect::<Vec<_>>(),
+            ["ground", "walls"]
+        );
+        for cell in [
+            glam::ivec2(-20, -2),
+            glam::ivec2(20, 2),
+            glam::ivec2(21, 0),
+            glam::ivec2(17, -40),
+        ] {
+            for layer in 0..2 {
+                assert_eq!(tilemap.get(layer, cell), original.get(layer, cell));
+            }
+        }
+        drop(tilemap);
+        assert_eq!(SaveGame::capture(&restored).to_bytes(), bytes);
+
+        // Loading a save without a tilemap takes the current one away.
+        SaveGame::capture(&world::World::new())
+            .restore(&mut restored)
+            .unwrap();
+        assert!(restored.resource::<tilemap::Tilemap>().is_none());
+    }
+
+    #[test]
+    fn sprites_are_found_by_source_after_another_load_order() {
+        let (mut world, entities) = world();
+        let sources = |names: &[&str]| {
+            components::SpriteSources(names.iter().map(path::PathBuf::from).collect())
+        };
+        world.insert_resource(sources(&["a.fbx", "b.png"]));
+        world.insert_resource(tilemap());
+        world.insert(
+            entities[0],
+            components::SpriteInstance {
+                sprite: 0,
+                frame: 4,
+                size: glam::Vec2::splat(32.0),
+            },
+        );
+        let save = SaveGame::capture(&world);
+
+        let mut reordered = world::World::new();
+        reordered.insert_resource(sources(&["c.png", "b.png", "a.fbx"]));
+        save.restore(&mut reordered).unwrap();
+        assert_eq!(
+            reordered
+                .get::<components::SpriteInstance>(entities[0])
+                .unwrap()
+                .sprite,
+            2
+        );
+        let tilemap = reordered.resource::<tilemap::Tilemap>().unwrap();
+        assert_eq!(
+            tilemap
+                .tileset
+                .definitions
+                .iter()
+                .map(|definition| definition.sprite)
+                .collect::<Vec<_>>(),
+            [1, 2]
+        );
+        drop(tilemap);
+
+        let mut missing = world::World::new();
+        missing.insert_resource(sources(&["a.fbx"]));
+        let untouched = missing.spawn();
+        let error = save.restore(&mut missing).unwrap_err();
+        assert!(error.to_string().contains("b.png"), "{error}");
+        assert!(missing.is_alive(untouched));
+
+        // Without loaded sprites the save keeps its indices and brings its own table.
+        let mut headless = world::World::new();
+        save.restore(&mut headless).unwrap();
+        assert_eq!(
+            *headless.resource::<components::SpriteSources>().unwrap(),
+            sources(&["a.fbx", "b.png"])
+        );
+        assert_eq!(SaveGame::capture(&headless).to_bytes(), save.to_bytes());
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let (world, _) = world();
+        let mut bytes = SaveGame::capture(&world).to_bytes();
+        bytes[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        let error = SaveGame::from_bytes(&bytes).err().unwrap();
+        assert!(error.to_string().contains("not supported"));
+    }
+}
@@ -0,0 +1,481 @@
+use crate::world;
+use crate::world::behaviour;
+use crate::world::components;
+use crate::world::lighting;
+use crate::world::pathfinding;
+use crate::world::physics;
+use crate::world::physics::collision;
+use crate::world::tilemap;
+
+/// Appends little endian values to a save buffer.
+#[derive(Default)]
+pub struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+    pub fn u16(&mut self, value: u16) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+    pub fn u32(&mut self, value: u32) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+    pub fn u64(&mut self, value: u64) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+    pub fn f32(&mut self, value: f32) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+    pub fn vec2(&mut self, value: glam::Vec2) {
+        self.f32(value.x);
+        self.f32(value.y);
+    }
+    /// Length prefixed, so readers can skip blobs they do not understand.
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.u32(value.len() as u32);
+        self.bytes.extend(value);
+    }
+    pub fn string(&mut self, value: &str) {
+        self.bytes(value.as_bytes());
+    }
+    pub fn entity(&mut self, entity: world::Entity) {
+        self.u32(entity.index());
+        self.u32(entity.generation());
+    }
+}
+
+/// Reads values written by [`Writer`], failing instead of panicking on truncated input.
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+    /// Everything not read yet.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.bytes
+    }
+    fn take<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        let Some((taken, rest)) = self.bytes.split_first_chunk::<N>() else {
+            anyhow::bail!("save data is truncated");
+        };
+        self.bytes = rest;
+        Ok(*taken)
+    }
+    pub fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+    pub fn bool(&mut self) -> anyhow::Result<bool> {
+        Ok(self.u8()? != 0)
+    }
+    pub fn u16(&mut self) -> anyhow::Result<u16> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+    pub fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+    pub fn u64(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+    pub fn f32(&mut self) -> anyhow::Result<f32> {
+        Ok(f32::from_le_bytes(self.take()?))
+    }
+    pub fn vec2(&mut self) -> anyhow::Result<glam::Vec2> {
+        Ok(glam::vec2(self.f32()?, self.f32()?))
+    }
+    pub fn bytes(&mut self) -> anyhow::Result<&'a [u8]> {
+        let length = self.u32()? as usize;
+        anyhow::ensure!(self.bytes.len() >= length, "save data is truncated");
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(taken)
+    }
+    pub fn string(&mut self) -> anyhow::Result<String> {
+        Ok(String::from_utf8(self.bytes()?.to_vec())?)
+    }
+    pub fn entity(&mut self) -> anyhow::Result<(u32, u32)> {
+        Ok((self.u32()?, self.u32()?))
+    }
+}
+
+/// Components that are written into save games.
+///
+/// `NAME` identifies the component in the file and must never change once saves exist, rename
+/// the type freely but write a migration before changing the name or the encoding.
+pub trait Persist: Sized + 'static {
+    const NAME: &'static str;
+    fn write(&self, writer: &mut Writer);
+    fn read(reader: &mut Reader) -> anyhow::Result<Self>;
+}
+
+impl Persist for components::Position {
+    const NAME: &'static str = "position";
+    fn write(&self, writer: &mut Writer) {
+        writer.vec2(self.0);
+    }
+    fn read(reader: &mut Reader) -> anyhow::Result<Self> {
+        Ok(Self(reader.vec2()?))
+    }
+}
+
+impl Persist for components::Bounds {
+    const NAME: &'static str = "bounds";
+    fn write(&self, writer: &mut Writer) {
+        writer.vec2(self.half_extents);
+    }
+    fn read(reader: &mut Reader) -> anyhow::Result<Self> {
+        Ok(Self {
+            half_extents: reader.vec2()?,
+        })
+    }
+}
+
+/// The sprite is an index into the sprite table of the save, loading maps it to the sprite
+/// with the same source.
+impl Persist for components::SpriteInstance {
+    const NAME: &'static str = "sprite instance";
+    fn write(&self, writer: &mut Writer) {
+        writer.u32(self.sprite as u32);
+        writer.u16(self.frame);
+        writer.vec2(self.size);
+    }
+    fn read(reader: &mut Reader) -> anyhow::Result<Self> {
+        Ok(Self {
+            sprite: reader.u32()? as usize,
+            frame: reader.u16()?,
+            size: reader.vec2()?,
+        })
+    }
+}
+
+impl Persist for physics::Collider {
+    const NAME: &'static str = "collider";
+    fn write(&self, writer: &mut Writer) {
+        write_shape(&self.shape, writer);
+        writer.bool(self.is_trigger);
+        writer.f32(self.restitution);
+        writer.f32(self.friction);
+    }
+    fn read(reader: &mut Reader) -> anyhow::Result<Self> {
+        Ok(Self {
+            shape: read_shape(reader)?,
+            is_trigger: reader.bool()?,
+            restitution: reader.f32()?,
+            friction: reader.f32()?,
+        })
+    }
+}
+
+fn write_shape(shape: &collision::Shape, writer: &mut Writer) {
+    match shape {
+        collision::Shape::Aabb { half_extents } => {
+            writer.u8(0);
+            writer.vec2(*half_extents);
+        }
+        collision::Shape::Circle { radius } => {
+            writer.u8(1);
+            writer.f32(*radius);
+        }
+        collision::Shape::Polygon { vertices } => {
+            writer.u8(2);
+            writer.u32(vertices.len() as u32);
+            for vertex in vertices {
+                writer.vec2(*vertex);
+            }
+        }
+    }
+}
+
+fn read_shape(reader: &mut Reader) -> anyhow::Result<collision::Shape> {
+    Ok(match reader.u8()? {
+        0 => collision::Shape::Aabb {
+            half_extents: reader.vec2()?,
+        },
+        1 => collision::Shape::Circle {
+            radius: reader.f32()?,
+        },
+        2 => {
+            let count = reader.u32()?;
+            let vertices = (0..count)
+                .map(|_| reader.vec2())
+                .collect::<anyhow::Result<_>>()?;
+            collision::Shape::Polygon { vertices }
+        }
+        shape => anyhow::bail!("unknown collider shape {shape}"),
+    })
+}
+
+impl Persist for physics::RigidBody {
+    const NAME: &'static str = "rigid body";
+    fn write(&self, writer: &mut Writer) {
+        writer.u8(match self.kind {
+            physics::BodyKind::Static => 0,
+            physics::BodyKind::Kinematic => 1,
+            physics::BodyKind::Dynamic => 2,
+        });
+        writer.vec2(self.velocity);
+        writer.f32(self.inverse_mass());
+    }
+    fn read(reader: &mut Reader) -> anyhow::Result<Self> {
+        let kind = match reader.u8()? {
+            0 => physics::BodyKind::Static,
+            1 => physics::BodyKind::Kinematic,
+            2 => physics::BodyKind::Dynamic,
+            kind => anyhow::bail!("unknown body kind {kind}"),
+        };
+        Ok(Self::from_parts(kind, reader.vec2()?, reader.f32()?))
+    }
+}
+
+/// Only the goal is kept, paths are requested again after loading.
+impl Persist for pathfinding::Navigator {
+    const NAME: &'static str = "navigator";
+    fn write(&self, writer: &mut Writer) {
+        writer.bool(self.goal.is_some());
+        writer.vec2(self.goal.unwrap_or_default());
+        writer.f32(self.speed);
+        writer.f32(self.tolerance);
+    }
+    fn read(reader: &mut Reader) -> anyhow::Result<Self> {
+        let has_goal = reader.bool()?;
+        let goal = reader.vec2()?;
+        let mut navigator = Self::new(reader.f32()?);
+        navigator.goal = has_goal.then_some(goal);
+        navigator.tolerance = reader.f32()?;
+        Ok(navigator)
+    }
+}
+
+impl Persist for lighting::Light {
+    const NAME: &'static str = "light";
+    fn write(&self, writer: &mut Writer) {
+        match self.kind {
+            lighting::LightKind::Point => writer.u8(0),
+            lighting::LightKind::Spot {
+                direction,
+                half_angle,
+            } => {
+                writer.u8(1);
+                writer.f32(direction);
+                writer.f32(half_angle);
+            }
+        }
+        writer.vec2(self.color.truncate());
+        writer.f32(self.color.z);
+        writer.f32(self.intensity);
+        writer.f32(self.radius);
+        writer.f32(self.height);
+        writer.f32(self.source_radius);
+    }
+    fn read(reader: &mut Reader) -> anyhow::Result<Self> {
+        let kind = match reader.u8()? {
+            0 => lighting::LightKind::Point,
+            1 => lighting::LightKind::Spot {
+                direction: reader.f32()?,
+                half_angle: reader.f32()?,
+            },
+            kind => anyhow::bail!("unknown light kind {kind}"),
+        };
+        Ok(Self {
+            kind,
+            color: reader.vec2()?.extend(reader.f32()?),
+            intensity: reader.f32()?,
+            radius: reader.f32()?,
+            height: reader.f32()?,
+            source_radius: reader.f32()?,
+        })
+    }
+}
+
+impl Persist for lighting::Occluder {
+    const NAME: &'static str = "occluder";
+    fn write(&self, writer: &mut Writer) {
+        write_shape(&self.shape, writer);
+    }
+    fn read(reader: &mut Reader) -> anyhow::Result<Self> {
+        Ok(Self {
+            shape: read_shape(reader)?,
+        })
+    }
+}
+
+/// Writes the tileset and every set cell, layer by layer in row order so equal maps encode
+/// equally.
+pub fn write_tilemap(tilemap: &tilemap::Tilemap, writer: &mut Writer) {
+    writer.f32(tilemap.tile_size);
+    writer.u32(tilemap.tileset.definitions.len() as u32);
+    for definition in &tilemap.tileset.definitions {
+        writer.u32(definition.sprite as u32);
+        writer.u16(definition.frame);
+        writer.bool(definition.solid);
+        writer.bool(definition.autotile.is_some());
+        if let Some(rule) = &definition.autotile {
+            for frame in rule.frames {
+                writer.u16(frame);
+            }
+        }
+    }
+    writer.u32(tilemap.layers().len() as u32);
+    for layer in tilemap.layers() {
+        writer.string(&layer.name);
+        let mut cells = layer
+            .chunks()
+            .flat_map(|(coordinate, _)| {
+                let origin = coordinate * tilemap::CHUNK_SIZE;
+                (0..tilemap::CHUNK_SIZE * tilemap::CHUNK_SIZE).map(move |index| {
+                    origin + glam::ivec2(index % tilemap::CHUNK_SIZE, index / tilemap::CHUNK_SIZE)
+                })
+            })
+            .filter_map(|cell| Some((cell, layer.get(cell)?)))
+            .collect::<Vec<_>>();
+        cells.sort_by_key(|(cell, _)| (cell.y, cell.x));
+        writer.u32(cells.len() as u32);
+        for (cell, kind) in cells {
+            writer.u32(cell.x as u32);
+            writer.u32(cell.y as u32);
+            writer.u16(kind.0);
+        }
+    }
+}
+
+pub fn read_tilemap(reader: &mut Reader) -> anyhow::Result<tilemap::Tilemap> {
+    let tile_size = reader.f32()?;
+    let mut tileset = tilemap::Tileset::default();
+    for _ in 0..reader.u32()? {
+        let sprite = reader.u32()? as usize;
+        let frame = reader.u16()?;
+        let solid = reader.bool()?;
+        let autotile = if reader.bool()? {
+            let mut frames = [0; 16];
+            for frame in &mut frames {
+                *frame = reader.u16()?;
+            }
+            Some(tilemap::AutotileRule { frames })
+        } else {
+            None
+        };
+        tileset.add(tilemap::TileDefinition {
+            sprite,
+            frame,
+            solid,
+            autotile,
+        });
+    }
+    let mut tilemap = tilemap::Tilemap::new(tile_size, tileset);
+    for _ in 0..reader.u32()? {
+        let layer = tilemap.add_layer(reader.string()?);
+        for _ in 0..reader.u32()? {
+            let cell = glam::ivec2(reader.u32()? as i32, reader.u32()? as i32);
+            let kind = tilemap::TileKind(reader.u16()?);
+            anyhow::ensure!(
+                tilemap.tileset.get(kind).is_some(),
+                "tile {} is not in the tileset",
+                kind.0
+            );
+            tilemap.set(layer, cell, Some(kind));
+        }
+    }
+    Ok(tilemap)
+}
+
+pub type EncodedComponents = Vec<(world::Entity, Vec<u8>)>;
+
+/// Type erased [`Persist`] implementation of one component type.
+pub struct ComponentCodec {
+    pub name: &'static str,
+    /// Encodes every component of the type with its entity.
+    pub save: fn(&world::World) -> EncodedComponents,
+    pub load: fn(&mut world::World, world::Entity, &[u8]) -> anyhow::Result<()>,
+}
+
+impl ComponentCodec {
+    pub fn of<T: Persist>() -> Self {
+        Self {
+            name: T::NAME,
+            save: |world| {
+                world.storage::<T>().map_or_else(Vec::new, |storage| {
+                    storage
+                        .iter()
+                        .map(|(entity, component)| {
+                            let mut writer = Writer::default();
+                            component.write(&mut writer);
+                            (entity, writer.into_bytes())
+                        })
+                        .collect()
+                })
+            },
+            load: |world, entity, bytes| {
+                let mut reader = Reader::new(bytes);
+                let component = T::read(&mut reader)?;
+                anyhow::ensure!(reader.is_empty(), "{} has trailing bytes", T::NAME);
+                world.insert(entity, component);
+                Ok(())
+            },
+        }
+    }
+}
+
+/// Behaviours by the name of their tree and their state, the tree itself holds closures and is
+/// looked up in the world's [`behaviour::Library`] on load.
+fn behaviours() -> ComponentCodec {
+    ComponentCodec {
+        name: "behaviour",
+        save: |world| {
+            world
+                .storage::<behaviour::Behaviour>()
+                .map_or_else(Vec::new, |storage| {
+                    storage
+                        .iter()
+                        .map(|(entity, behaviour)| {
+                            let mut writer = Writer::default();
+                            writer.string(behaviour.tree().name());
+                            behaviour.write_state(&mut writer);
+                            (entity, writer.into_bytes())
+                        })
+                        .collect()
+                })
+        },
+        load: |world, entity, bytes| {
+            let mut reader = Reader::new(bytes);
+            let name = reader.string()?;
+            let tree = world
+                .resource::<behaviour::Library>()
+                .and_then(|library| library.get(&name).cloned())
+                .ok_or_else(|| anyhow::anyhow!("behaviour tree {name} is not registered"))?;
+            let mut behaviour = behaviour::Behaviour::new(tree);
+            behaviour.read_state(&mut reader)?;
+            anyhow::ensure!(reader.is_empty(), "behaviour has trailing bytes");
+            world.insert(entity, behaviour);
+            Ok(())
+        },
+    }
+}
+
+/// Every component type that survives saving and loading.
+pub fn components() -> Vec<ComponentCodec> {
+    vec![
+        ComponentCodec::of::<components::Position>(),
+        ComponentCodec::of::<components::Bounds>(),
+        ComponentCodec::of::<components::SpriteInstance>(),
+        ComponentCodec::of::<physics::Collider>(),
+        ComponentCodec::of::<physics::RigidBody>(),
+        ComponentCodec::of::<pathfinding::Navigator>(),
+        ComponentCodec::of::<lighting::Light>(),
+        ComponentCodec::of::<lighting::Occluder>(),
+        behaviours(),
+    ]
+}
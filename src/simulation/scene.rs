@@ -0,0 +1,349 @@
+use crate::rendering;
+use crate::simulation::replay;
+use crate::sprite;
+use crate::user_interface;
+use crate::world;
+use std::sync;
+
+/// Everything a scene may touch outside of itself while it is entered, updated or exited.
+pub struct SceneContext<'a, 'window> {
+    pub gpu_handle: &'a rendering::GpuHandle<'window>,
+    pub user_interface: &'a mut user_interface::UserInterface<'window>,
+    pub sprite_sheet: &'a mut Vec<sync::Arc<image::RgbaImage>>,
+    pub sprites: &'a mut Vec<sprite::Sprite>,
+    pub sprite_renderer: &'a mut sprite::renderer::SpriteRenderer<'window>,
+    pub light_renderer: &'a mut rendering::lighting::LightRenderer<'window>,
+    pub post_processor: &'a mut rendering::post::PostProcessor<'window>,
+    pub textures: &'a mut rendering::textures::TextureManager,
+    pub world: &'a mut world::World,
+    /// Records every tick run through [`SceneContext::tick`] while a recording is going.
+    pub recorder: Option<&'a mut replay::Recorder>,
+}
+
+impl SceneContext<'_, '_> {
+    /// Runs one fixed tick of the world.
+    pub fn tick(&mut self) {
+        self.world.update();
+        if let Some(recorder) = &mut self.recorder {
+            recorder.tick(self.world);
+        }
+    }
+}
+
+pub enum Transition<S: ?Sized = dyn Scene> {
+    None,
+    Push(Box<S>),
+    Pop,
+    Replace(Box<S>),
+    /// Exits every scene on the stack before entering the new one.
+    Reset(Box<S>),
+    Quit,
+}
+
+pub trait Scene {
+    fn name(&self) -> &'static str;
+    /// Overlays leave the scenes beneath them drawn, but only the top scene is updated.
+    fn is_overlay(&self) -> bool {
+        false
+    }
+    fn enter(&mut self, context: &mut SceneContext) {
+        let _ = context;
+    }
+    fn exit(&mut self, context: &mut SceneContext) {
+        let _ = context;
+    }
+    fn update(&mut self, context: &mut SceneContext) -> Transition;
+    fn user_interface(&mut self, context: &egui::Context) {
+        let _ = context;
+    }
+    fn render(&mut self, context: &mut SceneContext) {
+        let _ = context;
+    }
+    /// Returns whether the event was consumed.
+    fn handle_input(&mut self, event: &egui::Event) -> bool {
+        let _ = event;
+        false
+    }
+}
+
+/// What [`SceneStack`] calls on the scenes it holds, handing them a `C` as context.
+///
+/// Every [`Scene`] is staged with a [`SceneContext`], the split lets the stack itself run
+/// without a GPU.
+pub trait Staged<C> {
+    fn name(&self) -> &'static str;
+    fn is_overlay(&self) -> bool;
+    fn enter(&mut self, context: &mut C);
+    fn exit(&mut self, context: &mut C);
+    fn update(&mut self, context: &mut C) -> Transition<Self>;
+    fn render(&mut self, context: &mut C);
+}
+
+impl<'a, 'window> Staged<SceneContext<'a, 'window>> for dyn Scene {
+    fn name(&self) -> &'static str {
+        Scene::name(self)
+    }
+    fn is_overlay(&self) -> bool {
+        Scene::is_overlay(self)
+    }
+    fn enter(&mut self, context: &mut SceneContext<'a, 'window>) {
+        Scene::enter(self, context);
+    }
+    fn exit(&mut self, context: &mut SceneContext<'a, 'window>) {
+        Scene::exit(self, context);
+    }
+    fn update(&mut self, context: &mut SceneContext<'a, 'window>) -> Transition {
+        Scene::update(self, context)
+    }
+    fn render(&mut self, context: &mut SceneContext<'a, 'window>) {
+        Scene::render(self, context);
+    }
+}
+
+pub struct SceneStack<S: ?Sized = dyn Scene> {
+    scenes: Vec<Box<S>>,
+}
+
+impl<S: ?Sized> Default for SceneStack<S> {
+    fn default() -> Self {
+        Self { scenes: Vec::new() }
+    }
+}
+
+impl<S: ?Sized> SceneStack<S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.scenes.is_empty()
+    }
+    pub fn names<C>(&self) -> impl Iterator<Item = &'static str> + '_
+    where
+        S: Staged<C>,
+    {
+        self.scenes.iter().map(|scene| scene.name())
+    }
+    pub fn push<C>(&mut self, mut scene: Box<S>, context: &mut C)
+    where
+        S: Staged<C>,
+    {
+        log::info!("Entering scene: {}", scene.name());
+        scene.enter(context);
+        self.scenes.push(scene);
+    }
+    pub fn pop<C>(&mut self, context: &mut C) -> Option<Box<S>>
+    where
+        S: Staged<C>,
+    {
+        let mut scene = self.scenes.pop()?;
+        log::info!("Exiting scene: {}", scene.name());
+        scene.exit(context);
+        Some(scene)
+    }
+    pub fn replace<C>(&mut self, scene: Box<S>, context: &mut C)
+    where
+        S: Staged<C>,
+    {
+        self.pop(context);
+        self.push(scene, context);
+    }
+    pub fn reset<C>(&mut self, scene: Box<S>, context: &mut C)
+    where
+        S: Staged<C>,
+    {
+        while self.pop(context).is_some() {}
+        self.push(scene, context);
+    }
+    pub fn apply<C>(&mut self, transition: Transition<S>, context: &mut C)
+    where
+        S: Staged<C>,
+    {
+        match transition {
+            Transition::None => (),
+            Transition::Push(scene) => self.push(scene, context),
+            Transition::Pop => {
+                self.pop(context);
+            }
+            Transition::Replace(scene) => self.replace(scene, context),
+            Transition::Reset(scene) => self.reset(scene, context),
+            Transition::Quit => while self.pop(context).is_some() {},
+        }
+    }
+    pub fn update<C>(&mut self, context: &mut C)
+    where
+        S: Staged<C>,
+    {
+        let Some(scene) = self.scenes.last_mut() else {
+            return;
+        };
+        let transition = scene.update(context);
+        self.apply(transition, context);
+    }
+    pub fn render<C>(&mut self, context: &mut C)
+    where
+        S: Staged<C>,
+    {
+        let first_visible = self.first_visible();
+        for scene in &mut self.scenes[first_visible..] {
+            scene.render(context);
+        }
+    }
+    fn first_visible<C>(&self) -> usize
+    where
+        S: Staged<C>,
+    {
+        self.scenes
+            .iter()
+            .rposition(|scene| !scene.is_overlay())
+            .unwrap_or(0)
+    }
+}
+
+impl SceneStack {
+    /// Offers the event to the top scene only, overlays block input to the scenes beneath.
+    pub fn handle_input(&mut self, event: &egui::Event) -> bool {
+        self.scenes
+            .last_mut()
+            .is_some_and(|scene| scene.handle_input(event))
+    }
+    pub fn user_interface(&mut self, context: &egui::Context) {
+        let first_visible = self.first_visible::<SceneContext>();
+        for scene in &mut self.scenes[first_visible..] {
+            scene.user_interface(context);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The stack only needs a context to hand to its scenes, these tests use it as a log.
+    type Log = Vec<String>;
+
+    /// Logs what the stack does to it and returns the transitions it is given, one per update.
+    struct StubScene {
+        name: &'static str,
+        overlay: bool,
+        transitions: Vec<Transition<StubScene>>,
+    }
+
+    impl StubScene {
+        fn new(name: &'static str) -> Box<Self> {
+            Box::new(Self {
+                name,
+                overlay: false,
+                transitions: Vec::new(),
+            })
+        }
+        fn overlay(mut self: Box<Self>) -> Box<Self> {
+            self.overlay = true;
+            self
+        }
+        fn then(mut self: Box<Self>, transition: Transition<StubScene>) -> Box<Self> {
+            self.transitions.insert(0, transition);
+            self
+        }
+    }
+
+    impl Staged<Log> for StubScene {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+        fn is_overlay(&self) -> bool {
+            self.overlay
+        }
+        fn enter(&mut self, log: &mut Log) {
+            log.push(format!("enter {}", self.name));
+        }
+        fn exit(&mut self, log: &mut Log) {
+            log.push(format!("exit {}", self.name));
+        }
+        fn update(&mut self, log: &mut Log) -> Transition<StubScene> {
+            log.push(format!("update {}", self.name));
+            self.transitions.pop().unwrap_or(Transition::None)
+        }
+        fn render(&mut self, log: &mut Log) {
+            log.push(format!("render {}", self.name));
+        }
+    }
+
+    fn names(stack: &SceneStack<StubScene>) -> Vec<&'static str> {
+        stack.names::<Log>().collect()
+    }
+
+    #[test]
+    fn push_and_pop_enter_and_exit() {
+        let mut log = Log::new();
+        let mut stack = SceneStack::new();
+        stack.push(StubScene::new("game"), &mut log);
+        stack.push(StubScene::new("pause"), &mut log);
+        assert_eq!(names(&stack), ["game", "pause"]);
+        assert_eq!(std::mem::take(&mut log), ["enter game", "enter pause"]);
+
+        let popped = stack.pop(&mut log).expect("a scene to pop");
+        assert_eq!(popped.name, "pause");
+        assert_eq!(std::mem::take(&mut log), ["exit pause"]);
+        stack.pop(&mut log);
+        assert!(stack.pop(&mut log).is_none());
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn replace_swaps_only_the_top_scene() {
+        let mut log = Log::new();
+        let mut stack = SceneStack::new();
+        stack.push(StubScene::new("menu"), &mut log);
+        stack.push(StubScene::new("loading"), &mut log);
+        log.clear();
+
+        stack.replace(StubScene::new("game"), &mut log);
+        assert_eq!(log, ["exit loading", "enter game"]);
+        assert_eq!(names(&stack), ["menu", "game"]);
+    }
+
+    #[test]
+    fn update_applies_the_transition_of_the_top_scene() {
+        let mut log = Log::new();
+        let mut stack = SceneStack::new();
+        let game = StubScene::new("game").then(Transition::Push(
+            StubScene::new("pause").then(Transition::Pop),
+        ));
+        stack.push(game, &mut log);
+        log.clear();
+
+        stack.update(&mut log);
+        assert_eq!(std::mem::take(&mut log), ["update game", "enter pause"]);
+        stack.update(&mut log);
+        assert_eq!(std::mem::take(&mut log), ["update pause", "exit pause"]);
+        assert_eq!(names(&stack), ["game"]);
+
+        stack.apply(Transition::Reset(StubScene::new("menu")), &mut log);
+        assert_eq!(std::mem::take(&mut log), ["exit game", "enter menu"]);
+        stack.apply(Transition::Quit, &mut log);
+        assert_eq!(log, ["exit menu"]);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn updating_an_empty_stack_does_nothing() {
+        let mut log = Log::new();
+        let mut stack = SceneStack::<StubScene>::new();
+        stack.update(&mut log);
+        stack.render(&mut log);
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn overlays_render_over_the_scene_beneath() {
+        let mut log = Log::new();
+        let mut stack = SceneStack::new();
+        stack.push(StubScene::new("menu"), &mut log);
+        stack.push(StubScene::new("game"), &mut log);
+        stack.push(StubScene::new("pause").overlay(), &mut log);
+        log.clear();
+
+        stack.render(&mut log);
+        assert_eq!(log, ["render game", "render pause"]);
+    }
+}
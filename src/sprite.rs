@@ -1,19 +1,125 @@
 use crate::rendering;
-use std::sync;
+use crate::rendering::compression;
+use crate::rendering::sampler;
+use crate::rendering::textures;
+use std::path;
+use std::time;
+
+pub mod bake;
+pub mod renderer;
+pub mod sheet;
+
+/// Where one frame lies in the texture of its sprite.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub uv_min: glam::Vec2,
+    pub uv_max: glam::Vec2,
+    /// Point of the frame drawn at the entity position, from 0 to 1 from the top left corner.
+    pub pivot: glam::Vec2,
+    /// How long the frame shows when the sprite is animated, `None` if the source did not say.
+    pub duration: Option<time::Duration>,
+}
+
+impl Frame {
+    /// Frames of equal width side by side, pivoted on their centre.
+    pub fn strip(frames: u16) -> Vec<Frame> {
+        let count = frames.max(1) as f32;
+        (0..frames.max(1))
+            .map(|frame| Frame {
+                uv_min: glam::vec2(frame as f32 / count, 0.0),
+                uv_max: glam::vec2((frame as f32 + 1.0) / count, 1.0),
+                pivot: glam::Vec2::splat(0.5),
+                duration: None,
+            })
+            .collect()
+    }
+}
 
 pub struct Sprite {
-    texture: sync::Arc<GpuTexture>,
-    frames: u16,
+    texture: textures::TextureHandle,
+    frames: Vec<Frame>,
+    source: Option<path::PathBuf>,
+    normals: Option<textures::TextureHandle>,
 }
 
 impl Sprite {
-    pub fn new(texture: sync::Arc<GpuTexture>, frames: u16) -> Self {
-        Self { texture, frames }
+    /// A sprite whose texture is a strip of `frames` frames, like baked models.
+    pub fn new(texture: textures::TextureHandle, frames: u16) -> Self {
+        Self::with_frames(texture, Frame::strip(frames))
+    }
+    pub fn with_frames(texture: textures::TextureHandle, frames: Vec<Frame>) -> Self {
+        Self {
+            texture,
+            frames,
+            source: None,
+            normals: None,
+        }
+    }
+    /// The model or image the sprite was made from, watched for changes while the game runs.
+    pub fn with_source(mut self, source: path::PathBuf) -> Self {
+        self.source = Some(source);
+        self
+    }
+    pub fn source(&self) -> Option<&path::Path> {
+        self.source.as_deref()
+    }
+    /// A normal map laid out like the sheet, sprites without one are lit as if they were flat.
+    pub fn with_normals(mut self, normals: textures::TextureHandle) -> Self {
+        self.normals = Some(normals);
+        self
+    }
+    pub fn normals(&self) -> Option<textures::TextureHandle> {
+        self.normals
+    }
+    /// The sheet in the [`textures::TextureManager`], which may have to upload it again.
+    pub fn texture(&self) -> textures::TextureHandle {
+        self.texture
+    }
+    pub fn frames(&self) -> u16 {
+        self.frames.len() as u16
+    }
+    /// Frame `index`, wrapping around so animations can count up forever.
+    pub fn frame(&self, index: u16) -> Frame {
+        match self.frames.len() {
+            0 => Frame::strip(1)[0],
+            frames => self.frames[index as usize % frames],
+        }
+    }
+    /// Replaces the frames, for sources whose metadata changed while the game runs.
+    pub fn set_frames(&mut self, frames: Vec<Frame>) {
+        self.frames = frames;
     }
 }
 
+/// How [`GpuTexture::from_image_with`] samples the image.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TextureOptions {
+    pub sampler: sampler::Sampler,
+    /// Generates a full mip chain, for textures that are drawn smaller than they are. Only
+    /// colour textures get mips.
+    pub mipmaps: bool,
+    /// Keeps the values as they are instead of treating them as sRGB colour.
+    pub linear: bool,
+}
+
+impl TextureOptions {
+    /// Sprites shrink when the camera zooms out, but stay pixel sharp when it zooms in.
+    pub const SPRITE: Self = Self {
+        sampler: sampler::Sampler::PIXEL,
+        mipmaps: true,
+        linear: false,
+    };
+    /// Normal maps of sprites, sampled like them.
+    pub const NORMALS: Self = Self {
+        sampler: sampler::Sampler::PIXEL,
+        mipmaps: false,
+        linear: true,
+    };
+}
+
 pub struct GpuTexture {
     texture: wgpu::Texture,
+    #[allow(dead_code)]
     view: wgpu::TextureView,
     bind_group: wgpu::BindGroup,
 }
@@ -41,9 +147,17 @@ impl GpuTexture {
                 },
             ],
         };
+    /// A texture sampled with [`sampler::Sampler::NEAREST`].
     pub fn new(
         texture_descriptor: wgpu::TextureDescriptor,
         gpu_handle: rendering::GpuHandle,
+    ) -> Self {
+        Self::with_sampler(texture_descriptor, sampler::Sampler::NEAREST, gpu_handle)
+    }
+    pub fn with_sampler(
+        texture_descriptor: wgpu::TextureDescriptor,
+        sampler: sampler::Sampler,
+        gpu_handle: rendering::GpuHandle,
     ) -> Self {
         let gpu = gpu_handle.read().unwrap();
         let texture = gpu.device().create_texture(&texture_descriptor);
@@ -60,19 +174,7 @@ impl GpuTexture {
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&gpu.device().create_sampler(
-                        &wgpu::SamplerDescriptor {
-                            label: Some("Texture Sampler"),
-                            address_mode_u: wgpu::AddressMode::ClampToEdge,
-                            address_mode_v: wgpu::AddressMode::ClampToEdge,
-                            address_mode_w: wgpu::AddressMode::ClampToEdge,
-                            mag_filter: wgpu::FilterMode::Nearest,
-                            min_filter: wgpu::FilterMode::Nearest,
-                            mipmap_filter: wgpu::FilterMode::Nearest,
-                            anisotropy_clamp: 1,
-                            ..Default::default()
-                        },
-                    )),
+                    resource: wgpu::BindingResource::Sampler(&gpu.sampler(sampler)),
                 },
             ],
         });
@@ -82,6 +184,166 @@ impl GpuTexture {
             bind_group,
         }
     }
+    /// A nearest sampled texture without mips, like the user interface needs.
+    pub fn from_image(
+        label: &str,
+        image: &image::RgbaImage,
+        gpu_handle: rendering::GpuHandle,
+    ) -> Self {
+        Self::from_image_with(label, image, TextureOptions::default(), gpu_handle)
+    }
+    pub fn from_image_with(
+        label: &str,
+        image: &image::RgbaImage,
+        options: TextureOptions,
+        gpu_handle: rendering::GpuHandle,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width: image.width(),
+            height: image.height(),
+            depth_or_array_layers: 1,
+        };
+        let (mip_level_count, render_usage) = match options.mipmaps && !options.linear {
+            true => (
+                size.max_mips(wgpu::TextureDimension::D2),
+                wgpu::TextureUsages::RENDER_ATTACHMENT,
+            ),
+            false => (1, wgpu::TextureUsages::empty()),
+        };
+        let texture = Self::with_sampler(
+            wgpu::TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: match options.linear {
+                    true => wgpu::TextureFormat::Rgba8Unorm,
+                    false => rendering::mipmap::FORMAT,
+                },
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC
+                    | wgpu::TextureUsages::COPY_DST
+                    | render_usage,
+                view_formats: &[],
+            },
+            options.sampler,
+            gpu_handle.clone(),
+        );
+        texture.write_image(image, &gpu_handle);
+        texture
+    }
+    /// Replaces the contents with `image`, which must have the size of the texture, and
+    /// regenerates its mips.
+    ///
+    /// The texture, view and bind group stay the same, so every holder of the texture draws the
+    /// new contents from the next frame on.
+    pub fn write_image(&self, image: &image::RgbaImage, gpu_handle: &rendering::GpuHandle) {
+        let size = wgpu::Extent3d {
+            width: image.width(),
+            height: image.height(),
+            depth_or_array_layers: 1,
+        };
+        assert_eq!(
+            size,
+            self.texture.size(),
+            "image should match the texture size"
+        );
+        let gpu = gpu_handle.read().unwrap();
+        gpu.queue().write_texture(
+            self.texture.as_image_copy(),
+            image.as_raw(),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * image.width()),
+                rows_per_image: Some(image.height()),
+            },
+            size,
+        );
+        gpu.generate_mips(&self.texture);
+    }
+    /// A texture holding the baked mip chain of `image`, which is not regenerated on the GPU
+    /// since compressed formats cannot be rendered to.
+    pub fn from_compressed(
+        label: &str,
+        image: &compression::CompressedImage,
+        sampler: sampler::Sampler,
+        gpu_handle: rendering::GpuHandle,
+    ) -> Self {
+        let texture = Self::with_sampler(
+            wgpu::TextureDescriptor {
+                label: Some(label),
+                size: image.size(),
+                mip_level_count: image.levels.len() as u32,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: image.format.texture_format(),
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            sampler,
+            gpu_handle.clone(),
+        );
+        texture.write_compressed(image, &gpu_handle);
+        texture
+    }
+    /// Replaces every mip level with those of `image`, like [`GpuTexture::write_image`] does
+    /// for RGBA textures.
+    pub fn write_compressed(
+        &self,
+        image: &compression::CompressedImage,
+        gpu_handle: &rendering::GpuHandle,
+    ) {
+        let format = image.format.texture_format();
+        assert_eq!(
+            format,
+            self.texture.format(),
+            "image should match the texture format"
+        );
+        assert_eq!(
+            image.size(),
+            self.texture.size(),
+            "image should match the texture size"
+        );
+        let gpu = gpu_handle.read().unwrap();
+        for (level, data) in image.levels.iter().enumerate() {
+            let size = image
+                .size()
+                .mip_level_size(level as u32, wgpu::TextureDimension::D2)
+                .physical_size(format);
+            gpu.queue().write_texture(
+                self.texel_copy_texture_info(level as u32, wgpu::Origin3d::ZERO),
+                data,
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(size.width / 4 * 16),
+                    rows_per_image: Some(size.height / 4),
+                },
+                size,
+            );
+        }
+    }
+    pub fn size(&self) -> (u32, u32) {
+        (self.texture.width(), self.texture.height())
+    }
+    /// Bytes of GPU memory the texture takes up with every mip level.
+    pub fn memory_size(&self) -> u64 {
+        let format = self.texture.format();
+        let (block_width, block_height) = format.block_dimensions();
+        let block_size = format.block_copy_size(None).unwrap_or(4) as u64;
+        (0..self.texture.mip_level_count())
+            .map(|level| {
+                let size = self
+                    .texture
+                    .size()
+                    .mip_level_size(level, self.texture.dimension());
+                size.width.div_ceil(block_width) as u64
+                    * size.height.div_ceil(block_height) as u64
+                    * size.depth_or_array_layers as u64
+                    * block_size
+            })
+            .sum()
+    }
     pub fn texture(&self) -> &wgpu::Texture {
         &self.texture
     }
@@ -92,7 +354,7 @@ impl GpuTexture {
         &self,
         mip_level: u32,
         origin: wgpu::Origin3d,
-    ) -> wgpu::TexelCopyTextureInfo {
+    ) -> wgpu::TexelCopyTextureInfo<'_> {
         wgpu::TexelCopyTextureInfo {
             texture: self.texture(),
             mip_level,
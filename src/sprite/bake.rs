@@ -0,0 +1,371 @@
+use std::path;
+use std::sync;
+use std::sync::atomic;
+use std::thread;
+use std::time;
+
+pub const ANGLES: u16 = 8;
+pub const FRAME_SIZE: u32 = 64;
+/// Colour of every covered texel, models have no materials yet.
+const ALBEDO: image::Rgba<u8> = image::Rgba([255, 255, 255, 255]);
+
+/// A baked model, its colour and its normals laid out alike.
+pub struct BakedSheet {
+    /// Unshaded, the lighting pass shades it through the normals.
+    pub color: sync::Arc<image::RgbaImage>,
+    /// View space normals mapped to `0..=255`, x right, y up and z towards the camera.
+    pub normals: sync::Arc<image::RgbaImage>,
+}
+
+pub type SpriteSheets = Vec<BakedSheet>;
+
+#[derive(Default)]
+pub struct BakeProgress {
+    pub models_total: atomic::AtomicU32,
+    pub models_done: atomic::AtomicU32,
+    pub frames_total: atomic::AtomicU32,
+    pub frames_done: atomic::AtomicU32,
+    pub bytes_baked: atomic::AtomicU64,
+}
+
+impl BakeProgress {
+    pub fn fraction(&self) -> f32 {
+        let frames_total = self.frames_total.load(atomic::Ordering::Acquire);
+        if frames_total == 0 {
+            return 0.0;
+        }
+        self.frames_done.load(atomic::Ordering::Acquire) as f32 / frames_total as f32
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct CancelToken(sync::Arc<atomic::AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, atomic::Ordering::Release)
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(atomic::Ordering::Acquire)
+    }
+}
+
+enum Job {
+    Import {
+        model: usize,
+        path: path::PathBuf,
+    },
+    Render {
+        model: usize,
+        angle: u16,
+        mesh: sync::Arc<Mesh>,
+    },
+}
+
+enum JobResult {
+    Imported {
+        model: usize,
+        mesh: sync::Arc<Mesh>,
+    },
+    Rendered {
+        model: usize,
+        angle: u16,
+        frame: image::RgbaImage,
+        normals: image::RgbaImage,
+    },
+    Failed(anyhow::Error),
+}
+
+/// Bakes every model into a horizontal strip of [`ANGLES`] frames on a pool of worker threads.
+///
+/// Each model is imported by one job and then rendered by one job per angle. The returned
+/// thread yields one sheet per model in the order of `model_paths`.
+pub fn spawn(
+    model_paths: Vec<path::PathBuf>,
+    progress: sync::Arc<BakeProgress>,
+    cancel: CancelToken,
+) -> thread::JoinHandle<anyhow::Result<SpriteSheets>> {
+    let workers = thread::available_parallelism().map_or(1, std::num::NonZero::get);
+    progress
+        .models_total
+        .store(model_paths.len() as u32, atomic::Ordering::Release);
+    progress.frames_total.store(
+        model_paths.len() as u32 * ANGLES as u32,
+        atomic::Ordering::Release,
+    );
+
+    thread::spawn(move || {
+        thread::scope(|scope| {
+            let (job_sender, job_receiver) = crossbeam_channel::unbounded::<Job>();
+            let (result_sender, result_receiver) = crossbeam_channel::unbounded::<JobResult>();
+
+            for _ in 0..workers {
+                let job_receiver = job_receiver.clone();
+                let result_sender = result_sender.clone();
+                let cancel = cancel.clone();
+                scope.spawn(move || work(job_receiver, result_sender, cancel));
+            }
+            drop(result_sender);
+
+            coordinate(model_paths, job_sender, result_receiver, &progress, &cancel)
+        })
+    })
+}
+
+fn coordinate(
+    model_paths: Vec<path::PathBuf>,
+    job_sender: crossbeam_channel::Sender<Job>,
+    result_receiver: crossbeam_channel::Receiver<JobResult>,
+    progress: &BakeProgress,
+    cancel: &CancelToken,
+) -> anyhow::Result<SpriteSheets> {
+    let empty = || image::RgbaImage::new(FRAME_SIZE * ANGLES as u32, FRAME_SIZE);
+    let mut sheets = model_paths
+        .iter()
+        .map(|_| (empty(), empty()))
+        .collect::<Vec<_>>();
+    let mut frames_left = vec![ANGLES; model_paths.len()];
+    let mut outstanding = model_paths.len();
+
+    for (model, path) in model_paths.into_iter().enumerate() {
+        job_sender.send(Job::Import { model, path })?;
+    }
+
+    while outstanding > 0 {
+        if cancel.is_cancelled() {
+            return Err(anyhow::anyhow!("sprite baking was cancelled"));
+        }
+        let result = match result_receiver.recv_timeout(time::Duration::from_millis(50)) {
+            Ok(result) => result,
+            Err(crossbeam_channel::RecvTimeoutError::Timeout) => continue,
+            Err(crossbeam_channel::RecvTimeoutError::Disconnected) => {
+                return Err(anyhow::anyhow!("sprite baking workers exited early"));
+            }
+        };
+        outstanding -= 1;
+        match result {
+            JobResult::Imported { model, mesh } => {
+                for angle in 0..ANGLES {
+                    job_sender.send(Job::Render {
+                        model,
+                        angle,
+                        mesh: mesh.clone(),
+                    })?;
+                }
+                outstanding += ANGLES as usize;
+            }
+            JobResult::Rendered {
+                model,
+                angle,
+                frame,
+                normals,
+            } => {
+                let x = (angle as u32 * FRAME_SIZE) as i64;
+                image::imageops::replace(&mut sheets[model].0, &frame, x, 0);
+                image::imageops::replace(&mut sheets[model].1, &normals, x, 0);
+                progress.frames_done.fetch_add(1, atomic::Ordering::AcqRel);
+                progress
+                    .bytes_baked
+                    .fetch_add(frame.as_raw().len() as u64, atomic::Ordering::AcqRel);
+                frames_left[model] -= 1;
+                if frames_left[model] == 0 {
+                    progress.models_done.fetch_add(1, atomic::Ordering::AcqRel);
+                }
+            }
+            JobResult::Failed(error) => {
+                cancel.cancel();
+                return Err(error);
+            }
+        }
+    }
+
+    Ok(sheets
+        .into_iter()
+        .map(|(color, normals)| BakedSheet {
+            color: sync::Arc::new(color),
+            normals: sync::Arc::new(normals),
+        })
+        .collect())
+}
+
+fn work(
+    job_receiver: crossbeam_channel::Receiver<Job>,
+    result_sender: crossbeam_channel::Sender<JobResult>,
+    cancel: CancelToken,
+) {
+    let importer = asset_importer::Importer::new();
+    for job in job_receiver {
+        if cancel.is_cancelled() {
+            return;
+        }
+        let result = match job {
+            Job::Import { model, path } => match Mesh::import(&importer, &path) {
+                Ok(mesh) => JobResult::Imported {
+                    model,
+                    mesh: sync::Arc::new(mesh),
+                },
+                Err(error) => {
+                    JobResult::Failed(error.context(format!("failed to import {}", path.display())))
+                }
+            },
+            Job::Render { model, angle, mesh } => {
+                let (frame, normals) = mesh.render(angle);
+                JobResult::Rendered {
+                    model,
+                    angle,
+                    frame,
+                    normals,
+                }
+            }
+        };
+        if result_sender.send(result).is_err() {
+            return;
+        }
+    }
+}
+
+/// Triangle soup of every mesh in a model, normalised to fit inside the unit sphere.
+pub struct Mesh {
+    triangles: Vec<[glam::Vec3; 3]>,
+}
+
+impl Mesh {
+    fn import(importer: &asset_importer::Importer, path: &path::Path) -> anyhow::Result<Self> {
+        let scene = importer.import_file(path)?;
+        let mut triangles = Vec::new();
+        for mesh in scene.meshes() {
+            let vertices = mesh
+                .vertices()
+                .into_iter()
+                .map(|vertex| glam::vec3(vertex.x, vertex.y, vertex.z))
+                .collect::<Vec<_>>();
+            for face in mesh.faces() {
+                let indices = face
+                    .indices()
+                    .iter()
+                    .map(|&index| {
+                        vertices.get(index as usize).copied().ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "face index {index} is out of range for {} vertices",
+                                vertices.len()
+                            )
+                        })
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                for fan in 1..indices.len().saturating_sub(1) {
+                    triangles.push([indices[0], indices[fan], indices[fan + 1]]);
+                }
+            }
+        }
+
+        let (min, max) = triangles
+            .iter()
+            .flatten()
+            .fold((glam::Vec3::MAX, glam::Vec3::MIN), |(min, max), vertex| {
+                (min.min(*vertex), max.max(*vertex))
+            });
+        let center = (min + max) * 0.5;
+        let radius = triangles
+            .iter()
+            .flatten()
+            .map(|vertex| vertex.distance(center))
+            .fold(f32::EPSILON, f32::max);
+        for vertex in triangles.iter_mut().flatten() {
+            *vertex = (*vertex - center) / radius;
+        }
+
+        Ok(Self { triangles })
+    }
+
+    /// The unshaded colour of the model seen from `angle` and its normals.
+    fn render(&self, angle: u16) -> (image::RgbaImage, image::RgbaImage) {
+        let rotation = glam::Mat3::from_rotation_x(-30f32.to_radians())
+            * glam::Mat3::from_rotation_y(std::f32::consts::TAU * angle as f32 / ANGLES as f32);
+        let half = FRAME_SIZE as f32 * 0.5;
+
+        let mut frame = image::RgbaImage::new(FRAME_SIZE, FRAME_SIZE);
+        let mut normals = image::RgbaImage::new(FRAME_SIZE, FRAME_SIZE);
+        let mut depth = vec![f32::MIN; (FRAME_SIZE * FRAME_SIZE) as usize];
+
+        for triangle in &self.triangles {
+            let [a, b, c] = triangle.map(|vertex| rotation * vertex);
+            let normal = (b - a).cross(c - a).normalize_or_zero();
+            if normal.z <= 0.0 {
+                continue;
+            }
+            let encoded = (normal * 0.5 + 0.5) * 255.0;
+            let normal_color =
+                image::Rgba([encoded.x as u8, encoded.y as u8, encoded.z as u8, 255]);
+
+            let [a, b, c] = [a, b, c]
+                .map(|vertex| glam::vec3(half + vertex.x * half, half - vertex.y * half, vertex.z));
+            let area = edge(a, b, c);
+            if area.abs() <= f32::EPSILON {
+                continue;
+            }
+            let min = a.min(b).min(c).max(glam::Vec3::ZERO);
+            let max = a
+                .max(b)
+                .max(c)
+                .min(glam::Vec3::splat(FRAME_SIZE as f32 - 1.0));
+            for y in min.y as u32..=max.y as u32 {
+                for x in min.x as u32..=max.x as u32 {
+                    let point = glam::vec3(x as f32 + 0.5, y as f32 + 0.5, 0.0);
+                    let weights =
+                        glam::vec3(edge(b, c, point), edge(c, a, point), edge(a, b, point)) / area;
+                    if weights.min_element() < 0.0 {
+                        continue;
+                    }
+                    let z = weights.dot(glam::vec3(a.z, b.z, c.z));
+                    let texel = (y * FRAME_SIZE + x) as usize;
+                    if z > depth[texel] {
+                        depth[texel] = z;
+                        frame.put_pixel(x, y, ALBEDO);
+                        normals.put_pixel(x, y, normal_color);
+                    }
+                }
+            }
+        }
+        (frame, normals)
+    }
+}
+
+fn edge(a: glam::Vec3, b: glam::Vec3, point: glam::Vec3) -> f32 {
+    (b.x - a.x) * (point.y - a.y) - (b.y - a.y) * (point.x - a.x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colour_is_not_shaded_by_the_normals() {
+        // A pyramid whose faces all point a different way.
+        let apex = glam::vec3(0.0, 0.0, 0.8);
+        let base = [
+            glam::vec3(-0.5, -0.5, 0.0),
+            glam::vec3(0.5, -0.5, 0.0),
+            glam::vec3(0.5, 0.5, 0.0),
+            glam::vec3(-0.5, 0.5, 0.0),
+        ];
+        let mesh = Mesh {
+            triangles: (0..4)
+                .map(|side| [base[side], base[(side + 1) % 4], apex])
+                .collect(),
+        };
+        let (frame, normals) = mesh.render(1);
+        let covered = frame
+            .pixels()
+            .zip(normals.pixels())
+            .filter(|(color, _)| color[3] != 0)
+            .collect::<Vec<_>>();
+        assert!(!covered.is_empty());
+        assert!(covered.iter().all(|(color, _)| **color == ALBEDO));
+        let mut directions = covered
+            .iter()
+            .map(|(_, normal)| **normal)
+            .collect::<Vec<_>>();
+        directions.dedup();
+        assert!(directions.len() > 1);
+    }
+}
@@ -0,0 +1,498 @@
+use wgpu::util::DeviceExt;
+
+use crate::rendering;
+use crate::rendering::camera;
+use crate::rendering::shader;
+use crate::sprite;
+use crate::world;
+use crate::world::components;
+use crate::world::spatial;
+use crate::world::tilemap;
+
+use std::collections;
+use std::sync;
+
+const SHADER: &str = include_str!("sprite.wgsl");
+/// Format of the normals the sprites leave behind for the lighting.
+pub const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+const CAMERA_BIND_GROUP_LAYOUT_DESCRIPTOR: wgpu::BindGroupLayoutDescriptor<'_> =
+    wgpu::BindGroupLayoutDescriptor {
+        label: Some("sprite camera bind group layout"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+    };
+
+fn create_render_pipeline(
+    gpu: &rendering::Gpu,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    gpu.device()
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("sprite render pipeline"),
+            layout: Some(
+                &gpu.device()
+                    .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: Some("sprite render pipeline layout"),
+                        bind_group_layouts: &[
+                            &gpu.device().create_bind_group_layout(
+                                &sprite::GpuTexture::BIND_GROUP_LAYOUT_DESCRIPTOR,
+                            ),
+                            &gpu.device()
+                                .create_bind_group_layout(&CAMERA_BIND_GROUP_LAYOUT_DESCRIPTOR),
+                            &gpu.device().create_bind_group_layout(
+                                &sprite::GpuTexture::BIND_GROUP_LAYOUT_DESCRIPTOR,
+                            ),
+                        ],
+                        push_constant_ranges: &[],
+                    }),
+            ),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: Some("vertex_main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[SpriteVertexInstance::BUFFER_LAYOUT],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: Some("fragment_main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format: gpu.surface_config().format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::all(),
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: NORMAL_FORMAT,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::all(),
+                    }),
+                ],
+            }),
+            multiview: None,
+            cache: None,
+        })
+}
+
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct SpriteVertexInstance {
+    pub position: glam::Vec2,
+    pub size: glam::Vec2,
+    pub uv_min: glam::Vec2,
+    pub uv_max: glam::Vec2,
+}
+
+impl SpriteVertexInstance {
+    pub const BUFFER_LAYOUT: wgpu::VertexBufferLayout<'_> = wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<Self>() as u64,
+        step_mode: wgpu::VertexStepMode::Instance,
+        attributes: &[
+            wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Float32x2,
+                offset: 0,
+                shader_location: 0,
+            },
+            wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Float32x2,
+                offset: std::mem::size_of::<[glam::Vec2; 1]>() as u64,
+                shader_location: 1,
+            },
+            wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Float32x2,
+                offset: std::mem::size_of::<[glam::Vec2; 2]>() as u64,
+                shader_location: 2,
+            },
+            wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Float32x2,
+                offset: std::mem::size_of::<[glam::Vec2; 3]>() as u64,
+                shader_location: 3,
+            },
+        ],
+    };
+    /// A quad of `size` whose [`sprite::Frame::pivot`] lies on `position`.
+    pub fn new(position: glam::Vec2, size: glam::Vec2, frame: sprite::Frame) -> Self {
+        // The quad is centred on its position, y up while the pivot is y down.
+        let offset = glam::vec2(frame.pivot.x - 0.5, 0.5 - frame.pivot.y) * size;
+        Self {
+            position: position - offset,
+            size,
+            uv_min: frame.uv_min,
+            uv_max: frame.uv_max,
+        }
+    }
+}
+
+type Batches = Vec<(usize, std::ops::Range<u32>)>;
+
+fn push_batched(
+    instances: &mut Vec<SpriteVertexInstance>,
+    batches: &mut Batches,
+    sprite: usize,
+    instance: SpriteVertexInstance,
+) {
+    let start = instances.len() as u32;
+    instances.push(instance);
+    match batches.last_mut() {
+        Some((batch_sprite, range)) if *batch_sprite == sprite => range.end = start + 1,
+        _ => batches.push((sprite, start..start + 1)),
+    }
+}
+
+/// GPU copy of a [`tilemap::Chunk`], uploaded again only when the chunk revision or the number
+/// of loaded sprites changes.
+struct ChunkBuffer {
+    revision: u64,
+    sprites: usize,
+    /// `None` while none of the chunk's sprites are loaded.
+    buffer: Option<wgpu::Buffer>,
+    batches: Batches,
+}
+
+/// What the sprite pass draws, for the passes that light and present it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SceneTargets {
+    /// In the format of the output.
+    pub color: rendering::graph::ResourceId,
+    /// In [`NORMAL_FORMAT`], transparent where no sprite was drawn.
+    pub normals: rendering::graph::ResourceId,
+}
+
+/// Draws the [`tilemap::Tilemap`] and then every [`components::SpriteInstance`] the camera can
+/// see, culled through the [`spatial::SpatialIndex`] when the world has one.
+pub struct SpriteRenderer<'window> {
+    gpu_handle: rendering::GpuHandle<'window>,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    pipeline: shader::HotPipeline,
+    instance_buffer: wgpu::Buffer,
+    instances: Vec<SpriteVertexInstance>,
+    batches: Batches,
+    tile_chunks: collections::HashMap<(usize, glam::IVec2), ChunkBuffer>,
+    /// The [`tilemap::Tilemap::id`] the cached chunks were built from.
+    tilemap: Option<u64>,
+    visible_chunks: Vec<(usize, glam::IVec2)>,
+    /// Bound for sprites without a normal map.
+    flat_normals: sync::Arc<sprite::GpuTexture>,
+}
+
+impl<'window> SpriteRenderer<'window> {
+    const INITIAL_INSTANCES: u64 = 1024;
+
+    pub fn new(gpu_handle: rendering::GpuHandle<'window>) -> Self {
+        let gpu = gpu_handle.read().unwrap();
+        let camera_buffer = gpu
+            .device()
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("sprite camera buffer"),
+                contents: bytemuck::cast_slice(&[glam::Mat4::IDENTITY]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+        let camera_bind_group = gpu.device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sprite camera bind group"),
+            layout: &gpu
+                .device()
+                .create_bind_group_layout(&CAMERA_BIND_GROUP_LAYOUT_DESCRIPTOR),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+        });
+        let instance_buffer = Self::create_instance_buffer(gpu.device(), Self::INITIAL_INSTANCES);
+        drop(gpu);
+        let flat_normals = sync::Arc::new(sprite::GpuTexture::from_image_with(
+            "flat normals",
+            &image::RgbaImage::from_pixel(1, 1, image::Rgba([128, 128, 255, 255])),
+            sprite::TextureOptions::NORMALS,
+            gpu_handle.clone(),
+        ));
+        Self {
+            gpu_handle,
+            camera_buffer,
+            camera_bind_group,
+            pipeline: shader::HotPipeline::new(
+                "sprite shader",
+                SHADER,
+                "src/sprite/sprite.wgsl",
+                create_render_pipeline,
+            ),
+            instance_buffer,
+            instances: Vec::new(),
+            batches: Vec::new(),
+            tile_chunks: collections::HashMap::new(),
+            tilemap: None,
+            visible_chunks: Vec::new(),
+            flat_normals,
+        }
+    }
+    fn create_instance_buffer(device: &wgpu::Device, instances: u64) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sprite instance buffer"),
+            size: instances * std::mem::size_of::<SpriteVertexInstance>() as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+    /// Collects the visible sprites into one instance batch per sprite sheet.
+    fn prepare(&mut self, world: &world::World, sprites: &[sprite::Sprite], view: &spatial::Aabb) {
+        let mut visible = Vec::new();
+        match world.resource::<spatial::SpatialIndex>() {
+            Some(index) => {
+                for entity in index.query_aabb(view) {
+                    if let (Some(position), Some(instance)) = (
+                        world.get::<components::Position>(entity),
+                        world.get::<components::SpriteInstance>(entity),
+                    ) {
+                        visible.push((*position, *instance));
+                    }
+                }
+            }
+            None => world.query::<(&components::Position, &components::SpriteInstance)>(
+                |_, (position, instance)| visible.push((*position, *instance)),
+            ),
+        }
+        visible.retain(|(_, instance)| instance.sprite < sprites.len());
+        visible.sort_by_key(|(_, instance)| instance.sprite);
+
+        self.instances.clear();
+        self.batches.clear();
+        for (position, instance) in visible {
+            push_batched(
+                &mut self.instances,
+                &mut self.batches,
+                instance.sprite,
+                SpriteVertexInstance::new(
+                    position.0,
+                    instance.size,
+                    sprites[instance.sprite].frame(instance.frame),
+                ),
+            );
+        }
+    }
+    /// Uploads the visible tile chunks that changed since they were last drawn and drops the
+    /// copies of chunks that were emptied or belong to a replaced tilemap.
+    fn prepare_tiles(
+        &mut self,
+        world: &world::World,
+        sprites: &[sprite::Sprite],
+        view: &spatial::Aabb,
+        device: &wgpu::Device,
+    ) {
+        self.visible_chunks.clear();
+        let Some(tilemap) = world.resource::<tilemap::Tilemap>() else {
+            self.tile_chunks.clear();
+            self.tilemap = None;
+            return;
+        };
+        if self.tilemap != Some(tilemap.id()) {
+            self.tile_chunks.clear();
+            self.tilemap = Some(tilemap.id());
+        }
+        self.tile_chunks.retain(|(layer, coordinate), _| {
+            tilemap
+                .layers()
+                .get(*layer)
+                .and_then(|layer| layer.chunk(*coordinate))
+                .is_some_and(|chunk| !chunk.quads().is_empty())
+        });
+        for (layer_index, layer) in tilemap.layers().iter().enumerate() {
+            for (coordinate, chunk) in layer.chunks() {
+                if chunk.quads().is_empty() || !tilemap.chunk_aabb(coordinate).intersects(view) {
+                    continue;
+                }
+                let key = (layer_index, coordinate);
+                let stale = self.tile_chunks.get(&key).is_none_or(|cached| {
+                    cached.revision != chunk.revision() || cached.sprites != sprites.len()
+                });
+                if stale {
+                    let mut instances = Vec::new();
+                    let mut batches = Vec::new();
+                    for quad in chunk.quads() {
+                        let Some(sheet) = sprites.get(quad.sprite) else {
+                            continue;
+                        };
+                        push_batched(
+                            &mut instances,
+                            &mut batches,
+                            quad.sprite,
+                            SpriteVertexInstance::new(
+                                quad.position,
+                                glam::Vec2::splat(tilemap.tile_size),
+                                sheet.frame(quad.frame),
+                            ),
+                        );
+                    }
+                    let buffer = (!instances.is_empty()).then(|| {
+                        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                            label: Some("tile chunk instance buffer"),
+                            contents: bytemuck::cast_slice(&instances),
+                            usage: wgpu::BufferUsages::VERTEX,
+                        })
+                    });
+                    self.tile_chunks.insert(
+                        key,
+                        ChunkBuffer {
+                            revision: chunk.revision(),
+                            sprites: sprites.len(),
+                            buffer,
+                            batches,
+                        },
+                    );
+                }
+                if self.tile_chunks[&key].buffer.is_some() {
+                    self.visible_chunks.push(key);
+                }
+            }
+        }
+    }
+    /// Adds a pass drawing the sprites and their normals to new targets, making the sheets it
+    /// draws resident in `textures`.
+    pub fn render(
+        &mut self,
+        world: &world::World,
+        sprites: &[sprite::Sprite],
+        textures: &mut rendering::textures::TextureManager,
+    ) -> SceneTargets {
+        let camera = world
+            .resource::<camera::Camera>()
+            .map(|camera| *camera)
+            .unwrap_or_default();
+        let gpu = self.gpu_handle.read().unwrap();
+        let viewport = glam::vec2(
+            gpu.surface_config().width as f32,
+            gpu.surface_config().height as f32,
+        );
+        drop(gpu);
+        self.prepare(world, sprites, &camera.view_rect(viewport));
+
+        self.pipeline.refresh(&self.gpu_handle);
+        let gpu_handle = self.gpu_handle.clone();
+        let gpu = gpu_handle.read().unwrap();
+        self.prepare_tiles(world, sprites, &camera.view_rect(viewport), gpu.device());
+        drop(gpu);
+        // Resolved before the GPU is locked for the pass, evicted sheets are uploaded again.
+        let mut sheets = collections::HashMap::new();
+        let drawn = self
+            .visible_chunks
+            .iter()
+            .flat_map(|key| &self.tile_chunks[key].batches)
+            .chain(&self.batches)
+            .map(|(sprite, _)| *sprite);
+        for sprite in drawn {
+            if let collections::hash_map::Entry::Vacant(entry) = sheets.entry(sprite)
+                && let Some(texture) = textures.get(sprites[sprite].texture(), &gpu_handle)
+            {
+                let normals = sprites[sprite]
+                    .normals()
+                    .and_then(|normals| textures.get(normals, &gpu_handle))
+                    .unwrap_or_else(|| self.flat_normals.clone());
+                entry.insert((texture, normals));
+            }
+        }
+        let mut gpu = gpu_handle.write().unwrap();
+        let required = self.instances.len() as u64;
+        if required * std::mem::size_of::<SpriteVertexInstance>() as u64
+            > self.instance_buffer.size()
+        {
+            self.instance_buffer =
+                Self::create_instance_buffer(gpu.device(), required.next_power_of_two());
+        }
+        gpu.queue().write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[camera.view_projection(viewport)]),
+        );
+        if !self.instances.is_empty() {
+            gpu.queue().write_buffer(
+                &self.instance_buffer,
+                0,
+                bytemuck::cast_slice(&self.instances),
+            );
+        }
+
+        // Everything the pass draws is cloned out, it runs when the frame is submitted.
+        let draw_batches = |batches: &[(usize, std::ops::Range<u32>)]| {
+            batches
+                .iter()
+                .filter_map(|(sprite, range)| Some((sheets.get(sprite)?.clone(), range.clone())))
+                .collect::<Vec<_>>()
+        };
+        let mut draws = self
+            .visible_chunks
+            .iter()
+            .filter_map(|key| {
+                let chunk = &self.tile_chunks[key];
+                Some((chunk.buffer.clone()?, draw_batches(&chunk.batches)))
+            })
+            .collect::<Vec<_>>();
+        draws.push((self.instance_buffer.clone(), draw_batches(&self.batches)));
+        let pipeline = self.pipeline.current().clone();
+        let camera_bind_group = self.camera_bind_group.clone();
+        let format = gpu.surface_config().format;
+        let graph = gpu.render_graph();
+        let targets = SceneTargets {
+            color: graph.create_target(rendering::graph::TargetDescriptor {
+                label: "scene color",
+                format,
+                size: rendering::graph::TargetSize::Output,
+            }),
+            normals: graph.create_target(rendering::graph::TargetDescriptor {
+                label: "scene normals",
+                format: NORMAL_FORMAT,
+                size: rendering::graph::TargetSize::Output,
+            }),
+        };
+        graph
+            .add_pass("sprites")
+            .write(targets.color)
+            .write(targets.normals)
+            .execute(move |command_encoder, resources| {
+                let mut render_pass =
+                    command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("sprite render pass"),
+                        color_attachments: &[
+                            resources.color_attachment(targets.color),
+                            resources.color_attachment(targets.normals),
+                        ],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                    });
+                render_pass.set_pipeline(&pipeline);
+                render_pass.set_bind_group(1, &camera_bind_group, &[]);
+                for (buffer, batches) in &draws {
+                    render_pass.set_vertex_buffer(0, buffer.slice(..));
+                    for ((texture, normals), range) in batches {
+                        render_pass.set_bind_group(0, texture.bind_group(), &[]);
+                        render_pass.set_bind_group(2, normals.bind_group(), &[]);
+                        render_pass.draw(0..6, range.clone());
+                    }
+                }
+            });
+        targets
+    }
+}
@@ -0,0 +1,472 @@
+use crate::rendering::compression;
+use crate::rendering::textures;
+use crate::sprite;
+use crate::sprite::bake;
+use std::fs;
+use std::path;
+use std::sync;
+use std::thread;
+use std::time;
+
+/// Extension of the simple metadata format read by [`read_simple`].
+pub const SIMPLE_EXTENSION: &str = "sheet";
+
+/// A sheet image with the frames cut out of it, ready to become a [`sprite::Sprite`].
+#[derive(Clone)]
+pub struct SpriteSheet {
+    pub image: sync::Arc<image::RgbaImage>,
+    pub frames: Vec<sprite::Frame>,
+    /// The image block compressed, uploaded instead of it where present.
+    pub compressed: Option<sync::Arc<compression::CompressedImage>>,
+    /// Normals laid out like the image, only baked models have them.
+    pub normals: Option<sync::Arc<image::RgbaImage>>,
+}
+
+impl SpriteSheet {
+    /// A baked model, [`bake::ANGLES`] frames side by side.
+    pub fn baked(baked: bake::BakedSheet) -> Self {
+        Self {
+            image: baked.color,
+            frames: sprite::Frame::strip(bake::ANGLES),
+            compressed: None,
+            normals: Some(baked.normals),
+        }
+    }
+    /// Square frames side by side, or a single frame if the image is not such a strip.
+    pub fn strip(image: sync::Arc<image::RgbaImage>) -> Self {
+        let frames = if image.height() > 0 && image.width().is_multiple_of(image.height()) {
+            (image.width() / image.height()).clamp(1, u16::MAX as u32) as u16
+        } else {
+            1
+        };
+        Self {
+            image,
+            frames: sprite::Frame::strip(frames),
+            compressed: None,
+            normals: None,
+        }
+    }
+    /// Compresses the image to `format` through the cache next to `source`, it stays RGBA if
+    /// its size is not a multiple of the block size.
+    pub fn compress(&mut self, format: compression::CompressedFormat, source: &path::Path) {
+        self.compressed =
+            compression::compress_cached(&self.image, format, source).map(sync::Arc::new);
+    }
+    /// What `textures` uploads the sheet from, see [`SpriteSheet::register`].
+    pub fn texture_source(&self, source: &path::Path) -> textures::TextureSource {
+        match (&self.compressed, is_image(source)) {
+            (Some(compressed), _) => textures::TextureSource::Compressed(compressed.clone()),
+            (None, true) => textures::TextureSource::File(source.to_path_buf()),
+            (None, false) => textures::TextureSource::Image(self.image.clone()),
+        }
+    }
+    /// Hands the image to `textures` and makes the sprite, remembering `source` for hot
+    /// reloading. Compressed sheets are uploaded from their blocks again after an eviction,
+    /// other images from `source` and baked models from the CPU copy.
+    pub fn register(
+        &self,
+        label: &str,
+        source: path::PathBuf,
+        textures: &mut textures::TextureManager,
+    ) -> anyhow::Result<sprite::Sprite> {
+        let texture = textures.insert(
+            label,
+            self.texture_source(&source),
+            sprite::TextureOptions::SPRITE,
+        )?;
+        let mut sprite = sprite::Sprite::with_frames(texture, self.frames.clone());
+        if let Some(normals) = &self.normals {
+            let normals = textures.insert(
+                &format!("{label} normals"),
+                textures::TextureSource::Image(normals.clone()),
+                sprite::TextureOptions::NORMALS,
+            )?;
+            sprite = sprite.with_normals(normals);
+        }
+        Ok(sprite.with_source(source))
+    }
+}
+
+/// Whether `path` is loaded by [`load`] rather than baked.
+pub fn is_image(path: &path::Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+}
+
+/// The metadata file describing the frames of `image`, an Aseprite JSON export or a
+/// [`SIMPLE_EXTENSION`] file with the same name next to it.
+pub fn metadata_path(image: &path::Path) -> Option<path::PathBuf> {
+    ["json", SIMPLE_EXTENSION]
+        .into_iter()
+        .map(|extension| image.with_extension(extension))
+        .find(|path| path.is_file())
+}
+
+/// Reads a PNG and cuts it by its metadata, or as a strip of square frames without any.
+pub fn load(path: &path::Path) -> anyhow::Result<SpriteSheet> {
+    let image = sync::Arc::new(image::open(path)?.into_rgba8());
+    let frames = match metadata_path(path) {
+        None => return Ok(SpriteSheet::strip(image)),
+        Some(metadata) => {
+            let text = fs::read_to_string(&metadata)?;
+            let frames = if metadata
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                read_aseprite(&text, image.dimensions())
+            } else {
+                read_simple(&text, image.dimensions())
+            };
+            frames.map_err(|error| error.context(format!("in {}", metadata.display())))?
+        }
+    };
+    Ok(SpriteSheet {
+        image,
+        frames,
+        compressed: None,
+        normals: None,
+    })
+}
+
+/// Loads PNG sheets and bakes models on one thread, returning one sheet per path in order.
+///
+/// Only models are reported through `progress`, images load in no time next to them. Every
+/// sheet is compressed to `compression` on the same thread, or read from the cache next to its
+/// path, see [`rendering::Gpu::compression`](crate::rendering::Gpu::compression).
+pub fn spawn(
+    paths: Vec<path::PathBuf>,
+    compression: Option<compression::CompressedFormat>,
+    progress: sync::Arc<bake::BakeProgress>,
+    cancel: bake::CancelToken,
+) -> thread::JoinHandle<anyhow::Result<Vec<SpriteSheet>>> {
+    let models = paths
+        .iter()
+        .filter(|path| !is_image(path))
+        .cloned()
+        .collect::<Vec<_>>();
+    let baking = (!models.is_empty()).then(|| bake::spawn(models, progress, cancel.clone()));
+    thread::spawn(move || {
+        let mut images = Vec::new();
+        for path in paths.iter().filter(|path| is_image(path)) {
+            if cancel.is_cancelled() {
+                anyhow::bail!("sprite loading was cancelled");
+            }
+            images
+                .push(load(path).map_err(|error| {
+                    error.context(format!("failed to load {}", path.display()))
+                })?);
+        }
+        let baked = match baking {
+            Some(baking) => baking
+                .join()
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("sprite baking thread panicked")))?,
+            None => Vec::new(),
+        };
+        let (mut images, mut baked) = (images.into_iter(), baked.into_iter());
+        let mut sheets = paths
+            .iter()
+            .filter_map(|path| match is_image(path) {
+                true => images.next(),
+                false => baked.next().map(SpriteSheet::baked),
+            })
+            .collect::<Vec<_>>();
+        if let Some(format) = compression {
+            for (sheet, path) in sheets.iter_mut().zip(&paths) {
+                if cancel.is_cancelled() {
+                    anyhow::bail!("sprite loading was cancelled");
+                }
+                sheet.compress(format, path);
+            }
+        }
+        Ok(sheets)
+    })
+}
+
+/// Frames of an Aseprite JSON export, in the hash or the array layout.
+///
+/// Export untrimmed frames, trimming moves the frames away from their pivot. Pivots come from
+/// the first slice that has one, each key applying from its frame on.
+pub fn read_aseprite(text: &str, size: (u32, u32)) -> anyhow::Result<Vec<sprite::Frame>> {
+    anyhow::ensure!(size.0 > 0 && size.1 > 0, "the sheet image is empty");
+    let document = json::parse(text)?;
+    let entries = match &document["frames"] {
+        json::JsonValue::Array(frames) => frames.iter().collect::<Vec<_>>(),
+        json::JsonValue::Object(frames) => frames.iter().map(|(_, frame)| frame).collect(),
+        _ => anyhow::bail!("no frames"),
+    };
+    let number = |value: &json::JsonValue, name: &str| {
+        value[name]
+            .as_f32()
+            .ok_or_else(|| anyhow::anyhow!("frame without {name}"))
+    };
+    let pivot_keys = document["meta"]["slices"]
+        .members()
+        .map(|slice| {
+            slice["keys"]
+                .members()
+                .filter(|key| key["pivot"].is_object())
+                .collect::<Vec<_>>()
+        })
+        .find(|keys| !keys.is_empty())
+        .unwrap_or_default();
+    let texture = glam::vec2(size.0 as f32, size.1 as f32);
+    entries
+        .into_iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let rect = &entry["frame"];
+            let min = glam::vec2(number(rect, "x")?, number(rect, "y")?);
+            let extent = glam::vec2(number(rect, "w")?, number(rect, "h")?);
+            anyhow::ensure!(
+                extent.cmpgt(glam::Vec2::ZERO).all(),
+                "frame {index} is {}x{}",
+                extent.x,
+                extent.y
+            );
+            let pivot = pivot_keys
+                .iter()
+                .rev()
+                .find(|key| key["frame"].as_usize().is_some_and(|frame| frame <= index))
+                .map(|key| {
+                    let bounds = &key["bounds"];
+                    let pivot = &key["pivot"];
+                    Ok::<_, anyhow::Error>(
+                        glam::vec2(
+                            number(bounds, "x")? + number(pivot, "x")?,
+                            number(bounds, "y")? + number(pivot, "y")?,
+                        ) / extent,
+                    )
+                })
+                .transpose()?
+                .unwrap_or(glam::Vec2::splat(0.5));
+            Ok(sprite::Frame {
+                uv_min: min / texture,
+                uv_max: (min + extent) / texture,
+                pivot,
+                duration: entry["duration"].as_u64().map(time::Duration::from_millis),
+            })
+        })
+        .collect()
+}
+
+/// Frames of a grid described by `name = values` lines, `#` starts a comment:
+///
+/// - `frame_size = 32 32`, required, frames are cut row by row from the top left;
+/// - `count = 6`, defaults to every cell of the grid;
+/// - `pivot = 16 28` in pixels from the top left of a frame, defaults to the centre;
+/// - `duration = 100` in milliseconds, one value for every frame or one per frame.
+pub fn read_simple(text: &str, size: (u32, u32)) -> anyhow::Result<Vec<sprite::Frame>> {
+    let mut frame_size = None;
+    let mut count = None;
+    let mut pivot = None;
+    let mut durations = Vec::new();
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some((name, values)) = line.split_once('=') else {
+            anyhow::bail!("malformed line: {line}");
+        };
+        let values = values
+            .split_whitespace()
+            .map(str::parse::<u32>)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| anyhow::anyhow!("{line}: {error}"))?;
+        match (name.trim(), values.as_slice()) {
+            ("frame_size", [width, height]) if *width > 0 && *height > 0 => {
+                frame_size = Some(glam::uvec2(*width, *height))
+            }
+            ("frame_size", [_, _]) => anyhow::bail!("frames cannot be empty: {line}"),
+            ("count", [frames]) => count = Some(*frames),
+            ("pivot", [x, y]) => pivot = Some(glam::vec2(*x as f32, *y as f32)),
+            ("duration", durations_ms) => durations = durations_ms.to_vec(),
+            _ => anyhow::bail!("unknown or malformed setting: {line}"),
+        }
+    }
+    let frame_size = frame_size.ok_or_else(|| anyhow::anyhow!("frame_size is missing"))?;
+    let columns = size.0 / frame_size.x;
+    let cells = columns * (size.1 / frame_size.y);
+    let count = count.unwrap_or(cells);
+    if count == 0 || count > cells {
+        anyhow::bail!(
+            "{count} frames of {}x{} do not fit a {}x{} sheet",
+            frame_size.x,
+            frame_size.y,
+            size.0,
+            size.1
+        );
+    }
+    if durations.len() > 1 && durations.len() != count as usize {
+        anyhow::bail!("{} durations for {count} frames", durations.len());
+    }
+    let texture = glam::vec2(size.0 as f32, size.1 as f32);
+    let extent = frame_size.as_vec2();
+    Ok((0..count)
+        .map(|frame| {
+            let min = glam::uvec2(frame % columns, frame / columns).as_vec2() * extent;
+            let duration = match durations.as_slice() {
+                [] => None,
+                [every] => Some(*every),
+                each => Some(each[frame as usize]),
+            };
+            sprite::Frame {
+                uv_min: min / texture,
+                uv_max: (min + extent) / texture,
+                pivot: pivot.map_or(glam::Vec2::splat(0.5), |pivot| pivot / extent),
+                duration: duration.map(|duration| time::Duration::from_millis(duration as u64)),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: (u32, u32) = (64, 32);
+
+    fn error(result: anyhow::Result<Vec<sprite::Frame>>) -> String {
+        result.map(|_| ()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn reads_aseprite_hash_exports_with_pivots() {
+        let frames = read_aseprite(
+            r#"{
+                "frames": {
+                    "walk 0.png": { "frame": { "x": 0, "y": 0, "w": 32, "h": 32 }, "duration": 100 },
+                    "walk 1.png": { "frame": { "x": 32, "y": 0, "w": 32, "h": 32 }, "duration": 150 }
+                },
+                "meta": { "slices": [
+                    { "name": "hitbox", "keys": [ { "frame": 0, "bounds": { "x": 0, "y": 0, "w": 8, "h": 8 } } ] },
+                    { "name": "feet", "keys": [
+                        { "frame": 0, "bounds": { "x": 8, "y": 24, "w": 16, "h": 8 }, "pivot": { "x": 8, "y": 4 } },
+                        { "frame": 1, "bounds": { "x": 0, "y": 0, "w": 32, "h": 32 }, "pivot": { "x": 16, "y": 16 } }
+                    ] }
+                ] }
+            }"#,
+            SIZE,
+        )
+        .unwrap();
+        assert_eq!(
+            frames,
+            [
+                sprite::Frame {
+                    uv_min: glam::vec2(0.0, 0.0),
+                    uv_max: glam::vec2(0.5, 1.0),
+                    pivot: glam::vec2(0.5, 0.875),
+                    duration: Some(time::Duration::from_millis(100)),
+                },
+                sprite::Frame {
+                    uv_min: glam::vec2(0.5, 0.0),
+                    uv_max: glam::vec2(1.0, 1.0),
+                    pivot: glam::vec2(0.5, 0.5),
+                    duration: Some(time::Duration::from_millis(150)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_aseprite_array_exports() {
+        let frames = read_aseprite(
+            r#"{ "frames": [
+                { "frame": { "x": 32, "y": 0, "w": 32, "h": 16 } },
+                { "frame": { "x": 32, "y": 16, "w": 32, "h": 16 } }
+            ] }"#,
+            SIZE,
+        )
+        .unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].uv_min, glam::vec2(0.5, 0.5));
+        assert_eq!(frames[1].pivot, glam::Vec2::splat(0.5));
+        assert_eq!(frames[1].duration, None);
+    }
+
+    #[test]
+    fn rejects_malformed_aseprite_exports() {
+        assert!(read_aseprite("{ \"frames\": ", SIZE).is_err());
+        assert_eq!(error(read_aseprite("{}", SIZE)), "no frames");
+        assert_eq!(
+            error(read_aseprite(
+                r#"{ "frames": [ { "frame": { "x": 0, "y": 0, "h": 32 } } ] }"#,
+                SIZE
+            )),
+            "frame without w"
+        );
+        assert_eq!(
+            error(read_aseprite(
+                r#"{ "frames": [ { "frame": { "x": 0, "y": 0, "w": 0, "h": 32 } } ] }"#,
+                SIZE
+            )),
+            "frame 0 is 0x32"
+        );
+        assert_eq!(
+            error(read_aseprite(r#"{ "frames": [] }"#, (0, 32))),
+            "the sheet image is empty"
+        );
+    }
+
+    #[test]
+    fn reads_simple_grids() {
+        let frames = read_simple(
+            "# seven frames, the last cell stays empty\n\
+             frame_size = 16 16\n\
+             count = 7\n\
+             pivot = 8 12 # the feet\n\
+             duration = 100\n",
+            SIZE,
+        )
+        .unwrap();
+        assert_eq!(frames.len(), 7);
+        assert_eq!(frames[5].uv_min, glam::vec2(0.25, 0.5));
+        assert_eq!(frames[5].uv_max, glam::vec2(0.5, 1.0));
+        assert_eq!(frames[5].pivot, glam::vec2(0.5, 0.75));
+        assert!(
+            frames
+                .iter()
+                .all(|frame| frame.duration == Some(time::Duration::from_millis(100)))
+        );
+        let frames = read_simple("frame_size = 32 32\nduration = 50 70", SIZE).unwrap();
+        assert_eq!(
+            frames
+                .iter()
+                .map(|frame| frame.duration.unwrap().as_millis())
+                .collect::<Vec<_>>(),
+            [50, 70]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_simple_grids() {
+        for (text, message) in [
+            ("count = 2", "frame_size is missing"),
+            (
+                "frame_size = 0 16",
+                "frames cannot be empty: frame_size = 0 16",
+            ),
+            ("frame_size 16 16", "malformed line: frame_size 16 16"),
+            (
+                "frame_size = 16",
+                "unknown or malformed setting: frame_size = 16",
+            ),
+            ("scale = 2", "unknown or malformed setting: scale = 2"),
+            (
+                "frame_size = 16 16\ncount = 9",
+                "9 frames of 16x16 do not fit a 64x32 sheet",
+            ),
+            (
+                "frame_size = 80 16",
+                "0 frames of 80x16 do not fit a 64x32 sheet",
+            ),
+            (
+                "frame_size = 32 32\nduration = 1 2 3",
+                "3 durations for 2 frames",
+            ),
+        ] {
+            assert_eq!(error(read_simple(text, SIZE)), message, "{text}");
+        }
+        assert!(read_simple("frame_size = 16 -1", SIZE).is_err());
+    }
+}
@@ -0,0 +1,61 @@
+struct InstanceInput {
+    @location(0) position: vec2<f32>,
+    @location(1) size: vec2<f32>,
+    @location(2) uv_min: vec2<f32>,
+    @location(3) uv_max: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@group(1) @binding(0)
+var<uniform> view_projection: mat4x4<f32>;
+
+@vertex
+fn vertex_main(@builtin(vertex_index) vertex_index: u32, instance: InstanceInput) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+    let world_position = instance.position + (corner - vec2<f32>(0.5, 0.5)) * instance.size;
+
+    var output: VertexOutput;
+    output.clip_position = view_projection * vec4<f32>(world_position, 0.0, 1.0);
+    output.uv = mix(instance.uv_min, instance.uv_max, vec2<f32>(corner.x, 1.0 - corner.y));
+    return output;
+}
+
+@group(0) @binding(0)
+var texture_view: texture_2d<f32>;
+@group(0) @binding(1)
+var texture_sampler: sampler;
+@group(2) @binding(0)
+var normal_view: texture_2d<f32>;
+@group(2) @binding(1)
+var normal_sampler: sampler;
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    // Mapped to 0..1 like the baked normal maps, with the coverage of the colour.
+    @location(1) normal: vec4<f32>,
+}
+
+@fragment
+fn fragment_main(input: VertexOutput) -> FragmentOutput {
+    let color = textureSample(texture_view, texture_sampler, input.uv);
+    let normal = textureSample(normal_view, normal_sampler, input.uv);
+    if color.a <= 0.0 {
+        discard;
+    }
+    var output: FragmentOutput;
+    output.color = color;
+    output.normal = vec4<f32>(normal.rgb, color.a);
+    return output;
+}
@@ -2,24 +2,19 @@ use wgpu::util::DeviceExt;
 
 use crate::rendering;
 use crate::rendering::renderable::Vertex;
+use crate::rendering::shader;
+use crate::rendering::textures;
 use crate::sprite;
 
 use std::collections;
 use std::sync;
 
-const SHADER: &[u8] = include_bytes!("user_interface.wgsl");
-static RENDER_PIPLINE: sync::OnceLock<wgpu::RenderPipeline> = sync::OnceLock::new();
+const SHADER: &str = include_str!("user_interface.wgsl");
 
-fn init_render_pipeline(gpu: rendering::GpuHandle) -> wgpu::RenderPipeline {
-    let gpu = gpu.read().unwrap();
-    let shader = &gpu
-        .device()
-        .create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("user interface shader"),
-            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(
-                core::str::from_utf8(SHADER).unwrap(),
-            )),
-        });
+fn create_render_pipeline(
+    gpu: &rendering::Gpu,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
     gpu.device()
         .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("user interface render pipeline"),
@@ -65,7 +60,7 @@ fn init_render_pipeline(gpu: rendering::GpuHandle) -> wgpu::RenderPipeline {
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
                 targets: &[Some(wgpu::ColorTargetState {
                     format: gpu.surface_config().format,
-                    blend: Some(wgpu::BlendState::REPLACE),
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
                     write_mask: wgpu::ColorWrites::all(),
                 })],
             }),
@@ -79,6 +74,7 @@ pub struct UserInterface<'window> {
     renderer: UserInterfaceRenderer<'window>,
     pub user_interface_input: egui::RawInput,
     pub last_mouse_pos: egui::Pos2,
+    next_user_texture: u64,
 }
 
 impl<'window> UserInterface<'window> {
@@ -89,9 +85,42 @@ impl<'window> UserInterface<'window> {
             renderer,
             user_interface_input: egui::RawInput::default(),
             last_mouse_pos: egui::Pos2::default(),
+            next_user_texture: 0,
         }
     }
-    pub fn update<F: FnMut(&egui::Context)>(&mut self, root: F) {
+    /// Makes a texture of the [`textures::TextureManager`] drawable through `egui::Image`, it
+    /// only has to be resident while it is drawn. Registering a texture again returns the id
+    /// it already has.
+    pub fn register_texture(&mut self, texture: textures::TextureHandle) -> egui::TextureId {
+        if let Some(id) = self
+            .renderer
+            .managed
+            .iter()
+            .find_map(|(id, handle)| (*handle == texture).then_some(*id))
+        {
+            return id;
+        }
+        let id = egui::TextureId::User(self.next_user_texture);
+        self.next_user_texture += 1;
+        self.renderer.managed.insert(id, texture);
+        id
+    }
+    /// Whether a widget had keyboard focus at the end of the last frame.
+    pub fn wants_keyboard_input(&self) -> bool {
+        self.context.wants_keyboard_input()
+    }
+    pub fn has_focus(&self) -> bool {
+        self.context.memory(|memory| memory.focused().is_some())
+    }
+    /// Whether the pointer was over or dragging a window at the end of the last frame.
+    pub fn wants_pointer_input(&self) -> bool {
+        self.context.wants_pointer_input()
+    }
+    pub fn update<F: FnMut(&egui::Context)>(
+        &mut self,
+        textures: &mut textures::TextureManager,
+        root: F,
+    ) {
         let mut swap_input = egui::RawInput::default();
         std::mem::swap(&mut self.user_interface_input, &mut swap_input);
         let egui::FullOutput {
@@ -121,7 +150,15 @@ impl<'window> UserInterface<'window> {
             (surface_config.height as f32).recip(),
         ));
         drop(gpu);
-        self.renderer.render(&data);
+        let mut managed = collections::HashMap::new();
+        for renderable in &data {
+            if let Some(handle) = self.renderer.managed.get(&renderable.texture)
+                && let Some(texture) = textures.get(*handle, &self.renderer.gpu_handle)
+            {
+                managed.insert(renderable.texture, texture);
+            }
+        }
+        self.renderer.render(&data, &managed);
         for id in textures_delta.free {
             self.renderer.textures.remove(&id);
         }
@@ -132,8 +169,12 @@ pub struct UserInterfaceRenderer<'window> {
     gpu_handle: rendering::GpuHandle<'window>,
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
+    /// Textures egui asked for, like the font atlas.
     textures: collections::HashMap<egui::TextureId, sync::Arc<sprite::GpuTexture>>,
+    /// Textures registered through [`UserInterface::register_texture`].
+    managed: collections::HashMap<egui::TextureId, textures::TextureHandle>,
     projection_matrix: UserInterfaceProjectionMatrix<'window>,
+    pipeline: shader::HotPipeline,
 }
 
 impl<'window> UserInterfaceRenderer<'window> {
@@ -157,7 +198,14 @@ impl<'window> UserInterfaceRenderer<'window> {
             vertex_buffer,
             index_buffer,
             textures: collections::HashMap::new(),
+            managed: collections::HashMap::new(),
             projection_matrix: UserInterfaceProjectionMatrix::new(gpu_handle.clone()),
+            pipeline: shader::HotPipeline::new(
+                "user interface shader",
+                SHADER,
+                "src/user_interface.wgsl",
+                create_render_pipeline,
+            ),
         }
     }
     fn write_texture(&mut self, id: &egui::TextureId, image_delta: egui::epaint::ImageDelta) {
@@ -174,9 +222,7 @@ impl<'window> UserInterfaceRenderer<'window> {
 
         let gpu = self.gpu_handle.write().unwrap();
         let texture = self.textures.get(id).unwrap();
-        let image = match image_delta.image.clone() {
-            egui::ImageData::Color(color_image) => color_image,
-        };
+        let egui::ImageData::Color(image) = image_delta.image.clone();
         let texel_copy_info = if let Some([x, y]) = image_delta.pos {
             texture.texel_copy_texture_info(
                 0,
@@ -227,41 +273,14 @@ impl<'window> UserInterfaceRenderer<'window> {
             )),
         );
     }
-    fn render(&self, data: &[UserInterfaceRenderable]) {
-        let mut gpu = self.gpu_handle.write().unwrap();
-        let mut command_encoder =
-            gpu.device()
-                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                    label: Some("user interface command encoder"),
-                });
-        let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("user interface render pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &gpu
-                    .output()
-                    .unwrap()
-                    .texture
-                    .create_view(&wgpu::TextureViewDescriptor::default()),
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.0,
-                        g: 0.0,
-                        b: 0.0,
-                        a: 1.0,
-                    }),
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment: None,
-            timestamp_writes: None,
-            occlusion_query_set: None,
-        });
-
-        drop(gpu);
-        render_pass.set_pipeline(
-            RENDER_PIPLINE.get_or_init(|| init_render_pipeline(self.gpu_handle.clone())),
-        );
+    /// Adds a pass drawing over whatever the output holds. `managed` holds the resident textures
+    /// of the registered textures that are drawn.
+    fn render(
+        &mut self,
+        data: &[UserInterfaceRenderable],
+        managed: &collections::HashMap<egui::TextureId, sync::Arc<sprite::GpuTexture>>,
+    ) {
+        self.pipeline.refresh(&self.gpu_handle);
         let mut gpu = self.gpu_handle.write().unwrap();
         let vertices = data
             .iter()
@@ -288,34 +307,56 @@ impl<'window> UserInterfaceRenderer<'window> {
         )
         .copy_from_slice(&indices);
 
-        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-        render_pass.set_bind_group(1, &self.projection_matrix.bind_group, &[]);
-
+        // Registered textures whose source could not be read are left out.
         let mut base_vertex = 0;
+        let mut draws = Vec::new();
         for renderable in data {
-            render_pass.set_scissor_rect(
-                renderable.clip.min.x as u32,
-                renderable.clip.min.y as u32,
-                (renderable.clip.max.x as u32).min(gpu.surface_config().width),
-                (renderable.clip.max.y as u32).min(gpu.surface_config().height),
-            );
-
-            render_pass.set_bind_group(
-                0,
-                self.textures
-                    .get(&renderable.texture)
-                    .expect("texture id should be valid")
-                    .bind_group(),
-                &[],
-            );
-
-            render_pass.draw_indexed(0..renderable.indicies.len() as u32, base_vertex, 0..1);
-
+            let texture = self
+                .textures
+                .get(&renderable.texture)
+                .or_else(|| managed.get(&renderable.texture));
+            if let Some(texture) = texture {
+                draws.push((
+                    renderable.clip,
+                    texture.bind_group().clone(),
+                    renderable.indicies.len() as u32,
+                    base_vertex,
+                ));
+            }
             base_vertex += renderable.indicies.len() as i32;
         }
-        drop(render_pass);
-        gpu.push_command_buffer(command_encoder.finish());
+        let pipeline = self.pipeline.current().clone();
+        let vertex_buffer = self.vertex_buffer.clone();
+        let index_buffer = self.index_buffer.clone();
+        let projection_bind_group = self.projection_matrix.bind_group.clone();
+        gpu.render_graph()
+            .add_pass("user interface")
+            .modify(rendering::graph::OUTPUT)
+            .execute(move |command_encoder, resources| {
+                let mut render_pass =
+                    command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("user interface render pass"),
+                        color_attachments: &[resources.color_attachment(rendering::graph::OUTPUT)],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                    });
+                render_pass.set_pipeline(&pipeline);
+                render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+                render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                render_pass.set_bind_group(1, &projection_bind_group, &[]);
+                let (width, height) = resources.size(rendering::graph::OUTPUT);
+                for (clip, bind_group, indices, base_vertex) in &draws {
+                    render_pass.set_scissor_rect(
+                        clip.min.x as u32,
+                        clip.min.y as u32,
+                        (clip.max.x as u32).min(width),
+                        (clip.max.y as u32).min(height),
+                    );
+                    render_pass.set_bind_group(0, bind_group, &[]);
+                    render_pass.draw_indexed(0..*indices, *base_vertex, 0..1);
+                }
+            });
     }
 }
 
@@ -349,13 +390,12 @@ impl From<egui::epaint::ClippedPrimitive> for UserInterfaceRenderable {
             })
             .collect::<Vec<ColoredVertex>>();
 
-        let ret = UserInterfaceRenderable {
+        UserInterfaceRenderable {
             verticies,
             indicies: mesh.indices,
             texture: mesh.texture_id,
             clip: clip_rect,
-        };
-        ret
+        }
     }
 }
 
@@ -438,7 +478,7 @@ impl<'a, 'window> UserInterfaceProjectionMatrix<'window> {
             buffer,
         }
     }
-    pub fn update(&mut self, new_scale: glam::Vec2) {
+    pub fn update(&mut self, _new_scale: glam::Vec2) {
         let gpu = self.gpu_handle.read().unwrap();
         self.matrix = glam::Mat4::IDENTITY;
         // glam::Mat4::from_translation(glam::Vec3::new(-1.0, -1.0, 0.0))
@@ -0,0 +1,398 @@
+use std::any;
+use std::cell;
+use std::collections;
+
+pub mod behaviour;
+pub mod clock;
+pub mod commands;
+pub mod components;
+pub mod input;
+pub mod lighting;
+pub mod pathfinding;
+pub mod physics;
+pub mod query;
+pub mod random;
+pub mod spatial;
+pub mod storage;
+pub mod tilemap;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+impl Entity {
+    /// Rebuilds an id written out through [`Entity::index`] and [`Entity::generation`].
+    pub fn from_parts(index: u32, generation: u32) -> Self {
+        Self { index, generation }
+    }
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+/// Hands out entity ids, bumping the generation of an index each time it is recycled.
+#[derive(Default)]
+struct Entities {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+}
+
+impl Entities {
+    fn allocate(&mut self) -> Entity {
+        if let Some(index) = self.free.pop() {
+            self.alive[index as usize] = true;
+            return Entity {
+                index,
+                generation: self.generations[index as usize],
+            };
+        }
+        self.generations.push(0);
+        self.alive.push(true);
+        Entity {
+            index: self.generations.len() as u32 - 1,
+            generation: 0,
+        }
+    }
+    fn free(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+        let index = entity.index as usize;
+        self.alive[index] = false;
+        self.generations[index] = self.generations[index].wrapping_add(1);
+        self.free.push(entity.index);
+        true
+    }
+    fn is_alive(&self, entity: Entity) -> bool {
+        let index = entity.index as usize;
+        self.alive.get(index).copied().unwrap_or(false)
+            && self.generations[index] == entity.generation
+    }
+    fn len(&self) -> usize {
+        self.alive.len() - self.free.len()
+    }
+}
+
+/// Allocator state, so a restored world hands out the same entity ids as the one it was saved from.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EntitiesState {
+    pub generations: Vec<u32>,
+    pub alive: Vec<bool>,
+    pub free: Vec<u32>,
+}
+
+pub type System = Box<dyn FnMut(&World, &mut commands::Commands)>;
+
+pub struct World {
+    entities: Entities,
+    storages: collections::HashMap<any::TypeId, cell::RefCell<Box<dyn storage::AnyStorage>>>,
+    resources: collections::HashMap<any::TypeId, cell::RefCell<Box<dyn any::Any>>>,
+    systems: Vec<System>,
+    commands: commands::Commands,
+    tick: u64,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self {
+            entities: Entities::default(),
+            storages: collections::HashMap::new(),
+            resources: collections::HashMap::new(),
+            systems: Vec::new(),
+            commands: commands::Commands::new(),
+            tick: 0,
+        }
+    }
+    /// Number of updates run so far.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+    pub fn set_tick(&mut self, tick: u64) {
+        self.tick = tick;
+    }
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.entities
+            .alive
+            .iter()
+            .enumerate()
+            .filter(|(_, alive)| **alive)
+            .map(|(index, _)| Entity {
+                index: index as u32,
+                generation: self.entities.generations[index],
+            })
+    }
+    pub fn entities_state(&self) -> EntitiesState {
+        EntitiesState {
+            generations: self.entities.generations.clone(),
+            alive: self.entities.alive.clone(),
+            free: self.entities.free.clone(),
+        }
+    }
+    /// Drops every entity and component and restores the allocator, resources and systems stay.
+    ///
+    /// Fails without touching the world unless every dead index is free exactly once.
+    pub fn restore_entities(&mut self, state: EntitiesState) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            state.generations.len() == state.alive.len(),
+            "{} generations for {} entities",
+            state.generations.len(),
+            state.alive.len()
+        );
+        let mut free = vec![false; state.alive.len()];
+        for index in &state.free {
+            let index = *index as usize;
+            anyhow::ensure!(
+                index < state.alive.len(),
+                "free entity {index} is out of range"
+            );
+            anyhow::ensure!(!state.alive[index], "free entity {index} is alive");
+            anyhow::ensure!(!free[index], "entity {index} is free twice");
+            free[index] = true;
+        }
+        if let Some(index) = (0..free.len()).find(|index| !free[*index] && !state.alive[*index]) {
+            anyhow::bail!("dead entity {index} is not free");
+        }
+        self.storages.clear();
+        self.entities = Entities {
+            generations: state.generations,
+            alive: state.alive,
+            free: state.free,
+        };
+        Ok(())
+    }
+    pub fn spawn(&mut self) -> Entity {
+        self.entities.allocate()
+    }
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.entities.free(entity) {
+            return false;
+        }
+        for storage in self.storages.values_mut() {
+            storage.get_mut().remove_entity(entity);
+        }
+        true
+    }
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.entities.is_alive(entity)
+    }
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Returns the replaced component, dead entities are ignored.
+    pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) -> Option<T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+        self.storages
+            .entry(any::TypeId::of::<T>())
+            .or_insert_with(|| cell::RefCell::new(Box::new(storage::SparseSet::<T>::default())))
+            .get_mut()
+            .as_any_mut()
+            .downcast_mut::<storage::SparseSet<T>>()
+            .expect("storage is keyed by its component type")
+            .insert(entity, component)
+    }
+    pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+        self.storages
+            .get_mut(&any::TypeId::of::<T>())?
+            .get_mut()
+            .as_any_mut()
+            .downcast_mut::<storage::SparseSet<T>>()
+            .expect("storage is keyed by its component type")
+            .remove(entity)
+    }
+    pub fn get<T: 'static>(&self, entity: Entity) -> Option<cell::Ref<'_, T>> {
+        cell::Ref::filter_map(self.storage::<T>()?, |storage| storage.get(entity)).ok()
+    }
+    pub fn get_mut<T: 'static>(&self, entity: Entity) -> Option<cell::RefMut<'_, T>> {
+        cell::RefMut::filter_map(self.storage_mut::<T>()?, |storage| storage.get_mut(entity)).ok()
+    }
+    pub fn storage<T: 'static>(&self) -> Option<cell::Ref<'_, storage::SparseSet<T>>> {
+        let storage = self.storages.get(&any::TypeId::of::<T>())?.borrow();
+        Some(cell::Ref::map(storage, |storage| {
+            storage
+                .as_any()
+                .downcast_ref::<storage::SparseSet<T>>()
+                .expect("storage is keyed by its component type")
+        }))
+    }
+    pub fn storage_mut<T: 'static>(&self) -> Option<cell::RefMut<'_, storage::SparseSet<T>>> {
+        let storage = self.storages.get(&any::TypeId::of::<T>())?.borrow_mut();
+        Some(cell::RefMut::map(storage, |storage| {
+            storage
+                .as_any_mut()
+                .downcast_mut::<storage::SparseSet<T>>()
+                .expect("storage is keyed by its component type")
+        }))
+    }
+    /// Calls `each` for every entity that has all the components of `Q`.
+    pub fn query<Q: query::Query>(&self, mut each: impl FnMut(Entity, Q::Item<'_>)) {
+        let Some(mut fetch) = Q::fetch(self) else {
+            return;
+        };
+        let entities = Q::driver(&fetch).to_vec();
+        for entity in entities {
+            if let Some(item) = Q::get(&mut fetch, entity) {
+                each(entity, item);
+            }
+        }
+    }
+    /// Stores a value that is not attached to any entity, replacing the previous one of its type.
+    pub fn insert_resource<T: 'static>(&mut self, resource: T) {
+        self.resources.insert(
+            any::TypeId::of::<T>(),
+            cell::RefCell::new(Box::new(resource)),
+        );
+    }
+    pub fn remove_resource<T: 'static>(&mut self) -> Option<T> {
+        let resource = self.resources.remove(&any::TypeId::of::<T>())?;
+        resource
+            .into_inner()
+            .downcast()
+            .ok()
+            .map(|resource| *resource)
+    }
+    pub fn resource<T: 'static>(&self) -> Option<cell::Ref<'_, T>> {
+        let resource = self.resources.get(&any::TypeId::of::<T>())?.borrow();
+        cell::Ref::filter_map(resource, |resource| resource.downcast_ref()).ok()
+    }
+    pub fn resource_mut<T: 'static>(&self) -> Option<cell::RefMut<'_, T>> {
+        let resource = self.resources.get(&any::TypeId::of::<T>())?.borrow_mut();
+        cell::RefMut::filter_map(resource, |resource| resource.downcast_mut()).ok()
+    }
+    pub fn add_system(&mut self, system: impl FnMut(&World, &mut commands::Commands) + 'static) {
+        self.systems.push(Box::new(system));
+    }
+    pub fn apply_commands(&mut self, commands: &mut commands::Commands) {
+        commands.apply(self);
+    }
+    /// Runs every system in insertion order, applying their commands after each one.
+    pub fn update(&mut self) {
+        let mut systems = std::mem::take(&mut self.systems);
+        let mut commands = std::mem::take(&mut self.commands);
+        for system in &mut systems {
+            system(self, &mut commands);
+            commands.apply(self);
+        }
+        systems.append(&mut self.systems);
+        self.systems = systems;
+        self.commands = commands;
+        self.tick += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(i32);
+    #[derive(Debug, PartialEq)]
+    struct Velocity(i32);
+    #[derive(Debug, PartialEq)]
+    struct Name(&'static str);
+
+    fn positions(world: &World) -> Vec<(Entity, i32)> {
+        let mut positions = Vec::new();
+        world.query::<&Position>(|entity, position| positions.push((entity, position.0)));
+        positions.sort();
+        positions
+    }
+
+    #[test]
+    fn recycled_indices_get_a_new_generation() {
+        let mut world = World::new();
+        let first = world.spawn();
+        world.insert(first, Position(1));
+        assert!(world.despawn(first));
+        assert!(!world.despawn(first));
+
+        let second = world.spawn();
+        assert_eq!(second.index(), first.index());
+        assert_ne!(second.generation(), first.generation());
+        assert!(!world.is_alive(first));
+        assert!(world.get::<Position>(second).is_none());
+        assert!(world.insert(first, Position(2)).is_none());
+        assert!(world.get::<Position>(second).is_none());
+        assert_eq!(world.len(), 1);
+    }
+
+    #[test]
+    fn query_visits_entities_with_every_component() {
+        let mut world = World::new();
+        let moving = world.spawn();
+        world.insert(moving, Position(0));
+        world.insert(moving, Velocity(2));
+        let still = world.spawn();
+        world.insert(still, Position(5));
+        let ghost = world.spawn();
+        world.insert(ghost, Velocity(7));
+
+        world.query::<(&mut Position, &Velocity)>(|_, (position, velocity)| {
+            position.0 += velocity.0;
+        });
+        assert_eq!(positions(&world), [(moving, 2), (still, 5)]);
+
+        let mut visited = Vec::new();
+        world.query::<(&Position, Option<&Velocity>)>(|entity, (_, velocity)| {
+            visited.push((entity, velocity.map(|velocity| velocity.0)));
+        });
+        visited.sort();
+        assert_eq!(visited, [(moving, Some(2)), (still, None)]);
+    }
+
+    #[test]
+    fn query_without_a_storage_visits_nothing() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        world.insert(entity, Position(0));
+        let mut visited = 0;
+        world.query::<(&Position, &Name)>(|_, _| visited += 1);
+        assert_eq!(visited, 0);
+    }
+
+    #[test]
+    fn despawn_removes_every_component() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        world.insert(entity, Position(0));
+        world.insert(entity, Name("crate"));
+        world.despawn(entity);
+        assert!(positions(&world).is_empty());
+        assert!(world.storage::<Name>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn systems_see_the_commands_of_earlier_systems() {
+        let mut world = World::new();
+        world.insert_resource(Vec::<usize>::new());
+        world.add_system(|_, commands| {
+            commands.spawn().insert(Position(0));
+        });
+        world.add_system(|world, _| {
+            let count = world
+                .storage::<Position>()
+                .map_or(0, |storage| storage.len());
+            world.resource_mut::<Vec<usize>>().unwrap().push(count);
+        });
+        world.update();
+        world.update();
+        assert_eq!(*world.resource::<Vec<usize>>().unwrap(), [1, 2]);
+        assert_eq!(world.tick(), 2);
+    }
+}
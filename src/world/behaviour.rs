@@ -0,0 +1,656 @@
+use crate::simulation::save::codec;
+use crate::world;
+use std::collections;
+use std::sync;
+
+pub mod blackboard;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Status {
+    Success,
+    Failure,
+    Running,
+}
+
+/// What leaves see of the world while their entity's tree is ticked.
+pub struct Context<'a> {
+    pub world: &'a world::World,
+    pub commands: &'a mut world::commands::Commands,
+    pub entity: world::Entity,
+    pub blackboard: &'a mut blackboard::Blackboard,
+}
+
+pub type Action = Box<dyn Fn(&mut Context) -> Status>;
+pub type Condition = Box<dyn Fn(&Context) -> bool>;
+
+pub enum NodeKind {
+    /// Runs children in order until one fails, resuming at the running child on the next tick.
+    Sequence,
+    /// Runs children in order until one succeeds, resuming at the running child on the next tick.
+    Selector,
+    /// Runs every child that has not finished yet each tick, succeeds once `success` of them
+    /// succeeded and fails as soon as that can no longer happen.
+    Parallel {
+        success: usize,
+    },
+    Inverter,
+    /// Succeeds whenever its child finishes.
+    Succeeder,
+    /// Restarts its child after every success, `None` repeats forever.
+    Repeat {
+        times: Option<u32>,
+    },
+    /// Restarts its child after every failure, up to `attempts` times.
+    Retry {
+        attempts: u32,
+    },
+    /// Fails without ticking its child for `ticks` ticks after the child finished.
+    Cooldown {
+        ticks: u64,
+    },
+    Condition(Condition),
+    Action(Action),
+}
+
+impl NodeKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            NodeKind::Sequence => "sequence",
+            NodeKind::Selector => "selector",
+            NodeKind::Parallel { .. } => "parallel",
+            NodeKind::Inverter => "inverter",
+            NodeKind::Succeeder => "succeeder",
+            NodeKind::Repeat { .. } => "repeat",
+            NodeKind::Retry { .. } => "retry",
+            NodeKind::Cooldown { .. } => "cooldown",
+            NodeKind::Condition(_) => "condition",
+            NodeKind::Action(_) => "action",
+        }
+    }
+}
+
+pub struct Node {
+    pub name: String,
+    pub kind: NodeKind,
+}
+
+impl Node {
+    pub fn new(name: impl Into<String>, kind: NodeKind) -> Self {
+        Self {
+            name: name.into(),
+            kind,
+        }
+    }
+    pub fn sequence(name: impl Into<String>) -> Self {
+        Self::new(name, NodeKind::Sequence)
+    }
+    pub fn selector(name: impl Into<String>) -> Self {
+        Self::new(name, NodeKind::Selector)
+    }
+    pub fn parallel(name: impl Into<String>, success: usize) -> Self {
+        Self::new(name, NodeKind::Parallel { success })
+    }
+    pub fn condition(
+        name: impl Into<String>,
+        condition: impl Fn(&Context) -> bool + 'static,
+    ) -> Self {
+        Self::new(name, NodeKind::Condition(Box::new(condition)))
+    }
+    pub fn action(
+        name: impl Into<String>,
+        action: impl Fn(&mut Context) -> Status + 'static,
+    ) -> Self {
+        Self::new(name, NodeKind::Action(Box::new(action)))
+    }
+}
+
+/// Nodes of a behaviour tree stored in an arena, shared by every entity running it.
+///
+/// Composites and decorators take their children in the order they were added, decorators
+/// only look at their first child.
+pub struct BehaviourTree {
+    arena: atree::Arena<Node>,
+    root: atree::Token,
+}
+
+impl BehaviourTree {
+    pub fn new(root: Node) -> Self {
+        let (arena, root) = atree::Arena::with_data(root);
+        Self { arena, root }
+    }
+    pub fn root(&self) -> atree::Token {
+        self.root
+    }
+    /// The name of the root node, which [`Library`] knows the tree by.
+    pub fn name(&self) -> &str {
+        self.get(self.root).map_or("", |node| node.name.as_str())
+    }
+    pub fn add(&mut self, parent: atree::Token, node: Node) -> atree::Token {
+        parent.append(&mut self.arena, node)
+    }
+    pub fn get(&self, token: atree::Token) -> Option<&Node> {
+        self.arena.get(token).map(|node| &node.data)
+    }
+    pub fn children(&self, token: atree::Token) -> impl Iterator<Item = atree::Token> + '_ {
+        token.children_tokens(&self.arena)
+    }
+}
+
+/// Every behaviour tree the game runs, by name, so saved behaviours find their tree again.
+#[derive(Clone, Default)]
+pub struct Library {
+    trees: collections::BTreeMap<String, sync::Arc<BehaviourTree>>,
+}
+
+impl Library {
+    /// Adds `tree` under [`BehaviourTree::name`], replacing any tree of the same name.
+    // The closures are not Send, but [`Behaviour`] shares its tree through an Arc already.
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn register(&mut self, tree: BehaviourTree) -> sync::Arc<BehaviourTree> {
+        let tree = sync::Arc::new(tree);
+        self.trees.insert(tree.name().to_owned(), tree.clone());
+        tree
+    }
+    pub fn get(&self, name: &str) -> Option<&sync::Arc<BehaviourTree>> {
+        self.trees.get(name)
+    }
+}
+
+/// One line of [`Behaviour::describe`], listed depth first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeView {
+    pub depth: usize,
+    pub name: String,
+    pub kind: &'static str,
+    /// Status from the last tick, `None` if the node was not reached.
+    pub status: Option<Status>,
+}
+
+#[derive(Default)]
+struct Memory {
+    /// Running child of composites and finished runs of repeating decorators.
+    counters: collections::HashMap<atree::Token, u32>,
+    /// Tick at which each cooldown opens again, kept when the surrounding tree restarts.
+    cooldowns: collections::HashMap<atree::Token, u64>,
+    /// How the finished children of running parallel nodes ended, they are not ticked again
+    /// until their parallel node finishes.
+    finished: collections::HashMap<atree::Token, Status>,
+    statuses: collections::HashMap<atree::Token, Status>,
+    tick: u64,
+}
+
+/// Runs a [`BehaviourTree`] for its entity once per fixed update, see [`tick_system`].
+pub struct Behaviour {
+    tree: sync::Arc<BehaviourTree>,
+    pub blackboard: blackboard::Blackboard,
+    memory: Memory,
+}
+
+impl Behaviour {
+    pub fn new(tree: sync::Arc<BehaviourTree>) -> Self {
+        Self {
+            tree,
+            blackboard: blackboard::Blackboard::default(),
+            memory: Memory::default(),
+        }
+    }
+    pub fn tree(&self) -> &sync::Arc<BehaviourTree> {
+        &self.tree
+    }
+    /// Status of the root after the last tick.
+    pub fn status(&self) -> Option<Status> {
+        self.memory.statuses.get(&self.tree.root).copied()
+    }
+    /// Forgets running children and counters so the next tick starts from the root again.
+    pub fn restart(&mut self) {
+        self.memory.counters.clear();
+        self.memory.finished.clear();
+    }
+    pub fn tick(
+        &mut self,
+        world: &world::World,
+        commands: &mut world::commands::Commands,
+        entity: world::Entity,
+    ) -> Status {
+        let mut context = Context {
+            world,
+            commands,
+            entity,
+            blackboard: &mut self.blackboard,
+        };
+        self.memory.statuses.clear();
+        self.memory.tick += 1;
+        tick_node(&self.tree, self.tree.root, &mut self.memory, &mut context)
+    }
+    /// Writes the blackboard and what every node remembers, in tree order, for save games.
+    pub fn write_state(&self, writer: &mut codec::Writer) {
+        writer.u64(self.memory.tick);
+        let mut pending = vec![self.tree.root];
+        while let Some(token) = pending.pop() {
+            let counter = self.memory.counters.get(&token);
+            writer.bool(counter.is_some());
+            writer.u32(counter.copied().unwrap_or_default());
+            let cooldown = self.memory.cooldowns.get(&token);
+            writer.bool(cooldown.is_some());
+            writer.u64(cooldown.copied().unwrap_or_default());
+            write_status(writer, self.memory.finished.get(&token));
+            write_status(writer, self.memory.statuses.get(&token));
+            let children = self.tree.children(token).collect::<Vec<_>>();
+            pending.extend(children.into_iter().rev());
+        }
+        self.blackboard.write(writer);
+    }
+    /// Reads back what [`Behaviour::write_state`] wrote for the same tree.
+    pub fn read_state(&mut self, reader: &mut codec::Reader) -> anyhow::Result<()> {
+        let mut memory = Memory {
+            tick: reader.u64()?,
+            ..Memory::default()
+        };
+        let mut pending = vec![self.tree.root];
+        while let Some(token) = pending.pop() {
+            let has_counter = reader.bool()?;
+            let counter = reader.u32()?;
+            if has_counter {
+                memory.counters.insert(token, counter);
+            }
+            let has_cooldown = reader.bool()?;
+            let cooldown = reader.u64()?;
+            if has_cooldown {
+                memory.cooldowns.insert(token, cooldown);
+            }
+            if let Some(status) = read_status(reader)? {
+                memory.finished.insert(token, status);
+            }
+            if let Some(status) = read_status(reader)? {
+                memory.statuses.insert(token, status);
+            }
+            let children = self.tree.children(token).collect::<Vec<_>>();
+            pending.extend(children.into_iter().rev());
+        }
+        self.blackboard = blackboard::Blackboard::read(reader)?;
+        self.memory = memory;
+        Ok(())
+    }
+    pub fn describe(&self) -> Vec<NodeView> {
+        let mut views = Vec::new();
+        let mut pending = vec![(self.tree.root, 0)];
+        while let Some((token, depth)) = pending.pop() {
+            let Some(node) = self.tree.get(token) else {
+                continue;
+            };
+            views.push(NodeView {
+                depth,
+                name: node.name.clone(),
+                kind: node.kind.label(),
+                status: self.memory.statuses.get(&token).copied(),
+            });
+            let children = self.tree.children(token).collect::<Vec<_>>();
+            pending.extend(children.into_iter().rev().map(|child| (child, depth + 1)));
+        }
+        views
+    }
+}
+
+fn write_status(writer: &mut codec::Writer, status: Option<&Status>) {
+    writer.u8(match status {
+        None => 0,
+        Some(Status::Success) => 1,
+        Some(Status::Failure) => 2,
+        Some(Status::Running) => 3,
+    });
+}
+
+fn read_status(reader: &mut codec::Reader) -> anyhow::Result<Option<Status>> {
+    Ok(match reader.u8()? {
+        0 => None,
+        1 => Some(Status::Success),
+        2 => Some(Status::Failure),
+        3 => Some(Status::Running),
+        status => anyhow::bail!("unknown status {status}"),
+    })
+}
+
+fn tick_node(
+    tree: &BehaviourTree,
+    token: atree::Token,
+    memory: &mut Memory,
+    context: &mut Context,
+) -> Status {
+    let Some(node) = tree.get(token) else {
+        return Status::Failure;
+    };
+    let first_child = tree.children(token).next();
+    let status = match &node.kind {
+        NodeKind::Sequence => tick_composite(tree, token, memory, context, Status::Success),
+        NodeKind::Selector => tick_composite(tree, token, memory, context, Status::Failure),
+        NodeKind::Parallel { success } => {
+            let children = tree.children(token).collect::<Vec<_>>();
+            let (mut succeeded, mut failed) = (0, 0);
+            for child in &children {
+                let status = match memory.finished.get(child) {
+                    Some(status) => *status,
+                    None => {
+                        let status = tick_node(tree, *child, memory, context);
+                        if status != Status::Running {
+                            memory.finished.insert(*child, status);
+                        }
+                        status
+                    }
+                };
+                match status {
+                    Status::Success => succeeded += 1,
+                    Status::Failure => failed += 1,
+                    Status::Running => (),
+                }
+            }
+            let required = (*success).min(children.len());
+            if succeeded >= required {
+                Status::Success
+            } else if failed > children.len() - required {
+                Status::Failure
+            } else {
+                Status::Running
+            }
+        }
+        NodeKind::Inverter => match tick_child(tree, first_child, memory, context) {
+            Status::Success => Status::Failure,
+            Status::Failure => Status::Success,
+            Status::Running => Status::Running,
+        },
+        NodeKind::Succeeder => match tick_child(tree, first_child, memory, context) {
+            Status::Running => Status::Running,
+            _ => Status::Success,
+        },
+        NodeKind::Repeat { times } => match tick_child(tree, first_child, memory, context) {
+            Status::Success => {
+                let runs = memory.counters.entry(token).or_default();
+                *runs += 1;
+                match times {
+                    Some(times) if *runs >= *times => Status::Success,
+                    _ => Status::Running,
+                }
+            }
+            status => status,
+        },
+        NodeKind::Retry { attempts } => match tick_child(tree, first_child, memory, context) {
+            Status::Failure => {
+                let failures = memory.counters.entry(token).or_default();
+                *failures += 1;
+                if *failures >= *attempts {
+                    Status::Failure
+                } else {
+                    Status::Running
+                }
+            }
+            status => status,
+        },
+        NodeKind::Cooldown { ticks } => {
+            if memory
+                .cooldowns
+                .get(&token)
+                .is_some_and(|ready| memory.tick < *ready)
+            {
+                Status::Failure
+            } else {
+                let status = tick_child(tree, first_child, memory, context);
+                if status != Status::Running {
+                    memory.cooldowns.insert(token, memory.tick + ticks);
+                }
+                status
+            }
+        }
+        NodeKind::Condition(condition) => {
+            if condition(context) {
+                Status::Success
+            } else {
+                Status::Failure
+            }
+        }
+        NodeKind::Action(action) => action(context),
+    };
+    if status != Status::Running {
+        forget(tree, token, memory);
+    }
+    memory.statuses.insert(token, status);
+    status
+}
+
+/// Drops the counters and finished children of a finished node and everything below it,
+/// halting children a parallel node left running.
+fn forget(tree: &BehaviourTree, token: atree::Token, memory: &mut Memory) {
+    memory.counters.remove(&token);
+    memory.finished.remove(&token);
+    if memory.counters.is_empty() && memory.finished.is_empty() {
+        return;
+    }
+    for child in tree.children(token) {
+        forget(tree, child, memory);
+    }
+}
+
+fn tick_child(
+    tree: &BehaviourTree,
+    child: Option<atree::Token>,
+    memory: &mut Memory,
+    context: &mut Context,
+) -> Status {
+    child.map_or(Status::Failure, |child| {
+        tick_node(tree, child, memory, context)
+    })
+}
+
+/// Ticks children in order while they finish with `continue_on`, which is also the result once
+/// every child has.
+fn tick_composite(
+    tree: &BehaviourTree,
+    token: atree::Token,
+    memory: &mut Memory,
+    context: &mut Context,
+    continue_on: Status,
+) -> Status {
+    let children = tree.children(token).collect::<Vec<_>>();
+    let mut index = memory.counters.get(&token).copied().unwrap_or(0) as usize;
+    while let Some(child) = children.get(index) {
+        match tick_node(tree, *child, memory, context) {
+            Status::Running => {
+                memory.counters.insert(token, index as u32);
+                return Status::Running;
+            }
+            status if status == continue_on => index += 1,
+            status => return status,
+        }
+    }
+    continue_on
+}
+
+pub fn tick_system(world: &world::World, commands: &mut world::commands::Commands) {
+    world.query::<&mut Behaviour>(|entity, behaviour| {
+        behaviour.tick(world, commands, entity);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Status::{Failure, Running, Success};
+
+    /// An action returning `script` one status per tick, the last one from then on, and counting
+    /// its ticks on the blackboard under its name.
+    fn scripted(name: &'static str, script: &'static [Status]) -> Node {
+        Node::action(name, move |context| {
+            let ticks = context.blackboard.integer(name).unwrap_or_default();
+            context.blackboard.set(name, ticks + 1);
+            script[(ticks as usize).min(script.len() - 1)]
+        })
+    }
+
+    fn ticks(behaviour: &Behaviour, name: &str) -> i64 {
+        behaviour.blackboard.integer(name).unwrap_or_default()
+    }
+
+    /// Ticks `behaviour` `count` times, returning the status of every tick.
+    fn run(behaviour: &mut Behaviour, count: usize) -> Vec<Status> {
+        let mut world = world::World::new();
+        let entity = world.spawn();
+        let mut commands = world::commands::Commands::new();
+        (0..count)
+            .map(|_| behaviour.tick(&world, &mut commands, entity))
+            .collect()
+    }
+
+    fn behaviour(tree: BehaviourTree) -> Behaviour {
+        Behaviour::new(Library::default().register(tree))
+    }
+
+    #[test]
+    fn sequences_resume_at_the_running_child() {
+        let mut tree = BehaviourTree::new(Node::sequence("root"));
+        tree.add(tree.root(), scripted("first", &[Success]));
+        tree.add(
+            tree.root(),
+            scripted("second", &[Running, Running, Success]),
+        );
+        tree.add(tree.root(), scripted("third", &[Failure]));
+        let mut behaviour = behaviour(tree);
+        assert_eq!(run(&mut behaviour, 3), [Running, Running, Failure]);
+        assert_eq!(ticks(&behaviour, "first"), 1);
+        assert_eq!(ticks(&behaviour, "second"), 3);
+        assert_eq!(ticks(&behaviour, "third"), 1);
+        // Finished, so the next tick starts from the first child again.
+        run(&mut behaviour, 1);
+        assert_eq!(ticks(&behaviour, "first"), 2);
+    }
+
+    #[test]
+    fn selectors_resume_at_the_running_child() {
+        let mut tree = BehaviourTree::new(Node::selector("root"));
+        tree.add(tree.root(), scripted("first", &[Failure]));
+        tree.add(tree.root(), scripted("second", &[Running, Success]));
+        tree.add(tree.root(), scripted("third", &[Success]));
+        let mut behaviour = behaviour(tree);
+        assert_eq!(run(&mut behaviour, 2), [Running, Success]);
+        assert_eq!(ticks(&behaviour, "first"), 1);
+        assert_eq!(ticks(&behaviour, "second"), 2);
+        assert_eq!(ticks(&behaviour, "third"), 0);
+    }
+
+    #[test]
+    fn restart_goes_back_to_the_first_child() {
+        let mut tree = BehaviourTree::new(Node::sequence("root"));
+        tree.add(tree.root(), scripted("first", &[Success]));
+        tree.add(tree.root(), scripted("second", &[Running]));
+        let mut behaviour = behaviour(tree);
+        run(&mut behaviour, 2);
+        behaviour.restart();
+        run(&mut behaviour, 1);
+        assert_eq!(ticks(&behaviour, "first"), 2);
+    }
+
+    fn decorated(kind: NodeKind, child: Node) -> Behaviour {
+        let mut tree = BehaviourTree::new(Node::new("decorator", kind));
+        tree.add(tree.root(), child);
+        behaviour(tree)
+    }
+
+    #[test]
+    fn inverters_and_succeeders_map_finished_statuses() {
+        let script = &[Running, Success, Failure];
+        let mut inverter = decorated(NodeKind::Inverter, scripted("child", script));
+        assert_eq!(run(&mut inverter, 3), [Running, Failure, Success]);
+        let mut succeeder = decorated(NodeKind::Succeeder, scripted("child", script));
+        assert_eq!(run(&mut succeeder, 3), [Running, Success, Success]);
+    }
+
+    #[test]
+    fn repeats_count_successes_and_stop_at_failures() {
+        let mut repeat = decorated(
+            NodeKind::Repeat { times: Some(3) },
+            scripted("child", &[Success]),
+        );
+        assert_eq!(run(&mut repeat, 4), [Running, Running, Success, Running]);
+        let mut forever = decorated(
+            NodeKind::Repeat { times: None },
+            scripted("child", &[Success, Success, Failure]),
+        );
+        assert_eq!(run(&mut forever, 3), [Running, Running, Failure]);
+    }
+
+    #[test]
+    fn retries_count_failures_and_stop_at_successes() {
+        let mut retry = decorated(
+            NodeKind::Retry { attempts: 2 },
+            scripted("child", &[Failure]),
+        );
+        assert_eq!(run(&mut retry, 3), [Running, Failure, Running]);
+        let mut recovers = decorated(
+            NodeKind::Retry { attempts: 5 },
+            scripted("child", &[Failure, Success]),
+        );
+        assert_eq!(run(&mut recovers, 2), [Running, Success]);
+    }
+
+    #[test]
+    fn cooldowns_skip_their_child_after_it_finished() {
+        let mut cooldown = decorated(
+            NodeKind::Cooldown { ticks: 2 },
+            scripted("child", &[Running, Success]),
+        );
+        assert_eq!(
+            run(&mut cooldown, 6),
+            [Running, Success, Failure, Success, Failure, Success]
+        );
+        assert_eq!(ticks(&cooldown, "child"), 4);
+        // Restarting the tree keeps the cooldown closed.
+        cooldown.restart();
+        assert_eq!(run(&mut cooldown, 1), [Failure]);
+    }
+
+    #[test]
+    fn parallels_remember_children_that_finished() {
+        let mut tree = BehaviourTree::new(Node::parallel("root", 2));
+        tree.add(tree.root(), scripted("quick", &[Success]));
+        tree.add(tree.root(), scripted("slow", &[Running, Running, Success]));
+        tree.add(tree.root(), scripted("stuck", &[Running]));
+        let mut behaviour = behaviour(tree);
+        assert_eq!(run(&mut behaviour, 3), [Running, Running, Success]);
+        assert_eq!(ticks(&behaviour, "quick"), 1);
+        assert_eq!(ticks(&behaviour, "slow"), 3);
+        // Finished, so every child runs again.
+        run(&mut behaviour, 1);
+        assert_eq!(ticks(&behaviour, "quick"), 2);
+    }
+
+    #[test]
+    fn parallels_fail_once_success_is_out_of_reach() {
+        let mut tree = BehaviourTree::new(Node::parallel("root", 2));
+        tree.add(tree.root(), scripted("first", &[Failure]));
+        tree.add(tree.root(), scripted("second", &[Running, Failure]));
+        tree.add(tree.root(), scripted("third", &[Running]));
+        let mut behaviour = behaviour(tree);
+        assert_eq!(run(&mut behaviour, 2), [Running, Failure]);
+        assert_eq!(ticks(&behaviour, "first"), 1);
+    }
+
+    #[test]
+    fn state_reads_back_what_was_written() {
+        let mut tree = BehaviourTree::new(Node::parallel("root", 2));
+        tree.add(tree.root(), scripted("quick", &[Success]));
+        let cooldown = tree.add(
+            tree.root(),
+            Node::new("rest", NodeKind::Cooldown { ticks: 5 }),
+        );
+        tree.add(cooldown, scripted("slow", &[Running, Success]));
+        let tree = Library::default().register(tree);
+        let mut original = Behaviour::new(tree.clone());
+        run(&mut original, 2);
+        let mut writer = codec::Writer::default();
+        original.write_state(&mut writer);
+        let bytes = writer.into_bytes();
+        let mut restored = Behaviour::new(tree);
+        let mut reader = codec::Reader::new(&bytes);
+        restored.read_state(&mut reader).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!(run(&mut original, 3), run(&mut restored, 3));
+        assert_eq!(original.blackboard, restored.blackboard);
+    }
+}
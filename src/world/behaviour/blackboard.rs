@@ -0,0 +1,168 @@
+use crate::simulation::save::codec;
+use crate::world;
+use std::collections;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Integer(i64),
+    Float(f32),
+    Vector(glam::Vec2),
+    Entity(world::Entity),
+    Text(String),
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Self::Integer(value)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(value: f32) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<glam::Vec2> for Value {
+    fn from(value: glam::Vec2) -> Self {
+        Self::Vector(value)
+    }
+}
+
+impl From<world::Entity> for Value {
+    fn from(value: world::Entity) -> Self {
+        Self::Entity(value)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_owned())
+    }
+}
+
+/// Named values an entity's behaviour tree nodes share between ticks.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Blackboard {
+    values: collections::BTreeMap<String, Value>,
+}
+
+impl Blackboard {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+    pub fn set(&mut self, key: impl Into<String>, value: impl Into<Value>) -> Option<Value> {
+        self.values.insert(key.into(), value.into())
+    }
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        self.values.remove(key)
+    }
+    pub fn contains(&self, key: &str) -> bool {
+        self.values.contains_key(key)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.values.iter().map(|(key, value)| (key.as_str(), value))
+    }
+    pub fn bool(&self, key: &str) -> Option<bool> {
+        match self.get(key)? {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+    pub fn integer(&self, key: &str) -> Option<i64> {
+        match self.get(key)? {
+            Value::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+    pub fn float(&self, key: &str) -> Option<f32> {
+        match self.get(key)? {
+            Value::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+    pub fn vector(&self, key: &str) -> Option<glam::Vec2> {
+        match self.get(key)? {
+            Value::Vector(value) => Some(*value),
+            _ => None,
+        }
+    }
+    pub fn entity(&self, key: &str) -> Option<world::Entity> {
+        match self.get(key)? {
+            Value::Entity(value) => Some(*value),
+            _ => None,
+        }
+    }
+    pub fn text(&self, key: &str) -> Option<&str> {
+        match self.get(key)? {
+            Value::Text(value) => Some(value),
+            _ => None,
+        }
+    }
+    /// Every key and value in key order.
+    pub fn write(&self, writer: &mut codec::Writer) {
+        writer.u32(self.values.len() as u32);
+        for (key, value) in &self.values {
+            writer.string(key);
+            match value {
+                Value::Bool(value) => {
+                    writer.u8(0);
+                    writer.bool(*value);
+                }
+                Value::Integer(value) => {
+                    writer.u8(1);
+                    writer.u64(*value as u64);
+                }
+                Value::Float(value) => {
+                    writer.u8(2);
+                    writer.f32(*value);
+                }
+                Value::Vector(value) => {
+                    writer.u8(3);
+                    writer.vec2(*value);
+                }
+                Value::Entity(value) => {
+                    writer.u8(4);
+                    writer.entity(*value);
+                }
+                Value::Text(value) => {
+                    writer.u8(5);
+                    writer.string(value);
+                }
+            }
+        }
+    }
+    pub fn read(reader: &mut codec::Reader) -> anyhow::Result<Self> {
+        let mut blackboard = Self::default();
+        for _ in 0..reader.u32()? {
+            let key = reader.string()?;
+            let value = match reader.u8()? {
+                0 => Value::Bool(reader.bool()?),
+                1 => Value::Integer(reader.u64()? as i64),
+                2 => Value::Float(reader.f32()?),
+                3 => Value::Vector(reader.vec2()?),
+                4 => {
+                    let (index, generation) = reader.entity()?;
+                    Value::Entity(world::Entity::from_parts(index, generation))
+                }
+                5 => Value::Text(reader.string()?),
+                tag => anyhow::bail!("unknown blackboard value {tag}"),
+            };
+            blackboard.values.insert(key, value);
+        }
+        Ok(blackboard)
+    }
+}
@@ -0,0 +1,82 @@
+use std::time;
+
+/// How often the world ticks, each `World::update` is one tick.
+pub const TICKS_PER_SECOND: u32 = 60;
+/// Seconds of world time per tick.
+pub const TIMESTEP: f32 = 1.0 / TICKS_PER_SECOND as f32;
+/// A frame never runs more ticks than this, the time past it is dropped so a stall slows the
+/// world down instead of making it catch up in a burst.
+const MAX_TICKS_PER_FRAME: u32 = 5;
+
+/// Turns the time between frames into a whole number of fixed ticks, carrying the remainder
+/// over to the next frame.
+#[derive(Clone, Debug, Default)]
+pub struct FixedClock {
+    last: Option<time::Instant>,
+    accumulated: time::Duration,
+}
+
+impl FixedClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Ticks to run for the time since the previous call, none on the first call.
+    pub fn ticks(&mut self) -> u32 {
+        let now = time::Instant::now();
+        let elapsed = self.last.map_or(time::Duration::ZERO, |last| now - last);
+        self.last = Some(now);
+        self.advance(elapsed)
+    }
+    /// Ticks to run for `elapsed` more seconds of real time.
+    pub fn advance(&mut self, elapsed: time::Duration) -> u32 {
+        let timestep = time::Duration::from_secs(1) / TICKS_PER_SECOND;
+        self.accumulated += elapsed;
+        let ticks = (self.accumulated.as_nanos() / timestep.as_nanos()) as u32;
+        if ticks > MAX_TICKS_PER_FRAME {
+            self.accumulated = time::Duration::ZERO;
+            return MAX_TICKS_PER_FRAME;
+        }
+        self.accumulated -= timestep * ticks;
+        ticks
+    }
+    /// Forgets the time since the previous call, for when the world was not running.
+    pub fn pause(&mut self) {
+        self.last = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(millis: u64) -> time::Duration {
+        time::Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn ticks_at_a_fixed_rate_whatever_the_frame_rate() {
+        for frame in [4, 7, 16, 33] {
+            let mut clock = FixedClock::new();
+            let ticks = (0..1000 / frame)
+                .map(|_| clock.advance(millis(frame)))
+                .sum::<u32>();
+            let expected = (1000 / frame * frame) as u32 * TICKS_PER_SECOND / 1000;
+            assert!(ticks.abs_diff(expected) <= 1, "{frame} ms: {ticks}");
+        }
+    }
+
+    #[test]
+    fn carries_the_remainder_over() {
+        let mut clock = FixedClock::new();
+        assert_eq!(clock.advance(millis(10)), 0);
+        assert_eq!(clock.advance(millis(10)), 1);
+        assert_eq!(clock.advance(millis(30)), 2);
+    }
+
+    #[test]
+    fn drops_time_past_the_limit() {
+        let mut clock = FixedClock::new();
+        assert_eq!(clock.advance(millis(2000)), MAX_TICKS_PER_FRAME);
+        assert_eq!(clock.advance(millis(10)), 0);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::world;
+
+type Command = Box<dyn FnOnce(&mut world::World)>;
+type Insert = Box<dyn FnOnce(&mut world::World, world::Entity)>;
+
+/// Structural changes recorded while systems only hold a shared borrow of the world.
+#[derive(Default)]
+pub struct Commands {
+    queue: Vec<Command>,
+}
+
+impl Commands {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+    pub fn spawn(&mut self) -> EntityCommands<'_> {
+        EntityCommands {
+            commands: self,
+            inserts: Vec::new(),
+        }
+    }
+    pub fn despawn(&mut self, entity: world::Entity) {
+        self.push(move |world| {
+            world.despawn(entity);
+        });
+    }
+    pub fn insert<T: 'static>(&mut self, entity: world::Entity, component: T) {
+        self.push(move |world| {
+            world.insert(entity, component);
+        });
+    }
+    pub fn remove<T: 'static>(&mut self, entity: world::Entity) {
+        self.push(move |world| {
+            world.remove::<T>(entity);
+        });
+    }
+    pub fn push(&mut self, command: impl FnOnce(&mut world::World) + 'static) {
+        self.queue.push(Box::new(command));
+    }
+    pub fn apply(&mut self, world: &mut world::World) {
+        for command in self.queue.drain(..) {
+            command(world);
+        }
+    }
+}
+
+/// Collects the components of an entity that is spawned once the commands are applied.
+pub struct EntityCommands<'a> {
+    commands: &'a mut Commands,
+    inserts: Vec<Insert>,
+}
+
+impl EntityCommands<'_> {
+    pub fn insert<T: 'static>(mut self, component: T) -> Self {
+        self.inserts.push(Box::new(move |world, entity| {
+            world.insert(entity, component);
+        }));
+        self
+    }
+}
+
+impl Drop for EntityCommands<'_> {
+    fn drop(&mut self) {
+        let inserts = std::mem::take(&mut self.inserts);
+        self.commands.push(move |world| {
+            let entity = world.spawn();
+            for insert in inserts {
+                insert(world, entity);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+    #[derive(Debug, PartialEq)]
+    struct Armour(u32);
+
+    #[test]
+    fn commands_wait_until_applied() {
+        let mut world = world::World::new();
+        let target = world.spawn();
+        world.insert(target, Health(3));
+
+        let mut commands = Commands::new();
+        commands.spawn().insert(Health(10)).insert(Armour(2));
+        commands.insert(target, Armour(1));
+        commands.remove::<Health>(target);
+        assert!(!commands.is_empty());
+        assert_eq!(world.len(), 1);
+        assert_eq!(*world.get::<Health>(target).unwrap(), Health(3));
+
+        world.apply_commands(&mut commands);
+        assert!(commands.is_empty());
+        assert_eq!(world.len(), 2);
+        assert!(world.get::<Health>(target).is_none());
+        assert_eq!(*world.get::<Armour>(target).unwrap(), Armour(1));
+        let mut spawned = Vec::new();
+        world.query::<(&Health, &Armour)>(|entity, (health, armour)| {
+            spawned.push((entity, health.0, armour.0));
+        });
+        assert_eq!(spawned.len(), 1);
+        assert_ne!(spawned[0].0, target);
+        assert_eq!((spawned[0].1, spawned[0].2), (10, 2));
+    }
+
+    #[test]
+    fn commands_apply_in_order() {
+        let mut world = world::World::new();
+        let entity = world.spawn();
+        let mut commands = Commands::new();
+        commands.insert(entity, Health(1));
+        commands.despawn(entity);
+        commands.insert(entity, Health(2));
+        world.apply_commands(&mut commands);
+        assert!(!world.is_alive(entity));
+        assert!(world.storage::<Health>().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,35 @@
+use crate::world::spatial;
+use std::path;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Position(pub glam::Vec2);
+
+/// Extent of an entity around its [`Position`], used by the spatial index.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bounds {
+    pub half_extents: glam::Vec2,
+}
+
+impl Bounds {
+    pub fn new(size: glam::Vec2) -> Self {
+        Self {
+            half_extents: size * 0.5,
+        }
+    }
+    pub fn aabb(&self, position: Position) -> spatial::Aabb {
+        spatial::Aabb::from_center(position.0, self.half_extents)
+    }
+}
+
+/// Draws one frame of a [`crate::sprite::Sprite`] from `Simulation::sprites` centred on the entity.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SpriteInstance {
+    pub sprite: usize,
+    pub frame: u16,
+    pub size: glam::Vec2,
+}
+
+/// Where each sprite [`SpriteInstance::sprite`] points at was loaded from, kept in step with
+/// `Simulation::sprites` so saves can name sprites by a key that survives another load order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpriteSources(pub Vec<path::PathBuf>);
@@ -0,0 +1,410 @@
+use crate::world;
+
+pub mod bindings;
+pub mod gamepad;
+pub mod touch;
+
+/// Context that is consulted after the active one, for actions that work everywhere.
+pub const GLOBAL: &str = "global";
+pub const GAMEPLAY: &str = "gameplay";
+pub const MENU: &str = "menu";
+
+/// Input events delivered during the current fixed update.
+///
+/// `Simulation` fills this before the world updates and replays fill it from their recording,
+/// so systems that read input from here behave the same in both.
+#[derive(Clone, Debug, Default)]
+pub struct InputEvents {
+    pub events: Vec<egui::Event>,
+    pub gamepad: Vec<gamepad::GamepadEvent>,
+    /// Gestures the platform recognized itself, like trackpad rotation, see
+    /// [`Actions::gestures`] for every gesture of the update.
+    pub gestures: Vec<touch::Gesture>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum GamepadButton {
+    South,
+    East,
+    West,
+    North,
+    LeftShoulder,
+    RightShoulder,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+impl GamepadButton {
+    pub const ALL: [Self; 16] = [
+        Self::South,
+        Self::East,
+        Self::West,
+        Self::North,
+        Self::LeftShoulder,
+        Self::RightShoulder,
+        Self::LeftTrigger,
+        Self::RightTrigger,
+        Self::Select,
+        Self::Start,
+        Self::LeftStick,
+        Self::RightStick,
+        Self::DPadUp,
+        Self::DPadDown,
+        Self::DPadLeft,
+        Self::DPadRight,
+    ];
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+impl GamepadAxis {
+    pub const ALL: [Self; 6] = [
+        Self::LeftStickX,
+        Self::LeftStickY,
+        Self::RightStickX,
+        Self::RightStickY,
+        Self::LeftTrigger,
+        Self::RightTrigger,
+    ];
+}
+
+/// A physical input that can be bound to an action or one side of an axis.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Binding {
+    Key(egui::Key),
+    Mouse(egui::PointerButton),
+    Gamepad(GamepadButton),
+}
+
+impl Binding {
+    pub fn is_gamepad(&self) -> bool {
+        matches!(self, Binding::Gamepad(_))
+    }
+}
+
+/// Events of one update that gameplay may see.
+///
+/// Keyboard input is dropped while egui has a focused text field and pointer presses, new
+/// touches, wheel and zoom input while the pointer is over a window, releases always pass so
+/// nothing sticks.
+pub fn uncaptured(
+    events: impl IntoIterator<Item = egui::Event>,
+    keyboard_captured: bool,
+    pointer_captured: bool,
+) -> Vec<egui::Event> {
+    events
+        .into_iter()
+        .filter(|event| match event {
+            egui::Event::Key { pressed, .. } => !keyboard_captured || !pressed,
+            egui::Event::PointerButton { pressed, .. } => !pointer_captured || !pressed,
+            egui::Event::Touch { phase, .. } => {
+                !pointer_captured || *phase != egui::TouchPhase::Start
+            }
+            egui::Event::MouseWheel { .. } | egui::Event::Zoom(_) => !pointer_captured,
+            _ => true,
+        })
+        .collect()
+}
+
+/// Hands the input of one update to the world, through [`InputEvents`] and [`Actions`].
+pub fn feed(world: &world::World, input: InputEvents) {
+    if let Some(mut actions) = world.resource_mut::<Actions>() {
+        actions.update(&input);
+    }
+    if let Some(mut events) = world.resource_mut::<InputEvents>() {
+        *events = input;
+    }
+}
+
+/// Named actions and axes resolved from raw input through [`bindings::Bindings`].
+///
+/// Contexts are stacked, the top one is looked up first and [`GLOBAL`] after it. A binding
+/// belongs to the first of those contexts that maps it, so the menu binding Escape hides the
+/// gameplay binding of Escape instead of both firing.
+pub struct Actions {
+    bindings: bindings::Bindings,
+    contexts: Vec<String>,
+    held: Vec<Binding>,
+    pressed: Vec<Binding>,
+    released: Vec<Binding>,
+    /// Which gamepad holds which button, a button binding is held while any gamepad holds it.
+    gamepad_buttons: Vec<(gamepad::GamepadId, GamepadButton)>,
+    gamepad_axes: Vec<(gamepad::GamepadId, GamepadAxis, f32)>,
+    pointer: Option<glam::Vec2>,
+    wheel: glam::Vec2,
+    touch: touch::GestureRecognizer,
+    gestures: Vec<touch::Gesture>,
+    rebinding: Option<(String, String)>,
+}
+
+impl Actions {
+    pub fn new(bindings: bindings::Bindings) -> Self {
+        Self {
+            bindings,
+            contexts: Vec::new(),
+            held: Vec::new(),
+            pressed: Vec::new(),
+            released: Vec::new(),
+            gamepad_buttons: Vec::new(),
+            gamepad_axes: Vec::new(),
+            pointer: None,
+            wheel: glam::Vec2::ZERO,
+            touch: touch::GestureRecognizer::default(),
+            gestures: Vec::new(),
+            rebinding: None,
+        }
+    }
+    pub fn bindings(&self) -> &bindings::Bindings {
+        &self.bindings
+    }
+    pub fn set_bindings(&mut self, bindings: bindings::Bindings) {
+        self.bindings = bindings;
+    }
+    pub fn push_context(&mut self, context: &str) {
+        self.contexts.push(context.to_owned());
+    }
+    /// Removes the topmost `context`, scenes pop what they pushed even if others pushed on top.
+    pub fn pop_context(&mut self, context: &str) {
+        if let Some(index) = self.contexts.iter().rposition(|other| other == context) {
+            self.contexts.remove(index);
+        }
+    }
+    pub fn context(&self) -> Option<&str> {
+        self.contexts.last().map(String::as_str)
+    }
+    /// The whole stack, bottom first.
+    pub fn contexts(&self) -> &[String] {
+        &self.contexts
+    }
+    pub fn set_contexts(&mut self, contexts: Vec<String>) {
+        self.contexts = contexts;
+    }
+    /// Binds the next pressed key, mouse or gamepad button to `action`, see [`Actions::is_rebinding`].
+    pub fn start_rebind(&mut self, context: &str, action: &str) {
+        self.rebinding = Some((context.to_owned(), action.to_owned()));
+    }
+    pub fn cancel_rebind(&mut self) {
+        self.rebinding = None;
+    }
+    /// While waiting for a new binding every action reads as released.
+    pub fn is_rebinding(&self) -> bool {
+        self.rebinding.is_some()
+    }
+    /// Starts a new update, forgetting last update's presses, releases and wheel movement.
+    pub fn update(&mut self, input: &InputEvents) {
+        self.pressed.clear();
+        self.released.clear();
+        self.wheel = glam::Vec2::ZERO;
+        self.gestures.clone_from(&input.gestures);
+        self.touch.update(&input.events, &mut self.gestures);
+        for event in &input.events {
+            match event {
+                egui::Event::Key {
+                    key,
+                    physical_key,
+                    pressed,
+                    repeat: false,
+                    ..
+                } => self.set(Binding::Key(physical_key.unwrap_or(*key)), *pressed),
+                egui::Event::PointerButton {
+                    pos,
+                    button,
+                    pressed,
+                    ..
+                } => {
+                    self.pointer = Some(glam::vec2(pos.x, pos.y));
+                    self.set(Binding::Mouse(*button), *pressed);
+                }
+                egui::Event::PointerMoved(position) => {
+                    self.pointer = Some(glam::vec2(position.x, position.y));
+                }
+                egui::Event::PointerGone => self.pointer = None,
+                egui::Event::MouseWheel { delta, .. } => {
+                    self.wheel += glam::vec2(delta.x, delta.y);
+                }
+                _ => (),
+            }
+        }
+        for event in &input.gamepad {
+            match event {
+                gamepad::GamepadEvent::Connected(_) => (),
+                gamepad::GamepadEvent::Disconnected(disconnected) => {
+                    let held = self
+                        .gamepad_buttons
+                        .iter()
+                        .filter(|(gamepad, _)| gamepad == disconnected)
+                        .map(|(_, button)| *button)
+                        .collect::<Vec<_>>();
+                    for button in held {
+                        self.set_gamepad_button(*disconnected, button, false);
+                    }
+                    self.gamepad_axes
+                        .retain(|(gamepad, _, _)| gamepad != disconnected);
+                }
+                gamepad::GamepadEvent::Button {
+                    gamepad,
+                    button,
+                    pressed,
+                } => self.set_gamepad_button(*gamepad, *button, *pressed),
+                gamepad::GamepadEvent::Axis {
+                    gamepad,
+                    axis,
+                    value,
+                } => self.set_gamepad_axis(*gamepad, *axis, *value),
+            }
+        }
+    }
+    /// Presses or releases a binding directly, outside of [`Actions::update`].
+    pub fn set(&mut self, binding: Binding, pressed: bool) {
+        let held = self.held.contains(&binding);
+        if pressed && !held {
+            self.held.push(binding);
+            match self.rebinding.take() {
+                Some((context, action)) => self.bindings.bind(&context, &action, binding),
+                None => self.pressed.push(binding),
+            }
+        } else if !pressed && held {
+            self.held.retain(|other| *other != binding);
+            self.released.push(binding);
+        }
+    }
+    /// Presses or releases a button of one gamepad, its binding stays held while another
+    /// gamepad still holds the same button.
+    pub fn set_gamepad_button(
+        &mut self,
+        gamepad: gamepad::GamepadId,
+        button: GamepadButton,
+        pressed: bool,
+    ) {
+        self.gamepad_buttons
+            .retain(|held| *held != (gamepad, button));
+        if pressed {
+            self.gamepad_buttons.push((gamepad, button));
+        }
+        let held = self
+            .gamepad_buttons
+            .iter()
+            .any(|(_, other)| *other == button);
+        self.set(Binding::Gamepad(button), held);
+    }
+    /// Value of an analog axis of one gamepad, triggers range from 0 to 1 and sticks from -1
+    /// to 1.
+    pub fn set_gamepad_axis(&mut self, gamepad: gamepad::GamepadId, axis: GamepadAxis, value: f32) {
+        match self
+            .gamepad_axes
+            .iter_mut()
+            .find(|(other_gamepad, other, _)| *other_gamepad == gamepad && *other == axis)
+        {
+            Some((_, _, current)) => *current = value,
+            None => self.gamepad_axes.push((gamepad, axis, value)),
+        }
+    }
+    /// The value furthest from rest across all gamepads.
+    pub fn gamepad_axis(&self, axis: GamepadAxis) -> f32 {
+        self.gamepad_axes
+            .iter()
+            .filter(|(_, other, _)| *other == axis)
+            .map(|(_, _, value)| *value)
+            .fold(0.0, |furthest, value| {
+                if value.abs() > furthest.abs() {
+                    value
+                } else {
+                    furthest
+                }
+            })
+    }
+    /// Last pointer position in window pixels, `None` once the pointer left the window.
+    pub fn pointer(&self) -> Option<glam::Vec2> {
+        self.pointer
+    }
+    pub fn wheel(&self) -> glam::Vec2 {
+        self.wheel
+    }
+    /// Gestures of the last update, from touches and from the platform.
+    pub fn gestures(&self) -> &[touch::Gesture] {
+        &self.gestures
+    }
+    pub fn is_down(&self, action: &str) -> bool {
+        self.action_bindings(action)
+            .any(|binding| self.held.contains(&binding))
+    }
+    pub fn just_pressed(&self, action: &str) -> bool {
+        self.action_bindings(action)
+            .any(|binding| self.pressed.contains(&binding))
+    }
+    pub fn just_released(&self, action: &str) -> bool {
+        self.action_bindings(action)
+            .any(|binding| self.released.contains(&binding))
+    }
+    /// Digital bindings count as full deflection, the result is clamped to -1..=1.
+    pub fn axis(&self, axis: &str) -> f32 {
+        let Some((context, binding)) = self.active_contexts().find_map(|context| {
+            self.bindings
+                .axis(context, axis)
+                .map(|binding| (context, binding))
+        }) else {
+            return 0.0;
+        };
+        if self.is_rebinding() {
+            return 0.0;
+        }
+        let side = |bindings: &[Binding]| {
+            bindings
+                .iter()
+                .any(|binding| self.held.contains(binding) && self.owner(*binding) == Some(context))
+                as u8 as f32
+        };
+        let analog = binding
+            .analog
+            .iter()
+            .map(|axis| self.gamepad_axis(*axis))
+            .sum::<f32>();
+        (side(&binding.positive) - side(&binding.negative) + analog).clamp(-1.0, 1.0)
+    }
+    fn active_contexts(&self) -> impl Iterator<Item = &str> {
+        self.contexts
+            .last()
+            .map(String::as_str)
+            .into_iter()
+            .chain(std::iter::once(GLOBAL))
+    }
+    /// The context a held binding is routed to.
+    fn owner(&self, binding: Binding) -> Option<&str> {
+        self.active_contexts()
+            .find(|context| self.bindings.maps(context, binding))
+    }
+    /// Bindings of `action` in the first active context defining it that are routed to it.
+    fn action_bindings(&self, action: &str) -> impl Iterator<Item = Binding> + '_ {
+        let found = (!self.is_rebinding())
+            .then(|| {
+                self.active_contexts().find_map(|context| {
+                    self.bindings
+                        .action(context, action)
+                        .map(|bindings| (context, bindings))
+                })
+            })
+            .flatten();
+        found.into_iter().flat_map(move |(context, bindings)| {
+            bindings
+                .iter()
+                .copied()
+                .filter(move |binding| self.owner(*binding) == Some(context))
+        })
+    }
+}
@@ -0,0 +1,388 @@
+use crate::world::input;
+use std::collections;
+use std::fs;
+use std::io::Write;
+use std::path;
+
+/// Both sides of an axis, plus analog gamepad axes that are added on top.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AxisBinding {
+    pub negative: Vec<input::Binding>,
+    pub positive: Vec<input::Binding>,
+    pub analog: Vec<input::GamepadAxis>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ContextBindings {
+    pub actions: collections::BTreeMap<String, Vec<input::Binding>>,
+    pub axes: collections::BTreeMap<String, AxisBinding>,
+}
+
+/// Which bindings drive which action or axis, per input context.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bindings {
+    pub contexts: collections::BTreeMap<String, ContextBindings>,
+}
+
+impl Bindings {
+    pub fn defaults() -> Self {
+        use input::Binding::{Gamepad, Key, Mouse};
+        use input::GamepadButton;
+        let mut bindings = Self::default();
+        let global = bindings.context_mut(input::GLOBAL);
+        global
+            .actions
+            .insert("debugger".to_owned(), vec![Key(egui::Key::F3)]);
+
+        let gameplay = bindings.context_mut(input::GAMEPLAY);
+        for (action, defaults) in [
+            (
+                "pause",
+                vec![Key(egui::Key::Escape), Gamepad(GamepadButton::Start)],
+            ),
+            (
+                "primary",
+                vec![
+                    Mouse(egui::PointerButton::Primary),
+                    Gamepad(GamepadButton::South),
+                ],
+            ),
+            (
+                "secondary",
+                vec![
+                    Mouse(egui::PointerButton::Secondary),
+                    Gamepad(GamepadButton::East),
+                ],
+            ),
+        ] {
+            gameplay.actions.insert(action.to_owned(), defaults);
+        }
+        gameplay.axes.insert(
+            "move x".to_owned(),
+            AxisBinding {
+                negative: vec![
+                    Key(egui::Key::A),
+                    Key(egui::Key::ArrowLeft),
+                    Gamepad(GamepadButton::DPadLeft),
+                ],
+                positive: vec![
+                    Key(egui::Key::D),
+                    Key(egui::Key::ArrowRight),
+                    Gamepad(GamepadButton::DPadRight),
+                ],
+                analog: vec![input::GamepadAxis::LeftStickX],
+            },
+        );
+        gameplay.axes.insert(
+            "move y".to_owned(),
+            AxisBinding {
+                negative: vec![
+                    Key(egui::Key::S),
+                    Key(egui::Key::ArrowDown),
+                    Gamepad(GamepadButton::DPadDown),
+                ],
+                positive: vec![
+                    Key(egui::Key::W),
+                    Key(egui::Key::ArrowUp),
+                    Gamepad(GamepadButton::DPadUp),
+                ],
+                analog: vec![input::GamepadAxis::LeftStickY],
+            },
+        );
+
+        let menu = bindings.context_mut(input::MENU);
+        for (action, defaults) in [
+            (
+                "back",
+                vec![Key(egui::Key::Escape), Gamepad(GamepadButton::East)],
+            ),
+            (
+                "confirm",
+                vec![Key(egui::Key::Enter), Gamepad(GamepadButton::South)],
+            ),
+        ] {
+            menu.actions.insert(action.to_owned(), defaults);
+        }
+        bindings
+    }
+    pub fn context_mut(&mut self, context: &str) -> &mut ContextBindings {
+        self.contexts.entry(context.to_owned()).or_default()
+    }
+    pub fn action(&self, context: &str, action: &str) -> Option<&[input::Binding]> {
+        self.contexts
+            .get(context)?
+            .actions
+            .get(action)
+            .map(Vec::as_slice)
+    }
+    pub fn axis(&self, context: &str, axis: &str) -> Option<&AxisBinding> {
+        self.contexts.get(context)?.axes.get(axis)
+    }
+    /// Whether any action or axis of `context` uses `binding`.
+    pub fn maps(&self, context: &str, binding: input::Binding) -> bool {
+        self.contexts.get(context).is_some_and(|bindings| {
+            bindings
+                .actions
+                .values()
+                .any(|bindings| bindings.contains(&binding))
+                || bindings.axes.values().any(|axis| {
+                    axis.negative.contains(&binding) || axis.positive.contains(&binding)
+                })
+        })
+    }
+    /// Makes `binding` the only binding of its device kind for `action`, so rebinding a key keeps
+    /// the gamepad button. It is taken away from every other action of the context so one press
+    /// never means two things.
+    pub fn bind(&mut self, context: &str, action: &str, binding: input::Binding) {
+        let bindings = self.context_mut(context);
+        for others in bindings.actions.values_mut() {
+            others.retain(|other| *other != binding);
+        }
+        for axis in bindings.axes.values_mut() {
+            axis.negative.retain(|other| *other != binding);
+            axis.positive.retain(|other| *other != binding);
+        }
+        let bound = bindings.actions.entry(action.to_owned()).or_default();
+        bound.retain(|other| other.is_gamepad() != binding.is_gamepad());
+        bound.insert(0, binding);
+    }
+
+    /// One line per action or axis, `context|action|name|bindings` or
+    /// `context|axis|name|negative|positive|analog` with bindings separated by commas.
+    pub fn to_text(&self) -> String {
+        let list = |bindings: &[input::Binding]| {
+            bindings
+                .iter()
+                .map(|binding| binding_to_text(*binding))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let mut text = String::new();
+        for (context, bindings) in &self.contexts {
+            for (action, bindings) in &bindings.actions {
+                text += &format!("{context}|action|{action}|{}\n", list(bindings));
+            }
+            for (axis, binding) in &bindings.axes {
+                let analog = binding
+                    .analog
+                    .iter()
+                    .map(|axis| format!("{axis:?}"))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                text += &format!(
+                    "{context}|axis|{axis}|{}|{}|{analog}\n",
+                    list(&binding.negative),
+                    list(&binding.positive),
+                );
+            }
+        }
+        text
+    }
+    /// Reads [`Bindings::to_text`] output over the defaults, so actions added since the file was
+    /// written keep their default bindings.
+    pub fn from_text(text: &str) -> anyhow::Result<Self> {
+        let list = |text: &str| {
+            text.split(',')
+                .filter(|binding| !binding.is_empty())
+                .map(binding_from_text)
+                .collect::<anyhow::Result<Vec<_>>>()
+        };
+        let mut bindings = Self::defaults();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let fields = line.split('|').collect::<Vec<_>>();
+            match fields.as_slice() {
+                [context, "action", action, bound] => {
+                    bindings
+                        .context_mut(context)
+                        .actions
+                        .insert((*action).to_owned(), list(bound)?);
+                }
+                [context, "axis", axis, negative, positive, analog] => {
+                    let analog = analog
+                        .split(',')
+                        .filter(|axis| !axis.is_empty())
+                        .map(gamepad_axis_from_text)
+                        .collect::<anyhow::Result<Vec<_>>>()?;
+                    bindings.context_mut(context).axes.insert(
+                        (*axis).to_owned(),
+                        AxisBinding {
+                            negative: list(negative)?,
+                            positive: list(positive)?,
+                            analog,
+                        },
+                    );
+                }
+                _ => anyhow::bail!("malformed binding line: {line}"),
+            }
+        }
+        Ok(bindings)
+    }
+    /// Bindings live next to the executable, like saves.
+    pub fn path() -> path::PathBuf {
+        let mut path = std::env::current_exe().unwrap_or_default();
+        path.pop();
+        path.join("bindings.txt")
+    }
+    /// The saved bindings, or the defaults if there are none or they cannot be read.
+    pub fn load() -> Self {
+        let path = Self::path();
+        let Ok(text) = fs::read_to_string(&path) else {
+            return Self::defaults();
+        };
+        Self::from_text(&text).unwrap_or_else(|error| {
+            log::warn!("Ignoring {}: {error:#}", path.display());
+            Self::defaults()
+        })
+    }
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = Self::path();
+        let temporary = path.with_extension("txt.tmp");
+        let mut file = fs::File::create(&temporary)?;
+        file.write_all(self.to_text().as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&temporary, &path)?;
+        Ok(())
+    }
+}
+
+/// What menus show for a binding.
+pub fn binding_name(binding: input::Binding) -> String {
+    match binding {
+        input::Binding::Key(key) => key.name().to_owned(),
+        input::Binding::Mouse(button) => format!("Mouse {button:?}"),
+        input::Binding::Gamepad(button) => format!("Gamepad {button:?}"),
+    }
+}
+
+fn binding_to_text(binding: input::Binding) -> String {
+    match binding {
+        input::Binding::Key(key) => format!("key:{}", key.name()),
+        input::Binding::Mouse(button) => format!("mouse:{button:?}"),
+        input::Binding::Gamepad(button) => format!("gamepad:{button:?}"),
+    }
+}
+
+fn binding_from_text(text: &str) -> anyhow::Result<input::Binding> {
+    let Some((device, name)) = text.split_once(':') else {
+        anyhow::bail!("binding without a device: {text}");
+    };
+    Ok(match device {
+        "key" => input::Binding::Key(
+            egui::Key::from_name(name).ok_or_else(|| anyhow::anyhow!("unknown key {name}"))?,
+        ),
+        "mouse" => input::Binding::Mouse(match name {
+            "Primary" => egui::PointerButton::Primary,
+            "Secondary" => egui::PointerButton::Secondary,
+            "Middle" => egui::PointerButton::Middle,
+            "Extra1" => egui::PointerButton::Extra1,
+            "Extra2" => egui::PointerButton::Extra2,
+            _ => anyhow::bail!("unknown mouse button {name}"),
+        }),
+        "gamepad" => input::Binding::Gamepad(
+            input::GamepadButton::ALL
+                .into_iter()
+                .find(|button| format!("{button:?}") == name)
+                .ok_or_else(|| anyhow::anyhow!("unknown gamepad button {name}"))?,
+        ),
+        _ => anyhow::bail!("unknown input device {device}"),
+    })
+}
+
+fn gamepad_axis_from_text(text: &str) -> anyhow::Result<input::GamepadAxis> {
+    input::GamepadAxis::ALL
+        .into_iter()
+        .find(|axis| format!("{axis:?}") == text)
+        .ok_or_else(|| anyhow::anyhow!("unknown gamepad axis {text}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_binding_round_trips_through_its_name() {
+        let keys = egui::Key::ALL.iter().map(|key| input::Binding::Key(*key));
+        let mouse = [
+            egui::PointerButton::Primary,
+            egui::PointerButton::Secondary,
+            egui::PointerButton::Middle,
+            egui::PointerButton::Extra1,
+            egui::PointerButton::Extra2,
+        ]
+        .into_iter()
+        .map(input::Binding::Mouse);
+        let gamepad = input::GamepadButton::ALL
+            .into_iter()
+            .map(input::Binding::Gamepad);
+        for binding in keys.chain(mouse).chain(gamepad) {
+            let text = binding_to_text(binding);
+            assert!(
+                !text.contains([',', '|', '\n']),
+                "{text} clashes with the separators"
+            );
+            assert_eq!(binding_from_text(&text).unwrap(), binding, "{text}");
+        }
+        for axis in input::GamepadAxis::ALL {
+            assert_eq!(gamepad_axis_from_text(&format!("{axis:?}")).unwrap(), axis);
+        }
+    }
+
+    #[test]
+    fn bindings_round_trip_through_text() {
+        let mut bindings = Bindings::defaults();
+        assert_eq!(Bindings::from_text(&bindings.to_text()).unwrap(), bindings);
+
+        bindings.bind(
+            input::GAMEPLAY,
+            "pause",
+            input::Binding::Key(egui::Key::Backtick),
+        );
+        bindings.bind(
+            input::GAMEPLAY,
+            "primary",
+            input::Binding::Gamepad(input::GamepadButton::RightTrigger),
+        );
+        bindings.context_mut("editor").axes.insert(
+            "zoom".to_owned(),
+            AxisBinding {
+                negative: vec![input::Binding::Key(egui::Key::Minus)],
+                positive: vec![input::Binding::Key(egui::Key::Plus)],
+                analog: vec![input::GamepadAxis::RightStickY],
+            },
+        );
+        bindings
+            .context_mut("editor")
+            .actions
+            .insert("unbound".to_owned(), Vec::new());
+        assert_eq!(Bindings::from_text(&bindings.to_text()).unwrap(), bindings);
+    }
+
+    #[test]
+    fn missing_lines_keep_their_defaults() {
+        let bindings = Bindings::from_text("gameplay|action|pause|key:P\n").unwrap();
+        assert_eq!(
+            bindings.action(input::GAMEPLAY, "pause"),
+            Some([input::Binding::Key(egui::Key::P)].as_slice())
+        );
+        assert_eq!(
+            bindings.action(input::GLOBAL, "debugger"),
+            Bindings::defaults().action(input::GLOBAL, "debugger")
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_names() {
+        for text in [
+            "gameplay|action|pause|key:NotAKey",
+            "gameplay|action|pause|mouse:Extra3",
+            "gameplay|action|pause|gamepad:Turbo",
+            "gameplay|action|pause|wheel:Up",
+            "gameplay|action|pause|P",
+            "gameplay|axis|move x|||Throttle",
+            "gameplay|pause",
+        ] {
+            assert!(Bindings::from_text(text).is_err(), "{text}");
+        }
+    }
+}
@@ -0,0 +1,582 @@
+use crate::world::input;
+use std::collections;
+use std::sync;
+use std::time;
+
+/// Stick and trigger values past this count as a direction when navigating menus.
+const NAVIGATION_THRESHOLD: f32 = 0.5;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GamepadId(pub u32);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GamepadEvent {
+    Connected(GamepadId),
+    Disconnected(GamepadId),
+    Button {
+        gamepad: GamepadId,
+        button: input::GamepadButton,
+        pressed: bool,
+    },
+    /// Sticks range from -1 to 1 with up and right positive, triggers from 0 to 1.
+    Axis {
+        gamepad: GamepadId,
+        axis: input::GamepadAxis,
+        value: f32,
+    },
+}
+
+/// A request to shake one gamepad, or all of them when `gamepad` is `None`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rumble {
+    pub gamepad: Option<GamepadId>,
+    /// Low frequency motor, 0 to 1.
+    pub strong: f32,
+    /// High frequency motor, 0 to 1.
+    pub weak: f32,
+    pub duration: time::Duration,
+}
+
+/// Rumbles requested by systems during an update, sent to the gamepads once it is done.
+#[derive(Clone, Debug, Default)]
+pub struct Rumbles {
+    pub requests: Vec<Rumble>,
+}
+
+impl Rumbles {
+    pub fn request(&mut self, rumble: Rumble) {
+        self.requests.push(rumble);
+    }
+}
+
+/// A source of gamepad input, reporting raw axis values that [`Gamepads`] applies deadzones to.
+///
+/// Ids only have to be unique within one backend. There is no backend for real hardware yet,
+/// only [`MockBackend`], so gamepads are driven through [`MockGamepads`] until one is added.
+pub trait Backend {
+    fn name(&self) -> &str;
+    /// Appends everything that happened since the last poll.
+    fn poll(&mut self, events: &mut Vec<GamepadEvent>);
+    /// Returns whether the backend can rumble at all.
+    fn rumble(&mut self, gamepad: GamepadId, rumble: Rumble) -> bool {
+        let _ = (gamepad, rumble);
+        false
+    }
+}
+
+/// Scales values between `inner` and `outer` to the full range and cuts off the rest.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Deadzone {
+    pub inner: f32,
+    pub outer: f32,
+}
+
+impl Deadzone {
+    pub fn apply(&self, value: f32) -> f32 {
+        let magnitude = value.abs();
+        if magnitude <= self.inner {
+            return 0.0;
+        }
+        let scaled =
+            ((magnitude - self.inner) / (self.outer - self.inner).max(f32::EPSILON)).min(1.0);
+        scaled.copysign(value)
+    }
+    /// Applies the deadzone to the length of a stick, so diagonals are not cut off early.
+    pub fn apply_radial(&self, stick: glam::Vec2) -> glam::Vec2 {
+        let length = stick.length();
+        if length <= self.inner {
+            return glam::Vec2::ZERO;
+        }
+        stick / length * self.apply(length)
+    }
+}
+
+fn stick_partner(axis: input::GamepadAxis) -> Option<(input::GamepadAxis, input::GamepadAxis)> {
+    use input::GamepadAxis::*;
+    match axis {
+        LeftStickX | LeftStickY => Some((LeftStickX, LeftStickY)),
+        RightStickX | RightStickY => Some((RightStickX, RightStickY)),
+        LeftTrigger | RightTrigger => None,
+    }
+}
+
+/// Every connected gamepad across all backends, with deadzones applied.
+///
+/// Backend ids are renumbered so gamepads of different backends never share an id. Gameplay
+/// treats all gamepads as one, see [`input::Actions`].
+pub struct Gamepads {
+    backends: Vec<Box<dyn Backend>>,
+    pub stick_deadzone: Deadzone,
+    pub trigger_deadzone: Deadzone,
+    ids: collections::BTreeMap<(usize, GamepadId), GamepadId>,
+    next_id: u32,
+    raw: collections::BTreeMap<(GamepadId, input::GamepadAxis), f32>,
+    values: collections::BTreeMap<(GamepadId, input::GamepadAxis), f32>,
+    navigation: Option<egui::Key>,
+}
+
+impl Default for Gamepads {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Gamepads {
+    pub fn new() -> Self {
+        Self {
+            backends: Vec::new(),
+            stick_deadzone: Deadzone {
+                inner: 0.15,
+                outer: 0.95,
+            },
+            trigger_deadzone: Deadzone {
+                inner: 0.05,
+                outer: 1.0,
+            },
+            ids: collections::BTreeMap::new(),
+            next_id: 0,
+            raw: collections::BTreeMap::new(),
+            values: collections::BTreeMap::new(),
+            navigation: None,
+        }
+    }
+    pub fn add_backend(&mut self, backend: Box<dyn Backend>) {
+        log::info!("Gamepad backend: {}", backend.name());
+        self.backends.push(backend);
+    }
+    pub fn connected(&self) -> impl Iterator<Item = GamepadId> + '_ {
+        self.ids.values().copied()
+    }
+    /// Everything that changed since the last poll, axes only when their value after the
+    /// deadzone did, starting from rest.
+    pub fn poll(&mut self) -> Vec<GamepadEvent> {
+        let mut events = Vec::new();
+        let mut polled = Vec::new();
+        for index in 0..self.backends.len() {
+            self.backends[index].poll(&mut polled);
+            for event in polled.drain(..) {
+                self.process(index, event, &mut events);
+            }
+        }
+        events
+    }
+    fn id(&mut self, backend: usize, local: GamepadId) -> GamepadId {
+        *self.ids.entry((backend, local)).or_insert_with(|| {
+            self.next_id += 1;
+            GamepadId(self.next_id - 1)
+        })
+    }
+    fn process(&mut self, backend: usize, event: GamepadEvent, events: &mut Vec<GamepadEvent>) {
+        match event {
+            GamepadEvent::Connected(local) => {
+                let gamepad = self.id(backend, local);
+                events.push(GamepadEvent::Connected(gamepad));
+            }
+            GamepadEvent::Disconnected(local) => {
+                if let Some(gamepad) = self.ids.remove(&(backend, local)) {
+                    self.raw.retain(|(other, _), _| *other != gamepad);
+                    self.values.retain(|(other, _), _| *other != gamepad);
+                    events.push(GamepadEvent::Disconnected(gamepad));
+                }
+            }
+            GamepadEvent::Button {
+                gamepad,
+                button,
+                pressed,
+            } => events.push(GamepadEvent::Button {
+                gamepad: self.id(backend, gamepad),
+                button,
+                pressed,
+            }),
+            GamepadEvent::Axis {
+                gamepad,
+                axis,
+                value,
+            } => {
+                let gamepad = self.id(backend, gamepad);
+                self.raw.insert((gamepad, axis), value);
+                let processed = match stick_partner(axis) {
+                    Some((x, y)) => {
+                        let raw = |axis| self.raw.get(&(gamepad, axis)).copied().unwrap_or(0.0);
+                        let stick = self.stick_deadzone.apply_radial(glam::vec2(raw(x), raw(y)));
+                        vec![(x, stick.x), (y, stick.y)]
+                    }
+                    None => vec![(axis, self.trigger_deadzone.apply(value))],
+                };
+                for (axis, value) in processed {
+                    let previous = self.values.insert((gamepad, axis), value);
+                    if previous.unwrap_or(0.0) != value {
+                        events.push(GamepadEvent::Axis {
+                            gamepad,
+                            axis,
+                            value,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    pub fn rumble(&mut self, rumble: Rumble) {
+        let targets = self
+            .ids
+            .iter()
+            .filter(|(_, gamepad)| rumble.gamepad.is_none_or(|target| target == **gamepad))
+            .map(|((backend, local), _)| (*backend, *local))
+            .collect::<Vec<_>>();
+        for (backend, local) in targets {
+            self.backends[backend].rumble(local, rumble);
+        }
+    }
+    /// Keys that let a gamepad move egui's focus and press the focused widget.
+    ///
+    /// The D-pad and left stick move the focus, or focus the first widget through Tab while
+    /// nothing is focused, and the south button presses Enter.
+    pub fn navigation(&mut self, events: &[GamepadEvent], has_focus: bool) -> Vec<egui::Event> {
+        use input::GamepadButton;
+        let mut keys = Vec::new();
+        for event in events {
+            if let GamepadEvent::Button {
+                button,
+                pressed: true,
+                ..
+            } = event
+            {
+                keys.extend(match button {
+                    GamepadButton::DPadUp => Some(egui::Key::ArrowUp),
+                    GamepadButton::DPadDown => Some(egui::Key::ArrowDown),
+                    GamepadButton::DPadLeft => Some(egui::Key::ArrowLeft),
+                    GamepadButton::DPadRight => Some(egui::Key::ArrowRight),
+                    GamepadButton::South => Some(egui::Key::Enter),
+                    _ => None,
+                });
+            }
+        }
+        let stick =
+            self.values
+                .iter()
+                .fold(glam::Vec2::ZERO, |stick, ((_, axis), value)| match axis {
+                    input::GamepadAxis::LeftStickX => stick + glam::vec2(*value, 0.0),
+                    input::GamepadAxis::LeftStickY => stick + glam::vec2(0.0, *value),
+                    _ => stick,
+                });
+        let direction = if stick.length() < NAVIGATION_THRESHOLD {
+            None
+        } else if stick.x.abs() > stick.y.abs() {
+            Some(if stick.x > 0.0 {
+                egui::Key::ArrowRight
+            } else {
+                egui::Key::ArrowLeft
+            })
+        } else {
+            Some(if stick.y > 0.0 {
+                egui::Key::ArrowUp
+            } else {
+                egui::Key::ArrowDown
+            })
+        };
+        if direction != self.navigation {
+            keys.extend(direction);
+            self.navigation = direction;
+        }
+        keys.into_iter()
+            .flat_map(|key| {
+                let (key, shift) = match key {
+                    egui::Key::ArrowUp | egui::Key::ArrowLeft if !has_focus => {
+                        (egui::Key::Tab, true)
+                    }
+                    egui::Key::ArrowDown | egui::Key::ArrowRight if !has_focus => {
+                        (egui::Key::Tab, false)
+                    }
+                    key => (key, false),
+                };
+                let modifiers = egui::Modifiers {
+                    shift,
+                    ..egui::Modifiers::NONE
+                };
+                [true, false].map(|pressed| egui::Event::Key {
+                    key,
+                    physical_key: None,
+                    pressed,
+                    repeat: false,
+                    modifiers,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    pending: Vec<GamepadEvent>,
+    rumbles: Vec<(GamepadId, Rumble)>,
+}
+
+/// Virtual gamepads driven through a [`MockGamepads`] handle, for tools and tests without
+/// hardware.
+pub struct MockBackend {
+    state: sync::Arc<sync::Mutex<MockState>>,
+}
+
+/// Presses buttons and moves axes of a [`MockBackend`] from anywhere.
+#[derive(Clone)]
+pub struct MockGamepads {
+    state: sync::Arc<sync::Mutex<MockState>>,
+}
+
+impl MockBackend {
+    pub fn new() -> (Self, MockGamepads) {
+        let state = sync::Arc::new(sync::Mutex::new(MockState::default()));
+        (
+            Self {
+                state: state.clone(),
+            },
+            MockGamepads { state },
+        )
+    }
+}
+
+impl Backend for MockBackend {
+    fn name(&self) -> &str {
+        "mock"
+    }
+    fn poll(&mut self, events: &mut Vec<GamepadEvent>) {
+        events.append(&mut self.state.lock().unwrap().pending);
+    }
+    fn rumble(&mut self, gamepad: GamepadId, rumble: Rumble) -> bool {
+        self.state.lock().unwrap().rumbles.push((gamepad, rumble));
+        true
+    }
+}
+
+impl MockGamepads {
+    fn push(&self, event: GamepadEvent) {
+        self.state.lock().unwrap().pending.push(event);
+    }
+    pub fn connect(&self, gamepad: GamepadId) {
+        self.push(GamepadEvent::Connected(gamepad));
+    }
+    pub fn disconnect(&self, gamepad: GamepadId) {
+        self.push(GamepadEvent::Disconnected(gamepad));
+    }
+    pub fn set_button(&self, gamepad: GamepadId, button: input::GamepadButton, pressed: bool) {
+        self.push(GamepadEvent::Button {
+            gamepad,
+            button,
+            pressed,
+        });
+    }
+    /// Sets the raw value, before deadzones.
+    pub fn set_axis(&self, gamepad: GamepadId, axis: input::GamepadAxis, value: f32) {
+        self.push(GamepadEvent::Axis {
+            gamepad,
+            axis,
+            value,
+        });
+    }
+    /// Rumbles requested since the last call.
+    pub fn take_rumbles(&self) -> Vec<(GamepadId, Rumble)> {
+        std::mem::take(&mut self.state.lock().unwrap().rumbles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::input::bindings;
+
+    fn gamepads() -> (Gamepads, MockGamepads) {
+        let (backend, mock) = MockBackend::new();
+        let mut gamepads = Gamepads::new();
+        gamepads.add_backend(Box::new(backend));
+        (gamepads, mock)
+    }
+
+    #[test]
+    fn connects_and_renumbers_across_backends() {
+        let (mut gamepads, first) = gamepads();
+        let (backend, second) = MockBackend::new();
+        gamepads.add_backend(Box::new(backend));
+        first.connect(GamepadId(0));
+        second.connect(GamepadId(0));
+        assert_eq!(
+            gamepads.poll(),
+            [
+                GamepadEvent::Connected(GamepadId(0)),
+                GamepadEvent::Connected(GamepadId(1)),
+            ]
+        );
+        assert_eq!(
+            gamepads.connected().collect::<Vec<_>>(),
+            [GamepadId(0), GamepadId(1)]
+        );
+    }
+
+    #[test]
+    fn passes_buttons_on() {
+        let (mut gamepads, mock) = gamepads();
+        mock.connect(GamepadId(4));
+        gamepads.poll();
+        mock.set_button(GamepadId(4), input::GamepadButton::South, true);
+        mock.set_button(GamepadId(4), input::GamepadButton::South, false);
+        assert_eq!(
+            gamepads.poll(),
+            [true, false].map(|pressed| GamepadEvent::Button {
+                gamepad: GamepadId(0),
+                button: input::GamepadButton::South,
+                pressed,
+            })
+        );
+    }
+
+    #[test]
+    fn applies_deadzones_to_axes() {
+        let (mut gamepads, mock) = gamepads();
+        let gamepad = GamepadId(0);
+        mock.connect(gamepad);
+        gamepads.poll();
+
+        // Inside the deadzone nothing changes, so nothing is reported.
+        mock.set_axis(gamepad, input::GamepadAxis::LeftStickX, 0.1);
+        mock.set_axis(gamepad, input::GamepadAxis::LeftTrigger, 0.02);
+        assert_eq!(gamepads.poll(), []);
+
+        mock.set_axis(gamepad, input::GamepadAxis::LeftStickX, 0.55);
+        mock.set_axis(gamepad, input::GamepadAxis::RightTrigger, 1.0);
+        let events = gamepads.poll();
+        let [
+            GamepadEvent::Axis {
+                axis: input::GamepadAxis::LeftStickX,
+                value: stick,
+                ..
+            },
+            GamepadEvent::Axis {
+                axis: input::GamepadAxis::RightTrigger,
+                value: trigger,
+                ..
+            },
+        ] = events.as_slice()
+        else {
+            panic!("unexpected events {events:?}");
+        };
+        assert!((stick - 0.5).abs() < 1e-5, "{stick}");
+        assert_eq!(*trigger, 1.0);
+
+        // The stick deadzone is radial, a diagonal past it moves both axes.
+        mock.set_axis(gamepad, input::GamepadAxis::RightStickX, 0.12);
+        mock.set_axis(gamepad, input::GamepadAxis::RightStickY, -0.12);
+        let events = gamepads.poll();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            GamepadEvent::Axis {
+                axis: input::GamepadAxis::RightStickY,
+                value,
+                ..
+            } if *value < 0.0
+        )));
+    }
+
+    #[test]
+    fn disconnect_forgets_the_gamepad() {
+        let (mut gamepads, mock) = gamepads();
+        mock.connect(GamepadId(0));
+        mock.set_axis(GamepadId(0), input::GamepadAxis::LeftStickX, 1.0);
+        gamepads.poll();
+        mock.disconnect(GamepadId(0));
+        mock.disconnect(GamepadId(7));
+        assert_eq!(gamepads.poll(), [GamepadEvent::Disconnected(GamepadId(0))]);
+        assert_eq!(gamepads.connected().count(), 0);
+
+        // Reconnecting starts from a resting stick and a new id.
+        mock.connect(GamepadId(0));
+        mock.set_axis(GamepadId(0), input::GamepadAxis::LeftStickY, 1.0);
+        let events = gamepads.poll();
+        assert_eq!(events[0], GamepadEvent::Connected(GamepadId(1)));
+        assert!(events[1..].iter().all(|event| !matches!(
+            event,
+            GamepadEvent::Axis {
+                axis: input::GamepadAxis::LeftStickX,
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn disconnect_releases_only_that_gamepads_bindings() {
+        let mut actions = input::Actions::new(bindings::Bindings::defaults());
+        actions.push_context(input::GAMEPLAY);
+        let first = GamepadId(0);
+        let second = GamepadId(1);
+        let south = input::GamepadButton::South;
+        let update = |actions: &mut input::Actions, gamepad: Vec<GamepadEvent>| {
+            actions.update(&input::InputEvents {
+                gamepad,
+                ..Default::default()
+            });
+        };
+        update(
+            &mut actions,
+            vec![
+                GamepadEvent::Button {
+                    gamepad: first,
+                    button: south,
+                    pressed: true,
+                },
+                GamepadEvent::Button {
+                    gamepad: second,
+                    button: south,
+                    pressed: true,
+                },
+                GamepadEvent::Button {
+                    gamepad: second,
+                    button: input::GamepadButton::East,
+                    pressed: true,
+                },
+                GamepadEvent::Axis {
+                    gamepad: first,
+                    axis: input::GamepadAxis::LeftStickX,
+                    value: 0.75,
+                },
+                GamepadEvent::Axis {
+                    gamepad: second,
+                    axis: input::GamepadAxis::LeftStickX,
+                    value: -0.25,
+                },
+            ],
+        );
+        update(&mut actions, vec![GamepadEvent::Disconnected(second)]);
+        assert!(actions.is_down("primary"));
+        assert!(!actions.is_down("secondary"));
+        assert!(actions.just_released("secondary"));
+        assert_eq!(actions.gamepad_axis(input::GamepadAxis::LeftStickX), 0.75);
+
+        update(&mut actions, vec![GamepadEvent::Disconnected(first)]);
+        assert!(!actions.is_down("primary"));
+        assert_eq!(actions.gamepad_axis(input::GamepadAxis::LeftStickX), 0.0);
+    }
+
+    #[test]
+    fn rumbles_the_targeted_gamepads() {
+        let (mut gamepads, mock) = gamepads();
+        mock.connect(GamepadId(3));
+        mock.connect(GamepadId(5));
+        gamepads.poll();
+        let rumble = Rumble {
+            gamepad: Some(GamepadId(1)),
+            strong: 0.5,
+            weak: 0.25,
+            duration: time::Duration::from_millis(200),
+        };
+        gamepads.rumble(rumble);
+        assert_eq!(mock.take_rumbles(), [(GamepadId(5), rumble)]);
+
+        let everyone = Rumble {
+            gamepad: None,
+            ..rumble
+        };
+        gamepads.rumble(everyone);
+        assert_eq!(
+            mock.take_rumbles(),
+            [(GamepadId(3), everyone), (GamepadId(5), everyone)]
+        );
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections;
+
+/// Fingers that moved further than this in window pixels drag instead of tapping.
+pub const TAP_DISTANCE: f32 = 12.0;
+/// Longest press, in updates, that still counts as a tap.
+pub const TAP_UPDATES: u64 = 15;
+/// Most updates between two taps of a double tap.
+pub const DOUBLE_TAP_UPDATES: u64 = 20;
+
+/// Touch and trackpad gestures in window pixels, y pointing down like egui.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Gesture {
+    Tap {
+        position: glam::Vec2,
+    },
+    DoubleTap {
+        position: glam::Vec2,
+    },
+    /// One finger moved past [`TAP_DISTANCE`], `delta` is the movement since the last update.
+    Drag {
+        position: glam::Vec2,
+        delta: glam::Vec2,
+    },
+    /// Two fingers or a trackpad pinch, `scale` is relative to the last update.
+    Pinch {
+        center: glam::Vec2,
+        scale: f32,
+    },
+    /// Two fingers or a trackpad rotation, `angle` is in radians since the last update and
+    /// clockwise on screen.
+    Rotate {
+        center: glam::Vec2,
+        angle: f32,
+    },
+}
+
+struct Finger {
+    start: glam::Vec2,
+    position: glam::Vec2,
+    started: u64,
+    dragging: bool,
+}
+
+/// Turns egui touch events into [`Gesture`]s, counting time in updates so replays agree.
+#[derive(Default)]
+pub struct GestureRecognizer {
+    fingers: collections::BTreeMap<u64, Finger>,
+    update: u64,
+    last_tap: Option<(u64, glam::Vec2)>,
+    /// Set once a second finger touches and kept until every finger is lifted, so lifting the
+    /// fingers of a pinch does not tap.
+    multi_touch: bool,
+    pointer: glam::Vec2,
+}
+
+impl GestureRecognizer {
+    pub fn update(&mut self, events: &[egui::Event], gestures: &mut Vec<Gesture>) {
+        self.update += 1;
+        let before = self.pair();
+        for event in events {
+            match event {
+                egui::Event::Touch { id, phase, pos, .. } => {
+                    self.touch(id.0, *phase, glam::vec2(pos.x, pos.y), gestures)
+                }
+                egui::Event::PointerMoved(position) => {
+                    self.pointer = glam::vec2(position.x, position.y);
+                }
+                egui::Event::Zoom(scale) => gestures.push(Gesture::Pinch {
+                    center: self.pointer,
+                    scale: *scale,
+                }),
+                _ => (),
+            }
+        }
+        let (Some((ids, a, b)), Some((after_ids, after_a, after_b))) = (before, self.pair()) else {
+            return;
+        };
+        if ids != after_ids {
+            return;
+        }
+        let center = (after_a + after_b) * 0.5;
+        let (span, after_span) = (b - a, after_b - after_a);
+        if span.length() > f32::EPSILON && after_span.length() > f32::EPSILON {
+            let scale = after_span.length() / span.length();
+            if scale != 1.0 {
+                gestures.push(Gesture::Pinch { center, scale });
+            }
+            let angle = span.angle_to(after_span);
+            if angle != 0.0 {
+                gestures.push(Gesture::Rotate { center, angle });
+            }
+        }
+    }
+    fn touch(
+        &mut self,
+        id: u64,
+        phase: egui::TouchPhase,
+        position: glam::Vec2,
+        gestures: &mut Vec<Gesture>,
+    ) {
+        match phase {
+            egui::TouchPhase::Start => {
+                self.fingers.insert(
+                    id,
+                    Finger {
+                        start: position,
+                        position,
+                        started: self.update,
+                        dragging: false,
+                    },
+                );
+                self.multi_touch |= self.fingers.len() > 1;
+            }
+            egui::TouchPhase::Move => {
+                let single = !self.multi_touch;
+                let Some(finger) = self.fingers.get_mut(&id) else {
+                    return;
+                };
+                let delta = position - finger.position;
+                finger.position = position;
+                finger.dragging |= single && finger.start.distance(position) > TAP_DISTANCE;
+                if single && finger.dragging {
+                    gestures.push(Gesture::Drag { position, delta });
+                }
+            }
+            egui::TouchPhase::End => {
+                if let Some(finger) = self.fingers.remove(&id)
+                    && !self.multi_touch
+                    && !finger.dragging
+                    && self.update - finger.started <= TAP_UPDATES
+                {
+                    let double = self.last_tap.is_some_and(|(update, last)| {
+                        self.update - update <= DOUBLE_TAP_UPDATES
+                            && last.distance(position) <= TAP_DISTANCE
+                    });
+                    if double {
+                        self.last_tap = None;
+                        gestures.push(Gesture::DoubleTap { position });
+                    } else {
+                        self.last_tap = Some((self.update, position));
+                        gestures.push(Gesture::Tap { position });
+                    }
+                }
+            }
+            egui::TouchPhase::Cancel => {
+                self.fingers.remove(&id);
+            }
+        }
+        if self.fingers.is_empty() {
+            self.multi_touch = false;
+        }
+    }
+    /// The two fingers that touched first, with their ids.
+    fn pair(&self) -> Option<((u64, u64), glam::Vec2, glam::Vec2)> {
+        let mut fingers = self.fingers.iter();
+        let (a_id, a) = fingers.next()?;
+        let (b_id, b) = fingers.next()?;
+        Some(((*a_id, *b_id), a.position, b.position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(id: u64, phase: egui::TouchPhase, x: f32, y: f32) -> egui::Event {
+        egui::Event::Touch {
+            device_id: egui::TouchDeviceId(0),
+            id: egui::TouchId(id),
+            phase,
+            pos: egui::pos2(x, y),
+            force: None,
+        }
+    }
+
+    /// Feeds one update per list of events and collects every gesture.
+    fn recognize(updates: &[Vec<egui::Event>]) -> Vec<Gesture> {
+        let mut recognizer = GestureRecognizer::default();
+        let mut gestures = Vec::new();
+        for events in updates {
+            recognizer.update(events, &mut gestures);
+        }
+        gestures
+    }
+
+    fn idle(updates: u64) -> impl Iterator<Item = Vec<egui::Event>> {
+        (0..updates).map(|_| Vec::new())
+    }
+
+    fn tap(x: f32, y: f32) -> [Vec<egui::Event>; 2] {
+        [
+            vec![touch(0, egui::TouchPhase::Start, x, y)],
+            vec![touch(0, egui::TouchPhase::End, x, y)],
+        ]
+    }
+
+    #[test]
+    fn taps_and_double_taps_within_the_window() {
+        let position = glam::vec2(40.0, 60.0);
+        let taps = |gap: u64| {
+            let updates = tap(40.0, 60.0)
+                .into_iter()
+                .chain(idle(gap))
+                .chain(tap(44.0, 58.0))
+                .collect::<Vec<_>>();
+            recognize(&updates)
+        };
+        // The second tap ends `gap + 2` updates after the first.
+        assert_eq!(
+            taps(DOUBLE_TAP_UPDATES - 2),
+            [
+                Gesture::Tap { position },
+                Gesture::DoubleTap {
+                    position: glam::vec2(44.0, 58.0)
+                },
+            ]
+        );
+        assert_eq!(
+            taps(DOUBLE_TAP_UPDATES - 1),
+            [
+                Gesture::Tap { position },
+                Gesture::Tap {
+                    position: glam::vec2(44.0, 58.0)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn long_presses_do_not_tap() {
+        let held = |updates: u64| {
+            let [start, end] = tap(0.0, 0.0);
+            recognize(
+                &std::iter::once(start)
+                    .chain(idle(updates - 1))
+                    .chain([end])
+                    .collect::<Vec<_>>(),
+            )
+        };
+        assert_eq!(held(TAP_UPDATES).len(), 1);
+        assert_eq!(held(TAP_UPDATES + 1), []);
+    }
+
+    #[test]
+    fn drags_once_past_the_threshold() {
+        let gestures = recognize(&[
+            vec![touch(0, egui::TouchPhase::Start, 0.0, 0.0)],
+            vec![touch(0, egui::TouchPhase::Move, TAP_DISTANCE, 0.0)],
+            vec![touch(0, egui::TouchPhase::Move, TAP_DISTANCE + 1.0, 0.0)],
+            vec![touch(0, egui::TouchPhase::Move, TAP_DISTANCE + 1.0, 5.0)],
+            vec![touch(0, egui::TouchPhase::End, TAP_DISTANCE + 1.0, 5.0)],
+        ]);
+        assert_eq!(
+            gestures,
+            [
+                Gesture::Drag {
+                    position: glam::vec2(TAP_DISTANCE + 1.0, 0.0),
+                    delta: glam::vec2(1.0, 0.0),
+                },
+                Gesture::Drag {
+                    position: glam::vec2(TAP_DISTANCE + 1.0, 5.0),
+                    delta: glam::vec2(0.0, 5.0),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn pinches_relative_to_the_last_update() {
+        let gestures = recognize(&[
+            vec![
+                touch(0, egui::TouchPhase::Start, 90.0, 100.0),
+                touch(1, egui::TouchPhase::Start, 110.0, 100.0),
+            ],
+            vec![
+                touch(0, egui::TouchPhase::Move, 80.0, 100.0),
+                touch(1, egui::TouchPhase::Move, 120.0, 100.0),
+            ],
+            vec![
+                touch(0, egui::TouchPhase::Move, 90.0, 100.0),
+                touch(1, egui::TouchPhase::Move, 110.0, 100.0),
+            ],
+            vec![
+                touch(0, egui::TouchPhase::End, 90.0, 100.0),
+                touch(1, egui::TouchPhase::End, 110.0, 100.0),
+            ],
+        ]);
+        let center = glam::vec2(100.0, 100.0);
+        assert_eq!(
+            gestures,
+            [
+                Gesture::Pinch { center, scale: 2.0 },
+                Gesture::Pinch { center, scale: 0.5 },
+            ]
+        );
+    }
+
+    #[test]
+    fn rotates_clockwise_on_screen_as_positive() {
+        // The second finger swings from right of the first to below it, clockwise with y down.
+        let gestures = recognize(&[
+            vec![
+                touch(0, egui::TouchPhase::Start, 0.0, 0.0),
+                touch(1, egui::TouchPhase::Start, 10.0, 0.0),
+            ],
+            vec![touch(1, egui::TouchPhase::Move, 0.0, 10.0)],
+        ]);
+        let [Gesture::Rotate { center, angle }] = gestures.as_slice() else {
+            panic!("unexpected gestures {gestures:?}");
+        };
+        assert_eq!(*center, glam::vec2(0.0, 5.0));
+        assert!(
+            (angle - std::f32::consts::FRAC_PI_2).abs() < 1e-5,
+            "{angle}"
+        );
+    }
+
+    #[test]
+    fn cancelled_touches_make_no_gesture() {
+        assert_eq!(
+            recognize(&[
+                vec![touch(0, egui::TouchPhase::Start, 5.0, 5.0)],
+                vec![touch(0, egui::TouchPhase::Cancel, 5.0, 5.0)],
+                vec![touch(0, egui::TouchPhase::End, 5.0, 5.0)],
+            ]),
+            []
+        );
+        // A cancelled second finger ends the pinch without the other finger tapping.
+        assert_eq!(
+            recognize(&[
+                vec![
+                    touch(0, egui::TouchPhase::Start, 0.0, 0.0),
+                    touch(1, egui::TouchPhase::Start, 10.0, 0.0),
+                ],
+                vec![touch(1, egui::TouchPhase::Cancel, 10.0, 0.0)],
+                vec![touch(1, egui::TouchPhase::Move, 20.0, 0.0)],
+                vec![touch(0, egui::TouchPhase::End, 0.0, 0.0)],
+            ]),
+            []
+        );
+    }
+}
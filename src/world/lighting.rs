@@ -0,0 +1,104 @@
+use crate::world::physics;
+use crate::world::physics::collision;
+
+/// Light every pixel gets before the [`Light`]s are added, a resource. Worlds without one are
+/// lit fully, as if there was no lighting at all.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AmbientLight {
+    pub color: glam::Vec3,
+}
+
+impl AmbientLight {
+    pub fn new(color: glam::Vec3, intensity: f32) -> Self {
+        Self {
+            color: color * intensity,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightKind {
+    /// Shines in every direction.
+    Point,
+    /// Shines along `direction`, in radians counter clockwise from +x, fading out at
+    /// `half_angle` to either side.
+    Spot { direction: f32, half_angle: f32 },
+}
+
+/// Lights the sprites around the entity's position and casts shadows from [`Occluder`]s.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: glam::Vec3,
+    pub intensity: f32,
+    /// Distance at which the light has faded out, in world units.
+    pub radius: f32,
+    /// Height above the sprites, lower lights graze their normals more.
+    pub height: f32,
+    /// Size of the light itself, larger lights cast softer shadow edges and 0 casts hard ones.
+    pub source_radius: f32,
+}
+
+impl Light {
+    pub fn point(color: glam::Vec3, intensity: f32, radius: f32) -> Self {
+        Self {
+            kind: LightKind::Point,
+            color,
+            intensity,
+            radius,
+            height: radius * 0.25,
+            source_radius: radius * 0.05,
+        }
+    }
+    pub fn spot(
+        color: glam::Vec3,
+        intensity: f32,
+        radius: f32,
+        direction: f32,
+        half_angle: f32,
+    ) -> Self {
+        Self {
+            kind: LightKind::Spot {
+                direction,
+                half_angle,
+            },
+            ..Self::point(color, intensity, radius)
+        }
+    }
+    pub fn with_height(mut self, height: f32) -> Self {
+        self.height = height;
+        self
+    }
+    pub fn with_source_radius(mut self, source_radius: f32) -> Self {
+        self.source_radius = source_radius;
+        self
+    }
+}
+
+/// Blocks light around the entity's position. Polygons cast the shadow of their bounding box.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Occluder {
+    pub shape: collision::Shape,
+}
+
+impl Occluder {
+    pub fn aabb(half_extents: glam::Vec2) -> Self {
+        Self {
+            shape: collision::Shape::Aabb { half_extents },
+        }
+    }
+    pub fn circle(radius: f32) -> Self {
+        Self {
+            shape: collision::Shape::Circle { radius },
+        }
+    }
+}
+
+/// Casts the shadow of the collider, for walls and props that block both.
+impl From<&physics::Collider> for Occluder {
+    fn from(collider: &physics::Collider) -> Self {
+        Self {
+            shape: collider.shape.clone(),
+        }
+    }
+}
@@ -0,0 +1,553 @@
+use crate::world;
+use crate::world::clock;
+use crate::world::components;
+use crate::world::physics;
+use crate::world::tilemap;
+use std::cmp;
+use std::collections;
+use std::sync;
+
+pub mod flow_field;
+pub mod hierarchical;
+pub mod service;
+
+const ORTHOGONAL: [glam::IVec2; 4] = [
+    glam::IVec2::X,
+    glam::IVec2::NEG_X,
+    glam::IVec2::Y,
+    glam::IVec2::NEG_Y,
+];
+const DIAGONAL: [glam::IVec2; 4] = [
+    glam::ivec2(1, 1),
+    glam::ivec2(1, -1),
+    glam::ivec2(-1, 1),
+    glam::ivec2(-1, -1),
+];
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DiagonalRule {
+    Never,
+    /// Diagonal steps are allowed even between two blocked cells.
+    Always,
+    /// Diagonal steps need at least one of the two orthogonal cells they pass to be open.
+    AllowOneBlocked,
+    /// Diagonal steps need both orthogonal cells they pass to be open.
+    #[default]
+    NoCornerCutting,
+}
+
+/// Movement cost of every cell of a rectangular region, `None` for blocked cells.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NavigationGrid {
+    origin: glam::IVec2,
+    size: glam::IVec2,
+    costs: Vec<Option<f32>>,
+    min_cost: f32,
+}
+
+impl NavigationGrid {
+    pub fn new(origin: glam::IVec2, size: glam::IVec2) -> Self {
+        Self {
+            origin,
+            size,
+            costs: vec![Some(1.0); (size.x.max(0) * size.y.max(0)) as usize],
+            min_cost: 1.0,
+        }
+    }
+    /// Blocks every solid tile of the map, everything else costs one.
+    pub fn from_tilemap(tilemap: &tilemap::Tilemap) -> Self {
+        let Some((min, max)) = tilemap.cell_bounds() else {
+            return Self::new(glam::IVec2::ZERO, glam::IVec2::ZERO);
+        };
+        let mut grid = Self::new(min, max - min + glam::IVec2::ONE);
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let cell = glam::ivec2(x, y);
+                if tilemap.is_solid(cell) {
+                    grid.set_cost(cell, None);
+                }
+            }
+        }
+        grid
+    }
+    pub fn origin(&self) -> glam::IVec2 {
+        self.origin
+    }
+    pub fn size(&self) -> glam::IVec2 {
+        self.size
+    }
+    pub fn contains(&self, cell: glam::IVec2) -> bool {
+        let local = cell - self.origin;
+        local.cmpge(glam::IVec2::ZERO).all() && local.cmplt(self.size).all()
+    }
+    pub fn cost(&self, cell: glam::IVec2) -> Option<f32> {
+        self.index(cell).and_then(|index| self.costs[index])
+    }
+    pub fn is_open(&self, cell: glam::IVec2) -> bool {
+        self.cost(cell).is_some()
+    }
+    /// Sets the cost of entering a cell, costs below one are clamped to keep the heuristic admissible.
+    pub fn set_cost(&mut self, cell: glam::IVec2, cost: Option<f32>) {
+        if let Some(index) = self.index(cell) {
+            self.costs[index] = cost.map(|cost| cost.max(f32::EPSILON));
+            self.min_cost = self
+                .costs
+                .iter()
+                .flatten()
+                .copied()
+                .fold(f32::MAX, f32::min)
+                .min(1.0);
+        }
+    }
+    pub fn index(&self, cell: glam::IVec2) -> Option<usize> {
+        self.contains(cell).then(|| {
+            let local = cell - self.origin;
+            (local.y * self.size.x + local.x) as usize
+        })
+    }
+    pub fn cell(&self, index: usize) -> glam::IVec2 {
+        self.origin + glam::ivec2(index as i32 % self.size.x, index as i32 / self.size.x)
+    }
+    /// Open neighbours of a cell and the cost of stepping onto them.
+    pub fn neighbours(
+        &self,
+        cell: glam::IVec2,
+        rule: DiagonalRule,
+    ) -> impl Iterator<Item = (glam::IVec2, f32)> + '_ {
+        let orthogonal = ORTHOGONAL.into_iter().filter_map(move |offset| {
+            let next = cell + offset;
+            Some((next, self.cost(next)?))
+        });
+        let diagonal = DIAGONAL.into_iter().filter_map(move |offset| {
+            let next = cell + offset;
+            let cost = self.cost(next)?;
+            let first = self.is_open(cell + glam::ivec2(offset.x, 0));
+            let second = self.is_open(cell + glam::ivec2(0, offset.y));
+            let allowed = match rule {
+                DiagonalRule::Never => false,
+                DiagonalRule::Always => true,
+                DiagonalRule::AllowOneBlocked => first || second,
+                DiagonalRule::NoCornerCutting => first && second,
+            };
+            allowed.then_some((next, cost * std::f32::consts::SQRT_2))
+        });
+        orthogonal.chain(diagonal)
+    }
+    fn heuristic(&self, from: glam::IVec2, to: glam::IVec2, rule: DiagonalRule) -> f32 {
+        let delta = (to - from).abs().as_vec2();
+        let distance = match rule {
+            DiagonalRule::Never => delta.x + delta.y,
+            _ => delta.max_element() + (std::f32::consts::SQRT_2 - 1.0) * delta.min_element(),
+        };
+        distance * self.min_cost
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path {
+    pub cells: Vec<glam::IVec2>,
+    pub cost: f32,
+}
+
+/// Best first search over the grid, only visiting cells for which `within` returns true.
+pub fn find_path_within(
+    grid: &NavigationGrid,
+    start: glam::IVec2,
+    goal: glam::IVec2,
+    rule: DiagonalRule,
+    within: impl Fn(glam::IVec2) -> bool,
+) -> Option<Path> {
+    if !grid.is_open(start) || !grid.is_open(goal) || !within(start) || !within(goal) {
+        return None;
+    }
+    let mut open = collections::BinaryHeap::new();
+    let mut costs = collections::HashMap::from([(start, 0.0)]);
+    let mut came_from = collections::HashMap::new();
+    open.push(Open {
+        estimate: grid.heuristic(start, goal, rule),
+        node: start,
+    });
+
+    while let Some(Open { node: cell, .. }) = open.pop() {
+        if cell == goal {
+            let mut cells = vec![goal];
+            while let Some(previous) = came_from.get(cells.last().expect("starts with the goal")) {
+                cells.push(*previous);
+            }
+            cells.reverse();
+            return Some(Path {
+                cells,
+                cost: costs[&goal],
+            });
+        }
+        let cost = costs[&cell];
+        for (next, step) in grid.neighbours(cell, rule) {
+            if !within(next) {
+                continue;
+            }
+            let next_cost = cost + step;
+            if costs.get(&next).is_none_or(|known| next_cost < *known) {
+                costs.insert(next, next_cost);
+                came_from.insert(next, cell);
+                open.push(Open {
+                    estimate: next_cost + grid.heuristic(next, goal, rule),
+                    node: next,
+                });
+            }
+        }
+    }
+    None
+}
+
+pub fn find_path(
+    grid: &NavigationGrid,
+    start: glam::IVec2,
+    goal: glam::IVec2,
+    rule: DiagonalRule,
+) -> Option<Path> {
+    find_path_within(grid, start, goal, rule, |_| true)
+}
+
+/// Walks an entity to `goal` along paths from the [`service::PathService`] resource.
+///
+/// Entities with a [`physics::RigidBody`] are steered through its velocity, others are moved
+/// directly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Navigator {
+    pub goal: Option<glam::Vec2>,
+    pub speed: f32,
+    /// Distance at which a waypoint counts as reached.
+    pub tolerance: f32,
+    /// Goal cell and ticket of the outstanding path request.
+    requested: Option<(glam::IVec2, service::Ticket)>,
+    /// Goal cell the current waypoints lead to.
+    resolved: Option<glam::IVec2>,
+    /// [`service::PathService::grid_source`] the outstanding request or the current waypoints
+    /// were planned on.
+    planned_on: Option<(u64, u64)>,
+    waypoints: collections::VecDeque<glam::Vec2>,
+    unreachable: bool,
+}
+
+impl Navigator {
+    pub fn new(speed: f32) -> Self {
+        Self {
+            goal: None,
+            speed,
+            tolerance: 2.0,
+            requested: None,
+            resolved: None,
+            planned_on: None,
+            waypoints: collections::VecDeque::new(),
+            unreachable: false,
+        }
+    }
+    pub fn waypoints(&self) -> impl Iterator<Item = glam::Vec2> + '_ {
+        self.waypoints.iter().copied()
+    }
+    pub fn is_waiting(&self) -> bool {
+        self.requested.is_some()
+    }
+    /// Whether the last path request for the current goal found no path.
+    pub fn is_unreachable(&self) -> bool {
+        self.unreachable
+    }
+}
+
+/// Keeps the [`service::PathService`] grid in step with the [`tilemap::Tilemap`], hands finished
+/// paths to their [`Navigator`]s and moves them along.
+///
+/// Without a tilemap or path service there is nothing to path around, navigators head straight
+/// for their goal.
+pub fn update_system(world: &world::World, _commands: &mut world::commands::Commands) {
+    let tilemap = world.resource::<tilemap::Tilemap>();
+    let mut service = world.resource_mut::<service::PathService>();
+    if let (Some(tilemap), Some(service)) = (&tilemap, &mut service) {
+        // A loaded tilemap may share its revision with the one it replaced, but never its id.
+        let source = (tilemap.id(), tilemap.revision());
+        if service.grid_source() != Some(source) {
+            let grid = NavigationGrid::from_tilemap(tilemap);
+            service.set_grid(sync::Arc::new(grid), source);
+        }
+        service.poll();
+    }
+
+    let timestep = world
+        .resource::<physics::PhysicsSettings>()
+        .map_or(clock::TIMESTEP, |settings| settings.timestep);
+    world.query::<(&mut Navigator, &mut components::Position)>(|entity, (navigator, position)| {
+        match (tilemap.as_deref(), service.as_deref_mut()) {
+            (Some(tilemap), Some(service)) => follow_paths(navigator, position, tilemap, service),
+            (_, service) => {
+                if let (Some((_, ticket)), Some(service)) = (navigator.requested.take(), service) {
+                    service.cancel(ticket);
+                }
+                navigator.resolved = None;
+                navigator.unreachable = false;
+                navigator.waypoints = navigator.goal.into_iter().collect();
+            }
+        }
+
+        while navigator
+            .waypoints
+            .front()
+            .is_some_and(|waypoint| waypoint.distance(position.0) <= navigator.tolerance)
+        {
+            navigator.waypoints.pop_front();
+        }
+        let velocity = navigator
+            .waypoints
+            .front()
+            .map_or(glam::Vec2::ZERO, |waypoint| {
+                let offset = *waypoint - position.0;
+                offset.clamp_length_max(navigator.speed.min(offset.length() / timestep))
+            });
+        match world.get_mut::<physics::RigidBody>(entity) {
+            Some(mut body) => body.velocity = velocity,
+            None => position.0 += velocity * timestep,
+        }
+    });
+}
+
+/// Takes the path the navigator asked for once it arrived and asks again when the goal moved
+/// to another cell or the grid changed, keeping the old waypoints until the new path arrives.
+fn follow_paths(
+    navigator: &mut Navigator,
+    position: &components::Position,
+    tilemap: &tilemap::Tilemap,
+    service: &mut service::PathService,
+) {
+    let goal = navigator.goal.map(|goal| tilemap.world_to_cell(goal));
+    let grid = service.grid_source();
+    if let Some((requested, ticket)) = navigator.requested {
+        if Some(requested) != goal || navigator.planned_on != grid {
+            service.cancel(ticket);
+            navigator.requested = None;
+        } else if let Some(service::Outcome::Path(path)) = service.take(ticket) {
+            navigator.requested = None;
+            navigator.resolved = goal;
+            navigator.unreachable = path.is_none();
+            navigator.waypoints = path
+                .into_iter()
+                .flat_map(|path| path.cells.into_iter().skip(1))
+                .map(|cell| (cell.as_vec2() + 0.5) * tilemap.tile_size)
+                .chain(navigator.goal.filter(|_| !navigator.unreachable))
+                .collect();
+        }
+    }
+    match goal {
+        None => {
+            navigator.waypoints.clear();
+            navigator.resolved = None;
+            navigator.unreachable = false;
+        }
+        Some(goal)
+            if navigator.requested.is_none()
+                && (navigator.resolved != Some(goal) || navigator.planned_on != grid) =>
+        {
+            let start = tilemap.world_to_cell(position.0);
+            let ticket = service.request_path(start, goal);
+            navigator.requested = Some((goal, ticket));
+            navigator.planned_on = grid;
+        }
+        Some(_) => (),
+    }
+}
+
+/// Entry of a search frontier, either a grid cell or a node of a coarser graph.
+struct Open<T> {
+    estimate: f32,
+    node: T,
+}
+
+impl<T> PartialEq for Open<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == cmp::Ordering::Equal
+    }
+}
+
+impl<T> Eq for Open<T> {}
+
+impl<T> PartialOrd for Open<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Open<T> {
+    /// Reversed so the binary heap pops the lowest estimate first.
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time;
+
+    fn open_grid(size: i32) -> NavigationGrid {
+        NavigationGrid::new(glam::IVec2::ZERO, glam::IVec2::splat(size))
+    }
+
+    #[test]
+    fn paths_go_around_walls() {
+        let mut grid = open_grid(5);
+        for y in 0..4 {
+            grid.set_cost(glam::ivec2(2, y), None);
+        }
+        let path = find_path(
+            &grid,
+            glam::ivec2(0, 0),
+            glam::ivec2(4, 0),
+            DiagonalRule::Never,
+        )
+        .unwrap();
+        assert_eq!(path.cost, 12.0);
+        assert_eq!(path.cells.len(), 13);
+        assert!(path.cells.contains(&glam::ivec2(2, 4)));
+        assert!(
+            path.cells.windows(2).all(|step| {
+                (step[1] - step[0]).abs().element_sum() == 1 && grid.is_open(step[1])
+            })
+        );
+        assert_eq!(
+            find_path(
+                &grid,
+                glam::ivec2(0, 0),
+                glam::ivec2(2, 0),
+                DiagonalRule::Never
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn diagonal_rules_decide_corner_cutting() {
+        let (start, goal) = (glam::ivec2(0, 0), glam::ivec2(1, 1));
+        let cost =
+            |grid: &NavigationGrid, rule| find_path(grid, start, goal, rule).map(|path| path.cost);
+        let mut grid = open_grid(3);
+        grid.set_cost(glam::ivec2(1, 0), None);
+        assert_eq!(cost(&grid, DiagonalRule::Never), Some(2.0));
+        assert_eq!(
+            cost(&grid, DiagonalRule::Always),
+            Some(std::f32::consts::SQRT_2)
+        );
+        assert_eq!(
+            cost(&grid, DiagonalRule::AllowOneBlocked),
+            Some(std::f32::consts::SQRT_2)
+        );
+        assert_eq!(cost(&grid, DiagonalRule::NoCornerCutting), Some(2.0));
+        grid.set_cost(glam::ivec2(0, 1), None);
+        assert_eq!(
+            cost(&grid, DiagonalRule::Always),
+            Some(std::f32::consts::SQRT_2)
+        );
+        assert_eq!(cost(&grid, DiagonalRule::AllowOneBlocked), None);
+        assert_eq!(cost(&grid, DiagonalRule::NoCornerCutting), None);
+    }
+
+    #[test]
+    fn expensive_cells_are_walked_around() {
+        let mut grid = open_grid(3);
+        grid.set_cost(glam::ivec2(1, 1), Some(10.0));
+        let path = find_path(
+            &grid,
+            glam::ivec2(0, 1),
+            glam::ivec2(2, 1),
+            DiagonalRule::Never,
+        )
+        .unwrap();
+        assert_eq!(path.cost, 4.0);
+        assert!(!path.cells.contains(&glam::ivec2(1, 1)));
+        grid.set_cost(glam::ivec2(1, 1), Some(2.0));
+        let path = find_path(
+            &grid,
+            glam::ivec2(0, 1),
+            glam::ivec2(2, 1),
+            DiagonalRule::Never,
+        )
+        .unwrap();
+        assert_eq!(path.cost, 3.0);
+        assert_eq!(path.cells[1], glam::ivec2(1, 1));
+    }
+
+    /// A 9x3 floor with an optional wall across column 4, the navigator stands still at the
+    /// left end heading for the right end.
+    fn corridor(closed: bool) -> (world::World, world::Entity) {
+        let mut tileset = tilemap::Tileset::default();
+        let floor = tileset.add(tilemap::TileDefinition {
+            sprite: 0,
+            frame: 0,
+            solid: false,
+            autotile: None,
+        });
+        let mut tiles = tilemap::Tilemap::new(1.0, tileset);
+        let ground = tiles.add_layer("ground");
+        tiles.fill(ground, glam::ivec2(0, 0), glam::ivec2(8, 2), Some(floor));
+        tiles.add_layer("walls");
+        let mut world = world::World::new();
+        world.insert_resource(tiles);
+        set_wall(&mut world, closed);
+        world.insert_resource(service::PathService::new(DiagonalRule::Never));
+        world.add_system(update_system);
+        let walker = world.spawn();
+        world.insert(walker, components::Position(glam::vec2(0.5, 1.5)));
+        let mut navigator = Navigator::new(0.0);
+        navigator.goal = Some(glam::vec2(8.5, 1.5));
+        world.insert(walker, navigator);
+        (world, walker)
+    }
+
+    fn set_wall(world: &mut world::World, closed: bool) {
+        let mut tiles = world.resource_mut::<tilemap::Tilemap>().unwrap();
+        let wall = closed.then(|| {
+            tiles.tileset.add(tilemap::TileDefinition {
+                sprite: 0,
+                frame: 0,
+                solid: true,
+                autotile: None,
+            })
+        });
+        // The grid covers whole chunks, so the wall runs the height of the chunk.
+        let top = tilemap::CHUNK_SIZE - 1;
+        tiles.fill(1, glam::ivec2(4, 0), glam::ivec2(4, top), wall);
+    }
+
+    /// Ticks until the navigator has an answer for its current request.
+    fn settle(world: &mut world::World, walker: world::Entity) {
+        for _ in 0..1000 {
+            world.update();
+            if !world.get::<Navigator>(walker).unwrap().is_waiting() {
+                return;
+            }
+            thread::sleep(time::Duration::from_millis(1));
+        }
+        panic!("the path service did not answer");
+    }
+
+    #[test]
+    fn navigators_plan_again_when_the_grid_changes() {
+        let (mut world, walker) = corridor(false);
+        settle(&mut world, walker);
+        assert!(!world.get::<Navigator>(walker).unwrap().is_unreachable());
+
+        set_wall(&mut world, true);
+        world.update();
+        assert!(world.get::<Navigator>(walker).unwrap().is_waiting());
+        settle(&mut world, walker);
+        let navigator = world.get::<Navigator>(walker).unwrap();
+        assert!(navigator.is_unreachable());
+        assert_eq!(navigator.waypoints().count(), 0);
+        drop(navigator);
+
+        set_wall(&mut world, false);
+        world.update();
+        settle(&mut world, walker);
+        let navigator = world.get::<Navigator>(walker).unwrap();
+        assert!(!navigator.is_unreachable());
+        assert_eq!(navigator.waypoints().last(), Some(glam::vec2(8.5, 1.5)));
+    }
+}
@@ -0,0 +1,136 @@
+use crate::world::pathfinding;
+use std::collections;
+
+/// Distance from every cell of a grid to the nearest goal and the step leading towards it,
+/// so any number of agents heading for the same goals can share one search.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlowField {
+    origin: glam::IVec2,
+    size: glam::IVec2,
+    costs: Vec<f32>,
+    directions: Vec<glam::IVec2>,
+}
+
+impl FlowField {
+    /// Runs Dijkstra outwards from the goals, cells that cannot reach any goal keep an infinite cost.
+    pub fn new(
+        grid: &pathfinding::NavigationGrid,
+        goals: &[glam::IVec2],
+        rule: pathfinding::DiagonalRule,
+    ) -> Self {
+        let cell_count = (grid.size().x * grid.size().y).max(0) as usize;
+        let mut field = Self {
+            origin: grid.origin(),
+            size: grid.size(),
+            costs: vec![f32::INFINITY; cell_count],
+            directions: vec![glam::IVec2::ZERO; cell_count],
+        };
+
+        let mut open = collections::BinaryHeap::new();
+        for goal in goals {
+            if let Some(index) = grid.index(*goal).filter(|_| grid.is_open(*goal)) {
+                field.costs[index] = 0.0;
+                open.push(pathfinding::Open {
+                    estimate: 0.0,
+                    node: *goal,
+                });
+            }
+        }
+        while let Some(pathfinding::Open {
+            estimate: cost,
+            node: cell,
+        }) = open.pop()
+        {
+            let index = grid.index(cell).expect("only cells of the grid are queued");
+            if cost > field.costs[index] {
+                continue;
+            }
+            // Agents walk from `next` onto `cell`, so the step is priced by the cost of `cell`.
+            let enter = grid.cost(cell).expect("only open cells are queued");
+            for (next, step) in grid.neighbours(cell, rule) {
+                let next_index = grid.index(next).expect("neighbours lie inside the grid");
+                let scale = step / grid.cost(next).expect("neighbours are open");
+                let next_cost = cost + enter * scale;
+                if next_cost < field.costs[next_index] {
+                    field.costs[next_index] = next_cost;
+                    field.directions[next_index] = cell - next;
+                    open.push(pathfinding::Open {
+                        estimate: next_cost,
+                        node: next,
+                    });
+                }
+            }
+        }
+        field
+    }
+    pub fn origin(&self) -> glam::IVec2 {
+        self.origin
+    }
+    pub fn size(&self) -> glam::IVec2 {
+        self.size
+    }
+    fn index(&self, cell: glam::IVec2) -> Option<usize> {
+        let local = cell - self.origin;
+        (local.cmpge(glam::IVec2::ZERO).all() && local.cmplt(self.size).all())
+            .then(|| (local.y * self.size.x + local.x) as usize)
+    }
+    /// Cost of the cheapest path from the cell to a goal, `None` when no goal can be reached.
+    pub fn cost(&self, cell: glam::IVec2) -> Option<f32> {
+        self.index(cell)
+            .map(|index| self.costs[index])
+            .filter(|cost| cost.is_finite())
+    }
+    /// Offset to the next cell towards the nearest goal, zero on goals and unreachable cells.
+    pub fn direction(&self, cell: glam::IVec2) -> glam::IVec2 {
+        self.index(cell)
+            .map_or(glam::IVec2::ZERO, |index| self.directions[index])
+    }
+    pub fn is_goal(&self, cell: glam::IVec2) -> bool {
+        self.cost(cell) == Some(0.0)
+    }
+    /// Follows the field from a cell until it reaches a goal.
+    pub fn path(&self, start: glam::IVec2) -> Option<pathfinding::Path> {
+        let cost = self.cost(start)?;
+        let mut cells = vec![start];
+        let mut cell = start;
+        while !self.is_goal(cell) {
+            cell += self.direction(cell);
+            cells.push(cell);
+        }
+        Some(pathfinding::Path { cells, cost })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn costs_match_searches_to_the_nearest_goal() {
+        let rule = pathfinding::DiagonalRule::NoCornerCutting;
+        let mut grid = pathfinding::NavigationGrid::new(glam::IVec2::ZERO, glam::ivec2(12, 8));
+        for y in 1..8 {
+            grid.set_cost(glam::ivec2(5, y), None);
+        }
+        grid.set_cost(glam::ivec2(8, 3), Some(4.0));
+        let goals = [glam::ivec2(11, 7), glam::ivec2(0, 7)];
+        let field = FlowField::new(&grid, &goals, rule);
+        for index in 0..(12 * 8) {
+            let cell = grid.cell(index);
+            let nearest = goals
+                .iter()
+                .filter_map(|goal| pathfinding::find_path(&grid, cell, *goal, rule))
+                .map(|path| path.cost)
+                .min_by(f32::total_cmp);
+            match (field.cost(cell), nearest) {
+                (Some(cost), Some(nearest)) => assert!((cost - nearest).abs() < 1e-3, "{cell}"),
+                (cost, nearest) => assert_eq!(cost, nearest, "{cell}"),
+            }
+            if let Some(path) = field.path(cell) {
+                assert!(goals.contains(path.cells.last().unwrap()));
+                assert!(path.cells.iter().all(|cell| grid.is_open(*cell)));
+            }
+        }
+        assert_eq!(field.direction(glam::ivec2(4, 7)), glam::ivec2(-1, 0));
+    }
+}
@@ -0,0 +1,321 @@
+use crate::world::pathfinding;
+use std::collections;
+
+pub const DEFAULT_CLUSTER_SIZE: i32 = 16;
+
+struct Edge {
+    to: usize,
+    cost: f32,
+    /// Cells walked after leaving the source node, ending on the target node.
+    cells: Vec<glam::IVec2>,
+}
+
+/// Grid split into square clusters joined by entrances along their borders, so long paths
+/// search a small graph of entrances and only run cell-level searches inside single clusters.
+///
+/// Paths cross cluster borders orthogonally and are therefore not always the shortest, use
+/// [`pathfinding::find_path`] when that matters more than speed.
+pub struct HierarchicalGrid {
+    grid: pathfinding::NavigationGrid,
+    rule: pathfinding::DiagonalRule,
+    cluster_size: i32,
+    nodes: Vec<glam::IVec2>,
+    edges: Vec<Vec<Edge>>,
+    clusters: collections::HashMap<glam::IVec2, Vec<usize>>,
+}
+
+impl HierarchicalGrid {
+    pub fn new(
+        grid: pathfinding::NavigationGrid,
+        rule: pathfinding::DiagonalRule,
+        cluster_size: i32,
+    ) -> Self {
+        let mut hierarchy = Self {
+            grid,
+            rule,
+            cluster_size: cluster_size.max(2),
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            clusters: collections::HashMap::new(),
+        };
+        hierarchy.build_entrances();
+        let clusters = hierarchy.clusters.keys().copied().collect::<Vec<_>>();
+        for cluster in clusters {
+            hierarchy.connect_cluster(cluster);
+        }
+        hierarchy
+    }
+    pub fn grid(&self) -> &pathfinding::NavigationGrid {
+        &self.grid
+    }
+    pub fn rule(&self) -> pathfinding::DiagonalRule {
+        self.rule
+    }
+    /// Number of entrance nodes in the abstract graph.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+    pub fn cluster_of(&self, cell: glam::IVec2) -> glam::IVec2 {
+        (cell - self.grid.origin()).div_euclid(glam::IVec2::splat(self.cluster_size))
+    }
+    fn in_cluster(&self, cluster: glam::IVec2) -> impl Fn(glam::IVec2) -> bool + '_ {
+        move |cell| self.cluster_of(cell) == cluster
+    }
+    fn node(&mut self, cell: glam::IVec2) -> usize {
+        let cluster = self.cluster_of(cell);
+        let nodes = self.clusters.entry(cluster).or_default();
+        if let Some(node) = nodes.iter().find(|node| self.nodes[**node] == cell) {
+            return *node;
+        }
+        nodes.push(self.nodes.len());
+        self.nodes.push(cell);
+        self.edges.push(Vec::new());
+        self.nodes.len() - 1
+    }
+    /// Places one entrance in the middle of every open stretch of each cluster border.
+    fn build_entrances(&mut self) {
+        let size = self.grid.size();
+        let origin = self.grid.origin();
+        let mut borders = Vec::new();
+        for x in (self.cluster_size..size.x).step_by(self.cluster_size as usize) {
+            let column = (0..size.y)
+                .map(|y| (origin + glam::ivec2(x - 1, y), origin + glam::ivec2(x, y)))
+                .collect::<Vec<_>>();
+            borders.push(column);
+        }
+        for y in (self.cluster_size..size.y).step_by(self.cluster_size as usize) {
+            let row = (0..size.x)
+                .map(|x| (origin + glam::ivec2(x, y - 1), origin + glam::ivec2(x, y)))
+                .collect::<Vec<_>>();
+            borders.push(row);
+        }
+
+        for border in borders {
+            // Stretches also end where the border moves on to the next pair of clusters.
+            let stretches = border.chunk_by(|(first, _), (second, _)| {
+                self.cluster_of(*first) == self.cluster_of(*second)
+            });
+            for stretch in stretches.collect::<Vec<_>>() {
+                let open = stretch.chunk_by(|(first, second), (third, fourth)| {
+                    self.is_crossing(*first, *second) == self.is_crossing(*third, *fourth)
+                });
+                for run in open.collect::<Vec<_>>() {
+                    let (inside, outside) = run[run.len() / 2];
+                    if !self.is_crossing(inside, outside) {
+                        continue;
+                    }
+                    let (first, second) = (self.node(inside), self.node(outside));
+                    self.edges[first].push(Edge {
+                        to: second,
+                        cost: self.grid.cost(outside).expect("crossings are open"),
+                        cells: vec![outside],
+                    });
+                    self.edges[second].push(Edge {
+                        to: first,
+                        cost: self.grid.cost(inside).expect("crossings are open"),
+                        cells: vec![inside],
+                    });
+                }
+            }
+        }
+    }
+    fn is_crossing(&self, inside: glam::IVec2, outside: glam::IVec2) -> bool {
+        self.grid.is_open(inside) && self.grid.is_open(outside)
+    }
+    /// Caches the path between every pair of entrances of a cluster.
+    fn connect_cluster(&mut self, cluster: glam::IVec2) {
+        let nodes = self.clusters[&cluster].clone();
+        for from in &nodes {
+            for to in &nodes {
+                if from == to {
+                    continue;
+                }
+                if let Some(edge) = self.local_edge(self.nodes[*from], self.nodes[*to], *to) {
+                    self.edges[*from].push(edge);
+                }
+            }
+        }
+    }
+    fn local_edge(&self, from: glam::IVec2, to: glam::IVec2, node: usize) -> Option<Edge> {
+        let path = pathfinding::find_path_within(
+            &self.grid,
+            from,
+            to,
+            self.rule,
+            self.in_cluster(self.cluster_of(from)),
+        )?;
+        Some(Edge {
+            to: node,
+            cost: path.cost,
+            cells: path.cells[1..].to_vec(),
+        })
+    }
+    pub fn find_path(&self, start: glam::IVec2, goal: glam::IVec2) -> Option<pathfinding::Path> {
+        if !self.grid.is_open(start) || !self.grid.is_open(goal) {
+            return None;
+        }
+        let (start_cluster, goal_cluster) = (self.cluster_of(start), self.cluster_of(goal));
+        if start_cluster == goal_cluster {
+            let local = pathfinding::find_path_within(
+                &self.grid,
+                start,
+                goal,
+                self.rule,
+                self.in_cluster(start_cluster),
+            );
+            if local.is_some() {
+                return local;
+            }
+        }
+
+        // Start and goal join the graph as two extra nodes for this search only.
+        let (start_node, goal_node) = (self.nodes.len(), self.nodes.len() + 1);
+        let entrances = |cluster| self.clusters.get(&cluster).into_iter().flatten();
+        let start_edges = entrances(start_cluster)
+            .filter_map(|node| self.local_edge(start, self.nodes[*node], *node))
+            .collect::<Vec<_>>();
+        let goal_edges = entrances(goal_cluster)
+            .filter_map(|node| Some((*node, self.local_edge(self.nodes[*node], goal, goal_node)?)))
+            .collect::<collections::HashMap<_, _>>();
+        let edges_of = |node: usize| {
+            let edges = match node {
+                _ if node == start_node => &start_edges[..],
+                _ => &self.edges[node][..],
+            };
+            edges.iter().chain(goal_edges.get(&node))
+        };
+        let cell_of = |node: usize| match node {
+            _ if node == start_node => start,
+            _ if node == goal_node => goal,
+            _ => self.nodes[node],
+        };
+
+        let mut open = collections::BinaryHeap::new();
+        let mut costs = collections::HashMap::from([(start_node, 0.0)]);
+        let mut came_from = collections::HashMap::<usize, (usize, &Edge)>::new();
+        open.push(pathfinding::Open {
+            estimate: self.grid.heuristic(start, goal, self.rule),
+            node: start_node,
+        });
+        while let Some(pathfinding::Open { node, .. }) = open.pop() {
+            if node == goal_node {
+                let mut edges = Vec::new();
+                let mut current = goal_node;
+                while let Some((previous, edge)) = came_from.get(&current) {
+                    edges.push(*edge);
+                    current = *previous;
+                }
+                let cells = std::iter::once(start)
+                    .chain(
+                        edges
+                            .iter()
+                            .rev()
+                            .flat_map(|edge| edge.cells.iter().copied()),
+                    )
+                    .collect();
+                return Some(pathfinding::Path {
+                    cells,
+                    cost: costs[&goal_node],
+                });
+            }
+            let cost = costs[&node];
+            for edge in edges_of(node) {
+                let next_cost = cost + edge.cost;
+                if costs.get(&edge.to).is_none_or(|known| next_cost < *known) {
+                    costs.insert(edge.to, next_cost);
+                    came_from.insert(edge.to, (node, edge));
+                    open.push(pathfinding::Open {
+                        estimate: next_cost
+                            + self.grid.heuristic(cell_of(edge.to), goal, self.rule),
+                        node: edge.to,
+                    });
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 32x32 grid of 8x8 clusters with walls that have gaps in them.
+    fn maze() -> pathfinding::NavigationGrid {
+        let mut grid = pathfinding::NavigationGrid::new(glam::IVec2::ZERO, glam::IVec2::splat(32));
+        for y in 0..28 {
+            grid.set_cost(glam::ivec2(10, y), None);
+        }
+        for y in 4..32 {
+            grid.set_cost(glam::ivec2(21, y), None);
+        }
+        for x in 12..20 {
+            grid.set_cost(glam::ivec2(x, 14), Some(5.0));
+        }
+        grid
+    }
+
+    /// What walking `path` costs, checking every step goes to an open neighbour.
+    fn walked_cost(grid: &pathfinding::NavigationGrid, path: &pathfinding::Path) -> f32 {
+        path.cells
+            .windows(2)
+            .map(|step| {
+                grid.neighbours(step[0], pathfinding::DiagonalRule::NoCornerCutting)
+                    .find(|(next, _)| *next == step[1])
+                    .map(|(_, cost)| cost)
+                    .expect("steps go between neighbours")
+            })
+            .sum()
+    }
+
+    #[test]
+    fn paths_cost_close_to_the_flat_search() {
+        let rule = pathfinding::DiagonalRule::NoCornerCutting;
+        let hierarchy = HierarchicalGrid::new(maze(), rule, 8);
+        for (start, goal) in [
+            (glam::ivec2(0, 0), glam::ivec2(31, 0)),
+            (glam::ivec2(2, 30), glam::ivec2(30, 30)),
+            (glam::ivec2(15, 2), glam::ivec2(15, 29)),
+        ] {
+            let flat = pathfinding::find_path(hierarchy.grid(), start, goal, rule).unwrap();
+            let path = hierarchy.find_path(start, goal).unwrap();
+            assert_eq!(path.cells.first(), Some(&start));
+            assert_eq!(path.cells.last(), Some(&goal));
+            assert!((walked_cost(hierarchy.grid(), &path) - path.cost).abs() < 1e-3);
+            assert!(path.cost >= flat.cost - 1e-3, "{start} to {goal}");
+            assert!(
+                path.cost <= flat.cost * 1.2,
+                "{start} to {goal}: {} against {}",
+                path.cost,
+                flat.cost
+            );
+        }
+    }
+
+    #[test]
+    fn paths_inside_one_cluster_are_the_flat_ones() {
+        let rule = pathfinding::DiagonalRule::NoCornerCutting;
+        let hierarchy = HierarchicalGrid::new(maze(), rule, 8);
+        let (start, goal) = (glam::ivec2(1, 1), glam::ivec2(6, 5));
+        assert_eq!(
+            hierarchy.find_path(start, goal),
+            pathfinding::find_path(hierarchy.grid(), start, goal, rule)
+        );
+    }
+
+    #[test]
+    fn walled_off_goals_have_no_path() {
+        let mut grid = maze();
+        for y in 0..32 {
+            grid.set_cost(glam::ivec2(10, y), None);
+        }
+        let hierarchy = HierarchicalGrid::new(grid, pathfinding::DiagonalRule::NoCornerCutting, 8);
+        assert_eq!(
+            hierarchy.find_path(glam::ivec2(0, 0), glam::ivec2(31, 31)),
+            None
+        );
+    }
+}
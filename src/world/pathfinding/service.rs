@@ -0,0 +1,221 @@
+use crate::world::pathfinding;
+use crate::world::pathfinding::flow_field;
+use crate::world::pathfinding::hierarchical;
+use std::collections;
+use std::sync;
+use std::thread;
+
+/// Grids with more cells than this are searched through a [`hierarchical::HierarchicalGrid`].
+const HIERARCHY_THRESHOLD: i32 = 64 * 64;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Ticket(u64);
+
+impl Ticket {
+    /// Tickets are numbered in request order, so a deterministic simulation asks for the same
+    /// numbers every run.
+    pub fn number(&self) -> u64 {
+        self.0
+    }
+    pub fn from_number(number: u64) -> Self {
+        Self(number)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Outcome {
+    Path(Option<pathfinding::Path>),
+    FlowField(sync::Arc<flow_field::FlowField>),
+}
+
+enum Request {
+    SetGrid(sync::Arc<pathfinding::NavigationGrid>),
+    Path {
+        ticket: Ticket,
+        start: glam::IVec2,
+        goal: glam::IVec2,
+    },
+    FlowField {
+        ticket: Ticket,
+        goals: Vec<glam::IVec2>,
+    },
+}
+
+/// Answers path and flow field requests on a worker thread so searches never stall a frame.
+///
+/// Requests are answered in order against the last grid handed to [`PathService::set_grid`],
+/// results are collected by [`PathService::poll`] and picked up with [`PathService::take`].
+///
+/// How fast the worker answers decides the tick an answer is taken on, so recordings keep
+/// [`PathService::taken`] and replays hand it back through [`PathService::replay_taken`].
+pub struct PathService {
+    requests: async_channel::Sender<Request>,
+    outcomes: async_channel::Receiver<(Ticket, Outcome)>,
+    finished: collections::HashMap<Ticket, Outcome>,
+    cancelled: collections::HashSet<Ticket>,
+    /// Answers taken since the last poll.
+    taken: Vec<Ticket>,
+    /// While replaying, the answers to take since the last poll, waiting for them if needed.
+    scheduled: Option<collections::HashSet<Ticket>>,
+    next_ticket: u64,
+    grid_source: Option<(u64, u64)>,
+    worker: Option<thread::JoinHandle<()>>,
+}
+
+impl PathService {
+    pub fn new(rule: pathfinding::DiagonalRule) -> Self {
+        let (requests, request_receiver) = async_channel::unbounded();
+        let (outcome_sender, outcomes) = async_channel::unbounded();
+        let worker = thread::spawn(move || work(request_receiver, outcome_sender, rule));
+        Self {
+            requests,
+            outcomes,
+            finished: collections::HashMap::new(),
+            cancelled: collections::HashSet::new(),
+            taken: Vec::new(),
+            scheduled: None,
+            next_ticket: 0,
+            grid_source: None,
+            worker: Some(worker),
+        }
+    }
+    /// Id and revision of the tilemap the current grid was built from, see
+    /// [`pathfinding::update_system`].
+    pub fn grid_source(&self) -> Option<(u64, u64)> {
+        self.grid_source
+    }
+    pub fn set_grid(&mut self, grid: sync::Arc<pathfinding::NavigationGrid>, source: (u64, u64)) {
+        self.grid_source = Some(source);
+        self.send(Request::SetGrid(grid));
+    }
+    pub fn request_path(&mut self, start: glam::IVec2, goal: glam::IVec2) -> Ticket {
+        let ticket = self.ticket();
+        self.send(Request::Path {
+            ticket,
+            start,
+            goal,
+        });
+        ticket
+    }
+    pub fn request_flow_field(&mut self, goals: Vec<glam::IVec2>) -> Ticket {
+        let ticket = self.ticket();
+        self.send(Request::FlowField { ticket, goals });
+        ticket
+    }
+    /// Moves every answer the worker has sent so far into the finished set and starts a new
+    /// list of [`PathService::taken`] answers.
+    pub fn poll(&mut self) {
+        self.taken.clear();
+        self.receive();
+    }
+    /// The answer to `ticket` if it arrived, never blocks outside of replays.
+    pub fn take(&mut self, ticket: Ticket) -> Option<Outcome> {
+        let outcome = match &self.scheduled {
+            Some(scheduled) if !scheduled.contains(&ticket) => None,
+            Some(_) => self.wait(ticket),
+            None => self.finished.remove(&ticket),
+        };
+        if outcome.is_some() {
+            self.taken.push(ticket);
+        }
+        outcome
+    }
+    /// Answers taken since the last poll, in the order they were taken.
+    pub fn taken(&self) -> &[Ticket] {
+        &self.taken
+    }
+    /// Makes [`PathService::take`] hand out exactly `tickets` until the next call, waiting for
+    /// the worker where it is behind the recording.
+    pub fn replay_taken(&mut self, tickets: impl IntoIterator<Item = Ticket>) {
+        self.scheduled = Some(tickets.into_iter().collect());
+    }
+    fn wait(&mut self, ticket: Ticket) -> Option<Outcome> {
+        self.receive();
+        while !self.finished.contains_key(&ticket) {
+            let (finished, outcome) = self.outcomes.recv_blocking().ok()?;
+            if !self.cancelled.remove(&finished) {
+                self.finished.insert(finished, outcome);
+            }
+        }
+        self.finished.remove(&ticket)
+    }
+    fn receive(&mut self) {
+        while let Ok((ticket, outcome)) = self.outcomes.try_recv() {
+            if !self.cancelled.remove(&ticket) {
+                self.finished.insert(ticket, outcome);
+            }
+        }
+    }
+    /// Forgets a request, its answer is dropped whenever it arrives.
+    pub fn cancel(&mut self, ticket: Ticket) {
+        if self.finished.remove(&ticket).is_none() {
+            self.cancelled.insert(ticket);
+        }
+    }
+    fn ticket(&mut self) -> Ticket {
+        self.next_ticket += 1;
+        Ticket(self.next_ticket)
+    }
+    fn send(&self, request: Request) {
+        if self.requests.send_blocking(request).is_err() {
+            log::error!("path service worker exited, request dropped");
+        }
+    }
+}
+
+impl Drop for PathService {
+    fn drop(&mut self) {
+        self.requests.close();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn work(
+    requests: async_channel::Receiver<Request>,
+    outcomes: async_channel::Sender<(Ticket, Outcome)>,
+    rule: pathfinding::DiagonalRule,
+) {
+    let mut grid = sync::Arc::new(pathfinding::NavigationGrid::new(
+        glam::IVec2::ZERO,
+        glam::IVec2::ZERO,
+    ));
+    let mut hierarchy = None;
+    while let Ok(request) = requests.recv_blocking() {
+        let answer = match request {
+            Request::SetGrid(next) => {
+                let size = next.size();
+                hierarchy = (size.x * size.y > HIERARCHY_THRESHOLD).then(|| {
+                    hierarchical::HierarchicalGrid::new(
+                        (*next).clone(),
+                        rule,
+                        hierarchical::DEFAULT_CLUSTER_SIZE,
+                    )
+                });
+                grid = next;
+                continue;
+            }
+            Request::Path {
+                ticket,
+                start,
+                goal,
+            } => {
+                let path = match &hierarchy {
+                    Some(hierarchy) => hierarchy.find_path(start, goal),
+                    None => pathfinding::find_path(&grid, start, goal, rule),
+                };
+                (ticket, Outcome::Path(path))
+            }
+            Request::FlowField { ticket, goals } => (
+                ticket,
+                Outcome::FlowField(sync::Arc::new(flow_field::FlowField::new(
+                    &grid, &goals, rule,
+                ))),
+            ),
+        };
+        if outcomes.send_blocking(answer).is_err() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,520 @@
+use crate::world;
+use crate::world::clock;
+use crate::world::components;
+use crate::world::spatial;
+use crate::world::tilemap;
+use std::collections;
+
+pub mod collision;
+
+const POSITION_CORRECTION: f32 = 0.8;
+const PENETRATION_SLOP: f32 = 0.01;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Collider {
+    pub shape: collision::Shape,
+    /// Triggers report overlaps through [`CollisionEvents`] but are never pushed apart.
+    pub is_trigger: bool,
+    pub restitution: f32,
+    pub friction: f32,
+}
+
+impl Collider {
+    pub fn new(shape: collision::Shape) -> Self {
+        Self {
+            shape,
+            is_trigger: false,
+            restitution: 0.0,
+            friction: 0.2,
+        }
+    }
+    pub fn aabb(half_extents: glam::Vec2) -> Self {
+        Self::new(collision::Shape::Aabb { half_extents })
+    }
+    pub fn circle(radius: f32) -> Self {
+        Self::new(collision::Shape::Circle { radius })
+    }
+    pub fn polygon(vertices: Vec<glam::Vec2>) -> Self {
+        Self::new(collision::Shape::polygon(vertices))
+    }
+    pub fn trigger(mut self) -> Self {
+        self.is_trigger = true;
+        self
+    }
+    pub fn bounds(&self) -> components::Bounds {
+        components::Bounds {
+            half_extents: self.shape.aabb(glam::Vec2::ZERO).half_extents(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BodyKind {
+    /// Never moves.
+    Static,
+    /// Moved only by its velocity, pushes dynamic bodies without being pushed back.
+    Kinematic,
+    /// Moved by its velocity, gravity and collisions.
+    Dynamic,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RigidBody {
+    pub kind: BodyKind,
+    pub velocity: glam::Vec2,
+    inverse_mass: f32,
+}
+
+impl RigidBody {
+    pub fn fixed() -> Self {
+        Self {
+            kind: BodyKind::Static,
+            velocity: glam::Vec2::ZERO,
+            inverse_mass: 0.0,
+        }
+    }
+    pub fn kinematic() -> Self {
+        Self {
+            kind: BodyKind::Kinematic,
+            ..Self::fixed()
+        }
+    }
+    pub fn dynamic(mass: f32) -> Self {
+        Self {
+            kind: BodyKind::Dynamic,
+            velocity: glam::Vec2::ZERO,
+            inverse_mass: if mass > 0.0 { mass.recip() } else { 0.0 },
+        }
+    }
+    /// Rebuilds a body exactly, [`RigidBody::dynamic`] would round the mass through its inverse.
+    pub fn from_parts(kind: BodyKind, velocity: glam::Vec2, inverse_mass: f32) -> Self {
+        Self {
+            kind,
+            velocity,
+            inverse_mass,
+        }
+    }
+    pub fn inverse_mass(&self) -> f32 {
+        self.inverse_mass
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PhysicsSettings {
+    pub gravity: glam::Vec2,
+    /// Seconds integrated per tick, anything but [`clock::TIMESTEP`] speeds the world up or
+    /// slows it down.
+    pub timestep: f32,
+}
+
+impl Default for PhysicsSettings {
+    fn default() -> Self {
+        Self {
+            gravity: glam::Vec2::ZERO,
+            timestep: clock::TIMESTEP,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CollisionEvent {
+    Began {
+        first: world::Entity,
+        second: world::Entity,
+        trigger: bool,
+        manifold: collision::Manifold,
+    },
+    Ended {
+        first: world::Entity,
+        second: world::Entity,
+        trigger: bool,
+    },
+}
+
+/// Overlaps that began or ended during the last physics step, read by the systems after it.
+#[derive(Default)]
+pub struct CollisionEvents {
+    events: Vec<CollisionEvent>,
+    touching: collections::HashMap<(world::Entity, world::Entity), bool>,
+}
+
+impl CollisionEvents {
+    pub fn iter(&self) -> impl Iterator<Item = &CollisionEvent> {
+        self.events.iter()
+    }
+    pub fn is_touching(&self, first: world::Entity, second: world::Entity) -> bool {
+        self.touching
+            .contains_key(&(first.min(second), first.max(second)))
+    }
+}
+
+struct BodyState {
+    position: glam::Vec2,
+    velocity: glam::Vec2,
+    inverse_mass: f32,
+    collider: Collider,
+    moves: bool,
+}
+
+/// Integrates bodies, finds overlaps through the [`spatial::SpatialIndex`] and pushes dynamic
+/// bodies out of whatever they hit.
+pub fn step_system(world: &world::World, commands: &mut world::commands::Commands) {
+    let settings = world
+        .resource::<PhysicsSettings>()
+        .map(|settings| *settings)
+        .unwrap_or_default();
+
+    // Only rewritten when they differ, so the spatial index does not move every collider.
+    let mut resized = Vec::new();
+    world.query::<(&Collider, &components::Bounds)>(|entity, (collider, bounds)| {
+        if collider.bounds() != *bounds {
+            resized.push((entity, collider.bounds()));
+        }
+    });
+    for (entity, bounds) in resized {
+        if let Some(mut current) = world.get_mut::<components::Bounds>(entity) {
+            *current = bounds;
+        }
+    }
+    world.query::<(&Collider, &components::Position)>(|entity, (collider, _)| {
+        if world.get::<components::Bounds>(entity).is_none() {
+            commands.insert(entity, collider.bounds());
+        }
+    });
+
+    let mut bodies = collections::BTreeMap::new();
+    world.query::<(&Collider, &components::Position, Option<&RigidBody>)>(
+        |entity, (collider, position, body)| {
+            let body = body.copied().unwrap_or_else(RigidBody::fixed);
+            let mut velocity = body.velocity;
+            if body.kind == BodyKind::Dynamic {
+                velocity += settings.gravity * settings.timestep;
+            }
+            bodies.insert(
+                entity,
+                BodyState {
+                    position: position.0 + velocity * settings.timestep,
+                    velocity,
+                    inverse_mass: body.inverse_mass,
+                    collider: collider.clone(),
+                    moves: body.kind != BodyKind::Static,
+                },
+            );
+        },
+    );
+    write_back(world, &bodies);
+
+    let Some(mut index) = world.resource_mut::<spatial::SpatialIndex>() else {
+        return;
+    };
+    index.sync(world);
+
+    let mut pairs = collections::BTreeSet::new();
+    for (entity, body) in &bodies {
+        if !body.moves && !body.collider.is_trigger {
+            continue;
+        }
+        for other in index.query_aabb(&body.collider.shape.aabb(body.position)) {
+            if other != *entity && bodies.contains_key(&other) {
+                pairs.insert((*entity.min(&other), *entity.max(&other)));
+            }
+        }
+    }
+    drop(index);
+
+    let mut touching = collections::HashMap::new();
+    let mut began = Vec::new();
+    for (first, second) in pairs {
+        let (first_body, second_body) = (&bodies[&first], &bodies[&second]);
+        let Some(manifold) = collision::collide(
+            &first_body.collider.shape,
+            first_body.position,
+            &second_body.collider.shape,
+            second_body.position,
+        ) else {
+            continue;
+        };
+        let trigger = first_body.collider.is_trigger || second_body.collider.is_trigger;
+        touching.insert((first, second), trigger);
+        if !trigger {
+            resolve(&mut bodies, first, second, &manifold);
+        }
+        began.push((first, second, trigger, manifold));
+    }
+    if let Some(tilemap) = world.resource::<tilemap::Tilemap>() {
+        for body in bodies.values_mut() {
+            if body.inverse_mass > 0.0 && !body.collider.is_trigger {
+                resolve_tiles(&tilemap, body);
+            }
+        }
+    }
+    write_back(world, &bodies);
+
+    if let Some(mut events) = world.resource_mut::<CollisionEvents>() {
+        let previous = std::mem::replace(&mut events.touching, touching);
+        events.events.clear();
+        for (first, second, trigger, manifold) in began {
+            if !previous.contains_key(&(first, second)) {
+                events.events.push(CollisionEvent::Began {
+                    first,
+                    second,
+                    trigger,
+                    manifold,
+                });
+            }
+        }
+        for ((first, second), trigger) in previous {
+            if !events.touching.contains_key(&(first, second)) {
+                events.events.push(CollisionEvent::Ended {
+                    first,
+                    second,
+                    trigger,
+                });
+            }
+        }
+    }
+}
+
+fn resolve(
+    bodies: &mut collections::BTreeMap<world::Entity, BodyState>,
+    first: world::Entity,
+    second: world::Entity,
+    manifold: &collision::Manifold,
+) {
+    let (first_inverse_mass, second_inverse_mass) =
+        (bodies[&first].inverse_mass, bodies[&second].inverse_mass);
+    let inverse_mass = first_inverse_mass + second_inverse_mass;
+    if inverse_mass <= 0.0 {
+        return;
+    }
+    let normal = manifold.normal;
+    let relative_velocity = bodies[&second].velocity - bodies[&first].velocity;
+    let normal_velocity = relative_velocity.dot(normal);
+
+    let mut impulse = glam::Vec2::ZERO;
+    if normal_velocity < 0.0 {
+        let restitution = bodies[&first]
+            .collider
+            .restitution
+            .min(bodies[&second].collider.restitution);
+        let normal_impulse = -(1.0 + restitution) * normal_velocity / inverse_mass;
+
+        let tangent = (relative_velocity - normal * normal_velocity).normalize_or_zero();
+        let friction =
+            (bodies[&first].collider.friction * bodies[&second].collider.friction).sqrt();
+        let tangent_impulse = (-relative_velocity.dot(tangent) / inverse_mass)
+            .clamp(-normal_impulse * friction, normal_impulse * friction);
+        impulse = normal * normal_impulse + tangent * tangent_impulse;
+    }
+    let correction =
+        normal * (manifold.depth - PENETRATION_SLOP).max(0.0) / inverse_mass * POSITION_CORRECTION;
+
+    let first_body = bodies
+        .get_mut(&first)
+        .expect("pairs only hold known bodies");
+    first_body.velocity -= impulse * first_inverse_mass;
+    first_body.position -= correction * first_inverse_mass;
+    let second_body = bodies
+        .get_mut(&second)
+        .expect("pairs only hold known bodies");
+    second_body.velocity += impulse * second_inverse_mass;
+    second_body.position += correction * second_inverse_mass;
+}
+
+/// Pushes a body out of every solid tile it overlaps, tiles act like static bodies.
+fn resolve_tiles(tilemap: &tilemap::Tilemap, body: &mut BodyState) {
+    for cell in tilemap.solid_cells(&body.collider.shape.aabb(body.position)) {
+        let Some(manifold) = collision::collide(
+            &body.collider.shape,
+            body.position,
+            &collision::Shape::Aabb {
+                half_extents: cell.half_extents(),
+            },
+            cell.center(),
+        ) else {
+            continue;
+        };
+        let normal_velocity = body.velocity.dot(manifold.normal);
+        if normal_velocity > 0.0 {
+            body.velocity -= manifold.normal * normal_velocity * (1.0 + body.collider.restitution);
+        }
+        body.position -= manifold.normal * (manifold.depth - PENETRATION_SLOP).max(0.0);
+    }
+}
+
+fn write_back(world: &world::World, bodies: &collections::BTreeMap<world::Entity, BodyState>) {
+    for (entity, body) in bodies {
+        if !body.moves {
+            continue;
+        }
+        if let Some(mut position) = world.get_mut::<components::Position>(*entity) {
+            position.0 = body.position;
+        }
+        if let Some(mut rigid_body) = world.get_mut::<RigidBody>(*entity) {
+            rigid_body.velocity = body.velocity;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> world::World {
+        let mut world = world::World::new();
+        world.insert_resource(spatial::SpatialIndex::new(spatial::Aabb::from_center(
+            glam::Vec2::ZERO,
+            glam::Vec2::splat(64.0),
+        )));
+        world.insert_resource(PhysicsSettings::default());
+        world.insert_resource(CollisionEvents::default());
+        world.add_system(spatial::update_system);
+        world.add_system(step_system);
+        world
+    }
+
+    fn spawn(
+        world: &mut world::World,
+        position: glam::Vec2,
+        collider: Collider,
+        body: RigidBody,
+    ) -> world::Entity {
+        let entity = world.spawn();
+        world.insert(entity, components::Position(position));
+        world.insert(entity, collider);
+        world.insert(entity, body);
+        entity
+    }
+
+    fn moving(velocity: glam::Vec2, body: RigidBody) -> RigidBody {
+        RigidBody { velocity, ..body }
+    }
+
+    fn position(world: &world::World, entity: world::Entity) -> glam::Vec2 {
+        world.get::<components::Position>(entity).unwrap().0
+    }
+
+    #[test]
+    fn dynamic_bodies_stop_at_static_walls() {
+        let mut world = world();
+        let ball = spawn(
+            &mut world,
+            glam::Vec2::ZERO,
+            Collider::aabb(glam::Vec2::ONE),
+            moving(glam::vec2(10.0, 0.0), RigidBody::dynamic(1.0)),
+        );
+        spawn(
+            &mut world,
+            glam::vec2(5.0, 0.0),
+            Collider::aabb(glam::vec2(1.0, 5.0)),
+            RigidBody::fixed(),
+        );
+        for _ in 0..120 {
+            world.update();
+        }
+        assert!(
+            position(&world, ball).x < 3.0 + 0.1,
+            "{}",
+            position(&world, ball)
+        );
+        assert!(world.get::<RigidBody>(ball).unwrap().velocity.x <= 0.0);
+    }
+
+    #[test]
+    fn dynamic_bodies_stop_at_solid_tiles() {
+        let mut world = world();
+        let mut tileset = tilemap::Tileset::default();
+        let wall = tileset.add(tilemap::TileDefinition {
+            sprite: 0,
+            frame: 0,
+            solid: true,
+            autotile: None,
+        });
+        let mut tiles = tilemap::Tilemap::new(1.0, tileset);
+        let layer = tiles.add_layer("walls");
+        tiles.fill(layer, glam::ivec2(3, -3), glam::ivec2(3, 3), Some(wall));
+        world.insert_resource(tiles);
+        let ball = spawn(
+            &mut world,
+            glam::vec2(0.0, 0.5),
+            Collider::aabb(glam::Vec2::splat(0.25)),
+            moving(glam::vec2(6.0, 0.0), RigidBody::dynamic(1.0)),
+        );
+        for _ in 0..120 {
+            world.update();
+        }
+        assert!(
+            position(&world, ball).x < 2.75 + 0.05,
+            "{}",
+            position(&world, ball)
+        );
+    }
+
+    #[test]
+    fn kinematic_bodies_push_dynamic_ones() {
+        let mut world = world();
+        let pusher = spawn(
+            &mut world,
+            glam::Vec2::ZERO,
+            Collider::aabb(glam::Vec2::ONE),
+            moving(glam::vec2(5.0, 0.0), RigidBody::kinematic()),
+        );
+        let block = spawn(
+            &mut world,
+            glam::vec2(3.0, 0.0),
+            Collider::aabb(glam::Vec2::ONE),
+            RigidBody::dynamic(1.0),
+        );
+        for _ in 0..120 {
+            world.update();
+        }
+        // The kinematic body keeps its path, the dynamic one is shoved ahead of it.
+        assert!((position(&world, pusher).x - 10.0).abs() < 1e-3);
+        assert!(
+            position(&world, block).x > 10.0 + 2.0 - 0.1,
+            "{}",
+            position(&world, block)
+        );
+    }
+
+    #[test]
+    fn triggers_report_when_overlaps_begin_and_end() {
+        let mut world = world();
+        let ball = spawn(
+            &mut world,
+            glam::Vec2::ZERO,
+            Collider::circle(0.5),
+            moving(glam::vec2(6.0, 0.0), RigidBody::dynamic(1.0)),
+        );
+        let sensor = spawn(
+            &mut world,
+            glam::vec2(5.0, 0.0),
+            Collider::aabb(glam::Vec2::ONE).trigger(),
+            RigidBody::fixed(),
+        );
+        let mut events = Vec::new();
+        for _ in 0..120 {
+            world.update();
+            let collisions = world.resource::<CollisionEvents>().unwrap();
+            events.extend(collisions.iter().map(|event| match event {
+                CollisionEvent::Began {
+                    first,
+                    second,
+                    trigger,
+                    ..
+                } => ("began", *first, *second, *trigger),
+                CollisionEvent::Ended {
+                    first,
+                    second,
+                    trigger,
+                } => ("ended", *first, *second, *trigger),
+            }));
+        }
+        assert_eq!(
+            events,
+            [("began", ball, sensor, true), ("ended", ball, sensor, true)]
+        );
+        // Triggers never push, the ball went straight through.
+        assert!((position(&world, ball).x - 12.0).abs() < 1e-3);
+    }
+}
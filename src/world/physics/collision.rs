@@ -0,0 +1,406 @@
+use crate::world::spatial;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    Aabb {
+        half_extents: glam::Vec2,
+    },
+    Circle {
+        radius: f32,
+    },
+    /// Convex, counter clockwise, relative to the entity position.
+    Polygon {
+        vertices: Vec<glam::Vec2>,
+    },
+}
+
+impl Shape {
+    /// Builds a polygon shape, fixing the winding of clockwise input.
+    pub fn polygon(mut vertices: Vec<glam::Vec2>) -> Self {
+        let twice_area = vertices
+            .iter()
+            .zip(vertices.iter().cycle().skip(1))
+            .map(|(a, b)| a.perp_dot(*b))
+            .sum::<f32>();
+        if twice_area < 0.0 {
+            vertices.reverse();
+        }
+        Shape::Polygon { vertices }
+    }
+    pub fn aabb(&self, position: glam::Vec2) -> spatial::Aabb {
+        match self {
+            Shape::Aabb { half_extents } => spatial::Aabb::from_center(position, *half_extents),
+            Shape::Circle { radius } => {
+                spatial::Aabb::from_center(position, glam::Vec2::splat(*radius))
+            }
+            Shape::Polygon { vertices } => {
+                let (min, max) = vertices
+                    .iter()
+                    .fold((glam::Vec2::MAX, glam::Vec2::MIN), |(min, max), vertex| {
+                        (min.min(*vertex), max.max(*vertex))
+                    });
+                spatial::Aabb::new(position + min, position + max)
+            }
+        }
+    }
+    fn world_vertices(&self, position: glam::Vec2) -> Vec<glam::Vec2> {
+        match self {
+            Shape::Aabb { half_extents } => [
+                glam::vec2(-1.0, -1.0),
+                glam::vec2(1.0, -1.0),
+                glam::vec2(1.0, 1.0),
+                glam::vec2(-1.0, 1.0),
+            ]
+            .map(|corner| position + corner * *half_extents)
+            .to_vec(),
+            Shape::Polygon { vertices } => {
+                vertices.iter().map(|vertex| position + *vertex).collect()
+            }
+            Shape::Circle { .. } => Vec::new(),
+        }
+    }
+}
+
+/// How two shapes overlap, `normal` points from the first shape towards the second.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Manifold {
+    pub normal: glam::Vec2,
+    pub depth: f32,
+    pub contacts: Vec<glam::Vec2>,
+}
+
+impl Manifold {
+    fn flipped(mut self) -> Self {
+        self.normal = -self.normal;
+        self
+    }
+}
+
+pub fn collide(
+    first: &Shape,
+    first_position: glam::Vec2,
+    second: &Shape,
+    second_position: glam::Vec2,
+) -> Option<Manifold> {
+    match (first, second) {
+        (
+            Shape::Circle {
+                radius: first_radius,
+            },
+            Shape::Circle {
+                radius: second_radius,
+            },
+        ) => circle_circle(
+            first_position,
+            *first_radius,
+            second_position,
+            *second_radius,
+        ),
+        (Shape::Circle { radius }, _) => polygon_circle(
+            &second.world_vertices(second_position),
+            second_position,
+            first_position,
+            *radius,
+        )
+        .map(Manifold::flipped),
+        (_, Shape::Circle { radius }) => polygon_circle(
+            &first.world_vertices(first_position),
+            first_position,
+            second_position,
+            *radius,
+        ),
+        _ => polygon_polygon(
+            &first.world_vertices(first_position),
+            first_position,
+            &second.world_vertices(second_position),
+            second_position,
+        ),
+    }
+}
+
+fn circle_circle(
+    first: glam::Vec2,
+    first_radius: f32,
+    second: glam::Vec2,
+    second_radius: f32,
+) -> Option<Manifold> {
+    let offset = second - first;
+    let distance = offset.length();
+    let depth = first_radius + second_radius - distance;
+    if depth <= 0.0 {
+        return None;
+    }
+    let normal = offset.try_normalize().unwrap_or(glam::Vec2::X);
+    Some(Manifold {
+        normal,
+        depth,
+        contacts: vec![first + normal * first_radius],
+    })
+}
+
+fn edge_normals(vertices: &[glam::Vec2]) -> impl Iterator<Item = glam::Vec2> + '_ {
+    vertices
+        .iter()
+        .zip(vertices.iter().cycle().skip(1))
+        .filter_map(|(a, b)| (*b - *a).perp().try_normalize().map(|normal| -normal))
+}
+
+fn project(vertices: &[glam::Vec2], axis: glam::Vec2) -> (f32, f32) {
+    vertices
+        .iter()
+        .map(|vertex| vertex.dot(axis))
+        .fold((f32::MAX, f32::MIN), |(min, max), projection| {
+            (min.min(projection), max.max(projection))
+        })
+}
+
+fn contains(vertices: &[glam::Vec2], point: glam::Vec2) -> bool {
+    vertices
+        .iter()
+        .zip(vertices.iter().cycle().skip(1))
+        .all(|(a, b)| (*b - *a).perp_dot(point - *a) >= 0.0)
+}
+
+/// Separating axis test between two convex polygons.
+fn polygon_polygon(
+    first: &[glam::Vec2],
+    first_center: glam::Vec2,
+    second: &[glam::Vec2],
+    second_center: glam::Vec2,
+) -> Option<Manifold> {
+    let mut best = (f32::MAX, glam::Vec2::X);
+    for axis in edge_normals(first).chain(edge_normals(second)) {
+        let (first_min, first_max) = project(first, axis);
+        let (second_min, second_max) = project(second, axis);
+        let overlap = (first_max - second_min).min(second_max - first_min);
+        if overlap <= 0.0 {
+            return None;
+        }
+        if overlap < best.0 {
+            best = (overlap, axis);
+        }
+    }
+    let (depth, mut normal) = best;
+    if normal.dot(second_center - first_center) < 0.0 {
+        normal = -normal;
+    }
+
+    let mut contacts = second
+        .iter()
+        .filter(|vertex| contains(first, **vertex))
+        .chain(first.iter().filter(|vertex| contains(second, **vertex)))
+        .copied()
+        .collect::<Vec<_>>();
+    if contacts.is_empty() {
+        contacts.push((first_center + second_center) * 0.5);
+    }
+    contacts.sort_by(|a, b| a.dot(normal).total_cmp(&b.dot(normal)));
+    contacts.truncate(2);
+
+    Some(Manifold {
+        normal,
+        depth,
+        contacts,
+    })
+}
+
+fn polygon_circle(
+    polygon: &[glam::Vec2],
+    polygon_center: glam::Vec2,
+    center: glam::Vec2,
+    radius: f32,
+) -> Option<Manifold> {
+    let closest = polygon.iter().min_by(|a, b| {
+        a.distance_squared(center)
+            .total_cmp(&b.distance_squared(center))
+    })?;
+    let mut best = (f32::MAX, glam::Vec2::X);
+    for axis in edge_normals(polygon).chain((center - *closest).try_normalize()) {
+        let (polygon_min, polygon_max) = project(polygon, axis);
+        let projection = center.dot(axis);
+        let overlap = (polygon_max - (projection - radius)).min(projection + radius - polygon_min);
+        if overlap <= 0.0 {
+            return None;
+        }
+        if overlap < best.0 {
+            best = (overlap, axis);
+        }
+    }
+    let (depth, mut normal) = best;
+    if normal.dot(center - polygon_center) < 0.0 {
+        normal = -normal;
+    }
+    Some(Manifold {
+        normal,
+        depth,
+        contacts: vec![center - normal * radius],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(half_extent: f32) -> Shape {
+        Shape::Aabb {
+            half_extents: glam::Vec2::splat(half_extent),
+        }
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn boxes_push_apart_along_the_shallowest_axis() {
+        let manifold = collide(
+            &square(1.0),
+            glam::Vec2::ZERO,
+            &square(1.0),
+            glam::vec2(1.5, 0.2),
+        )
+        .expect("overlapping boxes");
+        assert_eq!(manifold.normal, glam::Vec2::X);
+        assert_close(manifold.depth, 0.5);
+        assert!(!manifold.contacts.is_empty() && manifold.contacts.len() <= 2);
+
+        let flipped = collide(
+            &square(1.0),
+            glam::vec2(1.5, 0.2),
+            &square(1.0),
+            glam::Vec2::ZERO,
+        )
+        .expect("overlapping boxes");
+        assert_eq!(flipped.normal, -glam::Vec2::X);
+    }
+
+    #[test]
+    fn separated_or_touching_boxes_do_not_collide() {
+        let shape = square(1.0);
+        assert!(collide(&shape, glam::Vec2::ZERO, &shape, glam::vec2(2.5, 0.0)).is_none());
+        assert!(collide(&shape, glam::Vec2::ZERO, &shape, glam::vec2(2.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn diagonal_edges_separate_a_diamond_from_a_box_corner() {
+        let diamond = Shape::polygon(vec![
+            glam::vec2(0.0, -1.0),
+            glam::vec2(1.0, 0.0),
+            glam::vec2(0.0, 1.0),
+            glam::vec2(-1.0, 0.0),
+        ]);
+        // The bounding boxes overlap, only the diamond's edge normals separate them.
+        assert!(
+            collide(
+                &diamond,
+                glam::Vec2::ZERO,
+                &square(0.5),
+                glam::vec2(1.1, 1.1)
+            )
+            .is_none()
+        );
+
+        let manifold = collide(
+            &diamond,
+            glam::Vec2::ZERO,
+            &square(0.5),
+            glam::vec2(0.7, 0.7),
+        )
+        .expect("box corner inside the diamond");
+        let diagonal = glam::Vec2::ONE.normalize();
+        assert_close(manifold.normal.dot(diagonal), 1.0);
+        assert_close(manifold.depth, (1.0 - 0.4) / 2.0_f32.sqrt());
+        assert!(
+            manifold
+                .contacts
+                .iter()
+                .any(|contact| contact.abs_diff_eq(glam::vec2(0.2, 0.2), 1e-4))
+        );
+    }
+
+    #[test]
+    fn clockwise_polygons_are_rewound() {
+        let clockwise = vec![
+            glam::vec2(-1.0, -1.0),
+            glam::vec2(-1.0, 1.0),
+            glam::vec2(1.0, 1.0),
+            glam::vec2(1.0, -1.0),
+        ];
+        let Shape::Polygon { vertices } = Shape::polygon(clockwise.clone()) else {
+            unreachable!();
+        };
+        assert_eq!(vertices, clockwise.into_iter().rev().collect::<Vec<_>>());
+        let manifold = collide(
+            &Shape::Polygon { vertices },
+            glam::Vec2::ZERO,
+            &square(1.0),
+            glam::vec2(0.0, 1.75),
+        )
+        .expect("overlapping squares");
+        assert_eq!(manifold.normal, glam::Vec2::Y);
+        assert_close(manifold.depth, 0.25);
+    }
+
+    #[test]
+    fn circles_collide_along_the_line_between_centres() {
+        let circle = Shape::Circle { radius: 1.0 };
+        let manifold = collide(&circle, glam::Vec2::ZERO, &circle, glam::vec2(0.0, 1.5))
+            .expect("overlapping circles");
+        assert_eq!(manifold.normal, glam::Vec2::Y);
+        assert_close(manifold.depth, 0.5);
+        assert_eq!(manifold.contacts, [glam::vec2(0.0, 1.0)]);
+        assert!(collide(&circle, glam::Vec2::ZERO, &circle, glam::vec2(2.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn circle_and_box_normals_point_from_first_to_second() {
+        let circle = Shape::Circle { radius: 0.5 };
+        let manifold = collide(
+            &square(1.0),
+            glam::Vec2::ZERO,
+            &circle,
+            glam::vec2(1.25, 0.0),
+        )
+        .expect("circle overlapping the right edge");
+        assert_eq!(manifold.normal, glam::Vec2::X);
+        assert_close(manifold.depth, 0.25);
+        assert_eq!(manifold.contacts, [glam::vec2(0.75, 0.0)]);
+
+        let flipped = collide(
+            &circle,
+            glam::vec2(1.25, 0.0),
+            &square(1.0),
+            glam::Vec2::ZERO,
+        )
+        .expect("circle overlapping the right edge");
+        assert_eq!(flipped.normal, -glam::Vec2::X);
+        assert_close(flipped.depth, 0.25);
+    }
+
+    #[test]
+    fn circle_off_a_box_corner_does_not_collide() {
+        let circle = Shape::Circle { radius: 0.5 };
+        // Inside both face projections, but further than the radius from the corner.
+        assert!(
+            collide(
+                &square(1.0),
+                glam::Vec2::ZERO,
+                &circle,
+                glam::vec2(1.4, 1.4)
+            )
+            .is_none()
+        );
+        let manifold = collide(
+            &square(1.0),
+            glam::Vec2::ZERO,
+            &circle,
+            glam::vec2(1.3, 1.3),
+        )
+        .expect("circle over the corner");
+        assert_close(manifold.normal.dot(glam::Vec2::ONE.normalize()), 1.0);
+        assert_close(manifold.depth, 0.5 - 0.3 * 2.0_f32.sqrt());
+    }
+}
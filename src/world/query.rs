@@ -0,0 +1,92 @@
+use crate::world;
+use crate::world::storage;
+use std::cell;
+
+/// A set of component borrows that can be iterated together, e.g. `(&Position, &mut Velocity)`.
+///
+/// Storages are borrowed through `RefCell`s, so asking for the same component both shared and
+/// mutably in one query panics.
+pub trait Query {
+    type Fetch<'w>;
+    type Item<'f>;
+
+    fn fetch(world: &world::World) -> Option<Self::Fetch<'_>>;
+    /// The entities of the smallest storage involved, used to drive iteration.
+    fn driver<'f>(fetch: &'f Self::Fetch<'_>) -> &'f [world::Entity];
+    fn get<'f>(fetch: &'f mut Self::Fetch<'_>, entity: world::Entity) -> Option<Self::Item<'f>>;
+}
+
+impl<T: 'static> Query for &T {
+    type Fetch<'w> = cell::Ref<'w, storage::SparseSet<T>>;
+    type Item<'f> = &'f T;
+
+    fn fetch(world: &world::World) -> Option<Self::Fetch<'_>> {
+        world.storage::<T>()
+    }
+    fn driver<'f>(fetch: &'f Self::Fetch<'_>) -> &'f [world::Entity] {
+        fetch.entities()
+    }
+    fn get<'f>(fetch: &'f mut Self::Fetch<'_>, entity: world::Entity) -> Option<Self::Item<'f>> {
+        fetch.get(entity)
+    }
+}
+
+impl<T: 'static> Query for &mut T {
+    type Fetch<'w> = cell::RefMut<'w, storage::SparseSet<T>>;
+    type Item<'f> = &'f mut T;
+
+    fn fetch(world: &world::World) -> Option<Self::Fetch<'_>> {
+        world.storage_mut::<T>()
+    }
+    fn driver<'f>(fetch: &'f Self::Fetch<'_>) -> &'f [world::Entity] {
+        fetch.entities()
+    }
+    fn get<'f>(fetch: &'f mut Self::Fetch<'_>, entity: world::Entity) -> Option<Self::Item<'f>> {
+        fetch.get_mut(entity)
+    }
+}
+
+impl<T: 'static> Query for Option<&T> {
+    type Fetch<'w> = Option<cell::Ref<'w, storage::SparseSet<T>>>;
+    type Item<'f> = Option<&'f T>;
+
+    fn fetch(world: &world::World) -> Option<Self::Fetch<'_>> {
+        Some(world.storage::<T>())
+    }
+    fn driver<'f>(_fetch: &'f Self::Fetch<'_>) -> &'f [world::Entity] {
+        &[]
+    }
+    fn get<'f>(fetch: &'f mut Self::Fetch<'_>, entity: world::Entity) -> Option<Self::Item<'f>> {
+        Some(fetch.as_ref().and_then(|storage| storage.get(entity)))
+    }
+}
+
+macro_rules! tuple_query {
+    ($($name:ident $index:tt),+) => {
+        impl<$($name: Query),+> Query for ($($name,)+) {
+            type Fetch<'w> = ($($name::Fetch<'w>,)+);
+            type Item<'f> = ($($name::Item<'f>,)+);
+
+            fn fetch(world: &world::World) -> Option<Self::Fetch<'_>> {
+                Some(($($name::fetch(world)?,)+))
+            }
+            fn driver<'f>(fetch: &'f Self::Fetch<'_>) -> &'f [world::Entity] {
+                // Optional components drive nothing, so only non-empty drivers are considered.
+                [$($name::driver(&fetch.$index)),+]
+                    .into_iter()
+                    .filter(|entities| !entities.is_empty())
+                    .min_by_key(|entities| entities.len())
+                    .unwrap_or(&[])
+            }
+            fn get<'f>(fetch: &'f mut Self::Fetch<'_>, entity: world::Entity) -> Option<Self::Item<'f>> {
+                Some(($($name::get(&mut fetch.$index, entity)?,)+))
+            }
+        }
+    };
+}
+
+tuple_query!(A 0);
+tuple_query!(A 0, B 1);
+tuple_query!(A 0, B 1, C 2);
+tuple_query!(A 0, B 1, C 2, D 3);
+tuple_query!(A 0, B 1, C 2, D 3, E 4);
@@ -0,0 +1,208 @@
+use crate::simulation::save::codec;
+use std::collections;
+
+/// Independent xoshiro256++ generator, see [`Random::stream`].
+///
+/// The algorithm is fixed here rather than borrowed from `rand::rngs`, whose generators are
+/// allowed to change between releases and would break recorded replays.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stream {
+    state: [u64; 4],
+}
+
+impl Stream {
+    pub fn from_seed(seed: u64) -> Self {
+        let mut mixer = SplitMix64(seed);
+        Self {
+            state: std::array::from_fn(|_| mixer.next()),
+        }
+    }
+}
+
+impl rand::RngCore for Stream {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+    fn next_u64(&mut self) -> u64 {
+        let [a, b, c, d] = &mut self.state;
+        let result = a.wrapping_add(*d).rotate_left(23).wrapping_add(*a);
+        let t = *b << 17;
+        *c ^= *a;
+        *d ^= *b;
+        *b ^= *c;
+        *a ^= *d;
+        *c ^= t;
+        *d = d.rotate_left(45);
+        result
+    }
+    fn fill_bytes(&mut self, destination: &mut [u8]) {
+        rand::rand_core::impls::fill_bytes_via_next(self, destination)
+    }
+}
+
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// FNV-1a, stable across platforms and releases unlike the std hashers.
+fn hash_name(name: &str) -> u64 {
+    name.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Seeded source of every random number the simulation draws.
+///
+/// Each system asks for its own named stream, so adding draws to one system never shifts the
+/// numbers another one sees. Nothing in the simulation should use `rand::rng()` or other
+/// entropy sources, replays and lockstep peers only agree when they start from the same seed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Random {
+    seed: u64,
+    streams: collections::BTreeMap<String, Stream>,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            streams: collections::BTreeMap::new(),
+        }
+    }
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+    /// The stream of `name`, seeded from the simulation seed and the name the first time it is used.
+    pub fn stream(&mut self, name: &str) -> &mut Stream {
+        if !self.streams.contains_key(name) {
+            let stream = Stream::from_seed(self.seed ^ hash_name(name));
+            self.streams.insert(name.to_owned(), stream);
+        }
+        self.streams
+            .get_mut(name)
+            .expect("stream was just inserted")
+    }
+    pub fn state(&self) -> RandomState {
+        RandomState {
+            seed: self.seed,
+            streams: self
+                .streams
+                .iter()
+                .map(|(name, stream)| (name.clone(), stream.state))
+                .collect(),
+        }
+    }
+    pub fn restore(&mut self, state: &RandomState) {
+        *self = Self::from(state.clone());
+    }
+}
+
+impl From<RandomState> for Random {
+    fn from(state: RandomState) -> Self {
+        Self {
+            seed: state.seed,
+            streams: state
+                .streams
+                .into_iter()
+                .map(|(name, state)| (name, Stream { state }))
+                .collect(),
+        }
+    }
+}
+
+/// Everything needed to continue a [`Random`] exactly where it was.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RandomState {
+    pub seed: u64,
+    pub streams: Vec<(String, [u64; 4])>,
+}
+
+impl RandomState {
+    /// Seed, stream count, then per stream the name and state.
+    pub fn write(&self, writer: &mut codec::Writer) {
+        writer.u64(self.seed);
+        writer.u32(self.streams.len() as u32);
+        for (name, state) in &self.streams {
+            writer.string(name);
+            for word in state {
+                writer.u64(*word);
+            }
+        }
+    }
+    pub fn read(reader: &mut codec::Reader) -> anyhow::Result<Self> {
+        let seed = reader.u64()?;
+        let mut streams = Vec::new();
+        for _ in 0..reader.u32()? {
+            let name = reader.string()?;
+            let state = [reader.u64()?, reader.u64()?, reader.u64()?, reader.u64()?];
+            streams.push((name, state));
+        }
+        Ok(Self { seed, streams })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::RngCore;
+
+    #[test]
+    fn streams_do_not_shift_each_other() {
+        let mut first = Random::new(7);
+        let mut second = Random::new(7);
+        first.stream("loot").next_u64();
+        assert_eq!(
+            first.stream("spawns").next_u64(),
+            second.stream("spawns").next_u64()
+        );
+        assert_ne!(
+            Random::new(7).stream("loot").next_u64(),
+            Random::new(7).stream("spawns").next_u64()
+        );
+    }
+
+    #[test]
+    fn restored_state_continues_the_same_numbers() {
+        let mut random = Random::new(42);
+        random.stream("loot").next_u64();
+        random.stream("weather").next_u32();
+
+        let mut writer = codec::Writer::default();
+        random.state().write(&mut writer);
+        let bytes = writer.into_bytes();
+        let mut reader = codec::Reader::new(&bytes);
+        let state = RandomState::read(&mut reader).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!(state, random.state());
+
+        let mut restored = Random::new(0);
+        restored.restore(&state);
+        assert_eq!(restored, random);
+        for name in ["loot", "weather", "unused"] {
+            assert_eq!(
+                restored.stream(name).next_u64(),
+                random.stream(name).next_u64()
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_state_is_an_error() {
+        let mut random = Random::new(3);
+        random.stream("loot");
+        let mut writer = codec::Writer::default();
+        random.state().write(&mut writer);
+        let bytes = writer.into_bytes();
+        for length in 0..bytes.len() {
+            assert!(RandomState::read(&mut codec::Reader::new(&bytes[..length])).is_err());
+        }
+    }
+}
@@ -0,0 +1,525 @@
+use crate::world;
+use crate::world::components;
+use crate::world::storage;
+use std::cmp;
+use std::collections;
+
+const MAX_ITEMS: usize = 8;
+const MAX_DEPTH: u32 = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: glam::Vec2,
+    pub max: glam::Vec2,
+}
+
+impl Aabb {
+    pub fn new(min: glam::Vec2, max: glam::Vec2) -> Self {
+        Self { min, max }
+    }
+    pub fn from_center(center: glam::Vec2, half_extents: glam::Vec2) -> Self {
+        Self {
+            min: center - half_extents,
+            max: center + half_extents,
+        }
+    }
+    pub fn center(&self) -> glam::Vec2 {
+        (self.min + self.max) * 0.5
+    }
+    pub fn half_extents(&self) -> glam::Vec2 {
+        (self.max - self.min) * 0.5
+    }
+    pub fn contains(&self, other: &Aabb) -> bool {
+        self.min.cmple(other.min).all() && self.max.cmpge(other.max).all()
+    }
+    pub fn contains_point(&self, point: glam::Vec2) -> bool {
+        self.min.cmple(point).all() && self.max.cmpge(point).all()
+    }
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.cmple(other.max).all() && self.max.cmpge(other.min).all()
+    }
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.min(other.min), self.max.max(other.max))
+    }
+    pub fn distance_squared_to_point(&self, point: glam::Vec2) -> f32 {
+        (point.clamp(self.min, self.max) - point).length_squared()
+    }
+    /// Distance along `direction` at which the ray enters the box, zero if it starts inside.
+    pub fn ray_intersection(
+        &self,
+        origin: glam::Vec2,
+        direction: glam::Vec2,
+        max_distance: f32,
+    ) -> Option<f32> {
+        let inverse = direction.recip();
+        let first = (self.min - origin) * inverse;
+        let second = (self.max - origin) * inverse;
+        let enter = first.min(second).max_element().max(0.0);
+        let leave = first.max(second).min_element().min(max_distance);
+        (enter <= leave).then_some(enter)
+    }
+    fn quadrant(&self, quadrant: usize) -> Aabb {
+        let center = self.center();
+        let min = glam::vec2(
+            if quadrant & 1 == 0 {
+                self.min.x
+            } else {
+                center.x
+            },
+            if quadrant & 2 == 0 {
+                self.min.y
+            } else {
+                center.y
+            },
+        );
+        Aabb::new(min, min + self.half_extents())
+    }
+}
+
+struct Node {
+    bounds: Aabb,
+    items: Vec<(world::Entity, Aabb)>,
+    children: Option<Box<[Node; 4]>>,
+}
+
+impl Node {
+    fn new(bounds: Aabb) -> Self {
+        Self {
+            bounds,
+            items: Vec::new(),
+            children: None,
+        }
+    }
+    fn child_containing(&mut self, aabb: &Aabb) -> Option<&mut Node> {
+        self.children
+            .as_mut()?
+            .iter_mut()
+            .find(|child| child.bounds.contains(aabb))
+    }
+    fn insert(&mut self, entity: world::Entity, aabb: Aabb, depth: u32) {
+        if let Some(child) = self.child_containing(&aabb) {
+            return child.insert(entity, aabb, depth + 1);
+        }
+        self.items.push((entity, aabb));
+        if self.children.is_none() && self.items.len() > MAX_ITEMS && depth < MAX_DEPTH {
+            self.split(depth);
+        }
+    }
+    fn split(&mut self, depth: u32) {
+        self.children = Some(Box::new(std::array::from_fn(|quadrant| {
+            Node::new(self.bounds.quadrant(quadrant))
+        })));
+        for (entity, aabb) in std::mem::take(&mut self.items) {
+            self.insert(entity, aabb, depth);
+        }
+    }
+    fn remove(&mut self, entity: world::Entity, aabb: &Aabb) -> bool {
+        if let Some(position) = self.items.iter().position(|(item, _)| *item == entity) {
+            self.items.swap_remove(position);
+            return true;
+        }
+        let removed = self
+            .child_containing(aabb)
+            .is_some_and(|child| child.remove(entity, aabb));
+        if removed {
+            self.collapse();
+        }
+        removed
+    }
+    /// Pulls the items of leaf children back up once they would fit in this node again.
+    fn collapse(&mut self) {
+        let Some(children) = &self.children else {
+            return;
+        };
+        let leaves = children.iter().all(|child| child.children.is_none());
+        let count = self.items.len()
+            + children
+                .iter()
+                .map(|child| child.items.len())
+                .sum::<usize>();
+        if leaves && count <= MAX_ITEMS {
+            for child in self
+                .children
+                .take()
+                .into_iter()
+                .flat_map(|children| *children)
+            {
+                self.items.extend(child.items);
+            }
+        }
+    }
+    fn query(&self, area: &Aabb, found: &mut Vec<world::Entity>) {
+        found.extend(
+            self.items
+                .iter()
+                .filter(|(_, aabb)| aabb.intersects(area))
+                .map(|(entity, _)| *entity),
+        );
+        for child in self.children.iter().flat_map(|children| children.iter()) {
+            if child.bounds.intersects(area) {
+                child.query(area, found);
+            }
+        }
+    }
+    fn raycast(
+        &self,
+        origin: glam::Vec2,
+        direction: glam::Vec2,
+        max_distance: f32,
+        hits: &mut Vec<(world::Entity, f32)>,
+    ) {
+        hits.extend(self.items.iter().filter_map(|(entity, aabb)| {
+            Some((
+                *entity,
+                aabb.ray_intersection(origin, direction, max_distance)?,
+            ))
+        }));
+        for child in self.children.iter().flat_map(|children| children.iter()) {
+            if child
+                .bounds
+                .ray_intersection(origin, direction, max_distance)
+                .is_some()
+            {
+                child.raycast(origin, direction, max_distance, hits);
+            }
+        }
+    }
+}
+
+/// Quadtree of entity bounding boxes, kept in sync with [`components::Position`] and
+/// [`components::Bounds`] by [`update_system`].
+///
+/// Boxes are stored in the deepest node that fully contains them. Boxes outside of the
+/// root bounds stay in the root, so the index works for any world size but is only
+/// fast inside the bounds it was created with.
+pub struct SpatialIndex {
+    root: Node,
+    locations: collections::HashMap<world::Entity, Aabb>,
+    /// The storage change the last [`SpatialIndex::sync`] caught up to.
+    synced: u64,
+}
+
+impl SpatialIndex {
+    pub fn new(bounds: Aabb) -> Self {
+        Self {
+            root: Node::new(bounds),
+            locations: collections::HashMap::new(),
+            synced: 0,
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+    pub fn get(&self, entity: world::Entity) -> Option<Aabb> {
+        self.locations.get(&entity).copied()
+    }
+    /// Inserts or moves an entity, leaving the tree untouched when its box did not change.
+    pub fn update(&mut self, entity: world::Entity, aabb: Aabb) {
+        match self.locations.insert(entity, aabb) {
+            Some(previous) if previous == aabb => return,
+            Some(previous) => {
+                self.root.remove(entity, &previous);
+            }
+            None => (),
+        }
+        self.root.insert(entity, aabb, 0);
+    }
+    pub fn remove(&mut self, entity: world::Entity) -> bool {
+        let Some(aabb) = self.locations.remove(&entity) else {
+            return false;
+        };
+        self.root.remove(entity, &aabb)
+    }
+    pub fn query_aabb(&self, area: &Aabb) -> Vec<world::Entity> {
+        let mut found = Vec::new();
+        self.root.query(area, &mut found);
+        found
+    }
+    pub fn query_radius(&self, center: glam::Vec2, radius: f32) -> Vec<world::Entity> {
+        let mut found = self.query_aabb(&Aabb::from_center(center, glam::Vec2::splat(radius)));
+        found.retain(|entity| {
+            self.locations[entity].distance_squared_to_point(center) <= radius * radius
+        });
+        found
+    }
+    /// Every box hit by the ray, nearest first.
+    pub fn raycast(
+        &self,
+        origin: glam::Vec2,
+        direction: glam::Vec2,
+        max_distance: f32,
+    ) -> Vec<(world::Entity, f32)> {
+        let mut hits = Vec::new();
+        self.root.raycast(
+            origin,
+            direction.normalize_or_zero(),
+            max_distance,
+            &mut hits,
+        );
+        hits.sort_by(|(_, first), (_, second)| first.total_cmp(second));
+        hits
+    }
+    /// The `count` entities whose boxes are closest to `point`, nearest first.
+    pub fn nearest(&self, point: glam::Vec2, count: usize) -> Vec<world::Entity> {
+        let mut nearest = Vec::with_capacity(count);
+        let mut candidates = collections::BinaryHeap::new();
+        candidates.push(Candidate {
+            distance: 0.0,
+            kind: CandidateKind::Node(&self.root),
+        });
+        while nearest.len() < count {
+            let Some(candidate) = candidates.pop() else {
+                break;
+            };
+            match candidate.kind {
+                CandidateKind::Entity(entity) => nearest.push(entity),
+                CandidateKind::Node(node) => {
+                    candidates.extend(node.items.iter().map(|(entity, aabb)| Candidate {
+                        distance: aabb.distance_squared_to_point(point),
+                        kind: CandidateKind::Entity(*entity),
+                    }));
+                    candidates.extend(
+                        node.children
+                            .iter()
+                            .flat_map(|children| children.iter())
+                            .map(|child| Candidate {
+                                distance: child.bounds.distance_squared_to_point(point),
+                                kind: CandidateKind::Node(child),
+                            }),
+                    );
+                }
+            }
+        }
+        nearest
+    }
+    /// Moves the entities whose position or bounds changed since the last sync and drops the
+    /// ones that lost either.
+    pub fn sync(&mut self, world: &world::World) {
+        let since = self.synced;
+        self.synced = storage::current_change();
+        let (Some(positions), Some(bounds)) = (
+            world.storage::<components::Position>(),
+            world.storage::<components::Bounds>(),
+        ) else {
+            let stale = self.locations.keys().copied().collect::<Vec<_>>();
+            for entity in stale {
+                self.remove(entity);
+            }
+            return;
+        };
+        if positions.removed_since(since) || bounds.removed_since(since) {
+            let stale = self
+                .locations
+                .keys()
+                .copied()
+                .filter(|entity| !positions.contains(*entity) || !bounds.contains(*entity))
+                .collect::<Vec<_>>();
+            for entity in stale {
+                self.remove(entity);
+            }
+        }
+        let changed = positions
+            .changed_since(since)
+            .chain(bounds.changed_since(since))
+            .collect::<collections::BTreeSet<_>>();
+        for entity in changed {
+            if let (Some(position), Some(bounds)) = (positions.get(entity), bounds.get(entity)) {
+                self.update(entity, bounds.aabb(*position));
+            }
+        }
+    }
+}
+
+pub fn update_system(world: &world::World, _commands: &mut world::commands::Commands) {
+    if let Some(mut index) = world.resource_mut::<SpatialIndex>() {
+        index.sync(world);
+    }
+}
+
+struct Candidate<'a> {
+    distance: f32,
+    kind: CandidateKind<'a>,
+}
+
+enum CandidateKind<'a> {
+    Node(&'a Node),
+    Entity(world::Entity),
+}
+
+impl PartialEq for Candidate<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == cmp::Ordering::Equal
+    }
+}
+
+impl Eq for Candidate<'_> {}
+
+impl PartialOrd for Candidate<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate<'_> {
+    /// Reversed so the binary heap pops the closest candidate first.
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        other.distance.total_cmp(&self.distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Enough boxes on a grid that the root splits a few times.
+    fn grid() -> (world::World, SpatialIndex) {
+        let mut world = world::World::new();
+        for y in 0..10 {
+            for x in 0..10 {
+                let entity = world.spawn();
+                world.insert(
+                    entity,
+                    components::Position(glam::vec2(x as f32, y as f32) * 10.0),
+                );
+                world.insert(entity, components::Bounds::new(glam::Vec2::splat(2.0)));
+            }
+        }
+        let mut index = SpatialIndex::new(Aabb::new(glam::Vec2::ZERO, glam::Vec2::splat(128.0)));
+        index.sync(&world);
+        (world, index)
+    }
+
+    fn at(world: &world::World, entities: &[world::Entity]) -> Vec<glam::Vec2> {
+        entities
+            .iter()
+            .map(|entity| world.get::<components::Position>(*entity).unwrap().0)
+            .collect()
+    }
+
+    fn sorted(mut points: Vec<glam::Vec2>) -> Vec<glam::Vec2> {
+        points.sort_by(|first, second| {
+            first
+                .y
+                .total_cmp(&second.y)
+                .then(first.x.total_cmp(&second.x))
+        });
+        points
+    }
+
+    #[test]
+    fn range_query_finds_overlapping_boxes() {
+        let (world, index) = grid();
+        assert_eq!(index.len(), 100);
+        let found = index.query_aabb(&Aabb::new(glam::vec2(9.5, 19.5), glam::vec2(20.5, 20.5)));
+        assert_eq!(
+            sorted(at(&world, &found)),
+            [glam::vec2(10.0, 20.0), glam::vec2(20.0, 20.0)]
+        );
+        assert!(
+            index
+                .query_aabb(&Aabb::new(glam::vec2(3.0, 3.0), glam::vec2(7.0, 7.0)))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn radius_query_drops_the_corners_of_its_box() {
+        let (world, index) = grid();
+        let found = index.query_radius(glam::vec2(50.0, 50.0), 10.0);
+        assert_eq!(
+            sorted(at(&world, &found)),
+            [
+                glam::vec2(50.0, 40.0),
+                glam::vec2(40.0, 50.0),
+                glam::vec2(50.0, 50.0),
+                glam::vec2(60.0, 50.0),
+                glam::vec2(50.0, 60.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn raycast_hits_are_sorted_by_distance() {
+        let (world, index) = grid();
+        let hits = index.raycast(glam::vec2(-5.0, 30.0), glam::Vec2::X, 30.0);
+        let entities = hits.iter().map(|(entity, _)| *entity).collect::<Vec<_>>();
+        assert_eq!(
+            at(&world, &entities),
+            [
+                glam::vec2(0.0, 30.0),
+                glam::vec2(10.0, 30.0),
+                glam::vec2(20.0, 30.0),
+            ]
+        );
+        let distances = hits
+            .iter()
+            .map(|(_, distance)| *distance)
+            .collect::<Vec<_>>();
+        assert_eq!(distances, [4.0, 14.0, 24.0]);
+        assert!(
+            index
+                .raycast(glam::vec2(-5.0, 35.0), glam::Vec2::X, 100.0)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn nearest_returns_the_closest_first() {
+        let (world, index) = grid();
+        let nearest = index.nearest(glam::vec2(33.0, 71.0), 3);
+        assert_eq!(
+            at(&world, &nearest),
+            [
+                glam::vec2(30.0, 70.0),
+                glam::vec2(40.0, 70.0),
+                glam::vec2(30.0, 80.0),
+            ]
+        );
+        assert_eq!(index.nearest(glam::Vec2::ZERO, 1000).len(), 100);
+    }
+
+    #[test]
+    fn sync_follows_moved_resized_and_removed_entities() {
+        let (mut world, mut index) = grid();
+        let entities = world.entities().collect::<Vec<_>>();
+        let (moved, resized, removed, despawned) =
+            (entities[0], entities[1], entities[2], entities[3]);
+        world.get_mut::<components::Position>(moved).unwrap().0 = glam::vec2(75.0, 75.0);
+        *world.get_mut::<components::Bounds>(resized).unwrap() =
+            components::Bounds::new(glam::Vec2::splat(6.0));
+        world.remove::<components::Bounds>(removed);
+        world.despawn(despawned);
+        index.sync(&world);
+
+        assert_eq!(index.len(), 98);
+        assert_eq!(index.query_radius(glam::vec2(75.0, 75.0), 0.5), [moved]);
+        assert_eq!(
+            index.get(resized),
+            Some(Aabb::from_center(
+                glam::vec2(10.0, 0.0),
+                glam::Vec2::splat(3.0)
+            ))
+        );
+        assert!(index.get(removed).is_none());
+        assert!(index.get(despawned).is_none());
+    }
+
+    #[test]
+    fn sync_skips_unchanged_entities() {
+        let (world, mut index) = grid();
+        let synced = index.synced;
+        index.sync(&world);
+        assert_eq!(
+            world
+                .storage::<components::Position>()
+                .unwrap()
+                .changed_since(synced)
+                .count(),
+            0
+        );
+        assert_eq!(index.len(), 100);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::world;
+use std::any;
+use std::sync::atomic;
+
+/// Shared by every storage, so a stamp taken before a storage was replaced still orders before
+/// anything done to its replacement.
+static CHANGES: atomic::AtomicU64 = atomic::AtomicU64::new(0);
+
+fn next_change() -> u64 {
+    CHANGES.fetch_add(1, atomic::Ordering::Relaxed) + 1
+}
+
+/// The latest change made to any storage, pass it to [`SparseSet::changed_since`] later on.
+pub fn current_change() -> u64 {
+    CHANGES.load(atomic::Ordering::Relaxed)
+}
+
+/// Type erased view of a [`SparseSet`] so the world can remove components without knowing their type.
+pub trait AnyStorage: any::Any {
+    fn remove_entity(&mut self, entity: world::Entity);
+    fn as_any(&self) -> &dyn any::Any;
+    fn as_any_mut(&mut self) -> &mut dyn any::Any;
+}
+
+/// Densely packed components indexed through a sparse table of entity indices.
+///
+/// Inserting a component or borrowing it mutably counts as changing it, whether or not it was
+/// written to.
+pub struct SparseSet<T> {
+    sparse: Vec<Option<u32>>,
+    entities: Vec<world::Entity>,
+    components: Vec<T>,
+    changed: Vec<u64>,
+    created: u64,
+    removed: u64,
+}
+
+impl<T> Default for SparseSet<T> {
+    fn default() -> Self {
+        Self {
+            sparse: Vec::new(),
+            entities: Vec::new(),
+            components: Vec::new(),
+            changed: Vec::new(),
+            created: next_change(),
+            removed: 0,
+        }
+    }
+}
+
+impl<T> SparseSet<T> {
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+    pub fn entities(&self) -> &[world::Entity] {
+        &self.entities
+    }
+    pub fn contains(&self, entity: world::Entity) -> bool {
+        self.dense_index(entity).is_some()
+    }
+    pub fn insert(&mut self, entity: world::Entity, component: T) -> Option<T> {
+        if let Some(dense) = self.dense_index(entity) {
+            self.changed[dense] = next_change();
+            return Some(std::mem::replace(&mut self.components[dense], component));
+        }
+        let sparse = entity.index() as usize;
+        if sparse >= self.sparse.len() {
+            self.sparse.resize(sparse + 1, None);
+        }
+        self.sparse[sparse] = Some(self.components.len() as u32);
+        self.entities.push(entity);
+        self.components.push(component);
+        self.changed.push(next_change());
+        None
+    }
+    pub fn remove(&mut self, entity: world::Entity) -> Option<T> {
+        let dense = self.dense_index(entity)?;
+        self.sparse[entity.index() as usize] = None;
+        self.entities.swap_remove(dense);
+        self.changed.swap_remove(dense);
+        self.removed = next_change();
+        let component = self.components.swap_remove(dense);
+        if let Some(moved) = self.entities.get(dense) {
+            self.sparse[moved.index() as usize] = Some(dense as u32);
+        }
+        Some(component)
+    }
+    pub fn get(&self, entity: world::Entity) -> Option<&T> {
+        self.dense_index(entity)
+            .map(|dense| &self.components[dense])
+    }
+    pub fn get_mut(&mut self, entity: world::Entity) -> Option<&mut T> {
+        let dense = self.dense_index(entity)?;
+        self.changed[dense] = next_change();
+        Some(&mut self.components[dense])
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (world::Entity, &T)> {
+        self.entities.iter().copied().zip(self.components.iter())
+    }
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (world::Entity, &mut T)> {
+        let change = next_change();
+        self.changed.fill(change);
+        self.entities
+            .iter()
+            .copied()
+            .zip(self.components.iter_mut())
+    }
+    /// Entities whose component was inserted or borrowed mutably after the change `since`.
+    pub fn changed_since(&self, since: u64) -> impl Iterator<Item = world::Entity> + '_ {
+        self.entities
+            .iter()
+            .zip(&self.changed)
+            .filter(move |(_, changed)| **changed > since)
+            .map(|(entity, _)| *entity)
+    }
+    /// Whether a component may have been removed after the change `since`, including by the
+    /// whole storage being replaced.
+    pub fn removed_since(&self, since: u64) -> bool {
+        self.removed > since || self.created > since
+    }
+    fn dense_index(&self, entity: world::Entity) -> Option<usize> {
+        let dense = (*self.sparse.get(entity.index() as usize)?)? as usize;
+        (self.entities[dense] == entity).then_some(dense)
+    }
+}
+
+impl<T: 'static> AnyStorage for SparseSet<T> {
+    fn remove_entity(&mut self, entity: world::Entity) {
+        self.remove(entity);
+    }
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn any::Any {
+        self
+    }
+}
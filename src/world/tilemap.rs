@@ -0,0 +1,372 @@
+use crate::world;
+use crate::world::spatial;
+use std::collections;
+use std::sync::atomic;
+
+pub const CHUNK_SIZE: i32 = 16;
+
+static NEXT_TILEMAP_ID: atomic::AtomicU64 = atomic::AtomicU64::new(0);
+
+/// Index into [`Tileset::definitions`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TileKind(pub u16);
+
+/// Picks a frame from which of the four neighbours hold the same kind of tile.
+///
+/// The mask has bit 0 set for the tile above, 1 for the right, 2 for below and 3 for the left.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AutotileRule {
+    pub frames: [u16; 16],
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileDefinition {
+    pub sprite: usize,
+    pub frame: u16,
+    pub solid: bool,
+    pub autotile: Option<AutotileRule>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tileset {
+    pub definitions: Vec<TileDefinition>,
+}
+
+impl Tileset {
+    pub fn add(&mut self, definition: TileDefinition) -> TileKind {
+        self.definitions.push(definition);
+        TileKind(self.definitions.len() as u16 - 1)
+    }
+    pub fn get(&self, kind: TileKind) -> Option<&TileDefinition> {
+        self.definitions.get(kind.0 as usize)
+    }
+}
+
+/// A drawable tile of a chunk, sorted by sprite so the renderer can batch them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TileQuad {
+    pub sprite: usize,
+    pub frame: u16,
+    pub position: glam::Vec2,
+}
+
+pub struct Chunk {
+    tiles: Vec<Option<TileKind>>,
+    dirty: bool,
+    revision: u64,
+    quads: Vec<TileQuad>,
+}
+
+impl Chunk {
+    fn new() -> Self {
+        Self {
+            tiles: vec![None; (CHUNK_SIZE * CHUNK_SIZE) as usize],
+            dirty: true,
+            revision: 0,
+            quads: Vec::new(),
+        }
+    }
+    /// Increases every time the quads are rebuilt, so GPU copies know when to upload again.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+    pub fn quads(&self) -> &[TileQuad] {
+        &self.quads
+    }
+    fn local_index(cell: glam::IVec2) -> usize {
+        let local = cell.rem_euclid(glam::IVec2::splat(CHUNK_SIZE));
+        (local.y * CHUNK_SIZE + local.x) as usize
+    }
+}
+
+pub struct TileLayer {
+    pub name: String,
+    chunks: collections::HashMap<glam::IVec2, Chunk>,
+}
+
+impl TileLayer {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            chunks: collections::HashMap::new(),
+        }
+    }
+    pub fn get(&self, cell: glam::IVec2) -> Option<TileKind> {
+        self.chunks.get(&chunk_of(cell))?.tiles[Chunk::local_index(cell)]
+    }
+    pub fn chunk(&self, coordinate: glam::IVec2) -> Option<&Chunk> {
+        self.chunks.get(&coordinate)
+    }
+    pub fn chunks(&self) -> impl Iterator<Item = (glam::IVec2, &Chunk)> {
+        self.chunks
+            .iter()
+            .map(|(coordinate, chunk)| (*coordinate, chunk))
+    }
+    fn set(&mut self, cell: glam::IVec2, tile: Option<TileKind>) {
+        let chunk = self.chunks.entry(chunk_of(cell)).or_insert_with(Chunk::new);
+        chunk.tiles[Chunk::local_index(cell)] = tile;
+        // Neighbouring chunks may autotile against this cell.
+        for offset in [
+            glam::IVec2::ZERO,
+            glam::IVec2::X,
+            glam::IVec2::NEG_X,
+            glam::IVec2::Y,
+            glam::IVec2::NEG_Y,
+        ] {
+            if let Some(chunk) = self.chunks.get_mut(&chunk_of(cell + offset)) {
+                chunk.dirty = true;
+            }
+        }
+    }
+    fn autotile_mask(&self, cell: glam::IVec2, kind: TileKind) -> usize {
+        [
+            glam::IVec2::Y,
+            glam::IVec2::X,
+            glam::IVec2::NEG_Y,
+            glam::IVec2::NEG_X,
+        ]
+        .into_iter()
+        .enumerate()
+        .filter(|(_, offset)| self.get(cell + *offset) == Some(kind))
+        .fold(0, |mask, (bit, _)| mask | 1 << bit)
+    }
+    fn rebuild(&mut self, tileset: &Tileset, tile_size: f32) {
+        let dirty = self
+            .chunks
+            .iter()
+            .filter(|(_, chunk)| chunk.dirty)
+            .map(|(coordinate, _)| *coordinate)
+            .collect::<Vec<_>>();
+        for coordinate in dirty {
+            if self.chunks[&coordinate].tiles.iter().all(Option::is_none) {
+                self.chunks.remove(&coordinate);
+                continue;
+            }
+            let origin = coordinate * CHUNK_SIZE;
+            let mut quads = Vec::new();
+            for index in 0..CHUNK_SIZE * CHUNK_SIZE {
+                let cell = origin + glam::ivec2(index % CHUNK_SIZE, index / CHUNK_SIZE);
+                let Some(kind) = self.chunks[&coordinate].tiles[index as usize] else {
+                    continue;
+                };
+                let Some(definition) = tileset.get(kind) else {
+                    continue;
+                };
+                let frame = match &definition.autotile {
+                    Some(rule) => rule.frames[self.autotile_mask(cell, kind)],
+                    None => definition.frame,
+                };
+                quads.push(TileQuad {
+                    sprite: definition.sprite,
+                    frame,
+                    position: (cell.as_vec2() + 0.5) * tile_size,
+                });
+            }
+            quads.sort_by_key(|quad| quad.sprite);
+
+            let chunk = self
+                .chunks
+                .get_mut(&coordinate)
+                .expect("dirty chunks were just listed");
+            chunk.quads = quads;
+            chunk.dirty = false;
+            chunk.revision += 1;
+        }
+    }
+}
+
+/// Layers of tiles drawn beneath every entity sprite, first layer at the bottom.
+pub struct Tilemap {
+    pub tile_size: f32,
+    pub tileset: Tileset,
+    layers: Vec<TileLayer>,
+    revision: u64,
+    id: u64,
+}
+
+impl Tilemap {
+    pub fn new(tile_size: f32, tileset: Tileset) -> Self {
+        Self {
+            tile_size,
+            tileset,
+            layers: Vec::new(),
+            revision: 0,
+            id: NEXT_TILEMAP_ID.fetch_add(1, atomic::Ordering::Relaxed),
+        }
+    }
+    /// Unique to this tilemap, so caches notice when it is replaced by one with equal revisions.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    /// Increases every time a tile is set, so derived data like navigation grids know when to rebuild.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+    pub fn add_layer(&mut self, name: impl Into<String>) -> usize {
+        self.layers.push(TileLayer::new(name));
+        self.layers.len() - 1
+    }
+    pub fn layers(&self) -> &[TileLayer] {
+        &self.layers
+    }
+    pub fn get(&self, layer: usize, cell: glam::IVec2) -> Option<TileKind> {
+        self.layers.get(layer)?.get(cell)
+    }
+    pub fn set(&mut self, layer: usize, cell: glam::IVec2, tile: Option<TileKind>) {
+        if let Some(layer) = self.layers.get_mut(layer) {
+            layer.set(cell, tile);
+            self.revision += 1;
+        }
+    }
+    pub fn fill(
+        &mut self,
+        layer: usize,
+        min: glam::IVec2,
+        max: glam::IVec2,
+        tile: Option<TileKind>,
+    ) {
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                self.set(layer, glam::ivec2(x, y), tile);
+            }
+        }
+    }
+    /// Smallest and largest cell of every allocated chunk, `None` while the map is empty.
+    pub fn cell_bounds(&self) -> Option<(glam::IVec2, glam::IVec2)> {
+        self.layers
+            .iter()
+            .flat_map(|layer| layer.chunks.keys())
+            .fold(None, |bounds, chunk| {
+                let (min, max) = (
+                    *chunk * CHUNK_SIZE,
+                    (*chunk + glam::IVec2::ONE) * CHUNK_SIZE - glam::IVec2::ONE,
+                );
+                Some(match bounds {
+                    Some((low, high)) => (min.min(low), max.max(high)),
+                    None => (min, max),
+                })
+            })
+    }
+    pub fn world_to_cell(&self, position: glam::Vec2) -> glam::IVec2 {
+        (position / self.tile_size).floor().as_ivec2()
+    }
+    pub fn cell_aabb(&self, cell: glam::IVec2) -> spatial::Aabb {
+        spatial::Aabb::new(
+            cell.as_vec2() * self.tile_size,
+            (cell + glam::IVec2::ONE).as_vec2() * self.tile_size,
+        )
+    }
+    pub fn chunk_aabb(&self, chunk: glam::IVec2) -> spatial::Aabb {
+        spatial::Aabb::new(
+            (chunk * CHUNK_SIZE).as_vec2() * self.tile_size,
+            ((chunk + glam::IVec2::ONE) * CHUNK_SIZE).as_vec2() * self.tile_size,
+        )
+    }
+    /// Whether any layer holds a solid tile at the cell.
+    pub fn is_solid(&self, cell: glam::IVec2) -> bool {
+        self.layers.iter().any(|layer| {
+            layer
+                .get(cell)
+                .and_then(|kind| self.tileset.get(kind))
+                .is_some_and(|definition| definition.solid)
+        })
+    }
+    /// Boxes of every solid cell overlapping `area`.
+    pub fn solid_cells(&self, area: &spatial::Aabb) -> Vec<spatial::Aabb> {
+        let min = self.world_to_cell(area.min);
+        let max = self.world_to_cell(area.max);
+        let mut cells = Vec::new();
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let cell = glam::ivec2(x, y);
+                if self.is_solid(cell) {
+                    cells.push(self.cell_aabb(cell));
+                }
+            }
+        }
+        cells
+    }
+    pub fn rebuild_dirty(&mut self) {
+        for layer in &mut self.layers {
+            layer.rebuild(&self.tileset, self.tile_size);
+        }
+    }
+}
+
+fn chunk_of(cell: glam::IVec2) -> glam::IVec2 {
+    cell.div_euclid(glam::IVec2::splat(CHUNK_SIZE))
+}
+
+pub fn rebuild_system(world: &world::World, _commands: &mut world::commands::Commands) {
+    if let Some(mut tilemap) = world.resource_mut::<Tilemap>() {
+        tilemap.rebuild_dirty();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tilemap() -> (Tilemap, TileKind) {
+        let mut tileset = Tileset::default();
+        let grass = tileset.add(TileDefinition {
+            sprite: 0,
+            frame: 3,
+            solid: false,
+            autotile: None,
+        });
+        let mut tilemap = Tilemap::new(16.0, tileset);
+        tilemap.add_layer("ground");
+        (tilemap, grass)
+    }
+
+    #[test]
+    fn rebuilding_bumps_the_revision_of_dirty_chunks_once() {
+        let (mut tilemap, grass) = tilemap();
+        tilemap.set(0, glam::ivec2(1, 1), Some(grass));
+        tilemap.rebuild_dirty();
+        let chunk = tilemap.layers()[0].chunk(glam::IVec2::ZERO).unwrap();
+        assert_eq!(chunk.revision(), 1);
+        assert_eq!(
+            chunk.quads(),
+            [TileQuad {
+                sprite: 0,
+                frame: 3,
+                position: glam::vec2(24.0, 24.0),
+            }]
+        );
+        tilemap.rebuild_dirty();
+        assert_eq!(
+            tilemap.layers()[0]
+                .chunk(glam::IVec2::ZERO)
+                .unwrap()
+                .revision(),
+            1
+        );
+    }
+
+    #[test]
+    fn emptied_chunks_are_dropped() {
+        let (mut tilemap, grass) = tilemap();
+        tilemap.fill(0, glam::ivec2(-2, 0), glam::ivec2(1, 0), Some(grass));
+        tilemap.rebuild_dirty();
+        assert_eq!(tilemap.layers()[0].chunks().count(), 2);
+
+        tilemap.fill(0, glam::ivec2(-2, 0), glam::ivec2(-1, 0), None);
+        tilemap.rebuild_dirty();
+        let chunks = tilemap.layers()[0]
+            .chunks()
+            .map(|(coordinate, _)| coordinate)
+            .collect::<Vec<_>>();
+        assert_eq!(chunks, [glam::IVec2::ZERO]);
+        assert_eq!(
+            tilemap.cell_bounds(),
+            Some((glam::IVec2::ZERO, glam::IVec2::splat(CHUNK_SIZE - 1)))
+        );
+    }
+
+    #[test]
+    fn every_tilemap_has_its_own_id() {
+        assert_ne!(tilemap().0.id(), tilemap().0.id());
+    }
+}